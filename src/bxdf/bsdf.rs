@@ -33,7 +33,25 @@ pub struct BSDF<'a> {
     /// will leak since it won't be dropped. This would also migrate our BxDFs
     /// from Box<BxDF> to &BxDF. When unboxed traits land we can move to unboxed
     /// BxDFs here though.
-    bxdfs: &'a [Box<BxDF + Send + Sync>],
+    bxdfs: BxDFList<'a>,
+}
+
+/// The BxDFs making up a BSDF. Materials with constant properties create their
+/// BxDFs once and lend them to the BSDF while textured materials must create new
+/// ones for each hit point.
+/// TODO: With a memory pool the owned BxDFs would be allocated from the pool instead
+enum BxDFList<'a> {
+    Borrowed(&'a [Box<BxDF + Send + Sync>]),
+    Owned(Vec<Box<BxDF + Send + Sync>>),
+}
+
+impl<'a> BxDFList<'a> {
+    fn as_slice(&self) -> &[Box<BxDF + Send + Sync>] {
+        match *self {
+            BxDFList::Borrowed(b) => b,
+            BxDFList::Owned(ref b) => &b[..],
+        }
+    }
 }
 
 impl<'a> BSDF<'a> {
@@ -42,6 +60,16 @@ impl<'a> BSDF<'a> {
     pub fn new(bxdfs: &'a [Box<BxDF + Send + Sync>], eta: f32,
                dg: &DifferentialGeometry<'a>)
                -> BSDF<'a> {
+        BSDF::with_bxdfs(BxDFList::Borrowed(bxdfs), eta, dg)
+    }
+    /// Create a new BSDF which takes ownership of the BxDFs passed to shade the
+    /// differential geometry with refractive index `eta`. This is used by materials
+    /// which need to create their BxDFs for each hit point, eg. textured materials.
+    pub fn owned(bxdfs: Vec<Box<BxDF + Send + Sync>>, eta: f32, dg: &DifferentialGeometry<'a>)
+                 -> BSDF<'a> {
+        BSDF::with_bxdfs(BxDFList::Owned(bxdfs), eta, dg)
+    }
+    fn with_bxdfs(bxdfs: BxDFList<'a>, eta: f32, dg: &DifferentialGeometry<'a>) -> BSDF<'a> {
         let n = dg.n.normalized();
         let mut bitan = dg.dp_du.normalized();
         let tan = linalg::cross(&n, &bitan);
@@ -49,10 +77,10 @@ impl<'a> BSDF<'a> {
         BSDF { p: dg.p, n: n, ng: dg.ng, tan: tan, bitan: bitan, bxdfs: bxdfs, eta: eta }
     }
     /// Return the total number of BxDFs
-    pub fn num_bxdfs(&self) -> usize { self.bxdfs.len() }
+    pub fn num_bxdfs(&self) -> usize { self.bxdfs.as_slice().len() }
     /// Return the number of BxDFs matching the flags
     pub fn num_matching(&self, flags: EnumSet<BxDFType>) -> usize {
-        self.bxdfs.iter().filter(|x| x.matches(flags)).count()
+        self.bxdfs.as_slice().iter().filter(|x| x.matches(flags)).count()
     }
     /// Transform the vector from world space to shading space
     pub fn to_shading(&self, v: &Vector) -> Vector {
@@ -80,7 +108,8 @@ impl<'a> BSDF<'a> {
             flags.remove(&BxDFType::Reflection);
         }
         // Find all matching BxDFs and add their contribution to the material's color
-        self.bxdfs.iter().filter_map(|x| if x.matches(flags) { Some(x.eval(&w_o, &w_i)) } else { None })
+        self.bxdfs.as_slice().iter()
+            .filter_map(|x| if x.matches(flags) { Some(x.eval(&w_o, &w_i)) } else { None })
             .fold(Colorf::broadcast(0.0), |x, y| x + y)
     }
     /// Sample a component of the BSDF to get an incident light direction for light
@@ -105,10 +134,11 @@ impl<'a> BSDF<'a> {
 
         // TODO: We re-use our functions but actually do a lot of redundant computation. I'm not
         // sure that the compiler will eliminate it. Should just copy in the code from pdf and eval
+        // The pdf of all matching BxDFs already accounts for choosing between them, while
+        // specular components can only be sampled by the one we picked
         if !bxdf.bxdf_type().contains(&BxDFType::Specular) && n_matching > 1 {
             pdf = self.pdf(wo_world, &wi_world, flags);
-        }
-        if n_matching > 1 {
+        } else if n_matching > 1 {
             pdf /= n_matching as f32;
         }
 
@@ -123,7 +153,7 @@ impl<'a> BSDF<'a> {
         // should also normalize?
         let w_o = self.to_shading(wo_world).normalized();
         let w_i = self.to_shading(wi_world).normalized();
-        let (pdf_val, n_comps) = self.bxdfs.iter()
+        let (pdf_val, n_comps) = self.bxdfs.as_slice().iter()
             .filter_map(|x| if x.matches(flags) { Some(x.pdf(&w_o, &w_i)) } else { None })
             .fold((0.0, 0), |(p, n), y| (p + y, n + 1));
         if n_comps > 0 {
@@ -135,7 +165,7 @@ impl<'a> BSDF<'a> {
     /// Get the `i`th BxDF that matches the flags passed. There should not be fewer than i
     /// BxDFs that match the flags
     fn matching_at(&self, i: usize, flags: EnumSet<BxDFType>) -> &Box<BxDF + Send + Sync> {
-        let mut it = self.bxdfs.iter().filter(|x| x.matches(flags)).skip(i);
+        let mut it = self.bxdfs.as_slice().iter().filter(|x| x.matches(flags)).skip(i);
         match it.next() {
             Some(b) => b,
             None => panic!("Out of bounds index for BxDF type {:?}", flags)
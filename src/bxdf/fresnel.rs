@@ -68,6 +68,35 @@ impl Fresnel for Dielectric {
     }
 }
 
+/// Computes the Fresnel term for the outside of a dielectric boundary, light arriving
+/// from inside the material isn't reflected. This is used by materials which scatter
+/// light beneath their surface, where light reaching the surface from inside is
+/// transmitted back out diffusely
+#[derive(Clone, Copy, Debug)]
+pub struct ExteriorDielectric {
+    /// The Fresnel term for light arriving from outside
+    pub dielectric: Dielectric,
+}
+
+impl ExteriorDielectric {
+    /// Create a new exterior Dielectric Fresnel term for the boundary between two objects.
+    /// `eta_i`: refractive index of the material outside the boundary.
+    /// `eta_t`: refractive index of the material inside the boundary.
+    pub fn new(eta_i: f32, eta_t: f32) -> ExteriorDielectric {
+        ExteriorDielectric { dielectric: Dielectric::new(eta_i, eta_t) }
+    }
+}
+
+impl Fresnel for ExteriorDielectric {
+    fn fresnel(&self, cos_i: f32) -> Colorf {
+        if cos_i > 0.0 {
+            self.dielectric.fresnel(cos_i)
+        } else {
+            Colorf::black()
+        }
+    }
+}
+
 /// Computes the Fresnel term for conductive materials
 #[derive(Clone, Copy, Debug)]
 pub struct Conductor {
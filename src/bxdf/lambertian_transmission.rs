@@ -0,0 +1,85 @@
+//! Defines a Lambertian BTDF that describes light diffusely transmitted through a
+//! dielectric boundary, eg. light entering or leaving a translucent material which
+//! scatters it beneath its surface. Light arriving from outside is scaled by the Fresnel
+//! transmittance of the boundary, the rest of it is reflected by the surface. Light
+//! arriving from inside isn't reflected so it's always transmitted back out.
+
+use std::f32;
+use enum_set::EnumSet;
+
+use linalg::Vector;
+use film::Colorf;
+use mc;
+use bxdf::{self, BxDF, BxDFType};
+use bxdf::fresnel::{self, Fresnel};
+
+/// Lambertian BTDF that transmits light diffusely to the other side of the surface
+#[derive(Clone, Copy, Debug)]
+pub struct LambertianTransmission {
+    /// Color of the transmitted light
+    transmission: Colorf,
+    /// Fresnel term for the boundary the light is transmitted through
+    fresnel: fresnel::ExteriorDielectric,
+}
+
+impl LambertianTransmission {
+    /// Create a diffusely transmissive BTDF with the color and Fresnel term
+    pub fn new(c: &Colorf, fresnel: fresnel::ExteriorDielectric) -> LambertianTransmission {
+        LambertianTransmission { transmission: *c, fresnel: fresnel }
+    }
+}
+
+impl BxDF for LambertianTransmission {
+    fn bxdf_type(&self) -> EnumSet<BxDFType> {
+        let mut e = EnumSet::new();
+        e.insert(BxDFType::Diffuse);
+        e.insert(BxDFType::Transmission);
+        e
+    }
+    fn eval(&self, _: &Vector, w_i: &Vector) -> Colorf {
+        // The Fresnel term is for the light arriving along w_i, which is black when it
+        // arrives from inside the boundary
+        let f = Colorf::broadcast(1.0) - self.fresnel.fresnel(bxdf::cos_theta(w_i));
+        f * self.transmission * f32::consts::FRAC_1_PI
+    }
+    fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Colorf, Vector, f32) {
+        let mut w_i = mc::cos_sample_hemisphere(samples);
+        // The transmitted direction is on the opposite side of the surface from w_o
+        if w_o.z > 0.0 {
+            w_i.z *= -1.0;
+        }
+        (self.eval(w_o, &w_i), w_i, self.pdf(w_o, &w_i))
+    }
+    fn pdf(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+        if !bxdf::same_hemisphere(w_o, w_i) {
+            f32::abs(bxdf::cos_theta(w_i)) * f32::consts::FRAC_1_PI
+        } else {
+            0.0
+        }
+    }
+}
+
+#[test]
+fn test_energy_conservation() {
+    // Light arriving from either side of the surface is specularly reflected with the Fresnel
+    // reflectance of the exterior dielectric and the rest of it may be transmitted, so the
+    // reflectance and transmittance must sum to at most 1
+    let fresnel = fresnel::ExteriorDielectric::new(1.0, 1.5);
+    let btdf = LambertianTransmission::new(&Colorf::broadcast(1.0), fresnel);
+    let n = 32;
+    for &z in &[0.95, 0.5, 0.1, -0.1, -0.5, -0.95] {
+        let w_i = Vector::new(f32::sqrt(1.0 - z * z), 0.0, z);
+        let reflectance = fresnel.fresnel(bxdf::cos_theta(&w_i)).r;
+        let mut transmittance = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+                let w_o = btdf.sample(&w_i, &u).1;
+                let pdf = btdf.pdf(&w_i, &w_o);
+                transmittance += btdf.eval(&w_o, &w_i).r * f32::abs(bxdf::cos_theta(&w_o)) / pdf;
+            }
+        }
+        transmittance /= (n * n) as f32;
+        assert!(reflectance + transmittance <= 1.0 + 1e-4);
+    }
+}
@@ -12,6 +12,7 @@ use mc;
 
 pub use self::bsdf::BSDF;
 pub use self::lambertian::Lambertian;
+pub use self::lambertian_transmission::LambertianTransmission;
 pub use self::oren_nayar::OrenNayar;
 pub use self::specular_reflection::SpecularReflection;
 pub use self::specular_transmission::SpecularTransmission;
@@ -21,6 +22,7 @@ pub use self::microfacet_transmission::MicrofacetTransmission;
 
 pub mod bsdf;
 pub mod lambertian;
+pub mod lambertian_transmission;
 pub mod oren_nayar;
 pub mod fresnel;
 pub mod specular_reflection;
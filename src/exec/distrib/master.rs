@@ -10,11 +10,12 @@ use std::iter;
 
 use bincode::SizeLimit;
 use bincode::rustc_serialize::{encode, decode};
-use image;
 use mio::tcp::{TcpStream, Shutdown};
 use mio::*;
+use scoped_threadpool::Pool;
 
-use film::Image;
+use film::{Aov, Denoiser, Image, ToneMap};
+use film::output;
 use exec::Config;
 use exec::distrib::{worker, Instructions, Frame};
 use sampler::BlockQueue;
@@ -34,8 +35,8 @@ enum DistributedFrame {
 }
 
 impl DistributedFrame {
-    pub fn start(img_dim: (usize, usize)) -> DistributedFrame {
-        DistributedFrame::InProgress { num_reporting: 0, render: Image::new(img_dim) }
+    pub fn start(img_dim: (usize, usize), aovs: &[Aov]) -> DistributedFrame {
+        DistributedFrame::InProgress { num_reporting: 0, render: Image::new(img_dim, aovs) }
     }
 }
 
@@ -57,7 +58,7 @@ impl WorkerBuffer {
 
 /// The Master organizes the set of Worker processes and instructions them what parts
 /// of the scene to render. As workers report results the master collects them and
-/// saves out the PNG once all workers have reported the frame.
+/// saves out the image once all workers have reported the frame.
 pub struct Master {
     /// Hostnames of the workers to send work too
     workers: Vec<String>,
@@ -69,6 +70,14 @@ pub struct Master {
     /// List of the frames we're collecting or have completed
     frames: HashMap<usize, DistributedFrame>,
     img_dim: (usize, usize),
+    /// Tone mapping applied to frames saved in 8-bit formats
+    tone_map: ToneMap,
+    /// The AOVs being rendered alongside the frames
+    aovs: Vec<Aov>,
+    /// Denoiser run on the frames before they're saved, if any
+    denoiser: Option<Denoiser>,
+    /// Thread pool the frames are denoised on, with the number of threads in the config
+    denoise_pool: Pool,
     /// Number of 8x8 blocks we're assigning per worker
     blocks_per_worker: usize,
     /// Remainder of blocks that will be tacked on to the last
@@ -78,8 +87,11 @@ pub struct Master {
 
 impl Master {
     /// Create a new master that will contact the worker nodes passed and
-    /// send instructions on what parts of the scene to start rendering
-    pub fn start_workers(workers: Vec<String>, config: Config, img_dim: (usize, usize))
+    /// send instructions on what parts of the scene to start rendering. Frames are
+    /// saved with the scene's `tone_map` along with the `aovs` rendered by the workers, and are
+    /// denoised first if a `denoiser` is passed
+    pub fn start_workers(workers: Vec<String>, config: Config, img_dim: (usize, usize),
+                         tone_map: ToneMap, aovs: Vec<Aov>, denoiser: Option<Denoiser>)
                          -> (Master, EventLoop<Master>) {
         // Figure out how many blocks we have for this image and assign them to our workers
         let queue = BlockQueue::new((img_dim.0 as u32, img_dim.1 as u32), (8, 8), (0, 0));
@@ -104,10 +116,15 @@ impl Master {
             }
         }
         let worker_buffers: Vec<_> = iter::repeat(WorkerBuffer::new()).take(workers.len()).collect();
+        let denoise_pool = Pool::new(config.num_threads);
         let master = Master { workers: workers, connections: connections,
                               worker_buffers: worker_buffers, config: config,
                               frames: HashMap::new(),
                               img_dim: img_dim,
+                              tone_map: tone_map,
+                              aovs: aovs,
+                              denoiser: denoiser,
+                              denoise_pool: denoise_pool,
                               blocks_per_worker: blocks_per_worker,
                               blocks_remainder: blocks_remainder };
         (master, event_loop)
@@ -118,8 +135,9 @@ impl Master {
     fn save_results(&mut self, frame: Frame) {
         let frame_num = frame.frame as usize;
         let img_dim = self.img_dim;
+        let aovs = &self.aovs;
         // Find the frame being reported and create it if we haven't received parts of this frame yet
-        let mut df = self.frames.entry(frame_num).or_insert_with(|| DistributedFrame::start(img_dim));
+        let mut df = self.frames.entry(frame_num).or_insert_with(|| DistributedFrame::start(img_dim, aovs));
 
         let mut finished = false;
         match *df {
@@ -127,6 +145,10 @@ impl Master {
                 // Collect results from the worker and see if we've finished the frame and can save
                 // it out
                 render.add_blocks(frame.block_size, &frame.blocks, &frame.pixels);
+                render.add_splats(&frame.splats);
+                for (i, px) in frame.aov_pixels.iter().enumerate() {
+                    render.add_aov_blocks(i, frame.block_size, &frame.blocks, px);
+                }
                 *num_reporting += 1;
                 if *num_reporting == self.workers.len() {
                     let out_file = match self.config.out_path.extension() {
@@ -134,10 +156,14 @@ impl Master {
                         None => self.config.out_path.join(
                             PathBuf::from(format!("frame{:05}.png", frame_num))),
                     };
-                    let img = render.get_srgb8();
-                    let dim = render.dimensions();
-                    match image::save_buffer(&out_file.as_path(), &img[..], dim.0 as u32,
-                    dim.1 as u32, image::RGB(8)) {
+                    let mut aovs = render.get_aovs_linear();
+                    let img = match self.denoiser {
+                        Some(d) => d.denoise(render.dimensions(), render.get_linear(), &mut aovs,
+                                             &mut self.denoise_pool),
+                        None => render.get_linear(),
+                    };
+                    match output::save_frame(&out_file.as_path(), render.dimensions(), &img[..],
+                                             &aovs, &self.tone_map) {
                         Ok(_) => {},
                         Err(e) => println!("Error saving image, {}", e),
                     };
@@ -216,7 +242,8 @@ impl Handler for Master {
                 };
             let instr = Instructions::new(&self.config.scene_file,
                                           (self.config.frame_info.start, self.config.frame_info.end),
-                                          b_start, b_count);
+                                          b_start, b_count, self.config.time_limit,
+                                          self.config.noise_threshold);
             // Encode and send our instructions to the worker
             let bytes = encode(&instr, SizeLimit::Infinite).unwrap();
             if let Err(e) = self.connections[worker].write_all(&bytes[..]) {
@@ -28,6 +28,8 @@
 //!
 //! The master will send the workers the location of the scene file which is assumed to
 //! be on some shared filesystem or otherwise available at the same path on all the workers.
+//! If a time limit or noise threshold is passed to the master it's sent on to the workers,
+//! which render their blocks of each frame progressively until they reach it.
 //!
 //! # Running on GCE or EC2
 //!
@@ -61,13 +63,18 @@ struct Instructions {
     pub block_start: usize,
     /// Number of blocks this worker will render
     pub block_count: usize,
+    /// Time limit for rendering each frame progressively, if set
+    pub time_limit: Option<f64>,
+    /// Noise threshold to render each frame progressively to, if set
+    pub noise_threshold: Option<f32>,
 }
 
 impl Instructions {
-    pub fn new(scene: &str, frames: (usize, usize), block_start: usize,
-               block_count: usize) -> Instructions {
+    pub fn new(scene: &str, frames: (usize, usize), block_start: usize, block_count: usize,
+               time_limit: Option<f64>, noise_threshold: Option<f32>) -> Instructions {
         let mut instr = Instructions { encoded_size: 0, scene: scene.to_owned(), frames: frames,
-                       block_start: block_start, block_count: block_count };
+                       block_start: block_start, block_count: block_count,
+                       time_limit: time_limit, noise_threshold: noise_threshold };
         instr.encoded_size = encoded_size(&instr);
         instr
     }
@@ -87,13 +94,17 @@ struct Frame {
     pub blocks: Vec<(usize, usize)>,
     /// Sample data for each block, RGBW_F32 (W = weight)
     pub pixels: Vec<f32>,
+    /// Contributions splatted to the entire image, RGB_F32
+    pub splats: Vec<f32>,
+    /// Sample data for the same blocks for each AOV being rendered, RGBW_F32
+    pub aov_pixels: Vec<Vec<f32>>,
 }
 
 impl Frame {
     pub fn new(frame: usize, block_size: (usize, usize), blocks: Vec<(usize, usize)>,
-               pixels: Vec<f32>) -> Frame {
+               pixels: Vec<f32>, splats: Vec<f32>, aov_pixels: Vec<Vec<f32>>) -> Frame {
         let mut frame = Frame { encoded_size: 0, frame: frame, block_size: block_size,
-                            blocks: blocks, pixels: pixels };
+                            blocks: blocks, pixels: pixels, splats: splats, aov_pixels: aov_pixels };
         frame.encoded_size = encoded_size(&frame);
         frame
     }
@@ -37,19 +37,24 @@ impl Worker {
     /// what parts of it we've been assigned
     pub fn listen_for_master(num_threads: u32) -> Worker {
         let (instructions, master) = get_instructions();
-        let (scene, rt, spp, mut frame_info) = Scene::load_file(&instructions.scene);
+        let (scene, rt, sampler, mut frame_info) = Scene::load_file(&instructions.scene);
         frame_info.start = instructions.frames.0;
         frame_info.end = instructions.frames.1;
-        let config = Config::new(PathBuf::from("/tmp"), instructions.scene.clone(), spp,
-                                 num_threads, frame_info,
-                                 (instructions.block_start, instructions.block_count));
+        let mut config = Config::new(PathBuf::from("/tmp"), instructions.scene.clone(), sampler,
+                                     num_threads, frame_info,
+                                     (instructions.block_start, instructions.block_count));
+        config.time_limit = instructions.time_limit;
+        config.noise_threshold = instructions.noise_threshold;
         Worker { instructions: instructions, render_target: rt, scene: scene,
                  config: config, master: master }
     }
     /// Send our blocks back to the master
     pub fn send_results(&mut self) {
         let (block_size, blocks, pixels) = self.render_target.get_rendered_blocks();
-        let frame = Frame::new(self.config.current_frame, block_size, blocks, pixels);
+        let splats = self.render_target.get_splats();
+        let aov_pixels = (0..self.render_target.aovs().len())
+            .map(|i| self.render_target.get_aov_blocks(i, &blocks)).collect();
+        let frame = Frame::new(self.config.current_frame, block_size, blocks, pixels, splats, aov_pixels);
         let bytes = encode(&frame, SizeLimit::Infinite).unwrap();
         if let Err(e) = self.master.write_all(&bytes[..]) {
             panic!("Failed to send frame to {:?}: {}", self.master, e);
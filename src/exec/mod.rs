@@ -4,6 +4,7 @@
 use std::path::PathBuf;
 
 use film::{FrameInfo, RenderTarget};
+use sampler::SamplerConfig;
 use scene::Scene;
 
 pub use self::multithreaded::MultiThreaded;
@@ -19,20 +20,28 @@ pub struct Config {
     pub out_path: PathBuf,
     pub scene_file: String,
     pub num_threads: u32,
-    pub spp: usize,
+    /// The sampler to sample the image with
+    pub sampler: SamplerConfig,
     pub frame_info: FrameInfo,
     pub current_frame: usize,
     /// Which blocks the executor should render, stored
     /// as (start, count) of the block indices
-    pub select_blocks: (usize, usize)
+    pub select_blocks: (usize, usize),
+    /// Wall clock time in seconds to spend rendering each frame. If set the
+    /// frame is rendered progressively until the time runs out
+    pub time_limit: Option<f64>,
+    /// Standard error of each pixel's luminance relative to its mean to render the frame
+    /// to. If set the frame is rendered progressively until all pixels are below it
+    pub noise_threshold: Option<f32>,
 }
 
 impl Config {
-    pub fn new(out_path: PathBuf, scene_file: String, spp: usize, num_threads: u32,
+    pub fn new(out_path: PathBuf, scene_file: String, sampler: SamplerConfig, num_threads: u32,
                frame_info: FrameInfo, select_blocks: (usize, usize)) -> Config {
-        Config { out_path: out_path, scene_file: scene_file, spp: spp,
+        Config { out_path: out_path, scene_file: scene_file, sampler: sampler,
                  num_threads: num_threads, frame_info: frame_info,
-                 current_frame: frame_info.start, select_blocks: select_blocks }
+                 current_frame: frame_info.start, select_blocks: select_blocks,
+                 time_limit: None, noise_threshold: None }
     }
 }
 
@@ -1,16 +1,29 @@
 //! The multithreaded module provides a multithreaded execution for rendering
 //! the image.
+//!
+//! If a time limit or noise threshold is set in the config the image is rendered
+//! progressively, taking passes over all the blocks of the image with the sampler
+//! and accumulating the results until the time runs out or the estimated noise of
+//! all pixels falls below the threshold. The noise is estimated from how much the
+//! luminance of each pixel varies between passes, and once all the pixels in a block
+//! are below the threshold the block is left out of later passes. At least one full
+//! pass is always rendered, after that the time limit is checked before starting each block.
 
-use std::iter;
+use std::{cmp, iter, f32};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use clock_ticks;
 use scoped_threadpool::Pool;
-use rand::StdRng;
+use rand::{StdRng, Rng};
 
 use sampler::BlockQueue;
-use film::{RenderTarget, ImageSample, Colorf};
-use geometry::{Instance, Emitter};
-use sampler::{self, Sampler};
+use film::{RenderTarget, ImageSample, Colorf, Aov};
+use geometry::{Instance, Boundable, Intersection};
+use linalg::{self, Ray};
+use bxdf::BxDFType;
+use light::LightList;
+use sampler::{Sampler, SamplerConfig, Region, Sample};
 use scene::Scene;
 use exec::{Config, Exec};
 
@@ -20,6 +33,38 @@ pub struct MultiThreaded {
     pool: Pool,
 }
 
+/// Running sums of the mean luminance of a pixel found by each pass of a
+/// progressive render, used to estimate the noise remaining in the pixel
+#[derive(Clone, Copy, Debug)]
+struct PixelStats {
+    sum: f32,
+    sum_sqr: f32,
+    passes: u32,
+}
+
+impl PixelStats {
+    fn new() -> PixelStats {
+        PixelStats { sum: 0.0, sum_sqr: 0.0, passes: 0 }
+    }
+    /// Add the mean luminance found for the pixel by a pass
+    fn add(&mut self, luminance: f32) {
+        self.sum += luminance;
+        self.sum_sqr += luminance * luminance;
+        self.passes += 1;
+    }
+    /// Check if the standard error of the pixel's luminance relative to its mean is
+    /// below the `threshold`. We need at least two passes to estimate the error
+    fn converged(&self, threshold: f32) -> bool {
+        if self.passes < 2 {
+            return false;
+        }
+        let n = self.passes as f32;
+        let mean = self.sum / n;
+        let variance = f32::max(0.0, (self.sum_sqr - self.sum * mean) / (n - 1.0));
+        f32::sqrt(variance / n) <= threshold * mean
+    }
+}
+
 impl MultiThreaded {
     /// Create a new multithreaded renderer which will use `num_threads` to render the image
     pub fn new(num_threads: u32) -> MultiThreaded {
@@ -28,25 +73,99 @@ impl MultiThreaded {
     /// Launch a rendering job in parallel across the threads and wait for it to finish
     fn render_parallel(&mut self, scene: &Scene, rt: &RenderTarget, config: &Config) {
         let dim = rt.dimensions();
-        let block_queue = BlockQueue::new((dim.0 as u32, dim.1 as u32), (8, 8), config.select_blocks);
-        let light_list: Vec<_> = scene.bvh.iter().filter_map(|x| {
+        let mut block_queue = BlockQueue::new((dim.0 as u32, dim.1 as u32), (8, 8), config.select_blocks);
+        let lights: Vec<_> = scene.bvh.iter().filter_map(|x| {
             match *x {
                 Instance::Emitter(ref e) => Some(e),
                 _ => None,
             }
         }).collect();
-        assert!(!light_list.is_empty(), "At least one light is required");
+        let (_, scene_radius) = scene.bvh.bounds(0.0, 0.0).bounding_sphere();
+        let shutter = scene.active_camera().shutter_time();
+        let light_list = LightList::new(lights, scene_radius, shutter.0, shutter.1);
+        let progressive = config.time_limit.is_some() || config.noise_threshold.is_some();
+        let deadline = config.time_limit.map(|t| clock_ticks::precise_time_s() + t);
+        let pixel_stats = if progressive {
+            Some(Mutex::new(iter::repeat(PixelStats::new()).take(dim.0 * dim.1).collect()))
+        } else {
+            None
+        };
+        // Some integrators need multiple passes over the image, eg. to average photon maps
+        // traced with shrinking radii, so we always render at least that many
+        let min_passes = scene.integrator.passes();
+        let total_blocks = block_queue.len();
+        let mut blocks_rendered = 0;
+        let mut pass = 0;
+        loop {
+            self.preprocess(scene, &light_list, pass);
+            // The first pass always covers the whole image so we don't leave any of it black
+            let pass_deadline = if pass == 0 { None } else { deadline };
+            block_queue.reset();
+            blocks_rendered += self.render_pass(&block_queue, scene, rt, &light_list, config,
+                                                pass_deadline, pixel_stats.as_ref());
+            pass += 1;
+            if let Some(d) = deadline {
+                if clock_ticks::precise_time_s() >= d {
+                    println!("Frame {}: time limit reached after {} passes", config.current_frame, pass);
+                    break;
+                }
+            }
+            if pass < min_passes {
+                continue;
+            }
+            match (config.noise_threshold, pixel_stats.as_ref()) {
+                (Some(t), Some(stats)) => {
+                    // Only the blocks with pixels still above the threshold are rendered again
+                    let stats = stats.lock().unwrap();
+                    let block_dim = block_queue.block_dim();
+                    block_queue.retain(|b| !block_converged(&stats, b, block_dim, dim, t));
+                    println!("Frame {}: pass {} has {} blocks above the noise threshold",
+                             config.current_frame, pass, block_queue.len());
+                    if block_queue.is_empty() {
+                        break;
+                    }
+                },
+                _ => if deadline.is_none() {
+                    break;
+                },
+            }
+        }
+        // Each pass adds its splats to the image so they must be averaged over the number of
+        // passes taken, counting passes over part of the image by the fraction of blocks rendered
+        rt.scale_splats(total_blocks as f32 / blocks_rendered as f32);
+    }
+    /// Run the integrator's preprocessing for pass `pass` of the frame in parallel across the threads
+    fn preprocess(&mut self, scene: &Scene, light_list: &LightList, pass: usize) {
+        let n = self.pool.thread_count() as usize;
+        self.pool.scoped(|scope| {
+            for i in 0..n {
+                let l = &light_list;
+                scope.execute(move || scene.integrator.preprocess(scene, l, pass, i, n));
+            }
+        });
+        scene.integrator.finish_preprocess(pass);
+    }
+    /// Render a pass over the blocks in the queue in parallel across the threads, stopping
+    /// early if the `deadline` passes. If `pixel_stats` are passed the mean luminance of each
+    /// pixel rendered is added to its statistics. Returns the number of blocks rendered
+    fn render_pass(&mut self, block_queue: &BlockQueue, scene: &Scene, rt: &RenderTarget,
+                   light_list: &LightList, config: &Config, deadline: Option<f64>,
+                   pixel_stats: Option<&Mutex<Vec<PixelStats>>>) -> usize {
+        let blocks_rendered = AtomicUsize::new(0);
         let n = self.pool.thread_count();
         self.pool.scoped(|scope| {
             for _ in 0..n {
                 let b = &block_queue;
                 let r = &rt;
                 let l = &light_list;
+                let br = &blocks_rendered;
                 scope.execute(move || {
-                    thread_work(config.spp, b, scene, r, l);
+                    let blocks = thread_work(&config.sampler, b, scene, r, l, deadline, pixel_stats);
+                    br.fetch_add(blocks, Ordering::SeqCst);
                 });
             }
         });
+        blocks_rendered.load(Ordering::SeqCst)
     }
 }
 
@@ -67,21 +186,34 @@ impl Exec for MultiThreaded {
     }
 }
 
-fn thread_work(spp: usize, queue: &BlockQueue, scene: &Scene,
-               target: &RenderTarget, light_list: &[&Emitter]) {
-    let mut sampler = sampler::LowDiscrepancy::new(queue.block_dim(), spp);
+/// Work through the blocks in the queue, returns the number of blocks rendered
+fn thread_work(sampler_config: &SamplerConfig, queue: &BlockQueue, scene: &Scene,
+               target: &RenderTarget, light_list: &LightList, deadline: Option<f64>,
+               pixel_stats: Option<&Mutex<Vec<PixelStats>>>) -> usize {
+    let mut sampler = sampler_config.create(queue.block_dim());
     let mut sample_pos = Vec::with_capacity(sampler.max_spp());
     let mut time_samples: Vec<_> = iter::repeat(0.0).take(sampler.max_spp()).collect();
     let block_dim = queue.block_dim();
     let mut block_samples = Vec::with_capacity(sampler.max_spp() * (block_dim.0 * block_dim.1) as usize);
+    let mut block_splats = Vec::new();
+    let aovs = target.aovs();
+    let mut aov_samples: Vec<Vec<ImageSample>> = aovs.iter().map(|_| Vec::new()).collect();
+    // Check if any AOVs need the radiance split into direct and indirect light
+    let split_radiance = aovs.iter().any(|a| a.is_split_radiance());
+    let mut blocks_rendered = 0;
     let mut rng = match StdRng::new() {
         Ok(r) => r,
-        Err(e) => { println!("Failed to get StdRng, {}", e); return }
+        Err(e) => { println!("Failed to get StdRng, {}", e); return 0 }
     };
     let camera = scene.active_camera();
     // Grab a block from the queue and start working on it, submitting samples
     // to the render target thread after each pixel
     for b in queue.iter() {
+        if let Some(d) = deadline {
+            if clock_ticks::precise_time_s() >= d {
+                break;
+            }
+        }
         sampler.select_block(b);
         let mut pixel_samples = 0;
         while sampler.has_samples() {
@@ -89,13 +221,35 @@ fn thread_work(spp: usize, queue: &BlockQueue, scene: &Scene,
             sampler.get_samples(&mut sample_pos, &mut rng);
             sampler.get_samples_1d(&mut time_samples[..], &mut rng);
             for (s, t) in sample_pos.iter().zip(time_samples.iter()) {
+                // The first hit is found here so the integrator and the AOVs can share it
                 let mut ray = camera.generate_ray(s, *t);
-                if let Some(hit) = scene.intersect(&mut ray) {
-                    let c = scene.integrator.illumination(scene, light_list, &ray,
-                                                          &hit, &mut sampler, &mut rng).clamp();
-                    block_samples.push(ImageSample::new(s.0, s.1, c));
+                let hit = scene.intersect(&mut ray);
+                let split = if split_radiance {
+                    scene.integrator.camera_illumination_split(scene, light_list, &ray, hit.as_ref(),
+                                                               &mut *sampler, &mut rng)
                 } else {
-                    block_samples.push(ImageSample::new(s.0, s.1, Colorf::black()));
+                    None
+                };
+                // Samples aren't clamped so values above 1 are kept for HDR output, the image is
+                // only mapped to [0, 1] when it's saved in an 8-bit format
+                let c = match split {
+                    Some(ref l) => l.total(),
+                    None => scene.integrator.camera_illumination(scene, light_list, &ray, hit.as_ref(),
+                                                                 &mut *sampler, &mut rng, &mut block_splats),
+                };
+                block_samples.push(ImageSample::new(s.0, s.1, c));
+                if !aovs.is_empty() {
+                    for (a, samples) in aovs.iter().zip(aov_samples.iter_mut()) {
+                        let v = match *a {
+                            Aov::Direct => split.as_ref().map_or(Colorf::black(), |l| l.direct),
+                            Aov::Indirect => split.as_ref().map_or(Colorf::black(), |l| l.indirect),
+                            Aov::LightGroup(ref g) => {
+                                split.as_ref().map_or(Colorf::black(), |l| l.light_group(g.index))
+                            },
+                            _ => surface_aov(a, scene, &ray, hit.as_ref(), &mut rng),
+                        };
+                        samples.push(ImageSample::new(s.0, s.1, v));
+                    }
                 }
             }
             // If the samples are ok the samples for the next pixel start at the end of the current
@@ -105,7 +259,122 @@ fn thread_work(spp: usize, queue: &BlockQueue, scene: &Scene,
             }
         }
         target.write(&block_samples, sampler.get_region());
+        for (i, samples) in aov_samples.iter_mut().enumerate() {
+            target.write_aov(i, samples, sampler.get_region());
+            samples.clear();
+        }
+        // Each camera sample traces a light path so the splats are averaged over the samples
+        // taken for each pixel. The adaptive sampler takes a different number of samples for
+        // each pixel so we use the average number taken in the block
+        let block_pixels = (block_dim.0 * block_dim.1) as f32;
+        target.write_splats(&block_splats, block_pixels / block_samples.len() as f32);
+        if let Some(stats) = pixel_stats {
+            add_pixel_stats(stats, &block_samples, sampler.get_region(), target.dimensions());
+        }
         block_samples.clear();
+        block_splats.clear();
+        blocks_rendered += 1;
     }
+    blocks_rendered
+}
+
+/// Check if the noise of all the pixels in the block at block indices `block` is below the `threshold`
+fn block_converged(stats: &[PixelStats], block: &(u32, u32), block_dim: (u32, u32), image_dim: (usize, usize),
+                   threshold: f32) -> bool {
+    let start = ((block.0 * block_dim.0) as usize, (block.1 * block_dim.1) as usize);
+    let end = (cmp::min(start.0 + block_dim.0 as usize, image_dim.0),
+               cmp::min(start.1 + block_dim.1 as usize, image_dim.1));
+    (start.1..end.1).all(|y| (start.0..end.0).all(|x| stats[y * image_dim.0 + x].converged(threshold)))
 }
 
+/// Compute the value of the `aov` for the first surface hit by the camera ray `ray`, if any
+fn surface_aov(aov: &Aov, scene: &Scene, ray: &Ray, hit: Option<&Intersection>, rng: &mut StdRng) -> Colorf {
+    let hit = match hit {
+        Some(h) => h,
+        None => return Colorf::black(),
+    };
+    match *aov {
+        Aov::Depth => Colorf::broadcast((hit.dg.p - ray.o).length()),
+        Aov::Position => Colorf::new(hit.dg.p.x, hit.dg.p.y, hit.dg.p.z),
+        Aov::Id => Colorf::broadcast(scene.tag_id(hit.instance.tag()) as f32),
+        Aov::Normal => {
+            let bsdf = hit.material.bsdf(hit);
+            Colorf::new(bsdf.n.x, bsdf.n.y, bsdf.n.z)
+        },
+        Aov::Albedo => {
+            // Estimate the reflectance by sampling a direction to reflect light from, the estimates
+            // are averaged over the samples taken for the pixel
+            let bsdf = hit.material.bsdf(hit);
+            let sample = Sample::new(&(rng.next_f32(), rng.next_f32()), rng.next_f32());
+            let (f, w_i, pdf, _) = bsdf.sample(&-ray.d, BxDFType::all(), &sample);
+            if pdf > 0.0 {
+                f * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf
+            } else {
+                Colorf::black()
+            }
+        },
+        Aov::Direct | Aov::Indirect | Aov::LightGroup(_) | Aov::Noisy => Colorf::black(),
+    }
+}
+
+/// Add the mean luminance of the samples taken for each pixel in the block `region` to
+/// the statistics of the pixels in the image
+fn add_pixel_stats(stats: &Mutex<Vec<PixelStats>>, samples: &[ImageSample], region: &Region,
+                   image_dim: (usize, usize)) {
+    let dim = (region.dim.0 as usize, region.dim.1 as usize);
+    let mut block_sums: Vec<_> = iter::repeat((0.0, 0)).take(dim.0 * dim.1).collect();
+    for s in samples {
+        // Samples at the far edge of a pixel can be rounded onto the next one
+        let x = cmp::min(s.x as usize - region.start.0 as usize, dim.0 - 1);
+        let y = cmp::min(s.y as usize - region.start.1 as usize, dim.1 - 1);
+        let p: &mut (f32, usize) = &mut block_sums[y * dim.0 + x];
+        p.0 += s.color.luminance();
+        p.1 += 1;
+    }
+    let mut stats = stats.lock().unwrap();
+    for y in 0..dim.1 {
+        for x in 0..dim.0 {
+            let img_x = region.start.0 as usize + x;
+            let img_y = region.start.1 as usize + y;
+            let p = &block_sums[y * dim.0 + x];
+            if img_x < image_dim.0 && img_y < image_dim.1 && p.1 > 0 {
+                stats[img_y * image_dim.0 + img_x].add(p.0 / p.1 as f32);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_noisy_blocks_requeued() {
+    // Two 4x4 blocks in an 8x4 image, the left block has the same luminance each pass while
+    // one pixel in the right block alternates, so only the right block needs another pass
+    let image_dim = (8, 4);
+    let block_dim = (4, 4);
+    let stats = Mutex::new(iter::repeat(PixelStats::new()).take(image_dim.0 * image_dim.1).collect());
+    for pass in 0..4 {
+        for bx in 0..2 {
+            let mut region = Region::new((0, 0), block_dim);
+            region.select_region((bx, 0));
+            let mut samples = Vec::new();
+            for y in 0..4 {
+                for x in 0..4 {
+                    let c = if bx == 1 && x == 2 && y == 1 && pass % 2 == 0 { 1.0 } else { 0.5 };
+                    let p = ((bx * 4 + x) as f32 + 0.5, y as f32 + 0.5);
+                    samples.push(ImageSample::new(p.0, p.1, Colorf::broadcast(c)));
+                    samples.push(ImageSample::new(p.0 + 0.25, p.1 + 0.25, Colorf::broadcast(c)));
+                }
+            }
+            add_pixel_stats(&stats, &samples, &region, image_dim);
+        }
+    }
+    let stats = stats.lock().unwrap();
+    assert!(stats.iter().all(|p| p.passes == 4));
+    assert!(block_converged(&stats, &(0, 0), block_dim, image_dim, 0.01));
+    assert!(!block_converged(&stats, &(1, 0), block_dim, image_dim, 0.01));
+
+    let mut queue = BlockQueue::new((8, 4), block_dim, (0, 0));
+    assert_eq!(queue.iter().count(), 2);
+    queue.retain(|b| !block_converged(&stats, b, block_dim, image_dim, 0.01));
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.iter().collect::<Vec<_>>(), vec![(1, 0)]);
+}
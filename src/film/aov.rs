@@ -0,0 +1,200 @@
+//! Defines the arbitrary output variables (AOVs) which can be rendered alongside the
+//! image, eg. for compositing or denoising. Each AOV is computed for every camera sample
+//! and written to its own layer of the render target. AOVs holding colors and normals are
+//! filtered with the same reconstruction filter as the image, while depths and positions
+//! are averaged over the samples in each pixel and IDs take the sample nearest the pixel's
+//! center so they aren't blended with their neighbors.
+//!
+//! When the image is saved as an OpenEXR file the AOVs are written as additional layers
+//! in the same file, named `<aov>.<channel>`, eg. `normal.X`. Otherwise each AOV is written
+//! to its own OpenEXR file next to the image, named `<image>_<aov>.exr`.
+//!
+//! # Scene Usage Example
+//! The AOVs to render are listed in the film object of the scene.
+//!
+//! ```json
+//! "film": {
+//!     ...
+//!     "aovs": ["depth", "normal", "albedo", "position", "id", "direct", "indirect"]
+//! }
+//! ```
+//!
+//! The AOVs which can be rendered are:
+//!
+//! - `depth`: distance from the camera to the first surface hit along the camera ray, 0 where
+//!   nothing is hit. This is the Euclidean distance from the camera, not its camera space z.
+//! - `normal`: world space shading normal of the first surface hit.
+//! - `albedo`: reflectance of the first surface hit, estimated by sampling its BSDF.
+//! - `position`: world space position of the first surface hit.
+//! - `id`: ID of the tag of the object first hit, 0 where nothing is hit. The IDs of the
+//!   tags are printed when the scene is loaded.
+//! - `direct`: light reaching the camera after scattering at most once, including light
+//!   emitted by the surfaces seen by the camera.
+//! - `indirect`: light reaching the camera after scattering more than once.
+//!
+//! The image is the sum of the direct and indirect light. Only the path tracer and
+//! volumetric path tracer split the light into direct and indirect, with other integrators
+//! these AOVs are black and a warning is printed when the scene is loaded.
+//!
+//! When the image is denoised the noisy image is saved with it as well, in the `noisy` AOV.
+//! This AOV isn't rendered and can't be listed in the film's AOVs.
+//!
+//! # Light Groups
+//! The light emitted by groups of lights can also be rendered, so the lights can be
+//! rebalanced when compositing without re-rendering. Each light group lists the names of
+//! the lights in it and is rendered as an AOV named `light_<group>`, which holds the light
+//! from the group's lights reaching the camera, both directly and after bouncing around the
+//! scene. The light groups are listed in the film object of the scene.
+//!
+//! ```json
+//! "film": {
+//!     ...
+//!     "light_groups": [
+//!         {
+//!             "name": "key",
+//!             "lights": ["sun"]
+//!         },
+//!         {
+//!             "name": "fill",
+//!             "lights": ["lamp_left", "lamp_right"]
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! The image is the sum of the light groups if every light is in a group, lights which aren't
+//! in any group only contribute to the image. Each light listed must be a light in the scene
+//! and can only be in one group. Like the direct and indirect light, the light groups are only
+//! rendered by the path tracer and volumetric path tracer.
+
+/// A group of lights whose light is rendered to its own AOV
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightGroup {
+    /// Index of the group in the light groups of the scene, the lights in the group
+    /// are marked with this index
+    pub index: usize,
+    /// Name of the group, the group's AOV is named `light_<name>`
+    pub name: String,
+    /// Names of the lights in the group
+    pub lights: Vec<String>,
+}
+
+impl LightGroup {
+    /// Create the light group at `index` in the scene's light groups with the `lights` passed
+    pub fn new(index: usize, name: String, lights: Vec<String>) -> LightGroup {
+        LightGroup { index: index, name: name, lights: lights }
+    }
+}
+
+/// How the samples of an AOV are reconstructed into its pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AovFilter {
+    /// Samples are filtered with the image's reconstruction filter
+    Image,
+    /// Samples are averaged over the pixel they're in
+    Box,
+    /// Each pixel takes the value of the sample nearest its center
+    Nearest,
+}
+
+/// The arbitrary output variables which can be rendered alongside the image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    Position,
+    Id,
+    Direct,
+    Indirect,
+    LightGroup(LightGroup),
+    /// The image before it was denoised, only added to the AOVs when saving a denoised image
+    Noisy,
+}
+
+impl Aov {
+    /// Find the AOV with the name used in the scene file
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "depth" => Some(Aov::Depth),
+            "normal" => Some(Aov::Normal),
+            "albedo" => Some(Aov::Albedo),
+            "position" => Some(Aov::Position),
+            "id" => Some(Aov::Id),
+            "direct" => Some(Aov::Direct),
+            "indirect" => Some(Aov::Indirect),
+            _ => None,
+        }
+    }
+    /// Get the name of the AOV used in the scene file and output images, light groups are
+    /// named `light_<group>`
+    pub fn name(&self) -> String {
+        match *self {
+            Aov::Depth => "depth".to_owned(),
+            Aov::Normal => "normal".to_owned(),
+            Aov::Albedo => "albedo".to_owned(),
+            Aov::Position => "position".to_owned(),
+            Aov::Id => "id".to_owned(),
+            Aov::Direct => "direct".to_owned(),
+            Aov::Indirect => "indirect".to_owned(),
+            Aov::LightGroup(ref g) => format!("light_{}", g.name),
+            Aov::Noisy => "noisy".to_owned(),
+        }
+    }
+    /// Get the names of the channels of the AOV, the values of single channel AOVs
+    /// are stored in the red channel of their samples and pixels
+    pub fn channels(&self) -> &'static [&'static str] {
+        match *self {
+            Aov::Depth => &["Z"],
+            Aov::Id => &["ID"],
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Albedo | Aov::Direct | Aov::Indirect | Aov::LightGroup(_) | Aov::Noisy => &["R", "G", "B"],
+        }
+    }
+    /// Get how the samples of the AOV are reconstructed into its pixels, data which can't be
+    /// blended across the edges of objects isn't filtered with the image's filter
+    pub fn filter(&self) -> AovFilter {
+        match *self {
+            Aov::Depth | Aov::Position => AovFilter::Box,
+            Aov::Id => AovFilter::Nearest,
+            _ => AovFilter::Image,
+        }
+    }
+    /// Check if the AOV stores colors, other AOVs store data like positions or IDs which
+    /// need full float precision when saved
+    pub fn is_color(&self) -> bool {
+        match *self {
+            Aov::Albedo | Aov::Direct | Aov::Indirect | Aov::LightGroup(_) | Aov::Noisy => true,
+            _ => false,
+        }
+    }
+    /// Check if the AOV is computed from the light split into direct and indirect light
+    /// or into light groups by the integrator
+    pub fn is_split_radiance(&self) -> bool {
+        match *self {
+            Aov::Direct | Aov::Indirect | Aov::LightGroup(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn test_names_and_channels() {
+    for name in &["depth", "normal", "albedo", "position", "id", "direct", "indirect"] {
+        let aov = Aov::from_name(name).unwrap();
+        assert_eq!(aov.name(), *name);
+    }
+    assert_eq!(Aov::from_name("light_key"), None);
+    assert_eq!(Aov::from_name("beauty"), None);
+    assert_eq!(Aov::from_name("noisy"), None);
+    assert_eq!(Aov::Noisy.name(), "noisy");
+    let group = Aov::LightGroup(LightGroup::new(0, "key".to_owned(), vec!["sun".to_owned()]));
+    assert_eq!(group.name(), "light_key");
+    assert_eq!(Aov::Depth.channels(), &["Z"]);
+    assert_eq!(Aov::Id.channels(), &["ID"]);
+    assert_eq!(Aov::Position.channels(), &["X", "Y", "Z"]);
+    assert_eq!(group.channels(), &["R", "G", "B"]);
+    assert_eq!(Aov::Id.filter(), AovFilter::Nearest);
+    assert_eq!(Aov::Depth.filter(), AovFilter::Box);
+    assert_eq!(Aov::Normal.filter(), AovFilter::Image);
+}
@@ -36,6 +36,12 @@ pub struct Camera {
     cam_world: AnimatedTransform,
     /// Transformation from raster space to screen space
     raster_screen: Transform,
+    /// Transformation from screen space to raster space
+    screen_raster: Transform,
+    /// Area of the screen window, before scaling by the fov
+    screen_area: f32,
+    /// Dimensions of the image in pixels
+    dims: (usize, usize),
     /// The projective division matrix, the perspective matrix is changing in the
     /// case of animated FOV so we deconstruct it some to reduce creating a new
     /// transform each time
@@ -49,7 +55,7 @@ pub struct Camera {
     shutter_size: f32,
     /// Animation points for the field of view
     fov: CameraFov,
-    /// Scaling for the fov part of the projection matrix for the frame
+    /// Scaling for the fov part of the projection matrix if the fov isn't animated
     scaling: Vector,
     /// The frame this camera becomes active on
     pub active_at: usize,
@@ -74,6 +80,7 @@ impl Camera {
             * Transform::scale(&Vector::new(1.0 / (screen[1] - screen[0]), 1.0 / (screen[2] - screen[3]), 1.0))
             * Transform::translate(&Vector::new(-screen[0], -screen[3], 0.0));
         let raster_screen = screen_raster.inverse();
+        let screen_area = (screen[1] - screen[0]) * (screen[3] - screen[2]);
         let far = 1.0;
         let near = 1000.0;
         let proj_div = Matrix4::new(
@@ -83,7 +90,8 @@ impl Camera {
              0.0, 0.0, 1.0, 0.0]);
         let tan_fov = f32::tan(linalg::to_radians(fov) / 2.0);
         let scaling = Vector::new(tan_fov, tan_fov, 1.0);
-        Camera { cam_world: cam_world, raster_screen: raster_screen,
+        Camera { cam_world: cam_world, raster_screen: raster_screen, screen_raster: screen_raster,
+                 screen_area: screen_area, dims: dims,
                  proj_div_inv: Transform::from_mat(&proj_div).inverse(),
                  shutter_open: 0.0, shutter_close: 0.0, shutter_size: shutter_size,
                  fov: CameraFov::Unanimated(fov), scaling: scaling, active_at: active_at
@@ -107,6 +115,7 @@ impl Camera {
             * Transform::scale(&Vector::new(1.0 / (screen[1] - screen[0]), 1.0 / (screen[2] - screen[3]), 1.0))
             * Transform::translate(&Vector::new(-screen[0], -screen[3], 0.0));
         let raster_screen = screen_raster.inverse();
+        let screen_area = (screen[1] - screen[0]) * (screen[3] - screen[2]);
         let far = 1.0;
         let near = 1000.0;
         let proj_div = Matrix4::new(
@@ -116,7 +125,8 @@ impl Camera {
              0.0, 0.0, 1.0, 0.0]);
         let tan_fov = f32::tan(linalg::to_radians(fovs[0]) / 2.0);
         let scaling = Vector::new(tan_fov, tan_fov, 1.0);
-        Camera { cam_world: cam_world, raster_screen: raster_screen,
+        Camera { cam_world: cam_world, raster_screen: raster_screen, screen_raster: screen_raster,
+                 screen_area: screen_area, dims: dims,
                  proj_div_inv: Transform::from_mat(&proj_div).inverse(),
                  shutter_open: 0.0, shutter_close: 0.0, shutter_size: shutter_size,
                  fov: CameraFov::Animated(BSpline::new(fov_spline_degree, fovs, fov_knots)),
@@ -127,33 +137,125 @@ impl Camera {
     pub fn update_frame(&mut self, start: f32, end: f32) {
         self.shutter_open = start;
         self.shutter_close = start + self.shutter_size * (end - start);
-        // TODO: Is this the right spot to update the projection transform? It seems like
-        // you'd want to do it for each ray but this produces some very odd results, maybe
-        // resulting from different rays have different projection transformations?
-        let fov = match self.fov {
-            CameraFov::Unanimated(f) => f,
-            CameraFov::Animated(ref spline) => {
-                let domain = spline.knot_domain();
-                let t = linalg::clamp((start + end) / 2.0, domain.0, domain.1);
-                spline.point(t)
-            },
-        };
-        let tan_fov = f32::tan(linalg::to_radians(fov) / 2.0);
-        self.scaling = Vector::new(tan_fov, tan_fov, 1.0);
         println!("Shutter open from {} to {}", self.shutter_open, self.shutter_close);
     }
     /// Get the time that the shutter opens and closes at
     pub fn shutter_time(&self) -> (f32, f32) {
         (self.shutter_open, self.shutter_close)
     }
+    /// Get the scaling for the fov part of the projection matrix at `time` in the scene
+    fn scaling(&self, time: f32) -> Vector {
+        match self.fov {
+            CameraFov::Unanimated(_) => self.scaling,
+            CameraFov::Animated(ref spline) => {
+                let domain = spline.knot_domain();
+                let fov = spline.point(linalg::clamp(time, domain.0, domain.1));
+                let tan_fov = f32::tan(linalg::to_radians(fov) / 2.0);
+                Vector::new(tan_fov, tan_fov, 1.0)
+            },
+        }
+    }
     /// Generate a ray from the camera through the pixel `px`
     pub fn generate_ray(&self, px: &(f32, f32), time: f32) -> Ray {
-        // Take the raster space position -> camera space
-        let px_pos = self.scaling * (self.proj_div_inv * self.raster_screen * Point::new(px.0, px.1, 0.0));
-        let d = Vector::new(px_pos.x, px_pos.y, px_pos.z).normalized();
         // Compute the time being sampled for this frame based on shutter open/close times
         let frame_time = (self.shutter_close - self.shutter_open) * time + self.shutter_open;
+        // Take the raster space position -> camera space
+        let px_pos = self.scaling(frame_time) * (self.proj_div_inv * self.raster_screen * Point::new(px.0, px.1, 0.0));
+        let d = Vector::new(px_pos.x, px_pos.y, px_pos.z).normalized();
         self.cam_world.transform(frame_time) * Ray::new(&Point::broadcast(0.0), &d, frame_time)
     }
+    /// Compute the importance emitted by the camera along the ray `ray` leaving it, where the
+    /// ray's time is the time in the scene. Returns the importance and the raster position the
+    /// ray passes through, or None if the ray doesn't pass through the image.
+    /// See [Veach, Robust Monte Carlo Methods for Light Transport Simulation](http://graphics.stanford.edu/papers/veach_thesis/)
+    /// Chapter 10, the importance is normalized so the camera's measurement for each pixel
+    /// is its radiance.
+    pub fn importance(&self, ray: &Ray) -> Option<(f32, (f32, f32))> {
+        let d = self.cam_world.transform(ray.time).inv_mul_vector(&ray.d).normalized();
+        let scaling = self.scaling(ray.time);
+        let raster = match self.raster_position(&d, &scaling) {
+            Some(r) => r,
+            None => return None,
+        };
+        let cos_theta = d.z;
+        let area = self.screen_area * scaling.x * scaling.y;
+        Some((1.0 / (area * cos_theta * cos_theta * cos_theta * cos_theta), raster))
+    }
+    /// Compute the pdf with respect to solid angle of the camera generating the ray `ray`
+    pub fn pdf_direction(&self, ray: &Ray) -> f32 {
+        let d = self.cam_world.transform(ray.time).inv_mul_vector(&ray.d).normalized();
+        let scaling = self.scaling(ray.time);
+        if self.raster_position(&d, &scaling).is_none() {
+            return 0.0;
+        }
+        let cos_theta = d.z;
+        let area = self.screen_area * scaling.x * scaling.y;
+        1.0 / (area * cos_theta * cos_theta * cos_theta)
+    }
+    /// Sample the camera for the importance arriving at the point `p` at `time`. Returns the
+    /// importance, the direction from `p` to the camera, the pdf with respect to solid angle at `p`,
+    /// the raster position that sees `p` and the position of the camera. Returns None if `p`
+    /// isn't seen by the camera.
+    pub fn sample_incident(&self, p: &Point, time: f32) -> Option<(f32, Vector, f32, (f32, f32), Point)> {
+        let pos = self.cam_world.transform(time) * Point::broadcast(0.0);
+        let w_i = pos - *p;
+        let dist_sqr = w_i.length_sqr();
+        let w_i = w_i.normalized();
+        let ray = Ray::new(&pos, &-w_i, time);
+        match self.importance(&ray) {
+            Some((we, raster)) => {
+                let cos_theta = self.cam_world.transform(time).inv_mul_vector(&-w_i).normalized().z;
+                // The camera is a pinhole so its position is always sampled
+                Some((we, w_i, dist_sqr / cos_theta, raster, pos))
+            },
+            None => None,
+        }
+    }
+    /// Find the raster position seen by the camera space direction `d` with the fov `scaling`,
+    /// returns None if the direction is outside the image
+    fn raster_position(&self, d: &Vector, scaling: &Vector) -> Option<(f32, f32)> {
+        if d.z <= 0.0 {
+            return None;
+        }
+        let screen = Point::new(d.x / (d.z * scaling.x), d.y / (d.z * scaling.y), 0.0);
+        let raster = self.screen_raster * screen;
+        if raster.x < 0.0 || raster.x >= self.dims.0 as f32 || raster.y < 0.0 || raster.y >= self.dims.1 as f32 {
+            None
+        } else {
+            Some((raster.x, raster.y))
+        }
+    }
+}
+
+#[test]
+fn test_raster_position() {
+    let transform = AnimatedTransform::unanimated(&(Transform::translate(&Vector::new(1.0, 2.0, -5.0))
+                                                    * Transform::rotate_y(30.0)));
+    let camera = Camera::new(transform, 50.0, (320, 240), 0.5, 0);
+    for px in &[(0.5, 0.5), (160.0, 120.0), (300.25, 17.75), (12.0, 230.5)] {
+        let ray = camera.generate_ray(px, 0.0);
+        let (_, raster) = camera.importance(&ray).unwrap();
+        assert!(f32::abs(raster.0 - px.0) < 1e-2 && f32::abs(raster.1 - px.1) < 1e-2);
+        let (_, w_i, _, raster, _) = camera.sample_incident(&ray.at(4.0), 0.0).unwrap();
+        assert!(f32::abs(raster.0 - px.0) < 1e-2 && f32::abs(raster.1 - px.1) < 1e-2);
+        assert!(linalg::dot(&w_i, &ray.d) < -0.999);
+    }
 }
 
+
+#[test]
+fn test_animated_fov_raster_position() {
+    let transform = AnimatedTransform::unanimated(&Transform::translate(&Vector::new(1.0, 2.0, -5.0)));
+    let mut camera = Camera::animated_fov(transform, vec![30.0, 60.0], vec![0.0, 0.0, 1.0, 1.0], 1,
+                                          (320, 240), 1.0, 0);
+    camera.update_frame(0.0, 1.0);
+    let px = (300.25, 17.75);
+    // The fov changes during the frame so the camera sees the point through a different pixel
+    // at the start and end, the importance should use the fov at the time of the ray
+    assert!(linalg::dot(&camera.generate_ray(&px, 0.0).d, &camera.generate_ray(&px, 1.0).d) < 0.99);
+    for t in &[0.0, 0.25, 0.75, 1.0] {
+        let ray = camera.generate_ray(&px, *t);
+        let (_, raster) = camera.importance(&ray).unwrap();
+        assert!(f32::abs(raster.0 - px.0) < 1e-2 && f32::abs(raster.1 - px.1) < 1e-2);
+    }
+}
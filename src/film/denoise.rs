@@ -0,0 +1,266 @@
+//! Provides a denoiser which can be run on the rendered image before it's saved, so renders
+//! taken with few samples per pixel (eg. previews) can be used without most of their noise.
+//! The denoiser is a non-local means filter guided by the albedo and normal AOVs: each pixel
+//! is replaced by a weighted average of the pixels around it, where pixels are weighted by how
+//! similar the patches of color around them are and how similar their albedo and normals are.
+//! The color is divided by the albedo before filtering so textures aren't blurred, and the
+//! albedo is multiplied back in after. The image is split into bands of rows which are
+//! denoised in parallel.
+//!
+//! # Scene Usage Example
+//! The denoiser is enabled by adding a denoise object to the film. The albedo and normal AOVs
+//! are used to guide the denoiser, if they aren't listed in the film's AOVs they're added and
+//! saved with the image as well. The noisy image is also saved with the denoised image, as the
+//! `noisy` AOV. All the parameters are optional.
+//!
+//! ```json
+//! "film": {
+//!     ...
+//!     "denoise": {
+//!         "radius": 7,
+//!         "color": 0.5,
+//!         "albedo": 0.1,
+//!         "normal": 0.2
+//!     }
+//! }
+//! ```
+//!
+//! - `radius`: radius in pixels of the window of pixels averaged, defaults to 7.
+//! - `color`: how different the colors around two pixels can be and still be averaged,
+//!   higher values remove more noise but blur more detail. Defaults to 0.5.
+//! - `albedo`: how different the albedo of two pixels can be and still be averaged,
+//!   defaults to 0.1.
+//! - `normal`: how different the normals of two pixels can be and still be averaged,
+//!   defaults to 0.2.
+//!
+//! The `color`, `albedo` and `normal` scales must be greater than 0.
+
+use std::{cmp, iter, f32};
+
+use scoped_threadpool::Pool;
+
+use film::Aov;
+
+/// Radius of the patches of color compared to weight pixels
+const PATCH_RADIUS: i32 = 1;
+/// Albedo below which the color isn't divided by the albedo, eg. where nothing was hit
+const MIN_ALBEDO: f32 = 0.01;
+
+/// A non-local means denoiser guided by the albedo and normals of the image
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Denoiser {
+    /// Radius of the window of pixels averaged
+    radius: i32,
+    /// Scale of the color differences, higher values blur more
+    color: f32,
+    /// Standard deviation of the albedo differences
+    albedo: f32,
+    /// Standard deviation of the normal differences
+    normal: f32,
+}
+
+impl Denoiser {
+    /// Create a denoiser averaging pixels within `radius` pixels of each other, with the
+    /// `color`, `albedo` and `normal` scales controlling how different pixels can be
+    /// before they're no longer averaged
+    pub fn new(radius: u32, color: f32, albedo: f32, normal: f32) -> Denoiser {
+        Denoiser { radius: radius as i32, color: color, albedo: albedo, normal: normal }
+    }
+    /// Denoise the `dim.0` by `dim.1` linear RGB_F32 image `pixels` in parallel on the threads of
+    /// the `pool`, guided by the albedo and normal AOVs rendered with it. The noisy image is added
+    /// to the `aovs` as the `noisy` AOV so it's saved along with the denoised image
+    pub fn denoise(&self, dim: (usize, usize), pixels: Vec<f32>, aovs: &mut Vec<(Aov, Vec<f32>)>,
+                   pool: &mut Pool) -> Vec<f32> {
+        let denoised = {
+            let find_aov = |aov| aovs.iter().find(|a| a.0 == aov).map(|a| &a.1[..]);
+            let albedo = find_aov(Aov::Albedo).expect("The albedo AOV is required for denoising");
+            let normal = find_aov(Aov::Normal).expect("The normal AOV is required for denoising");
+            self.filter(dim, &pixels, albedo, normal, pool)
+        };
+        aovs.push((Aov::Noisy, pixels));
+        denoised
+    }
+    /// Denoise the `dim.0` by `dim.1` linear RGB_F32 image `pixels` with the RGB_F32 `albedo`
+    /// and XYZ_F32 `normal` feature images. The rows of the image are split into bands which
+    /// are filtered in parallel on the threads of the `pool`
+    pub fn filter(&self, dim: (usize, usize), pixels: &[f32], albedo: &[f32], normal: &[f32],
+                  pool: &mut Pool) -> Vec<f32> {
+        let num_px = dim.0 * dim.1;
+        assert!(pixels.len() == num_px * 3 && albedo.len() == num_px * 3 && normal.len() == num_px * 3,
+                "The image and feature images must have 3 channels per pixel");
+        // Divide out the albedo so the filter averages the lighting and not the textures
+        let modulation: Vec<f32> = albedo.iter().map(|&a| if a > MIN_ALBEDO { a } else { 1.0 }).collect();
+        let color: Vec<f32> = pixels.iter().zip(modulation.iter()).map(|(c, m)| c / m).collect();
+        let features = Features { color: &color, albedo: albedo, normal: normal, modulation: &modulation };
+
+        let mut denoised: Vec<f32> = iter::repeat(0.0).take(num_px * 3).collect();
+        let n = pool.thread_count() as usize;
+        let band_rows = cmp::max(1, (dim.1 + n - 1) / n);
+        pool.scoped(|scope| {
+            for (i, band) in denoised.chunks_mut(band_rows * dim.0 * 3).enumerate() {
+                let f = &features;
+                scope.execute(move || self.filter_rows(dim, i * band_rows, f, band));
+            }
+        });
+        denoised
+    }
+    /// Filter the rows of the image starting at row `start`, writing the denoised pixels
+    /// of the rows to `out`
+    fn filter_rows(&self, dim: (usize, usize), start: usize, features: &Features, out: &mut [f32]) {
+        let color = features.color;
+        let (width, height) = (dim.0 as i32, dim.1 as i32);
+        let rows = (out.len() / (dim.0 * 3)) as i32;
+        let start = start as i32;
+        // The patches around the pixels in the rows cover the rows next to them as well
+        let diff_start = cmp::max(0, start - PATCH_RADIUS);
+        let diff_end = cmp::min(height, start + rows + PATCH_RADIUS);
+        let band_px = (rows * width) as usize;
+        let mut sum: Vec<f32> = iter::repeat(0.0).take(band_px * 3).collect();
+        let mut weights: Vec<f32> = iter::repeat(0.0).take(band_px).collect();
+        let mut diff: Vec<f32> = iter::repeat(0.0).take(((diff_end - diff_start) * width) as usize).collect();
+        let spatial_var = 2.0 * f32::powf(self.radius as f32 / 2.0, 2.0);
+        let color_var = self.color * self.color;
+        let albedo_var = 2.0 * self.albedo * self.albedo;
+        let normal_var = 2.0 * self.normal * self.normal;
+        let patch_norm = 1.0 / ((2 * PATCH_RADIUS + 1) * (2 * PATCH_RADIUS + 1)) as f32;
+        // Accumulate the contribution of the neighbor at each offset to all pixels at once,
+        // so the difference of each pair of pixels is only found once for all the patches covering it
+        for oy in -self.radius..self.radius + 1 {
+            for ox in -self.radius..self.radius + 1 {
+                for y in diff_start..diff_end {
+                    for x in 0..width {
+                        let p = (y * width + x) as usize;
+                        let q = neighbor(x + ox, y + oy, dim);
+                        // The difference is relative to the darker of the colors so bright pixels,
+                        // eg. lights next to dark surfaces, aren't blurred into their neighbors
+                        let mut d = 0.0;
+                        for c in 0..3 {
+                            let (cp, cq) = (color[3 * p + c], color[3 * q + c]);
+                            let m = f32::min(f32::abs(cp), f32::abs(cq));
+                            d += (cp - cq) * (cp - cq) / (0.01 + m * m);
+                        }
+                        diff[((y - diff_start) * width + x) as usize] = d / 3.0;
+                    }
+                }
+                let spatial = ((ox * ox + oy * oy) as f32) / spatial_var;
+                for y in cmp::max(start, -oy)..cmp::min(start + rows, height - oy) {
+                    for x in cmp::max(0, -ox)..cmp::min(width, width - ox) {
+                        let p = (y * width + x) as usize;
+                        let q = ((y + oy) * width + x + ox) as usize;
+                        // Average the differences over the patch, clamping it to the image
+                        let mut patch_diff = 0.0;
+                        for py in y - PATCH_RADIUS..y + PATCH_RADIUS + 1 {
+                            let py = cmp::max(0, cmp::min(py, height - 1)) - diff_start;
+                            for px in x - PATCH_RADIUS..x + PATCH_RADIUS + 1 {
+                                let px = cmp::max(0, cmp::min(px, width - 1));
+                                patch_diff += diff[(py * width + px) as usize];
+                            }
+                        }
+                        patch_diff *= patch_norm;
+                        let mut albedo_diff = 0.0;
+                        let mut normal_diff = 0.0;
+                        for c in 0..3 {
+                            let da = features.albedo[3 * p + c] - features.albedo[3 * q + c];
+                            let dn = features.normal[3 * p + c] - features.normal[3 * q + c];
+                            albedo_diff += da * da;
+                            normal_diff += dn * dn;
+                        }
+                        let w = f32::exp(-spatial - patch_diff / color_var - albedo_diff / albedo_var
+                                         - normal_diff / normal_var);
+                        let b = ((y - start) * width + x) as usize;
+                        for c in 0..3 {
+                            sum[3 * b + c] += w * color[3 * q + c];
+                        }
+                        weights[b] += w;
+                    }
+                }
+            }
+        }
+        // Each pixel has a weight of 1 with itself so the weights are never 0
+        let modulation = &features.modulation[(start * width * 3) as usize..];
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = sum[i] / weights[i / 3] * modulation[i];
+        }
+    }
+}
+
+/// The images guiding the denoiser, shared by the threads filtering each band of rows
+struct Features<'a> {
+    /// The image with the albedo divided out
+    color: &'a [f32],
+    albedo: &'a [f32],
+    normal: &'a [f32],
+    /// The albedo divided out of the color, multiplied back in to the denoised image
+    modulation: &'a [f32],
+}
+
+/// Get the index of the pixel at `x`, `y`, clamping the position to the image
+fn neighbor(x: i32, y: i32, dim: (usize, usize)) -> usize {
+    let x = cmp::max(0, cmp::min(x, dim.0 as i32 - 1)) as usize;
+    let y = cmp::max(0, cmp::min(y, dim.1 as i32 - 1)) as usize;
+    y * dim.0 + x
+}
+
+#[test]
+fn test_constant_image() {
+    let dim = (8, 6);
+    let pixels: Vec<f32> = iter::repeat(0.25).take(dim.0 * dim.1 * 3).collect();
+    let albedo: Vec<f32> = iter::repeat(0.5).take(dim.0 * dim.1 * 3).collect();
+    let normal: Vec<f32> = iter::repeat(0.0).take(dim.0 * dim.1 * 3).collect();
+    let denoised = Denoiser::new(3, 0.5, 0.1, 0.2).filter(dim, &pixels, &albedo, &normal, &mut Pool::new(2));
+    for d in denoised {
+        assert!(f32::abs(d - 0.25) < 1e-5);
+    }
+}
+
+#[test]
+fn test_denoise_edges() {
+    // Noise on two surfaces facing different directions, the noise should be removed
+    // without blurring the surfaces together
+    let dim = (16, 16);
+    let mut pixels = Vec::new();
+    let mut normal = Vec::new();
+    for y in 0..dim.1 {
+        for x in 0..dim.0 {
+            let (c, n) = if x < dim.0 / 2 { (0.2, 0.0) } else { (0.8, 1.0) };
+            let noise = if (x * 7 + y * 3) % 4 < 2 { 0.02 } else { -0.02 };
+            for _ in 0..3 {
+                pixels.push(c + noise);
+                normal.push(n);
+            }
+        }
+    }
+    let albedo: Vec<f32> = iter::repeat(1.0).take(dim.0 * dim.1 * 3).collect();
+    let denoised = Denoiser::new(4, 0.5, 0.1, 0.2).filter(dim, &pixels, &albedo, &normal, &mut Pool::new(3));
+    for y in 0..dim.1 {
+        for x in 0..dim.0 {
+            let expected = if x < dim.0 / 2 { 0.2 } else { 0.8 };
+            let d = denoised[3 * (y * dim.0 + x)];
+            assert!(f32::abs(d - expected) < 0.01);
+        }
+    }
+}
+
+#[test]
+fn test_bands_match_single_thread() {
+    // The bands of rows denoised on each thread should be seamless
+    let dim = (9, 13);
+    let mut pixels = Vec::new();
+    let mut albedo = Vec::new();
+    let mut normal = Vec::new();
+    for y in 0..dim.1 {
+        for x in 0..dim.0 {
+            for c in 0..3 {
+                pixels.push(((x * 7 + y * 5 + c * 3) % 11) as f32 / 11.0);
+                albedo.push(if y < dim.1 / 2 { 0.5 } else { 0.8 });
+                normal.push(if x < dim.0 / 3 { 0.0 } else { 1.0 });
+            }
+        }
+    }
+    let denoiser = Denoiser::new(3, 0.5, 0.1, 0.2);
+    let single = denoiser.filter(dim, &pixels, &albedo, &normal, &mut Pool::new(1));
+    let banded = denoiser.filter(dim, &pixels, &albedo, &normal, &mut Pool::new(4));
+    for (s, b) in single.iter().zip(banded.iter()) {
+        assert!(f32::abs(s - b) < 1e-5);
+    }
+}
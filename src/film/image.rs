@@ -4,17 +4,26 @@
 use std::iter;
 
 use film::Colorf;
+use film::Aov;
 
 #[derive(Debug)]
 pub struct Image {
     dim: (usize, usize),
     pixels: Vec<Colorf>,
+    /// Contributions splatted directly to the pixels, added to the filtered pixel colors
+    splats: Vec<Colorf>,
+    /// The AOVs rendered alongside the image and their pixels
+    aovs: Vec<(Aov, Vec<Colorf>)>,
 }
 
 impl Image {
-    pub fn new(dimensions: (usize, usize)) -> Image {
+    pub fn new(dimensions: (usize, usize), aovs: &[Aov]) -> Image {
         let pixels = iter::repeat(Colorf::broadcast(0.0)).take(dimensions.0 * dimensions.1).collect();
-        Image { dim: dimensions, pixels: pixels }
+        let splats = iter::repeat(Colorf::broadcast(0.0)).take(dimensions.0 * dimensions.1).collect();
+        let aovs = aovs.iter().map(|a| {
+            (a.clone(), iter::repeat(Colorf::broadcast(0.0)).take(dimensions.0 * dimensions.1).collect())
+        }).collect();
+        Image { dim: dimensions, pixels: pixels, splats: splats, aovs: aovs }
     }
     /// Add the floating point RGBAf32 pixels to the image. It is assumed that `pixels` contains
     /// a `dim.0` by `dim.1` pixel image.
@@ -34,39 +43,116 @@ impl Image {
     /// the size of the blocks being passed, `blocks` contains the start points of each block and
     /// `pixels` contains `block_size.0 * block_size.1 * 4` floats for each block.
     pub fn add_blocks(&mut self, block_size: (usize, usize), blocks: &[(usize, usize)], pixels: &[f32]) {
-        let block_stride = block_size.0 * block_size.1 * 4;
-        for (i, b) in blocks.iter().enumerate() {
-            let block_px = &pixels[block_stride * i..block_stride * (i + 1)];
-            for by in 0..block_size.1 {
-                for bx in 0..block_size.0 {
-                    let c = &mut self.pixels[(by + b.1) * self.dim.0 + bx + b.0];
-                    let px = by * block_size.0 * 4 + bx * 4;
-                    for i in 0..4 {
-                        c[i] += block_px[px + i];
-                    }
-                }
-            }
+        add_blocks(&mut self.pixels, self.dim, block_size, blocks, pixels);
+    }
+    /// Add the blocks of RGBAf32 pixels for the AOV at index `aov` to the image. The blocks are
+    /// passed in the same format as `add_blocks`, see RenderTarget::get_aov_blocks.
+    pub fn add_aov_blocks(&mut self, aov: usize, block_size: (usize, usize), blocks: &[(usize, usize)],
+                          pixels: &[f32]) {
+        add_blocks(&mut self.aovs[aov].1, self.dim, block_size, blocks, pixels);
+    }
+    /// Add the RGBf32 splatted contributions to the image, as returned by RenderTarget::get_splats.
+    /// It's assumed that `splats` contains a `dim.0` by `dim.1` pixel image.
+    pub fn add_splats(&mut self, splats: &[f32]) {
+        for (c, s) in self.splats.iter_mut().zip(splats.chunks(3)) {
+            c.r += s[0];
+            c.g += s[1];
+            c.b += s[2];
         }
     }
-    /// Convert the Image to sRGB8 format and return it
-    pub fn get_srgb8(&self) -> Vec<u8> {
-        let mut render: Vec<u8> = iter::repeat(0u8).take(self.dim.0 * self.dim.1 * 3).collect();
+    /// Get the final linear RGB_F32 image, combining the filtered pixel colors with the
+    /// splatted contributions
+    pub fn get_linear(&self) -> Vec<f32> {
+        let mut render: Vec<f32> = iter::repeat(0.0).take(self.dim.0 * self.dim.1 * 3).collect();
         for y in 0..self.dim.1 {
             for x in 0..self.dim.0 {
                 let c = &self.pixels[y * self.dim.0 + x];
-                if c.a > 0.0 {
-                    let cn = (*c / c.a).clamp().to_srgb();
+                let splat = &self.splats[y * self.dim.0 + x];
+                if c.a > 0.0 || !splat.is_black() {
+                    let filtered = if c.a > 0.0 { *c / c.a } else { Colorf::black() };
+                    let cn = filtered + *splat;
                     let px = y  * self.dim.0 * 3 + x * 3;
                     for i in 0..3 {
-                        render[px + i] = (cn[i] * 255.0) as u8;
+                        render[px + i] = cn[i];
                     }
                 }
             }
         }
         render
     }
+    /// Get the final images of the AOVs, the pixels of each have as many F32 values as
+    /// the AOV has channels
+    pub fn get_aovs_linear(&self) -> Vec<(Aov, Vec<f32>)> {
+        self.aovs.iter().map(|&(ref aov, ref pixels)| {
+            let channels = aov.channels().len();
+            let mut render = Vec::with_capacity(pixels.len() * channels);
+            for c in pixels {
+                for i in 0..channels {
+                    render.push(if c.a > 0.0 { c[i] / c.a } else { 0.0 });
+                }
+            }
+            (aov.clone(), render)
+        }).collect()
+    }
     pub fn dimensions(&self) -> (usize, usize) {
         self.dim
     }
 }
 
+/// Add the blocks of RGBAf32 `pixels` to the `image_pixels` of a `dim.0` by `dim.1` image,
+/// see `Image::add_blocks`
+fn add_blocks(image_pixels: &mut [Colorf], dim: (usize, usize), block_size: (usize, usize),
+              blocks: &[(usize, usize)], pixels: &[f32]) {
+    let block_stride = block_size.0 * block_size.1 * 4;
+    for (i, b) in blocks.iter().enumerate() {
+        let block_px = &pixels[block_stride * i..block_stride * (i + 1)];
+        for by in 0..block_size.1 {
+            for bx in 0..block_size.0 {
+                let c = &mut image_pixels[(by + b.1) * dim.0 + bx + b.0];
+                let px = by * block_size.0 * 4 + bx * 4;
+                for i in 0..4 {
+                    c[i] += block_px[px + i];
+                }
+            }
+        }
+    }
+}
+
+
+#[test]
+fn test_add_aov_blocks() {
+    use film::{RenderTarget, ImageSample, ToneMap, ToneMapOperator};
+    use film::filter::{Filter, MitchellNetravali};
+    use sampler::Region;
+
+    // Adding the blocks sent by a worker to the image gives the same AOVs the worker rendered
+    let filter = Box::new(MitchellNetravali::new(2.0, 2.0, 1.0 / 3.0, 1.0 / 3.0)) as Box<Filter + Send + Sync>;
+    let tone_map = ToneMap::new(ToneMapOperator::Exposure, 0.0, None);
+    let aovs = vec![Aov::Depth, Aov::Normal];
+    let rt = RenderTarget::new((4, 4), (2, 2), filter, tone_map, aovs.clone(), None);
+    let region = Region::new((0, 0), (4, 4));
+    let mut samples = Vec::new();
+    for y in 0..4 {
+        for x in 0..4 {
+            let v = (y * 4 + x + 1) as f32;
+            samples.push(ImageSample::new(x as f32 + 0.5, y as f32 + 0.5, Colorf::new(v, 2.0 * v, 3.0 * v)));
+        }
+    }
+    rt.write(&samples, &region);
+    for i in 0..aovs.len() {
+        rt.write_aov(i, &samples, &region);
+    }
+    let (block_size, blocks, pixels) = rt.get_rendered_blocks();
+    assert_eq!(blocks.len(), 4);
+    let mut img = Image::new((4, 4), &aovs[..]);
+    img.add_blocks(block_size, &blocks, &pixels);
+    for i in 0..aovs.len() {
+        img.add_aov_blocks(i, block_size, &blocks, &rt.get_aov_blocks(i, &blocks));
+    }
+    let img_aovs = img.get_aovs_linear();
+    for i in 0..aovs.len() {
+        assert_eq!(img_aovs[i].0, aovs[i]);
+        assert_eq!(img_aovs[i].1, rt.get_aov_linear(i));
+    }
+    assert_eq!(img_aovs[0].1[5], 6.0);
+}
@@ -7,6 +7,9 @@ pub use self::camera::Camera;
 pub use self::render_target::ImageSample;
 pub use self::animated_color::{ColorKeyframe, AnimatedColor};
 pub use self::image::Image;
+pub use self::tone_map::{ToneMap, ToneMapOperator};
+pub use self::aov::{Aov, AovFilter, LightGroup};
+pub use self::denoise::Denoiser;
 
 pub mod color;
 pub mod render_target;
@@ -14,6 +17,10 @@ pub mod camera;
 pub mod filter;
 pub mod animated_color;
 pub mod image;
+pub mod output;
+pub mod tone_map;
+pub mod aov;
+pub mod denoise;
 
 /// Struct to store various parameters for the frame timing
 #[derive(Debug, Copy, Clone)]
@@ -0,0 +1,414 @@
+//! Provides functions for saving rendered images to disk. Images are passed as linear
+//! RGB_F32 pixels and saved in the format matching the file extension. OpenEXR (`.exr`)
+//! and Radiance HDR (`.hdr`) images are written as linear floating point so values
+//! above 1 are kept, other formats (eg. PNG, JPG or PPM) are tone mapped to 8-bit
+//! and written with the `image` crate. AOVs rendered with the image are always saved
+//! in OpenEXR images, see the aov module.
+
+use std::{f32, mem};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::fs::File;
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use image;
+
+use film::{Aov, ToneMap};
+
+/// The pixel types that channels in an OpenEXR image can be stored as
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExrPixelType {
+    /// 16-bit half precision floats
+    Half,
+    /// 32-bit single precision floats
+    Float,
+}
+
+/// A layer of channels to write to an OpenEXR image
+#[derive(Debug, Copy, Clone)]
+pub struct ExrLayer<'a> {
+    /// Name of the layer, which prefixes the names of its channels. The channels of the
+    /// layer with an empty name are the main image
+    pub name: &'a str,
+    /// Names of the channels in the layer
+    pub channels: &'a [&'a str],
+    /// The pixels of the layer, with a F32 value for each channel per pixel
+    pub pixels: &'a [f32],
+    /// The type to store the channels of the layer as
+    pub pixel_type: ExrPixelType,
+}
+
+/// Save the `dim.0` by `dim.1` linear RGB_F32 image `pixels` to `path`. The format to save
+/// is chosen by the file extension, `.exr` images are written with half float channels.
+/// The `tone_map` is applied to images saved in 8-bit formats.
+pub fn save_image(path: &Path, dim: (usize, usize), pixels: &[f32], tone_map: &ToneMap)
+                  -> io::Result<()> {
+    save_frame(path, dim, pixels, &[], tone_map)
+}
+
+/// Save the `dim.0` by `dim.1` linear RGB_F32 image `pixels` to `path` along with the `aovs`
+/// rendered with it, see `save_image`. The AOVs are written as layers of the image if it's
+/// saved as an OpenEXR image, otherwise each AOV is saved in an OpenEXR image named after the
+/// image and the AOV, eg. `frame00000_normal.exr`
+pub fn save_frame(path: &Path, dim: (usize, usize), pixels: &[f32], aovs: &[(Aov, Vec<f32>)],
+                  tone_map: &ToneMap) -> io::Result<()> {
+    let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    if ext.as_ref().map_or(false, |e| e == "exr") {
+        let mut layers = vec![ExrLayer { name: "", channels: &["R", "G", "B"], pixels: pixels,
+                                         pixel_type: ExrPixelType::Half }];
+        let names: Vec<_> = aovs.iter().map(|a| a.0.name()).collect();
+        layers.extend(aovs.iter().zip(names.iter()).map(|(&(ref aov, ref px), n)| aov_layer(n, aov, px)));
+        return write_exr_layers(path, dim, &layers[..]);
+    }
+    match ext {
+        Some(ref e) if e == "hdr" => write_hdr(path, dim, pixels)?,
+        _ => {
+            let img = tone_map.apply(pixels);
+            image::save_buffer(path, &img[..], dim.0 as u32, dim.1 as u32, image::RGB(8))?
+        }
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    for &(ref aov, ref px) in aovs {
+        let aov_path = path.with_file_name(format!("{}_{}.exr", stem, aov.name()));
+        write_exr_layers(&aov_path, dim, &[aov_layer("", aov, px)])?;
+    }
+    Ok(())
+}
+
+/// Make the OpenEXR layer to write the AOV's pixels in
+fn aov_layer<'a>(name: &'a str, aov: &Aov, pixels: &'a [f32]) -> ExrLayer<'a> {
+    let pixel_type = if aov.is_color() { ExrPixelType::Half } else { ExrPixelType::Float };
+    ExrLayer { name: name, channels: aov.channels(), pixels: pixels, pixel_type: pixel_type }
+}
+
+/// Write the `dim.0` by `dim.1` linear RGB_F32 image `pixels` to `path` as an uncompressed
+/// scanline OpenEXR image with channels of `pixel_type`.
+pub fn write_exr(path: &Path, dim: (usize, usize), pixels: &[f32], pixel_type: ExrPixelType)
+                 -> io::Result<()> {
+    write_exr_layers(path, dim, &[ExrLayer { name: "", channels: &["R", "G", "B"], pixels: pixels,
+                                             pixel_type: pixel_type }])
+}
+
+/// Write the `dim.0` by `dim.1` image made up of the `layers` to `path` as an uncompressed
+/// scanline OpenEXR image.
+pub fn write_exr_layers(path: &Path, dim: (usize, usize), layers: &[ExrLayer]) -> io::Result<()> {
+    // Find the full names of each channel along with where to find its values, the
+    // channels must be stored in alphabetical order
+    let mut channels = Vec::new();
+    for l in layers {
+        for (i, c) in l.channels.iter().enumerate() {
+            let name = if l.name.is_empty() { c.to_string() } else { format!("{}.{}", l.name, c) };
+            channels.push((name, l, i));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // The header is built in memory first so we know where the pixel data will start
+    let mut header = Vec::new();
+    // Magic number and version 2, with no flags set for a single part scanline image
+    header.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+    let chlist_size = channels.iter().fold(1, |sz, c| sz + c.0.len() + 1 + 16);
+    write_exr_attrib_header(&mut header, "channels", "chlist", chlist_size)?;
+    for &(ref name, l, _) in &channels {
+        header.write_all(name.as_bytes())?;
+        header.write_u8(0)?;
+        header.write_i32::<LittleEndian>(match l.pixel_type {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        })?;
+        // pLinear and the reserved bytes
+        header.write_all(&[0, 0, 0, 0])?;
+        // x and y sampling
+        header.write_i32::<LittleEndian>(1)?;
+        header.write_i32::<LittleEndian>(1)?;
+    }
+    header.write_u8(0)?;
+    write_exr_attrib_header(&mut header, "compression", "compression", 1)?;
+    header.write_u8(0)?;
+    for name in &["dataWindow", "displayWindow"] {
+        write_exr_attrib_header(&mut header, name, "box2i", 16)?;
+        header.write_i32::<LittleEndian>(0)?;
+        header.write_i32::<LittleEndian>(0)?;
+        header.write_i32::<LittleEndian>(dim.0 as i32 - 1)?;
+        header.write_i32::<LittleEndian>(dim.1 as i32 - 1)?;
+    }
+    // Scanlines are stored in increasing y order
+    write_exr_attrib_header(&mut header, "lineOrder", "lineOrder", 1)?;
+    header.write_u8(0)?;
+    write_exr_attrib_header(&mut header, "pixelAspectRatio", "float", 4)?;
+    header.write_f32::<LittleEndian>(1.0)?;
+    write_exr_attrib_header(&mut header, "screenWindowCenter", "v2f", 8)?;
+    header.write_f32::<LittleEndian>(0.0)?;
+    header.write_f32::<LittleEndian>(0.0)?;
+    write_exr_attrib_header(&mut header, "screenWindowWidth", "float", 4)?;
+    header.write_f32::<LittleEndian>(1.0)?;
+    // End of the header
+    header.write_u8(0)?;
+
+    let mut f = BufWriter::new(File::create(path)?);
+    f.write_all(&header[..])?;
+    // Uncompressed images store each scanline in its own chunk, the offset table
+    // holds the position of each chunk in the file
+    let line_size = channels.iter().fold(0, |sz, c| {
+        sz + dim.0 * match c.1.pixel_type {
+            ExrPixelType::Half => 2,
+            ExrPixelType::Float => 4,
+        }
+    });
+    let chunk_size = 8 + line_size;
+    let table_size = dim.1 * 8;
+    for y in 0..dim.1 {
+        f.write_u64::<LittleEndian>((header.len() + table_size + y * chunk_size) as u64)?;
+    }
+    for y in 0..dim.1 {
+        f.write_i32::<LittleEndian>(y as i32)?;
+        f.write_i32::<LittleEndian>(line_size as i32)?;
+        // Within the scanline all the pixels of each channel are stored together
+        for &(_, l, c) in &channels {
+            let stride = l.channels.len();
+            for x in 0..dim.0 {
+                let v = l.pixels[(y * dim.0 + x) * stride + c];
+                match l.pixel_type {
+                    ExrPixelType::Half => f.write_u16::<LittleEndian>(f32_to_half(v))?,
+                    ExrPixelType::Float => f.write_f32::<LittleEndian>(v)?,
+                }
+            }
+        }
+    }
+    f.flush()
+}
+
+/// Write the name, type and size of an attribute in an OpenEXR header
+fn write_exr_attrib_header<W: Write>(f: &mut W, name: &str, ty: &str, size: usize) -> io::Result<()> {
+    f.write_all(name.as_bytes())?;
+    f.write_u8(0)?;
+    f.write_all(ty.as_bytes())?;
+    f.write_u8(0)?;
+    f.write_i32::<LittleEndian>(size as i32)
+}
+
+/// Convert the 32-bit float to a 16-bit half float, rounding to the nearest half.
+/// Values too large to be represented become infinity
+pub fn f32_to_half(f: f32) -> u16 {
+    let bits = unsafe { mem::transmute::<f32, u32>(f) };
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+    // Infinity or NaN, making sure NaNs stay NaNs
+    if exp == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exp = exp - 127 + 15;
+    if half_exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // Values too small for a normalized half are stored as denormals, or flushed to zero
+    // if they're too small for those as well
+    let (m, shift) = if half_exp <= 0 {
+        if half_exp < -10 {
+            return sign;
+        }
+        (mantissa | 0x800000, (14 - half_exp) as u32)
+    } else {
+        (((half_exp as u32) << 23) | mantissa, 13)
+    };
+    // Round to nearest even, a carry out of the mantissa correctly increments the exponent
+    let half = m >> shift;
+    let rem = m & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let rounded = if rem > halfway || (rem == halfway && half & 1 == 1) { half + 1 } else { half };
+    sign | rounded as u16
+}
+
+/// Write the `dim.0` by `dim.1` linear RGB_F32 image `pixels` to `path` as a run length
+/// encoded Radiance HDR image
+pub fn write_hdr(path: &Path, dim: (usize, usize), pixels: &[f32]) -> io::Result<()> {
+    let mut f = BufWriter::new(File::create(path)?);
+    write!(f, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", dim.1, dim.0)?;
+    let mut scanline = Vec::with_capacity(dim.0 * 4);
+    let mut component = Vec::with_capacity(dim.0);
+    for y in 0..dim.1 {
+        scanline.clear();
+        for p in pixels[y * dim.0 * 3..(y + 1) * dim.0 * 3].chunks(3) {
+            scanline.extend_from_slice(&to_rgbe(p[0], p[1], p[2]));
+        }
+        // Run length encoding is only supported for scanlines of this width
+        if dim.0 < 8 || dim.0 > 0x7fff {
+            f.write_all(&scanline[..])?;
+            continue;
+        }
+        f.write_all(&[2, 2, (dim.0 >> 8) as u8, (dim.0 & 0xff) as u8])?;
+        // Each component of the scanline is run length encoded separately
+        for c in 0..4 {
+            component.clear();
+            let mut i = c;
+            while i < scanline.len() {
+                component.push(scanline[i]);
+                i += 4;
+            }
+            write_rle(&mut f, &component[..])?;
+        }
+    }
+    f.flush()
+}
+
+/// Convert the linear RGB color to the shared exponent RGBE format used by Radiance HDR images
+fn to_rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+    // Negative and NaN values can't be represented, so are written as black
+    let r = if r > 0.0 { r } else { 0.0 };
+    let g = if g > 0.0 { g } else { 0.0 };
+    let b = if b > 0.0 { b } else { 0.0 };
+    let v = f32::max(r, f32::max(g, b));
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // Find the exponent such that v = m * 2^e with m in [0.5, 1)
+    let mut e = f32::floor(f32::log2(v)) as i32 + 1;
+    if v / f32::powi(2.0, e) >= 1.0 {
+        e += 1;
+    } else if v / f32::powi(2.0, e) < 0.5 {
+        e -= 1;
+    }
+    let scale = 256.0 / f32::powi(2.0, e);
+    [(r * scale) as u8, (g * scale) as u8, (b * scale) as u8, (e + 128) as u8]
+}
+
+/// Write the bytes of a scanline component using the Radiance run length encoding
+fn write_rle<W: Write>(f: &mut W, data: &[u8]) -> io::Result<()> {
+    // Runs shorter than this are cheaper to store as part of a literal sequence
+    let min_run = 4;
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_length(data, i);
+        if run >= min_run {
+            f.write_all(&[128 + run as u8, data[i]])?;
+            i += run;
+        } else {
+            let start = i;
+            while i < data.len() && i - start < 128 && run_length(data, i) < min_run {
+                i += 1;
+            }
+            f.write_u8((i - start) as u8)?;
+            f.write_all(&data[start..i])?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the length of the run of bytes with the same value starting at `start`,
+/// up to the longest run that can be encoded
+fn run_length(data: &[u8], start: usize) -> usize {
+    let mut n = 1;
+    while start + n < data.len() && n < 127 && data[start + n] == data[start] {
+        n += 1;
+    }
+    n
+}
+
+#[test]
+fn test_f32_to_half() {
+    assert_eq!(f32_to_half(0.0), 0);
+    assert_eq!(f32_to_half(-0.0), 0x8000);
+    assert_eq!(f32_to_half(1.0), 0x3c00);
+    assert_eq!(f32_to_half(-2.0), 0xc000);
+    assert_eq!(f32_to_half(0.5), 0x3800);
+    assert_eq!(f32_to_half(65504.0), 0x7bff);
+    assert_eq!(f32_to_half(1e6), 0x7c00);
+    assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+    assert!(f32_to_half(f32::NAN) & 0x3ff != 0);
+    // The smallest half denormal and a value rounded up to it
+    assert_eq!(f32_to_half(f32::powi(2.0, -24)), 1);
+    assert_eq!(f32_to_half(f32::powi(2.0, -24) * 0.75), 1);
+    // 1 + 2^-11 is halfway between two halfs and should round to even
+    assert_eq!(f32_to_half(1.0 + f32::powi(2.0, -11)), 0x3c00);
+    assert_eq!(f32_to_half(1.0 + 3.0 * f32::powi(2.0, -11)), 0x3c02);
+}
+
+#[test]
+fn test_to_rgbe() {
+    assert_eq!(to_rgbe(1.0, 1.0, 1.0), [128, 128, 128, 129]);
+    assert_eq!(to_rgbe(0.5, 0.25, 0.0), [128, 64, 0, 128]);
+    assert_eq!(to_rgbe(0.0, -1.0, 0.0), [0, 0, 0, 0]);
+    assert_eq!(to_rgbe(3.0, 0.0, 0.0), [192, 0, 0, 130]);
+}
+
+#[test]
+fn test_write_rle() {
+    let data = [1, 1, 1, 1, 1, 2, 3, 4, 4];
+    let mut out = Vec::new();
+    write_rle(&mut out, &data).unwrap();
+    assert_eq!(out, vec![128 + 5, 1, 4, 2, 3, 4, 4]);
+}
+
+#[test]
+fn test_write_exr_layers() {
+    use std::fs;
+    use std::io::Cursor;
+    use byteorder::ReadBytesExt;
+
+    let read_str = |c: &mut Cursor<Vec<u8>>| {
+        let mut s = String::new();
+        loop {
+            match c.read_u8().unwrap() {
+                0 => return s,
+                b => s.push(b as char),
+            }
+        }
+    };
+    let dim = (2, 1);
+    let rgb = [0.0, 0.25, 0.5, 1.0, 2.0, 4.0];
+    let normal = [0.0, 0.0, 1.0, 1.0, 0.0, 0.0];
+    let id = [3.0, 7.0];
+    let layers = [ExrLayer { name: "normal", channels: &["X", "Y", "Z"], pixels: &normal,
+                             pixel_type: ExrPixelType::Float },
+                  ExrLayer { name: "", channels: &["R", "G", "B"], pixels: &rgb, pixel_type: ExrPixelType::Half },
+                  ExrLayer { name: "id", channels: &["ID"], pixels: &id, pixel_type: ExrPixelType::Float }];
+    // Name the file uniquely so concurrent test runs don't write over each other's files
+    let path = ::std::env::temp_dir().join(format!("tray_rust_test_exr_layers_{}.exr",
+                                                   ::clock_ticks::precise_time_ns()));
+    write_exr_layers(&path, dim, &layers).unwrap();
+    let mut data = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    // The channel list must hold the channels of all layers in alphabetical order
+    let mut c = Cursor::new(data);
+    c.set_position(8);
+    assert_eq!(read_str(&mut c), "channels");
+    assert_eq!(read_str(&mut c), "chlist");
+    c.read_i32::<LittleEndian>().unwrap();
+    let mut channels = Vec::new();
+    loop {
+        let name = read_str(&mut c);
+        if name.is_empty() {
+            break;
+        }
+        let ty = c.read_i32::<LittleEndian>().unwrap();
+        c.set_position(c.position() + 12);
+        channels.push((name, ty));
+    }
+    let expected = [("B", 1), ("G", 1), ("R", 1), ("id.ID", 2), ("normal.X", 2), ("normal.Y", 2), ("normal.Z", 2)];
+    assert_eq!(channels.len(), expected.len());
+    for (a, b) in channels.iter().zip(expected.iter()) {
+        assert_eq!(&a.0[..], b.0);
+        assert_eq!(a.1, b.1);
+    }
+    // Skip the rest of the header to find the single scanline, where the pixels of each
+    // channel are stored together in the same order
+    while !read_str(&mut c).is_empty() {
+        read_str(&mut c);
+        let size = c.read_i32::<LittleEndian>().unwrap();
+        c.set_position(c.position() + size as u64);
+    }
+    let offset = c.read_u64::<LittleEndian>().unwrap();
+    c.set_position(offset);
+    assert_eq!(c.read_i32::<LittleEndian>().unwrap(), 0);
+    assert_eq!(c.read_i32::<LittleEndian>().unwrap(), 3 * 2 * 2 + 4 * 4 * 2);
+    let halfs: Vec<_> = (0..6).map(|_| c.read_u16::<LittleEndian>().unwrap()).collect();
+    assert_eq!(halfs, vec![f32_to_half(0.5), f32_to_half(4.0), f32_to_half(0.25), f32_to_half(2.0),
+                           f32_to_half(0.0), f32_to_half(1.0)]);
+    let floats: Vec<_> = (0..8).map(|_| c.read_f32::<LittleEndian>().unwrap()).collect();
+    assert_eq!(floats, vec![3.0, 7.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0]);
+}
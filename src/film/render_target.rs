@@ -6,6 +6,7 @@ use std::{iter, cmp, f32};
 use std::sync::Mutex;
 
 use film::Colorf;
+use film::{Aov, AovFilter, Denoiser, ToneMap};
 use film::filter::Filter;
 use sampler::Region;
 
@@ -34,12 +35,29 @@ pub struct RenderTarget {
     filter: Box<Filter + Send + Sync>,
     filter_table: Vec<f32>,
     filter_pixel_width: (i32, i32),
+    /// Contributions splatted directly to pixels without filtering, eg. from light paths
+    /// connected to the camera by a bidirectional path tracer
+    splats: Mutex<Vec<Colorf>>,
+    /// Tone mapping applied to the image when converting it to 8-bit
+    tone_map: ToneMap,
+    /// The AOVs rendered alongside the image
+    aovs: Vec<Aov>,
+    /// Pixels of each AOV, stored in blocks like the image's pixels
+    aov_pixels: Vec<Vec<Mutex<Vec<Colorf>>>>,
+    /// Denoiser to run on the image before it's saved, if any
+    denoiser: Option<Denoiser>,
 }
 
 impl RenderTarget {
-    /// Create a render target with `width * height` pixels
+    /// Create a render target with `width * height` pixels, which will be converted to 8-bit
+    /// with the `tone_map`. Layers are created for each of the `aovs` to render with the image,
+    /// these must include the albedo and normal AOVs if a `denoiser` is used
     pub fn new(image_dim: (usize, usize), lock_size: (usize, usize),
-               filter: Box<Filter + Send + Sync>) -> RenderTarget {
+               filter: Box<Filter + Send + Sync>, tone_map: ToneMap, aovs: Vec<Aov>,
+               denoiser: Option<Denoiser>) -> RenderTarget {
+        if denoiser.is_some() && !(aovs.contains(&Aov::Albedo) && aovs.contains(&Aov::Normal)) {
+            panic!("The albedo and normal AOVs must be rendered to denoise the image");
+        }
         if image_dim.0 % lock_size.0 != 0 || image_dim.1 % lock_size.1 != 0 {
             panic!("Image with dimension {:?} not evenly divided by blocks of {:?}", image_dim, lock_size);
         }
@@ -59,11 +77,16 @@ impl RenderTarget {
 
         let x_blocks = width / lock_size.0;
         let y_blocks = height / lock_size.1;
-        let mut pixels_locked = Vec::with_capacity(x_blocks * y_blocks);
-        for _ in 0..x_blocks * y_blocks {
-            pixels_locked.push(Mutex::new(iter::repeat(Colorf::broadcast(0.0))
-                                          .take(lock_size.0 * lock_size.1).collect()));
-        }
+        let new_layer = || {
+            let mut pixels_locked = Vec::with_capacity(x_blocks * y_blocks);
+            for _ in 0..x_blocks * y_blocks {
+                pixels_locked.push(Mutex::new(iter::repeat(Colorf::broadcast(0.0))
+                                              .take(lock_size.0 * lock_size.1).collect()));
+            }
+            pixels_locked
+        };
+        let pixels_locked = new_layer();
+        let aov_pixels = aovs.iter().map(|_| new_layer()).collect();
 
         RenderTarget { width: width, height: height,
             pixels_locked: pixels_locked,
@@ -71,10 +94,56 @@ impl RenderTarget {
             filter: filter,
             filter_table: filter_table,
             filter_pixel_width: filter_pixel_width,
+            splats: Mutex::new(iter::repeat(Colorf::broadcast(0.0)).take(width * height).collect()),
+            tone_map: tone_map,
+            aovs: aovs,
+            aov_pixels: aov_pixels,
+            denoiser: denoiser,
         }
     }
     /// Write all the image samples to the render target
     pub fn write(&self, samples: &[ImageSample], region: &Region) {
+        self.write_layer(&self.pixels_locked, samples, region);
+    }
+    /// Write the samples of the AOV at index `aov` in the list of AOVs being rendered,
+    /// the samples are reconstructed into the pixels with the AOV's filter
+    pub fn write_aov(&self, aov: usize, samples: &[ImageSample], region: &Region) {
+        match self.aovs[aov].filter() {
+            AovFilter::Image => self.write_layer(&self.aov_pixels[aov], samples, region),
+            AovFilter::Box => self.write_layer_unfiltered(&self.aov_pixels[aov], samples, false),
+            AovFilter::Nearest => self.write_layer_unfiltered(&self.aov_pixels[aov], samples, true),
+        }
+    }
+    /// Write the samples to the pixels they're in without filtering them. The samples in each
+    /// pixel are averaged, or if `nearest` is set the pixel keeps the sample nearest its center.
+    /// Nearest samples are only used for single channel AOVs, so the squared distance of the
+    /// sample kept from the center is stored in the green channel of the pixel
+    fn write_layer_unfiltered(&self, layer: &[Mutex<Vec<Colorf>>], samples: &[ImageSample], nearest: bool) {
+        let lock_size = (self.lock_size.0 as usize, self.lock_size.1 as usize);
+        let blocks_per_row = self.width / lock_size.0;
+        for s in samples {
+            // Samples at the far edge of the image can be rounded onto the next pixel
+            let x = cmp::min(s.x as usize, self.width - 1);
+            let y = cmp::min(s.y as usize, self.height - 1);
+            let block_idx = (y / lock_size.1) * blocks_per_row + x / lock_size.0;
+            let mut pixels = layer[block_idx].lock().unwrap();
+            let p = &mut pixels[(y % lock_size.1) * lock_size.0 + x % lock_size.0];
+            if nearest {
+                let (dx, dy) = (s.x - x as f32 - 0.5, s.y - y as f32 - 0.5);
+                let dist = dx * dx + dy * dy;
+                if p.a == 0.0 || dist < p.g {
+                    *p = Colorf { r: s.color.r, g: dist, b: 0.0, a: 1.0 };
+                }
+            } else {
+                p.r += s.color.r;
+                p.g += s.color.g;
+                p.b += s.color.b;
+                p.a += 1.0;
+            }
+        }
+    }
+    /// Filter the samples and write them to the blocks of pixels in the layer
+    fn write_layer(&self, layer: &[Mutex<Vec<Colorf>>], samples: &[ImageSample], region: &Region) {
         // Determine which blocks we touch with our set of samples
         let x_range = (cmp::max(region.start.0 as i32 - self.filter_pixel_width.0, 0),
                        cmp::min(region.end.0 as i32 + self.filter_pixel_width.0, self.width as i32 - 1));
@@ -149,7 +218,7 @@ impl RenderTarget {
 
                 // Acquire lock for the block and write the filtered samples
                 let block_idx = (y * blocks_per_row + x) as usize;
-                let mut pixels = self.pixels_locked[block_idx].lock().unwrap();
+                let mut pixels = layer[block_idx].lock().unwrap();
                 for iy in y_write_range.0..y_write_range.1 {
                     for ix in x_write_range.0..x_write_range.1 {
                         let px = ((iy - block_y_start) * self.lock_size.0 + ix - block_x_start) as usize;
@@ -163,16 +232,46 @@ impl RenderTarget {
             }
         }
     }
+    /// Add the splatted samples to the pixels they're in, scaling their color by `scale`.
+    /// Unlike samples written with `write` splats are not filtered or normalized by the filter
+    /// weight, they're added directly to the final pixel color.
+    pub fn write_splats(&self, splats: &[ImageSample], scale: f32) {
+        let mut pixels = self.splats.lock().unwrap();
+        for s in splats {
+            let x = s.x as usize;
+            let y = s.y as usize;
+            if x < self.width && y < self.height {
+                let c = &mut pixels[y * self.width + x];
+                c.r += scale * s.color.r;
+                c.g += scale * s.color.g;
+                c.b += scale * s.color.b;
+            }
+        }
+    }
+    /// Scale the color of all the splatted contributions by `scale`, eg. to average the
+    /// splats written by multiple passes over the image
+    pub fn scale_splats(&self, scale: f32) {
+        for p in self.splats.lock().unwrap().iter_mut() {
+            p.r *= scale;
+            p.g *= scale;
+            p.b *= scale;
+        }
+    }
     /// Clear the render target to black
     pub fn clear(&mut self) {
+        for p in self.splats.lock().unwrap().iter_mut() {
+            *p = Colorf::broadcast(0.0);
+        }
         let x_blocks = self.width / self.lock_size.0 as usize;
         let y_blocks = self.height / self.lock_size.1 as usize;
-        for by in 0..y_blocks {
-            for bx in 0..x_blocks {
-                let block_idx = (by * x_blocks + bx) as usize;
-                let mut pixels = self.pixels_locked[block_idx].lock().unwrap();
-                for p in pixels.iter_mut() {
-                    *p = Colorf::broadcast(0.0);
+        for layer in iter::once(&self.pixels_locked).chain(self.aov_pixels.iter()) {
+            for by in 0..y_blocks {
+                for bx in 0..x_blocks {
+                    let block_idx = (by * x_blocks + bx) as usize;
+                    let mut pixels = layer[block_idx].lock().unwrap();
+                    for p in pixels.iter_mut() {
+                        *p = Colorf::broadcast(0.0);
+                    }
                 }
             }
         }
@@ -181,11 +280,25 @@ impl RenderTarget {
     pub fn dimensions(&self) -> (usize, usize) {
         (self.width, self.height)
     }
-    /// Convert the floating point color buffer to 24bpp sRGB for output to an image
-    pub fn get_render(&self) -> Vec<u8> {
-        let mut render: Vec<u8> = iter::repeat(0u8).take(self.width * self.height * 3).collect();
+    /// Get the AOVs being rendered alongside the image
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+    /// Get the denoiser to run on the image before it's saved, if any
+    pub fn denoiser(&self) -> Option<Denoiser> {
+        self.denoiser
+    }
+    /// Get the tone mapping used to convert the image to 8-bit
+    pub fn tone_map(&self) -> ToneMap {
+        self.tone_map
+    }
+    /// Get the final linear RGB_F32 image, combining the filtered pixel colors with the
+    /// splatted contributions. Colors are not clamped so values above 1 are kept
+    pub fn get_render_linear(&self) -> Vec<f32> {
+        let mut render: Vec<f32> = iter::repeat(0.0).take(self.width * self.height * 3).collect();
         let x_blocks = self.width / self.lock_size.0 as usize;
         let y_blocks = self.height / self.lock_size.1 as usize;
+        let splats = self.splats.lock().unwrap();
         for by in 0..y_blocks {
             for bx in 0..x_blocks {
                 let block_x_start = bx * self.lock_size.0 as usize;
@@ -195,11 +308,13 @@ impl RenderTarget {
                 for y in 0..self.lock_size.1 as usize {
                     for x in 0..self.lock_size.0 as usize {
                         let c = &pixels[y * self.lock_size.0 as usize + x];
-                        if c.a > 0.0 {
-                            let cn = (*c / c.a).clamp().to_srgb();
+                        let splat = &splats[(y + block_y_start) * self.width + x + block_x_start];
+                        if c.a > 0.0 || !splat.is_black() {
+                            let filtered = if c.a > 0.0 { *c / c.a } else { Colorf::black() };
+                            let cn = filtered + *splat;
                             let px = (y + block_y_start) * self.width * 3 + (x + block_x_start) * 3;
                             for i in 0..3 {
-                                render[px + i] = (cn[i] * 255.0) as u8;
+                                render[px + i] = cn[i];
                             }
                         }
                     }
@@ -239,7 +354,62 @@ impl RenderTarget {
         }
         (block_size, blocks, render)
     }
-    /// Get the raw floating point framebuffer
+    /// Get the pixels of the AOV at index `aov` for the blocks starting at the pixel positions
+    /// in `blocks`, eg. the blocks returned by `get_rendered_blocks`. The pixels are returned
+    /// in the same format as `get_rendered_blocks`
+    pub fn get_aov_blocks(&self, aov: usize, blocks: &[(usize, usize)]) -> Vec<f32> {
+        let block_size = (self.lock_size.0 as usize, self.lock_size.1 as usize);
+        let x_blocks = self.width / block_size.0;
+        let mut render = Vec::with_capacity(blocks.len() * block_size.0 * block_size.1 * 4);
+        for b in blocks {
+            let block_idx = (b.1 / block_size.1) * x_blocks + b.0 / block_size.0;
+            let pixels = self.aov_pixels[aov][block_idx].lock().unwrap();
+            for c in pixels.iter() {
+                for i in 0..4 {
+                    render.push(c[i]);
+                }
+            }
+        }
+        render
+    }
+    /// Get the final image of the AOV at index `aov` in the list of AOVs being rendered.
+    /// The pixels have as many F32 values as the AOV has channels
+    pub fn get_aov_linear(&self, aov: usize) -> Vec<f32> {
+        let channels = self.aovs[aov].channels().len();
+        let mut render: Vec<f32> = iter::repeat(0.0).take(self.width * self.height * channels).collect();
+        let x_blocks = self.width / self.lock_size.0 as usize;
+        let y_blocks = self.height / self.lock_size.1 as usize;
+        for by in 0..y_blocks {
+            for bx in 0..x_blocks {
+                let block_x_start = bx * self.lock_size.0 as usize;
+                let block_y_start = by * self.lock_size.1 as usize;
+                let block_idx = (by * x_blocks + bx) as usize;
+                let pixels = self.aov_pixels[aov][block_idx].lock().unwrap();
+                for y in 0..self.lock_size.1 as usize {
+                    for x in 0..self.lock_size.0 as usize {
+                        let c = &pixels[y * self.lock_size.0 as usize + x];
+                        if c.a > 0.0 {
+                            let px = ((y + block_y_start) * self.width + x + block_x_start) * channels;
+                            for i in 0..channels {
+                                render[px + i] = c[i] / c.a;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        render
+    }
+    /// Get the final images of all the AOVs being rendered, see `get_aov_linear`
+    pub fn get_aovs_linear(&self) -> Vec<(Aov, Vec<f32>)> {
+        self.aovs.iter().enumerate().map(|(i, a)| (a.clone(), self.get_aov_linear(i))).collect()
+    }
+    /// Get the splatted contributions to the image as RGB_F32 pixels, these should be added
+    /// to the filtered pixel colors to get the final image.
+    pub fn get_splats(&self) -> Vec<f32> {
+        self.splats.lock().unwrap().iter().flat_map(|c| vec![c.r, c.g, c.b].into_iter()).collect()
+    }
+    /// Get the raw floating point framebuffer, this doesn't include the splatted contributions
     pub fn get_renderf32(&self) -> Vec<f32> {
         let mut render: Vec<f32> = iter::repeat(0.0).take(self.width * self.height * 4).collect();
         let x_blocks = self.width / self.lock_size.0 as usize;
@@ -265,3 +435,37 @@ impl RenderTarget {
     }
 }
 
+
+#[test]
+fn test_write_aov() {
+    use film::filter::MitchellNetravali;
+    use film::ToneMapOperator;
+
+    let filter = Box::new(MitchellNetravali::new(2.0, 2.0, 1.0 / 3.0, 1.0 / 3.0)) as Box<Filter + Send + Sync>;
+    let tone_map = ToneMap::new(ToneMapOperator::Exposure, 0.0, None);
+    let rt = RenderTarget::new((4, 4), (2, 2), filter, tone_map, vec![Aov::Id, Aov::Depth, Aov::Albedo], None);
+    let region = Region::new((0, 0), (4, 4));
+    // Two samples in pixel (1, 1) hitting different objects at different depths
+    let samples = |a: f32, b: f32| {
+        vec![ImageSample::new(1.1, 1.9, Colorf::broadcast(a)), ImageSample::new(1.45, 1.55, Colorf::broadcast(b))]
+    };
+    rt.write_aov(0, &samples(3.0, 5.0), &region);
+    rt.write_aov(1, &samples(2.0, 4.0), &region);
+    rt.write_aov(2, &samples(0.5, 0.5), &region);
+    // Samples further from the pixel center don't replace the ID of the nearest one
+    rt.write_aov(0, &[ImageSample::new(1.9, 1.1, Colorf::broadcast(7.0))], &region);
+
+    let id = rt.get_aov_linear(0);
+    let depth = rt.get_aov_linear(1);
+    let albedo = rt.get_aov_linear(2);
+    assert_eq!(id.len(), 16);
+    assert_eq!(albedo.len(), 48);
+    let px = 4 + 1;
+    assert_eq!(id[px], 5.0);
+    assert_eq!(depth[px], 3.0);
+    assert!(f32::abs(albedo[3 * px] - 0.5) < 1e-5);
+    // Only colors are filtered into the neighboring pixels
+    assert_eq!(id[px + 1], 0.0);
+    assert_eq!(depth[px + 1], 0.0);
+    assert!(f32::abs(albedo[3 * (px + 1)] - 0.5) < 1e-5);
+}
@@ -0,0 +1,145 @@
+//! Provides the tone mapping operators used to map the linear HDR colors of the
+//! render to the [0, 1] range of 8-bit images. The colors are first scaled by the
+//! exposure, then mapped by the operator and finally gamma corrected for display.
+//! Floating point images (eg. EXR and HDR) are saved without tone mapping.
+//!
+//! # Scene Usage Example
+//! The tone mapping operator is specified in the film object of the scene, if none is
+//! specified the `exposure` operator is used with no exposure adjustment, which clamps
+//! the colors to [0, 1] and applies the sRGB transfer curve.
+//!
+//! ```json
+//! "film": {
+//!     ...
+//!     "tone_map": {
+//!         "type": "reinhard",
+//!         "exposure": 0.5,
+//!         "white": 4.0
+//!     }
+//! }
+//! ```
+//!
+//! All operators take an optional `exposure` in stops to scale the colors by before mapping
+//! them, defaulting to 0, and an optional `gamma` to use instead of the sRGB transfer curve.
+//!
+//! - `exposure`: clamps the exposed colors to [0, 1].
+//! - `reinhard`: the Reinhard operator applied to the luminance of the color, preserving its
+//!   hue. If the optional `white` luminance is set it and any brighter colors are mapped to
+//!   white, otherwise only infinite luminance is.
+//! - `aces`: a filmic curve with a soft roll off in the highlights, Krzysztof Narkowicz's
+//!   fit of the ACES reference rendering transform.
+//!
+//! ```json
+//! "tone_map": {
+//!     "type": "aces",
+//!     "exposure": -1.0,
+//!     "gamma": 2.2
+//! }
+//! ```
+
+use std::f32;
+
+use film::Colorf;
+
+/// The operators available to map the exposed linear colors to [0, 1]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMapOperator {
+    /// Clamp the colors to [0, 1]
+    Exposure,
+    /// The Reinhard operator applied to the luminance, with an optional luminance
+    /// that's mapped to white
+    Reinhard { white: Option<f32> },
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+/// A tone mapping operator along with the exposure and gamma correction to apply
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ToneMap {
+    operator: ToneMapOperator,
+    /// Exposure adjustment in stops
+    exposure: f32,
+    /// Gamma to correct the mapped colors with, the sRGB transfer curve is used if not set
+    gamma: Option<f32>,
+}
+
+impl ToneMap {
+    /// Create a tone mapping with the `operator`, `exposure` adjustment in stops and
+    /// `gamma`. If no gamma is passed the sRGB transfer curve is applied instead
+    pub fn new(operator: ToneMapOperator, exposure: f32, gamma: Option<f32>) -> ToneMap {
+        ToneMap { operator: operator, exposure: exposure, gamma: gamma }
+    }
+    /// Map the linear color to a gamma corrected color in [0, 1] for display
+    pub fn map(&self, c: &Colorf) -> Colorf {
+        let c = *c * f32::powf(2.0, self.exposure);
+        let mapped = match self.operator {
+            ToneMapOperator::Exposure => c,
+            ToneMapOperator::Reinhard { white } => {
+                let lum = c.luminance();
+                if lum > 0.0 {
+                    let lum_mapped = match white {
+                        Some(w) => lum * (1.0 + lum / (w * w)) / (1.0 + lum),
+                        None => lum / (1.0 + lum),
+                    };
+                    c * (lum_mapped / lum)
+                } else {
+                    Colorf::black()
+                }
+            },
+            ToneMapOperator::Aces => {
+                let mut m = Colorf::black();
+                for i in 0..3 {
+                    let x = f32::max(c[i], 0.0);
+                    m[i] = x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14);
+                }
+                m
+            },
+        }.clamp();
+        match self.gamma {
+            Some(g) => Colorf::new(f32::powf(mapped.r, 1.0 / g), f32::powf(mapped.g, 1.0 / g),
+                                   f32::powf(mapped.b, 1.0 / g)),
+            None => mapped.to_srgb(),
+        }
+    }
+    /// Tone map the linear RGB_F32 pixels to 24bpp RGB for output to an 8-bit image
+    pub fn apply(&self, pixels: &[f32]) -> Vec<u8> {
+        let mut render = Vec::with_capacity(pixels.len());
+        for p in pixels.chunks(3) {
+            let c = self.map(&Colorf::new(p[0], p[1], p[2]));
+            for i in 0..3 {
+                render.push((c[i] * 255.0) as u8);
+            }
+        }
+        render
+    }
+}
+
+#[test]
+fn test_exposure() {
+    let tm = ToneMap::new(ToneMapOperator::Exposure, 1.0, Some(1.0));
+    assert_eq!(tm.map(&Colorf::new(0.25, 0.5, 4.0)), Colorf::new(0.5, 1.0, 1.0));
+}
+
+#[test]
+fn test_reinhard() {
+    let tm = ToneMap::new(ToneMapOperator::Reinhard { white: None }, 0.0, Some(1.0));
+    let c = tm.map(&Colorf::broadcast(1.0));
+    assert!(f32::abs(c.r - 0.5) < 1e-5 && f32::abs(c.g - 0.5) < 1e-5 && f32::abs(c.b - 0.5) < 1e-5);
+    let tm = ToneMap::new(ToneMapOperator::Reinhard { white: Some(4.0) }, 0.0, Some(1.0));
+    let c = tm.map(&Colorf::broadcast(4.0));
+    assert!(f32::abs(c.r - 1.0) < 1e-5 && f32::abs(c.g - 1.0) < 1e-5 && f32::abs(c.b - 1.0) < 1e-5);
+    assert_eq!(tm.map(&Colorf::black()), Colorf::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn test_aces() {
+    let tm = ToneMap::new(ToneMapOperator::Aces, 0.0, Some(1.0));
+    assert_eq!(tm.map(&Colorf::black()), Colorf::new(0.0, 0.0, 0.0));
+    // The curve is monotonic until it's clamped to white a little above 7
+    let mut prev = 0.0;
+    for i in 1..70 {
+        let c = tm.map(&Colorf::broadcast(i as f32 * 0.1));
+        assert!(c.r > prev && c.r <= 1.0);
+        prev = c.r;
+    }
+}
@@ -68,6 +68,11 @@ impl BBox {
         let d = self.max - self.min;
         2.0 * (d.x * d.y + d.x * d.z + d.y * d.z)
     }
+    /// Compute the center and radius of a sphere bounding the box
+    pub fn bounding_sphere(&self) -> (Point, f32) {
+        let center = self.lerp(0.5, 0.5, 0.5);
+        (center, center.distance(&self.max))
+    }
     /// Optimized ray-box intersection test, for use in the BVH traversal where we have
     /// pre-computed the ray's inverse direction and which directions are negative, indicated
     /// by a 1 for negative and 0 for non-negative
@@ -12,8 +12,7 @@ use linalg::{Point, Ray, Axis, Vector};
 /// via the `Boundable` trait. The BVH is constructed using a SAH partitioning scheme
 pub struct BVH<T: Boundable> {
     /// The geometry stored in this BVH, this will be re-ordered to
-    /// fit the BVH construction layout. TODO: We may want to make
-    /// the geometry accessible by index
+    /// fit the BVH construction layout.
     geometry: Vec<T>,
     /// Indices into `geometry` sorted by the order they're accessed by BVH leaf nodes
     /// TODO: How can we re-sort `geometry to match this ordering?
@@ -131,6 +130,36 @@ impl<T: Boundable> BVH<T> {
     pub fn iter(&self) -> Iter<T> {
         self.geometry.iter()
     }
+    /// Get the object at index `i` in the geometry the BVH was built from
+    pub fn get(&self, i: usize) -> &T {
+        &self.geometry[i]
+    }
+    /// Get the number of nodes in the flattened tree, the root node is at index 0
+    pub fn num_nodes(&self) -> usize {
+        self.tree.len()
+    }
+    /// Get the bounds of the node at index `node` in the flattened tree
+    pub fn node_bounds(&self, node: usize) -> &BBox {
+        &self.tree[node].bounds
+    }
+    /// Get the indices of the two children of the node at index `node` in the flattened
+    /// tree, or None if it's a leaf
+    pub fn node_children(&self, node: usize) -> Option<(usize, usize)> {
+        match self.tree[node].node {
+            FlatNodeData::Interior { ref second_child, .. } => Some((node + 1, *second_child)),
+            FlatNodeData::Leaf { .. } => None,
+        }
+    }
+    /// Get the indices of the objects stored in the node at index `node` in the flattened
+    /// tree, which can be looked up with `get`. Interior nodes don't store any objects
+    pub fn node_geometry(&self, node: usize) -> &[usize] {
+        match self.tree[node].node {
+            FlatNodeData::Leaf { ref geom_offset, ref ngeom } => {
+                &self.ordered_geom[*geom_offset..*geom_offset + *ngeom]
+            },
+            FlatNodeData::Interior { .. } => &[],
+        }
+    }
     /// Construct the BVH tree using SAH splitting heuristic to determine split locations
     /// returns the root node of the subtree constructed over the slice of geom info passed
     /// and will increment `total_nodes` by the number of nodes in this subtree
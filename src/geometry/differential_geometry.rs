@@ -13,31 +13,47 @@ pub struct DifferentialGeometry<'a> {
     pub n: Normal,
     /// The geometry normal
     pub ng: Normal,
+    /// Surface parameterization coordinate u of the hit point
+    pub u: f32,
+    /// Surface parameterization coordinate v of the hit point
+    pub v: f32,
     /// Derivative of the point with respect to the u parameterization coord of the surface
     pub dp_du: Vector,
     /// Derivative of the point with respect to the v parameterization coord of the surface
     pub dp_dv: Vector,
+    /// The hit point in the space of the hit object, 3D textures are evaluated at this
+    /// point so they stay attached to the object as it's moved
+    pub p_obj: Point,
+    /// Derivative of the object space hit point with respect to u
+    pub dp_du_obj: Vector,
+    /// Derivative of the object space hit point with respect to v
+    pub dp_dv_obj: Vector,
     /// The geometry that was hit
     pub geom: &'a (Geometry + 'a),
+    /// Index of the material assigned to the hit geometry in the instance's material
+    /// table, None if the instance's material should be used
+    pub material_id: Option<usize>,
 }
 
 impl<'a> DifferentialGeometry<'a> {
     /// Setup the differential geometry. Note that the normal will be computed
     /// using cross(dp_du, dp_dv)
-    pub fn new(p: &Point, ng: &Normal, dp_du: &Vector, dp_dv: &Vector,
+    pub fn new(p: &Point, ng: &Normal, u: f32, v: f32, dp_du: &Vector, dp_dv: &Vector,
                geom: &'a (Geometry + 'a))
                -> DifferentialGeometry<'a> {
         let n = linalg::cross(dp_du, dp_dv).normalized();
-        DifferentialGeometry { p: *p, n: Normal::new(n.x, n.y, n.z), ng: ng.normalized(),
-                               dp_du: *dp_du, dp_dv: *dp_dv, geom: geom }
+        DifferentialGeometry { p: *p, n: Normal::new(n.x, n.y, n.z), ng: ng.normalized(), u: u, v: v,
+                               dp_du: *dp_du, dp_dv: *dp_dv, p_obj: *p, dp_du_obj: *dp_du, dp_dv_obj: *dp_dv,
+                               geom: geom, material_id: None }
     }
     /// Setup the differential geometry using the normal passed for the surface normal
-    pub fn with_normal(p: &Point, n: &Normal, dp_du: &Vector, dp_dv: &Vector,
+    pub fn with_normal(p: &Point, n: &Normal, u: f32, v: f32, dp_du: &Vector, dp_dv: &Vector,
                geom: &'a (Geometry + 'a))
                -> DifferentialGeometry<'a> {
         let nn = n.normalized();
-        DifferentialGeometry { p: *p, n: nn, ng: nn,
-                               dp_du: *dp_du, dp_dv: *dp_dv, geom: geom }
+        DifferentialGeometry { p: *p, n: nn, ng: nn, u: u, v: v,
+                               dp_du: *dp_du, dp_dv: *dp_dv, p_obj: *p, dp_du_obj: *dp_du, dp_dv_obj: *dp_dv,
+                               geom: geom, material_id: None }
     }
 }
 
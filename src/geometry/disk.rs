@@ -66,9 +66,11 @@ impl Geometry for Disk {
         }
         ray.max_t = t;
         let hit_radius = f32::sqrt(dist_sqr);
+        let u = phi / (f32::consts::PI * 2.0);
+        let v = (self.radius - hit_radius) / (self.radius - self.inner_radius);
         let dp_du = Vector::new(-f32::consts::PI * 2.0 * p.y, f32::consts::PI * 2.0 * p.x, 0.0);
         let dp_dv = ((self.inner_radius - self.radius) / hit_radius) * Vector::new(p.x, p.y, 0.0);
-        Some(DifferentialGeometry::new(&p, &Normal::new(0.0, 0.0, 1.0), &dp_du, &dp_dv, self))
+        Some(DifferentialGeometry::new(&p, &Normal::new(0.0, 0.0, 1.0), u, v, &dp_du, &dp_dv, self))
     }
 }
 
@@ -105,5 +107,13 @@ impl Sampleable for Disk {
             None => 0.0
         }
     }
+    fn sample_parameterization(&self, uv: &(f32, f32)) -> Option<DifferentialGeometry> {
+        let phi = uv.0 * f32::consts::PI * 2.0;
+        let r = self.radius - uv.1 * (self.radius - self.inner_radius);
+        let p = Point::new(r * f32::cos(phi), r * f32::sin(phi), 0.0);
+        let dp_du = Vector::new(-f32::consts::PI * 2.0 * p.y, f32::consts::PI * 2.0 * p.x, 0.0);
+        let dp_dv = (self.inner_radius - self.radius) * Vector::new(f32::cos(phi), f32::sin(phi), 0.0);
+        Some(DifferentialGeometry::new(&p, &Normal::new(0.0, 0.0, 1.0), uv.0, uv.1, &dp_du, &dp_dv, self))
+    }
 }
 
@@ -1,9 +1,9 @@
 //! An emitter is an instance of geometry that both receives and emits light
 //!
 //! # Scene Usage Example
-//! An emitter is an object in the scene that emits light, it can be a point light
-//! or an area light. The emitter takes an extra 'emitter' parameter to specify
-//! whether the instance is an area or point emitter and an 'emission' parameter
+//! An emitter is an object in the scene that emits light, it can be a point light,
+//! spot light, directional light, an area light or an environment light. The emitter takes an extra 'emitter' parameter
+//! to specify which type of emitter the instance is and an 'emission' parameter
 //! to set the color and strength of emitted light.
 //!
 //! ## Point Light Example
@@ -28,6 +28,63 @@
 //! ]
 //! ```
 //!
+//! ## Spot Light Example
+//! The spot light is a point light which only emits light in a cone, it shines along its local
+//! +Z axis and can be aimed with the transform. The `cone_angle` is the angle in degrees from the
+//! center of the cone to its edge and the light falls off smoothly to zero starting at the
+//! `falloff_angle`, which defaults to the cone angle to give a hard edge.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "my_spot_light",
+//!         "type": "emitter",
+//!         "emitter": "spot",
+//!         "emission": [1, 1, 1, 100],
+//!         "cone_angle": 30,
+//!         "falloff_angle": 20,
+//!         "transform": [
+//!             {
+//!                 "type": "rotate_x",
+//!                 "rotation": 90
+//!             },
+//!             {
+//!                 "type": "translate",
+//!                 "translation": [0, 10, 0]
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! ## Directional Light Example
+//! The directional light models a light infinitely far away, such as the sun, which
+//! lights the scene from a single direction. Like the spot light it shines along its local
+//! +Z axis and can be aimed with the transform, its position has no effect.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "sun",
+//!         "type": "emitter",
+//!         "emitter": "directional",
+//!         "emission": [1, 0.95, 0.9, 5],
+//!         "transform": [
+//!             {
+//!                 "type": "rotate_x",
+//!                 "rotation": 60
+//!             },
+//!             {
+//!                 "type": "rotate_y",
+//!                 "rotation": 30
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
 //! ## Area Light Example
 //! The area light looks similar to a regular receiver except it has an additional emission
 //! parameter. Area lights are also restricted somewhat in which geometry they can use as
@@ -56,23 +113,89 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! The emission of an area light can be varied over its surface by setting an `emission_texture`,
+//! which can be the name of a texture in the scene or a color. The color of the texture at the point
+//! on the light's surface is multiplied by the emission. Rectangles, disks and spheres are then
+//! sampled based on the texture so more samples are taken of the bright parts of the light.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "tv_screen",
+//!         "type": "emitter",
+//!         "emitter": "area",
+//!         "emission": [1, 1, 1, 5],
+//!         "emission_texture": "test_card",
+//!         "material": "black_plastic",
+//!         "geometry": {
+//!             "type": "rectangle",
+//!             "width": 1.6,
+//!             "height": 0.9
+//!         },
+//!         "transform": [...]
+//!     },
+//!     ...
+//! ]
+//! ```
+//!
+//! ## Environment Light Example
+//! The environment light surrounds the scene with light from infinitely far away, looked
+//! up from an equirectangular (latitude-longitude) image. Radiance HDR (.hdr) and OpenEXR
+//! (.exr) images can be used along with any other format the image texture can load, relative
+//! paths are resolved relative to the scene file. The top of the image is mapped to +Y and
+//! the center of the image to +X, the transform can be used to rotate the map. The emission
+//! scales the radiance from the image. Rays which leave the scene without hitting anything
+//! will see the environment.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!         "name": "sky",
+//!         "type": "emitter",
+//!         "emitter": "environment",
+//!         "file": "./uffizi.exr",
+//!         "emission": [1, 1, 1, 1],
+//!         "transform": [
+//!             {
+//!                 "type": "rotate_y",
+//!                 "rotation": 90
+//!             }
+//!         ]
+//!     },
+//!     ...
+//! ]
+//! ```
 
+use std::f32;
 use std::sync::Arc;
 
 use geometry::{Boundable, BBox, SampleableGeom, DifferentialGeometry};
 use material::Material;
-use linalg::{self, AnimatedTransform, Point, Ray, Vector, Normal};
+use linalg::{self, AnimatedTransform, Transform, Point, Ray, Vector, Normal};
 use film::{AnimatedColor, Colorf};
-use light::{Light, OcclusionTester};
+use light::{Light, OcclusionTester, EnvironmentMap, EmissionMap};
+use texture::Texture;
+use media::MediumInterface;
+use mc;
 
-/// The type of emitter, either a point light or an area light
-/// in which case the emitter has associated geometry and a material
+/// The type of emitter, either a point, spot or directional light, an area light in which
+/// case the emitter has associated geometry and a material, or a light surrounding the scene
 /// TODO: Am I happy with this design?
 enum EmitterType {
     Point,
-    /// The area light holds the geometry that is emitting the light
-    /// and the material for the geometry
-    Area(Arc<SampleableGeom + Send + Sync>, Arc<Material + Send + Sync>),
+    /// The spot light holds the cosine of the angle of its cone and of the angle
+    /// where the light begins falling off
+    Spot(f32, f32),
+    Directional,
+    /// The area light holds the geometry that is emitting the light, the material
+    /// for the geometry and optionally a texture varying the emission over its surface
+    Area(Arc<SampleableGeom + Send + Sync>, Arc<Material + Send + Sync>, Option<EmissionMap>),
+    /// The environment light holds the image that light arriving from infinitely
+    /// far away is looked up in
+    Environment(Arc<EnvironmentMap>),
+    /// A light surrounding the scene which emits the same radiance from every direction
+    Constant,
 }
 
 /// An instance of geometry in the scene that receives and emits light.
@@ -84,6 +207,10 @@ pub struct Emitter {
     transform: AnimatedTransform,
     /// Tag to identify the instance
     pub tag: String,
+    /// The media inside and outside of an area light's geometry, if it's a boundary between media
+    medium_interface: Option<MediumInterface>,
+    /// Index of the light group the light is in, if any
+    light_group: Option<usize>,
 }
 
 impl Emitter {
@@ -93,16 +220,25 @@ impl Emitter {
     /// good quality
     pub fn area(geom: Arc<SampleableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
                 emission: AnimatedColor, transform: AnimatedTransform, tag: String) -> Emitter {
-        // TODO: How to change this transform to handle scaling within the animation?
-        /*
-        if transform.has_scale() {
-            println!("Warning: scaling detected in area light transform, this may give incorrect results");
-        }
-        */
-        Emitter { emitter: EmitterType::Area(geom, material),
+        Emitter { emitter: EmitterType::Area(geom, material, None),
+                  emission: emission,
+                  transform: transform,
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
+    }
+    /// Create a new area light whose emission is varied over the surface of the geometry
+    /// by multiplying it with `texture`
+    pub fn textured_area(geom: Arc<SampleableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
+                         texture: Arc<Texture + Send + Sync>, emission: AnimatedColor,
+                         transform: AnimatedTransform, tag: String) -> Emitter {
+        let map = EmissionMap::new(texture, &*geom);
+        Emitter { emitter: EmitterType::Area(geom, material, Some(map)),
                   emission: emission,
                   transform: transform,
-                  tag: tag }
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
     }
     /// Create a point light at the origin that is transformed by `transform` to its location
     /// in the world
@@ -110,15 +246,63 @@ impl Emitter {
         Emitter { emitter: EmitterType::Point,
                   emission: emission,
                   transform: transform,
-                  tag: tag }
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
+    }
+    /// Create a spot light at the origin shining along +Z, which is transformed by `transform`
+    /// to its location and direction in the world. The light is emitted in a cone with angle
+    /// `cone_angle` (in degrees) around the spot light's direction and falls off smoothly
+    /// between `falloff_angle` and `cone_angle`
+    pub fn spot(transform: AnimatedTransform, emission: AnimatedColor, cone_angle: f32, falloff_angle: f32,
+                tag: String) -> Emitter {
+        let cos_cone = f32::cos(linalg::to_radians(cone_angle));
+        let cos_falloff = f32::cos(linalg::to_radians(f32::min(falloff_angle, cone_angle)));
+        Emitter { emitter: EmitterType::Spot(cos_cone, cos_falloff),
+                  emission: emission,
+                  transform: transform,
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
+    }
+    /// Create a directional light shining along +Z, which is rotated by `transform` to
+    /// its direction in the world
+    pub fn directional(transform: AnimatedTransform, emission: AnimatedColor, tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Directional,
+                  emission: emission,
+                  transform: transform,
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
+    }
+    /// Create an environment light which surrounds the scene with light from `map`, the map
+    /// is rotated by `transform` and its radiance scaled by `emission`
+    pub fn environment(map: Arc<EnvironmentMap>, transform: AnimatedTransform, emission: AnimatedColor,
+                       tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Environment(map),
+                  emission: emission,
+                  transform: transform,
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
+    }
+    /// Create a light surrounding the scene which emits the radiance `emission` from every direction
+    pub fn constant(emission: AnimatedColor, tag: String) -> Emitter {
+        Emitter { emitter: EmitterType::Constant,
+                  emission: emission,
+                  transform: AnimatedTransform::unanimated(&Transform::identity()),
+                  tag: tag,
+                  medium_interface: None,
+                  light_group: None }
     }
     /// Test the ray for intersection against this insance of geometry.
     /// returns Some(Intersection) if an intersection was found and None if not.
     /// If an intersection is found `ray.max_t` will be set accordingly
     pub fn intersect(&self, ray: &mut Ray) -> Option<(DifferentialGeometry, &Material)> {
         match self.emitter {
-            EmitterType::Point => None,
-            EmitterType::Area(ref geom, ref mat) => {
+            EmitterType::Point | EmitterType::Spot(..) | EmitterType::Directional
+                | EmitterType::Environment(_) | EmitterType::Constant => None,
+            EmitterType::Area(ref geom, ref mat, _) => {
                 let transform = self.transform.transform(ray.time);
                 let mut local = transform.inv_mul_ray(ray);
                 let mut dg = match geom.intersect(&mut local) {
@@ -126,19 +310,33 @@ impl Emitter {
                     None => return None,
                 };
                 ray.max_t = local.max_t;
-                dg.p = transform * dg.p;
-                dg.n = transform * dg.n;
-                dg.ng = transform * dg.ng;
-                dg.dp_du = transform * dg.dp_du;
-                dg.dp_dv = transform * dg.dp_dv;
+                transform_dg(&transform, &mut dg);
                 Some((dg, &**mat))
             },
         }
     }
     /// Return the radiance emitted by the light in the direction `w`
-    /// from point `p` on the light's surface with normal `n`
-    pub fn radiance(&self, w: &Vector, _: &Point, n: &Normal, time: f32) -> Colorf {
-        if linalg::dot(w, n) > 0.0 { self.emission.color(time) } else { Colorf::black() }
+    /// from the point on the light's surface described by `dg`
+    pub fn radiance(&self, w: &Vector, dg: &DifferentialGeometry, time: f32) -> Colorf {
+        if linalg::dot(w, &dg.ng) <= 0.0 {
+            return Colorf::black();
+        }
+        match self.emitter {
+            EmitterType::Area(_, _, Some(ref map)) => {
+                // The emission texture is applied to the light's surface in the light's space
+                let mut dg_l = *dg;
+                inv_transform_dg(&self.transform.transform(time), &mut dg_l);
+                self.emission.color(time) * map.color(&dg_l)
+            },
+            _ => self.emission.color(time),
+        }
+    }
+    /// Check if the light is infinitely far away from the scene, eg. a directional or environment light
+    pub fn is_infinite(&self) -> bool {
+        match self.emitter {
+            EmitterType::Directional | EmitterType::Environment(_) | EmitterType::Constant => true,
+            _ => false,
+        }
     }
     /// Get the transform to place the emitter into world space
     pub fn get_transform(&self) -> &AnimatedTransform {
@@ -148,13 +346,130 @@ impl Emitter {
     pub fn set_transform(&mut self, transform: AnimatedTransform) {
         self.transform = transform;
     }
+    /// Get the media on either side of the emitter's surface, if it's a boundary between media
+    pub fn medium_interface(&self) -> Option<&MediumInterface> {
+        self.medium_interface.as_ref()
+    }
+    /// Set the media on either side of the emitter's surface
+    pub fn set_medium_interface(&mut self, medium_interface: Option<MediumInterface>) {
+        self.medium_interface = medium_interface;
+    }
+    /// Get the index of the light group the light is in, if any
+    pub fn light_group(&self) -> Option<usize> {
+        self.light_group
+    }
+    /// Set the index of the light group the light is in
+    pub fn set_light_group(&mut self, light_group: Option<usize>) {
+        self.light_group = light_group;
+    }
+}
+
+/// Transform the differential geometry of a point on an area light from the light's space to the world
+fn transform_dg(transform: &Transform, dg: &mut DifferentialGeometry) {
+    dg.p = *transform * dg.p;
+    dg.n = *transform * dg.n;
+    dg.ng = *transform * dg.ng;
+    dg.dp_du = *transform * dg.dp_du;
+    dg.dp_dv = *transform * dg.dp_dv;
+}
+
+/// Transform the differential geometry of a point on an area light from the world to the light's space
+fn inv_transform_dg(transform: &Transform, dg: &mut DifferentialGeometry) {
+    dg.p = transform.inv_mul_point(&dg.p);
+    dg.n = transform.inv_mul_normal(&dg.n);
+    dg.ng = transform.inv_mul_normal(&dg.ng);
+    dg.dp_du = transform.inv_mul_vector(&dg.dp_du);
+    dg.dp_dv = transform.inv_mul_vector(&dg.dp_dv);
+}
+
+/// Find the point on the surface of a textured area light's geometry `geom` seen from `p` in
+/// direction `w`, both in the light's space. Returns the point and the pdf with respect to solid
+/// angle of sampling it, or None if the ray misses the light
+fn textured_area_hit<'a>(geom: &'a (SampleableGeom + Send + Sync), map: &EmissionMap, p: &Point, w: &Vector)
+                         -> Option<(DifferentialGeometry<'a>, f32)> {
+    let mut ray = Ray::segment(p, w, 0.001, f32::INFINITY, 0.0);
+    let dg = match geom.intersect(&mut ray) {
+        Some(dg) => dg,
+        None => return None,
+    };
+    let pdf = match map.pdf(&dg) {
+        Some(pdf_area) => {
+            let pdf = pdf_area * p.distance_sqr(&dg.p) / f32::abs(linalg::dot(&dg.ng, w));
+            if f32::is_finite(pdf) { pdf } else { 0.0 }
+        },
+        None => geom.pdf(p, w),
+    };
+    Some((dg, pdf))
+}
+
+/// Convert the solid angle density `pdf` of the direction from `p` to the point `p_light` with
+/// normal `n` on the light's surface, all in the light's space, to the density of the direction
+/// in world space. The solid angle the light's surface covers is only changed if it's scaled
+fn world_solid_angle_pdf(transform: &Transform, pdf: f32, p: &Point, p_light: &Point, n: &Normal) -> f32 {
+    if pdf == 0.0 || !transform.has_scale() {
+        return pdf;
+    }
+    let w_l = *p_light - *p;
+    let w = *transform * w_l;
+    let cos_l = f32::abs(linalg::dot(&w_l.normalized(), &Vector::new(n.x, n.y, n.z).normalized()));
+    let cos_w = f32::abs(linalg::dot(&w.normalized(), &(*transform * *n).normalized()));
+    // Convert to a density over the light's area in its space, then to one over its area in
+    // world space and back to solid angle at the world space distance
+    let pdf = pdf * cos_l * w.length_sqr() / (w_l.length_sqr() * cos_w * transform.area_scale(n));
+    if f32::is_finite(pdf) { pdf } else { 0.0 }
+}
+
+/// Find the differential geometry at the point `p` with normal `n` on the surface of `geom`,
+/// both in the light's space, by intersecting a short ray through the surface along the normal
+fn surface_dg<'a>(geom: &'a (SampleableGeom + Send + Sync), p: &Point, n: &Normal)
+                  -> Option<DifferentialGeometry<'a>> {
+    let d = Vector::new(n.x, n.y, n.z).normalized();
+    let mut ray = Ray::segment(&(*p + d * 0.001), &-d, 0.0, 0.002, 0.0);
+    geom.intersect(&mut ray)
+}
+
+/// Sample a direction in the hemisphere around `n` with a cosine distribution, returns
+/// the direction and its pdf w.r.t. solid angle
+fn cos_sample_around(n: &Normal, samples: &(f32, f32)) -> (Vector, f32) {
+    let w_z = Vector::new(n.x, n.y, n.z).normalized();
+    let (w_x, w_y) = linalg::coordinate_system(&w_z);
+    let d = mc::cos_sample_hemisphere(samples);
+    ((d.x * w_x + d.y * w_y + d.z * w_z).normalized(), mc::cos_hemisphere_pdf(d.z))
+}
+
+/// Sample a ray entering the scene bounding sphere with center `center` and radius `radius`
+/// travelling along `-w`, as emitted from a light infinitely far away in direction `w`. The
+/// origin of the ray is sampled uniformly on a disk covering the scene, returns the ray and
+/// the pdf of its origin w.r.t. area
+fn infinite_light_ray(center: &Point, radius: f32, w: &Vector, samples: &(f32, f32), time: f32) -> (Ray, f32) {
+    let (w_x, w_y) = linalg::coordinate_system(w);
+    let disk = mc::concentric_sample_disk(samples);
+    let o = *center + radius * (disk.0 * w_x + disk.1 * w_y + *w);
+    (Ray::segment(&o, &-*w, 0.001, f32::INFINITY, time), 1.0 / (f32::consts::PI * radius * radius))
+}
+
+/// Compute the falloff of the spot light's emission in a direction at angle `cos_theta`
+/// to the center of its cone
+fn spot_falloff(cos_theta: f32, cos_cone: f32, cos_falloff: f32) -> f32 {
+    if cos_theta < cos_cone {
+        0.0
+    } else if cos_theta >= cos_falloff {
+        1.0
+    } else {
+        let delta = (cos_theta - cos_cone) / (cos_falloff - cos_cone);
+        delta * delta * delta * delta
+    }
 }
 
 impl Boundable for Emitter {
     fn bounds(&self, start: f32, end: f32) -> BBox {
         match self.emitter {
-            EmitterType::Point => self.transform.animation_bounds(&BBox::singular(Point::broadcast(0.0)), start, end),
-            EmitterType::Area(ref g, _) => {
+            // Lights infinitely far away from the scene are just given a point at the origin
+            EmitterType::Point | EmitterType::Spot(..) | EmitterType::Directional
+                | EmitterType::Environment(_) | EmitterType::Constant => {
+                self.transform.animation_bounds(&BBox::singular(Point::broadcast(0.0)), start, end)
+            },
+            EmitterType::Area(ref g, _, _) => {
                 self.transform.animation_bounds(&g.bounds(start, end), start, end)
             },
         }
@@ -172,34 +487,331 @@ impl Light for Emitter {
                 let w_i = (pos - *p).normalized();
                 (self.emission.color(time) / pos.distance_sqr(p), w_i, 1.0, OcclusionTester::test_points(p, &pos, time))
             }
-            EmitterType::Area(ref g, _) => {
+            EmitterType::Spot(cos_cone, cos_falloff) => {
+                let transform = self.transform.transform(time);
+                let pos = transform * Point::broadcast(0.0);
+                let w_i = (pos - *p).normalized();
+                let cos_theta = transform.inv_mul_vector(&-w_i).normalized().z;
+                let falloff = spot_falloff(cos_theta, cos_cone, cos_falloff);
+                (self.emission.color(time) * falloff / pos.distance_sqr(p), w_i, 1.0,
+                 OcclusionTester::test_points(p, &pos, time))
+            },
+            EmitterType::Directional => {
+                let w_i = -(self.transform.transform(time) * Vector::new(0.0, 0.0, 1.0)).normalized();
+                (self.emission.color(time), w_i, 1.0, OcclusionTester::test_ray(p, &w_i, time))
+            },
+            EmitterType::Area(ref g, _, None) => {
                 let transform = self.transform.transform(time);
                 let p_l = transform.inv_mul_point(p);
                 let (p_sampled, normal) = g.sample(&p_l, samples);
                 let w_il = (p_sampled - p_l).normalized();
-                let pdf = g.pdf(&p_l, &w_il);
-                let radiance = self.radiance(&-w_il, &p_sampled, &normal, time);
+                let pdf = world_solid_angle_pdf(&transform, g.pdf(&p_l, &w_il), &p_l, &p_sampled, &normal);
+                let radiance = if linalg::dot(&-w_il, &normal) > 0.0 {
+                    self.emission.color(time)
+                } else {
+                    Colorf::black()
+                };
                 let p_w = transform * p_sampled;
-                (radiance, transform * w_il, pdf, OcclusionTester::test_points(p, &p_w, time))
+                (radiance, (transform * w_il).normalized(), pdf, OcclusionTester::test_points(p, &p_w, time))
+            },
+            EmitterType::Area(ref g, _, Some(ref map)) => {
+                let transform = self.transform.transform(time);
+                let p_l = transform.inv_mul_point(p);
+                let p_sampled = match map.sample(&**g, samples) {
+                    Some(dg) => dg.p,
+                    None => g.sample(&p_l, samples).0,
+                };
+                let w_il = (p_sampled - p_l).normalized();
+                let w_i = (transform * w_il).normalized();
+                let p_w = transform * p_sampled;
+                // Look up the emission at the point seen in the sampled direction. If the light's
+                // surface is in front of the sampled point the occlusion test will fail, so we only
+                // get radiance when this is the point we sampled
+                match textured_area_hit(&**g, map, &p_l, &w_il) {
+                    Some((dg, pdf)) => {
+                        let radiance = if linalg::dot(&-w_il, &dg.ng) > 0.0 {
+                            self.emission.color(time) * map.color(&dg)
+                        } else {
+                            Colorf::black()
+                        };
+                        let pdf = world_solid_angle_pdf(&transform, pdf, &p_l, &dg.p, &dg.ng);
+                        (radiance, w_i, pdf, OcclusionTester::test_points(p, &p_w, time))
+                    },
+                    None => (Colorf::black(), w_i, 0.0, OcclusionTester::test_points(p, &p_w, time)),
+                }
+            },
+            EmitterType::Environment(ref map) => {
+                let (w_l, pdf) = map.sample(samples);
+                let w_i = (self.transform.transform(time) * w_l).normalized();
+                let radiance = self.emission.color(time) * map.radiance(&w_l);
+                (radiance, w_i, pdf, OcclusionTester::test_ray(p, &w_i, time))
+            },
+            EmitterType::Constant => {
+                let w_i = mc::uniform_sample_sphere(samples);
+                (self.emission.color(time), w_i, mc::uniform_sphere_pdf(),
+                 OcclusionTester::test_ray(p, &w_i, time))
             },
         }
     }
     fn delta_light(&self) -> bool {
         match self.emitter {
-            EmitterType::Point => true,
+            EmitterType::Point | EmitterType::Spot(..) | EmitterType::Directional => true,
             _ => false,
         }
     }
     fn pdf(&self, p: &Point, w_i: &Vector, time: f32) -> f32 {
         match self.emitter {
-            EmitterType::Point => 0.0,
-            EmitterType::Area(ref g, _ ) => {
+            EmitterType::Point | EmitterType::Spot(..) | EmitterType::Directional => 0.0,
+            EmitterType::Area(ref g, _, ref map) => {
                 let transform = self.transform.transform(time);
                 let p_l = transform.inv_mul_point(p);
                 let w = (transform.inv_mul_vector(w_i)).normalized();
-                g.pdf(&p_l, &w)
+                match *map {
+                    Some(ref m) => textured_area_hit(&**g, m, &p_l, &w).map_or(0.0, |(dg, pdf)| {
+                        world_solid_angle_pdf(&transform, pdf, &p_l, &dg.p, &dg.ng)
+                    }),
+                    None => {
+                        let pdf = g.pdf(&p_l, &w);
+                        // The point hit is only needed to convert the density if the light is scaled
+                        if pdf == 0.0 || !transform.has_scale() {
+                            return pdf;
+                        }
+                        let mut ray = Ray::segment(&p_l, &w, 0.001, f32::INFINITY, 0.0);
+                        g.intersect(&mut ray).map_or(0.0, |dg| {
+                            world_solid_angle_pdf(&transform, pdf, &p_l, &dg.p, &dg.ng)
+                        })
+                    },
+                }
+            },
+            EmitterType::Environment(ref map) => {
+                let w = self.transform.transform(time).inv_mul_vector(w_i).normalized();
+                map.pdf(&w)
+            },
+            EmitterType::Constant => mc::uniform_sphere_pdf(),
+        }
+    }
+    fn power(&self, scene_radius: f32, time: f32) -> Colorf {
+        let emission = self.emission.color(time);
+        match self.emitter {
+            EmitterType::Point => 4.0 * f32::consts::PI * emission,
+            EmitterType::Spot(cos_cone, cos_falloff) => {
+                2.0 * f32::consts::PI * emission * (1.0 - 0.5 * (cos_cone + cos_falloff))
+            },
+            EmitterType::Directional | EmitterType::Constant => {
+                f32::consts::PI * scene_radius * scene_radius * emission
+            },
+            EmitterType::Area(ref g, _, ref map) => {
+                let emission = match *map {
+                    Some(ref m) => emission * m.average(),
+                    None => emission,
+                };
+                f32::consts::PI * g.transformed_surface_area(&self.transform.transform(time)) * emission
+            },
+            EmitterType::Environment(ref map) => {
+                f32::consts::PI * scene_radius * scene_radius * emission * map.average_radiance()
+            },
+        }
+    }
+    fn sample_emission(&self, scene_center: &Point, scene_radius: f32, pos_samples: &(f32, f32),
+                       dir_samples: &(f32, f32), time: f32) -> (Colorf, Ray, Normal, f32, f32) {
+        let transform = self.transform.transform(time);
+        let emission = self.emission.color(time);
+        match self.emitter {
+            EmitterType::Point => {
+                let pos = transform * Point::broadcast(0.0);
+                let d = mc::uniform_sample_sphere(dir_samples);
+                (emission, Ray::segment(&pos, &d, 0.001, f32::INFINITY, time), Normal::new(d.x, d.y, d.z),
+                 1.0, mc::uniform_sphere_pdf())
+            },
+            EmitterType::Spot(cos_cone, cos_falloff) => {
+                let pos = transform * Point::broadcast(0.0);
+                let d_l = mc::uniform_sample_cone(dir_samples, cos_cone);
+                let d = (transform * d_l).normalized();
+                (emission * spot_falloff(d_l.z, cos_cone, cos_falloff), Ray::segment(&pos, &d, 0.001, f32::INFINITY, time),
+                 Normal::new(d.x, d.y, d.z), 1.0, mc::uniform_cone_pdf(cos_cone))
+            },
+            EmitterType::Directional => {
+                let w = -(transform * Vector::new(0.0, 0.0, 1.0)).normalized();
+                let (ray, pdf_pos) = infinite_light_ray(scene_center, scene_radius, &w, pos_samples, time);
+                (emission, ray, Normal::new(-w.x, -w.y, -w.z), pdf_pos, 1.0)
+            },
+            EmitterType::Area(ref g, _, None) => {
+                let (p, n) = g.sample_uniform(pos_samples);
+                // The direction is sampled around the world space normal so it's cosine distributed
+                // about the normal even if the light is scaled non-uniformly
+                let n_w = (transform * n).normalized();
+                let (d, pdf_dir) = cos_sample_around(&n_w, dir_samples);
+                let ray = Ray::segment(&(transform * p), &d, 0.001, f32::INFINITY, time);
+                // The point is sampled uniformly over the object space surface, the density is
+                // converted to be over the transformed surface the ray leaves from
+                let pdf_pos = 1.0 / (g.surface_area() * transform.area_scale(&n));
+                (emission, ray, n_w, pdf_pos, pdf_dir)
+            },
+            EmitterType::Area(ref g, _, Some(ref map)) => {
+                // Find the point on the surface through the emission map if possible so we can also
+                // look up the texture at the point, otherwise find it from a uniformly sampled point
+                let dg = match map.sample(&**g, pos_samples) {
+                    Some(dg) => Some(dg),
+                    None => {
+                        let (p, n) = g.sample_uniform(pos_samples);
+                        surface_dg(&**g, &p, &n)
+                    },
+                };
+                match dg {
+                    Some(dg) => {
+                        let pdf_pos = map.pdf(&dg).unwrap_or(1.0 / g.surface_area())
+                            / transform.area_scale(&dg.ng);
+                        let n_w = (transform * dg.ng).normalized();
+                        let (d, pdf_dir) = cos_sample_around(&n_w, dir_samples);
+                        let ray = Ray::segment(&(transform * dg.p), &d, 0.001, f32::INFINITY, time);
+                        (emission * map.color(&dg), ray, n_w, pdf_pos, pdf_dir)
+                    },
+                    None => {
+                        let ray = Ray::segment(&Point::broadcast(0.0), &Vector::new(0.0, 0.0, 1.0), 0.001,
+                                               f32::INFINITY, time);
+                        (Colorf::black(), ray, Normal::new(0.0, 0.0, 1.0), 0.0, 0.0)
+                    },
+                }
+            },
+            EmitterType::Environment(ref map) => {
+                let (w_l, pdf_dir) = map.sample(dir_samples);
+                let w = (transform * w_l).normalized();
+                let (ray, pdf_pos) = infinite_light_ray(scene_center, scene_radius, &w, pos_samples, time);
+                (emission * map.radiance(&w_l), ray, Normal::new(-w.x, -w.y, -w.z), pdf_pos, pdf_dir)
+            },
+            EmitterType::Constant => {
+                let w = mc::uniform_sample_sphere(dir_samples);
+                let (ray, pdf_pos) = infinite_light_ray(scene_center, scene_radius, &w, pos_samples, time);
+                (emission, ray, Normal::new(-w.x, -w.y, -w.z), pdf_pos, mc::uniform_sphere_pdf())
+            },
+        }
+    }
+    fn pdf_emission(&self, ray: &Ray, n: &Normal, scene_radius: f32) -> (f32, f32) {
+        let transform = self.transform.transform(ray.time);
+        match self.emitter {
+            // The position of point and spot lights is a delta distribution so it can't be
+            // chosen by anything besides sampling the light
+            EmitterType::Point => (0.0, mc::uniform_sphere_pdf()),
+            EmitterType::Spot(cos_cone, _) => {
+                let d = transform.inv_mul_vector(&ray.d).normalized();
+                (0.0, if d.z >= cos_cone { mc::uniform_cone_pdf(cos_cone) } else { 0.0 })
+            },
+            EmitterType::Directional => (1.0 / (f32::consts::PI * scene_radius * scene_radius), 0.0),
+            EmitterType::Area(ref g, _, ref map) => {
+                let cos_theta = linalg::dot(&ray.d.normalized(), &n.normalized());
+                let pdf_dir = if cos_theta > 0.0 { mc::cos_hemisphere_pdf(cos_theta) } else { 0.0 };
+                let n_l = transform.inv_mul_normal(n);
+                let pdf_pos = match *map {
+                    Some(ref m) => {
+                        let p = transform.inv_mul_point(&ray.o);
+                        surface_dg(&**g, &p, &n_l).and_then(|dg| m.pdf(&dg)).unwrap_or(1.0 / g.surface_area())
+                    },
+                    None => 1.0 / g.surface_area(),
+                };
+                // Convert the object space density to be over the transformed surface
+                (pdf_pos / transform.area_scale(&n_l), pdf_dir)
+            },
+            EmitterType::Environment(ref map) => {
+                let w = transform.inv_mul_vector(&-ray.d).normalized();
+                (1.0 / (f32::consts::PI * scene_radius * scene_radius), map.pdf(&w))
+            },
+            EmitterType::Constant => {
+                (1.0 / (f32::consts::PI * scene_radius * scene_radius), mc::uniform_sphere_pdf())
+            },
+        }
+    }
+    fn escaped_radiance(&self, ray: &Ray) -> Colorf {
+        match self.emitter {
+            EmitterType::Environment(ref map) => {
+                let w = self.transform.transform(ray.time).inv_mul_vector(&ray.d).normalized();
+                self.emission.color(ray.time) * map.radiance(&w)
+            },
+            EmitterType::Constant => self.emission.color(ray.time),
+            _ => Colorf::black(),
+        }
+    }
+}
+
+
+#[test]
+fn test_textured_area_pdf() {
+    use geometry::Rectangle;
+    use material::Matte;
+    use film::ColorKeyframe;
+    use texture;
+
+    let bright = Arc::new(texture::ConstantColor::new(&Colorf::broadcast(1.0)));
+    let dark = Arc::new(texture::ConstantColor::new(&Colorf::broadcast(0.1)));
+    // A 3D texture so the emission depends on where the texture is evaluated
+    let tex = Arc::new(texture::Checkerboard3D::new(bright.clone(), dark, 2.0));
+    let mat = Arc::new(Matte::new(bright, Arc::new(texture::ConstantScalar::new(0.0))));
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(1.0), 0.0)]);
+    let transform = Transform::translate(&Vector::new(3.0, -1.0, 2.0)) * Transform::scale(&Vector::broadcast(2.0));
+    let light = Emitter::textured_area(Arc::new(Rectangle::new(2.0, 1.0)), mat, tex.clone(), emission,
+                                       AnimatedTransform::unanimated(&transform), "light".to_owned());
+    let p = Point::new(3.5, -0.5, 5.0);
+    let n = 16;
+    let mut lit = 0;
+    for i in 0..n {
+        for j in 0..n {
+            let samples = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+            let (li, w_i, pdf, _) = light.sample_incident(&p, &samples, 0.0);
+            assert!(pdf > 0.0);
+            assert!(f32::abs(pdf - light.pdf(&p, &w_i, 0.0)) < 1e-3 * pdf);
+            // The radiance along the sampled direction should match the radiance seen by
+            // a ray hitting the light, with the texture applied in the light's space
+            let mut ray = Ray::segment(&p, &w_i, 0.001, f32::INFINITY, 0.0);
+            let (dg, _) = light.intersect(&mut ray).unwrap();
+            let l = light.radiance(&-w_i, &dg, 0.0);
+            let mut dg_l = dg;
+            inv_transform_dg(&transform, &mut dg_l);
+            assert!(f32::abs(li.r - l.r) < 1e-4);
+            assert!(f32::abs(l.r - tex.sample_color(&dg_l).r) < 1e-4);
+            if !li.is_black() {
+                lit += 1;
             }
         }
     }
+    assert_eq!(lit, n * n);
 }
 
+#[test]
+fn test_scaled_area_emission_pdf() {
+    use geometry::Rectangle;
+    use material::Matte;
+    use film::ColorKeyframe;
+    use texture;
+
+    let white = Arc::new(texture::ConstantColor::new(&Colorf::broadcast(1.0)));
+    let mat = Arc::new(Matte::new(white, Arc::new(texture::ConstantScalar::new(0.0))));
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(1.0), 0.0)]);
+    // The 2x1 rectangle is 4x3 after scaling, so points on it are sampled with a density of 1/12
+    let transform = Transform::rotate_x(30.0) * Transform::scale(&Vector::new(2.0, 3.0, 1.0));
+    let light = Emitter::area(Arc::new(Rectangle::new(2.0, 1.0)), mat, emission,
+                              AnimatedTransform::unanimated(&transform), "light".to_owned());
+    // The light is selected by its power, which must also use the scaled area
+    assert!(f32::abs(light.power(10.0, 0.0).r - f32::consts::PI * 12.0) < 1e-3);
+    let n = 8;
+    for i in 0..n {
+        for j in 0..n {
+            let samples = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+            let (_, ray, normal, pdf_pos, pdf_dir) = light.sample_emission(&Point::broadcast(0.0), 10.0, &samples,
+                                                                           &samples, 0.0);
+            assert!(f32::abs(pdf_pos - 1.0 / 12.0) < 1e-5);
+            let (pdf_pos_eval, pdf_dir_eval) = light.pdf_emission(&ray, &normal, 10.0);
+            assert!(f32::abs(pdf_pos - pdf_pos_eval) < 1e-5);
+            assert!(f32::abs(pdf_dir - pdf_dir_eval) < 1e-3 * pdf_dir);
+
+            // Points are sampled uniformly over the scaled rectangle when lighting a point,
+            // so the solid angle density should match the uniform density over its world area
+            let p = Point::new(0.5, -2.0, 3.0);
+            let (_, w_i, pdf, _) = light.sample_incident(&p, &samples, 0.0);
+            assert!(f32::abs(w_i.length() - 1.0) < 1e-4);
+            let mut ray = Ray::segment(&p, &w_i, 0.001, f32::INFINITY, 0.0);
+            let (dg, _) = light.intersect(&mut ray).unwrap();
+            let expected = p.distance_sqr(&dg.p) / (f32::abs(linalg::dot(&w_i, &dg.ng.normalized())) * 12.0);
+            assert!(f32::abs(pdf - expected) < 1e-3 * expected);
+            assert!(f32::abs(pdf - light.pdf(&p, &w_i, 0.0)) < 1e-3 * pdf);
+        }
+    }
+}
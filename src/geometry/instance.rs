@@ -67,6 +67,9 @@ use geometry::{Intersection, Boundable, BBox, BoundableGeom, Receiver, Emitter,
 use material::Material;
 use linalg::{Ray, AnimatedTransform};
 use film::AnimatedColor;
+use light::EnvironmentMap;
+use texture::Texture;
+use media::MediumInterface;
 
 /// Defines an instance of some geometry with its own transform and material
 pub enum Instance {
@@ -80,16 +83,50 @@ impl Instance {
                transform: AnimatedTransform, tag: String) -> Instance {
         Instance::Receiver(Receiver::new(geom, material, transform, tag))
     }
+    /// Create an instance of the geometry in the scene that will only receive light and
+    /// can select its materials from the table of `materials`, eg. a mesh with multiple materials.
+    /// Geometry that doesn't select a material will use `material`
+    pub fn receiver_with_materials(geom: Arc<BoundableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
+                                   materials: Vec<Arc<Material + Send + Sync>>, transform: AnimatedTransform,
+                                   tag: String) -> Instance {
+        Instance::Receiver(Receiver::with_materials(geom, material, materials, transform, tag))
+    }
     /// Create an instance of the geometry in the scene that will emit and receive light
     pub fn area_light(geom: Arc<SampleableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
                emission: AnimatedColor, transform: AnimatedTransform, tag: String) -> Instance {
         Instance::Emitter(Emitter::area(geom, material, emission, transform, tag))
     }
+    /// Create an instance of the geometry in the scene that will emit and receive light, with
+    /// its emission varied over the surface by `texture`
+    pub fn textured_area_light(geom: Arc<SampleableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
+                               texture: Arc<Texture + Send + Sync>, emission: AnimatedColor,
+                               transform: AnimatedTransform, tag: String) -> Instance {
+        Instance::Emitter(Emitter::textured_area(geom, material, texture, emission, transform, tag))
+    }
     /// Create a point light at the origin that is transformed by `transform` to its location
     /// in the world
     pub fn point_light(transform: AnimatedTransform, emission: AnimatedColor, tag: String) ->  Instance {
         Instance::Emitter(Emitter::point(transform, emission, tag))
     }
+    /// Create a spot light at the origin shining along +Z that is transformed by `transform` to its
+    /// location and direction in the world
+    pub fn spot_light(transform: AnimatedTransform, emission: AnimatedColor, cone_angle: f32, falloff_angle: f32,
+                      tag: String) -> Instance {
+        Instance::Emitter(Emitter::spot(transform, emission, cone_angle, falloff_angle, tag))
+    }
+    /// Create a directional light shining along +Z that is rotated by `transform` to its direction in the world
+    pub fn directional_light(transform: AnimatedTransform, emission: AnimatedColor, tag: String) -> Instance {
+        Instance::Emitter(Emitter::directional(transform, emission, tag))
+    }
+    /// Create an environment light surrounding the scene with light from `map`, rotated by `transform`
+    pub fn environment_light(map: Arc<EnvironmentMap>, transform: AnimatedTransform, emission: AnimatedColor,
+                             tag: String) -> Instance {
+        Instance::Emitter(Emitter::environment(map, transform, emission, tag))
+    }
+    /// Create a light surrounding the scene which emits `emission` from every direction
+    pub fn constant_light(emission: AnimatedColor, tag: String) -> Instance {
+        Instance::Emitter(Emitter::constant(emission, tag))
+    }
     /// Test the ray for intersection against this insance of geometry.
     /// returns Some(Intersection) if an intersection was found and None if not.
     /// If an intersection is found `ray.max_t` will be set accordingly
@@ -124,6 +161,21 @@ impl Instance {
             Instance::Receiver(ref mut r) => r.set_transform(transform)
         }
     }
+    /// Get the media on either side of this instance's surface, None if the instance
+    /// isn't a boundary between media
+    pub fn medium_interface(&self) -> Option<&MediumInterface> {
+        match *self {
+            Instance::Emitter(ref e) => e.medium_interface(),
+            Instance::Receiver(ref r) => r.medium_interface(),
+        }
+    }
+    /// Set the media on either side of this instance's surface
+    pub fn set_medium_interface(&mut self, medium_interface: Option<MediumInterface>) {
+        match *self {
+            Instance::Emitter(ref mut e) => e.set_medium_interface(medium_interface),
+            Instance::Receiver(ref mut r) => r.set_medium_interface(medium_interface),
+        }
+    }
 }
 
 impl Boundable for Instance {
@@ -6,7 +6,10 @@
 //! model within the file to use. The file and other loaded models are kept loaded
 //! so you can easily use the same or other models in the file as well. If no name is
 //! assigned to the model in the file it will be given the name "`unnamed_model`",
-//! however it's recommended to name your models.
+//! however it's recommended to name your models. If the model name is omitted all the
+//! models in the file are combined into a single mesh. Smooth normals are computed for
+//! models without normals and models without texture coordinates use a default
+//! parameterization of each triangle.
 //!
 //! ```json
 //! "geometry": {
@@ -15,63 +18,152 @@
 //!     "model": "Suzanne"
 //! }
 //! ```
+//!
+//! Instead of specifying a material for a receiver using a mesh you can set `use_mtl`
+//! to use the materials assigned to the models in the OBJ's MTL file. Each triangle
+//! keeps the material of the model it came from, so a file with many materials can be
+//! used as a single object by omitting the model name. Diffuse colors and
+//! textures (Kd, map_Kd) are mapped to a matte material, or a plastic material if the
+//! material also has a specular color or texture (Ks, map_Ks), with the roughness
+//! computed from the specular exponent (Ns). Transparent materials (d < 1) are mapped to
+//! glass with the index of refraction (Ni). Texture paths are relative to the OBJ file.
+//! If a material is also given it's used for models that don't have an MTL material,
+//! otherwise they're given a white matte material.
+//!
+//! ```json
+//! {
+//!     "name": "sponza",
+//!     "type": "receiver",
+//!     "use_mtl": true,
+//!     "geometry": {
+//!         "type": "mesh",
+//!         "file": "./sponza.obj"
+//!     },
+//!     "transform": [...]
+//! }
+//! ```
+//!
+//! Meshes can also be used as the geometry of area lights, in which case points are
+//! sampled uniformly over the surface of the mesh by choosing triangles based on their area.
+//!
+//! ```json
+//! {
+//!     "name": "neon_sign",
+//!     "type": "emitter",
+//!     "emitter": "area",
+//!     "emission": [1.0, 0.2, 0.6, 10],
+//!     "material": "white_matte",
+//!     "geometry": {
+//!         "type": "mesh",
+//!         "file": "./sign.obj",
+//!         "model": "Letters"
+//!     },
+//!     "transform": [...]
+//! }
+//! ```
 
-extern crate tobj;
-
+use std::f32;
 use std::sync::Arc;
 use std::path::Path;
 use std::collections::HashMap;
 
-use geometry::{Geometry, DifferentialGeometry, Boundable, BBox, BVH};
-use linalg::{self, Normal, Vector, Ray, Point};
+use tobj;
+
+use geometry::{Geometry, DifferentialGeometry, Boundable, BBox, BVH, Sampleable};
+use linalg::{self, Normal, Vector, Ray, Point, Transform};
+use mc::Distribution1D;
 
 /// A mesh composed of triangles, specified by directly passing the position,
-/// normal and index buffers for the triangles making up the mesh
+/// normal and index buffers for the triangles making up the mesh. The normal and
+/// texture coordinate buffers may be empty, in which case the triangles will use their
+/// geometric normal and a default parameterization respectively.
 pub struct Mesh {
     bvh: BVH<Triangle>,
+    /// Distribution for choosing triangles in the BVH's geometry based on their area
+    area_distribution: Distribution1D,
+    surface_area: f32,
 }
 
 impl Mesh {
     /// Create a new Mesh from the triangles described in the buffers passed
     /// This data could come from an OBJ file via [tobj](https://github.com/Twinklebear/tobj)
-    /// for example.
+    /// for example. Each triangle is assigned the material id at the same index in
+    /// `material_ids`, which selects its material from the instance's material table.
     pub fn new(positions: Arc<Vec<Point>>, normals: Arc<Vec<Normal>>, texcoords: Arc<Vec<Point>>,
-               indices: Vec<u32>) -> Mesh {
-        let triangles = indices.chunks(3).map(|i| {
-            Triangle::new(i[0] as usize, i[1] as usize, i[2] as usize, positions.clone(),
+               indices: Vec<u32>, material_ids: Vec<Option<usize>>) -> Mesh {
+        assert_eq!(indices.len() / 3, material_ids.len());
+        let triangles = indices.chunks(3).zip(material_ids.into_iter()).map(|(i, m)| {
+            Triangle::new(i[0] as usize, i[1] as usize, i[2] as usize, m, positions.clone(),
                           normals.clone(), texcoords.clone())
             }).collect();
-        Mesh { bvh: BVH::unanimated(16, triangles) }
+        Mesh::from_triangles(triangles)
+    }
+    /// Create a single mesh containing the triangles of all the meshes passed. The
+    /// triangles keep the material ids they were assigned in their original mesh.
+    pub fn merge(meshes: &[Arc<Mesh>]) -> Mesh {
+        let triangles = meshes.iter().flat_map(|m| m.bvh.iter().cloned()).collect();
+        Mesh::from_triangles(triangles)
+    }
+    /// Build the mesh's BVH and area distribution for the triangles passed
+    fn from_triangles(triangles: Vec<Triangle>) -> Mesh {
+        let bvh = BVH::unanimated(16, triangles);
+        let areas: Vec<_> = bvh.iter().map(|t| t.surface_area()).collect();
+        let surface_area = areas.iter().fold(0.0, |s, a| s + a);
+        Mesh { bvh: bvh, area_distribution: Distribution1D::new(&areas[..]), surface_area: surface_area }
+    }
+    /// Compute smooth per-vertex normals for the triangles described by the position and
+    /// index buffers by averaging the normals of the triangles sharing each vertex, weighted
+    /// by the triangle's area
+    pub fn compute_normals(positions: &[Point], indices: &[u32]) -> Vec<Normal> {
+        let mut normals = vec![Normal::broadcast(0.0); positions.len()];
+        for i in indices.chunks(3) {
+            let (a, b, c) = (i[0] as usize, i[1] as usize, i[2] as usize);
+            let n = linalg::cross(&(positions[b] - positions[a]), &(positions[c] - positions[a]));
+            let n = Normal::new(n.x, n.y, n.z);
+            normals[a] = normals[a] + n;
+            normals[b] = normals[b] + n;
+            normals[c] = normals[c] + n;
+        }
+        for n in &mut normals {
+            if n.length_sqr() > 0.0 {
+                *n = n.normalized();
+            }
+        }
+        normals
     }
     /// Load all the meshes defined in an OBJ file and return them in a hashmap that maps the
-    /// model's name in the file to its loaded mesh
-    /// TODO: Currently materials are ignored
-    pub fn load_obj(file_name: &Path) -> HashMap<String, Arc<Mesh>> {
+    /// model's name in the file to its loaded mesh, along with the materials loaded from the
+    /// file's MTL files. The triangles of each mesh are assigned the index of their model's
+    /// material in the returned materials as their material id.
+    pub fn load_obj(file_name: &Path) -> (HashMap<String, Arc<Mesh>>, Vec<tobj::Material>) {
         match tobj::load_obj(file_name) {
-            Ok((models, _)) => {
+            Ok((models, materials)) => {
                 let mut meshes = HashMap::new();
                 for m in models {
                     println!("Loading model {}", m.name);
                     let mesh = m.mesh;
-                    if mesh.normals.is_empty() || mesh.texcoords.is_empty() {
-                        print!("Mesh::load_obj error! Normals and texture coordinates are required!");
-                        println!("Skipping {}", m.name);
-                        continue;
-                    }
                     println!("{} has {} triangles", m.name, mesh.indices.len() / 3);
-                    let positions = Arc::new(mesh.positions.chunks(3).map(|i| Point::new(i[0], i[1], i[2]))
-                                             .collect());
-                    let normals = Arc::new(mesh.normals.chunks(3).map(|i| Normal::new(i[0], i[1], i[2]))
-                                           .collect());
+                    let positions: Vec<_> = mesh.positions.chunks(3).map(|i| Point::new(i[0], i[1], i[2]))
+                                                .collect();
+                    let normals =
+                        if mesh.normals.is_empty() {
+                            println!("{} has no normals, computing smooth normals", m.name);
+                            Arc::new(Mesh::compute_normals(&positions[..], &mesh.indices[..]))
+                        } else {
+                            Arc::new(mesh.normals.chunks(3).map(|i| Normal::new(i[0], i[1], i[2])).collect())
+                        };
+                    let positions = Arc::new(positions);
                     let texcoords = Arc::new(mesh.texcoords.chunks(2).map(|i| Point::new(i[0], i[1], 0.0))
                                              .collect());
-                    meshes.insert(m.name, Arc::new(Mesh::new(positions, normals, texcoords, mesh.indices)));
+                    let material_ids = vec![mesh.material_id; mesh.indices.len() / 3];
+                    meshes.insert(m.name, Arc::new(Mesh::new(positions, normals, texcoords, mesh.indices,
+                                                             material_ids)));
                 }
-                meshes
+                (meshes, materials)
             },
             Err(e) => {
                 println!("Failed to load {:?} due to {:?}", file_name, e);
-                HashMap::new()
+                (HashMap::new(), Vec::new())
             },
         }
     }
@@ -89,12 +181,51 @@ impl Boundable for Mesh {
     }
 }
 
+impl Sampleable for Mesh {
+    /// Choose a triangle based on its area and uniformly sample a point on it,
+    /// which gives a uniform distribution of points over the surface of the mesh
+    fn sample_uniform(&self, samples: &(f32, f32)) -> (Point, Normal) {
+        let (x, _, i) = self.area_distribution.sample_continuous(samples.0);
+        // Re-use the sample used to pick the triangle for sampling a point on it
+        let u = f32::min(x * self.area_distribution.count() as f32 - i as f32, 0.99999994);
+        self.bvh.get(i).sample_uniform(&(u, samples.1))
+    }
+    fn sample(&self, _: &Point, samples: &(f32, f32)) -> (Point, Normal) {
+        self.sample_uniform(samples)
+    }
+    fn surface_area(&self) -> f32 {
+        self.surface_area
+    }
+    fn transformed_surface_area(&self, transform: &Transform) -> f32 {
+        self.bvh.iter().fold(0.0, |s, t| s + t.transformed_surface_area(transform))
+    }
+    /// Compute the PDF that the ray from `p` with direction `w_i` intersects the mesh.
+    /// Since points are sampled uniformly by area this is the same as for the
+    /// other shapes, converting the area density to solid angle at the hit point
+    fn pdf(&self, p: &Point, w_i: &Vector) -> f32 {
+        let mut ray = Ray::segment(p, w_i, 0.001, f32::INFINITY, 0.0);
+        match self.intersect(&mut ray) {
+            Some(d) => {
+                // The triangle's geometric normal is perpendicular to its derivatives, the
+                // normal in the differential geometry is the interpolated shading normal
+                let ng = linalg::cross(&d.dp_du, &d.dp_dv).normalized();
+                let pdf = p.distance_sqr(&ray.at(ray.max_t))
+                    / (f32::abs(linalg::dot(&ng, w_i)) * self.surface_area);
+                if f32::is_finite(pdf) { pdf } else { 0.0 }
+            },
+            None => 0.0
+        }
+    }
+}
+
 /// A triangle in some mesh. Just stores a reference to the mesh
 /// and the indices of each vertex
+#[derive(Clone)]
 pub struct Triangle {
     a: usize,
     b: usize,
     c: usize,
+    material_id: Option<usize>,
     positions: Arc<Vec<Point>>,
     normals: Arc<Vec<Normal>>,
     texcoords: Arc<Vec<Point>>,
@@ -102,9 +233,9 @@ pub struct Triangle {
 
 impl Triangle {
     /// Create a new triangle representing a triangle within the mesh passed
-    pub fn new(a: usize, b: usize, c: usize, positions: Arc<Vec<Point>>,
+    pub fn new(a: usize, b: usize, c: usize, material_id: Option<usize>, positions: Arc<Vec<Point>>,
                normals: Arc<Vec<Normal>>, texcoords: Arc<Vec<Point>>) -> Triangle {
-        Triangle { a: a, b: b, c: c, positions: positions, normals: normals,
+        Triangle { a: a, b: b, c: c, material_id: material_id, positions: positions, normals: normals,
                    texcoords: texcoords }
     }
 }
@@ -148,17 +279,30 @@ impl Geometry for Triangle {
         ray.max_t = t;
         let p = ray.at(t);
 
-        // Now compute normal at this location on the triangle
-        let na = &self.normals[self.a];
-        let nb = &self.normals[self.b];
-        let nc = &self.normals[self.c];
-        let n = (bary[0] * *na + bary[1] * *nb + bary[2] * *nc).normalized();
+        // Now compute normal at this location on the triangle, if the mesh doesn't
+        // have shading normals we use the geometric normal
+        let n =
+            if self.normals.is_empty() {
+                let n = linalg::cross(&e[0], &e[1]).normalized();
+                Normal::new(n.x, n.y, n.z)
+            } else {
+                let na = &self.normals[self.a];
+                let nb = &self.normals[self.b];
+                let nc = &self.normals[self.c];
+                (bary[0] * *na + bary[1] * *nb + bary[2] * *nc).normalized()
+            };
 
         // Compute parameterization of surface and various derivatives for texturing
-        // Triangles are parameterized by the obj texcoords at the vertices
-        let ta = &self.texcoords[self.a];
-        let tb = &self.texcoords[self.b];
-        let tc = &self.texcoords[self.c];
+        // Triangles are parameterized by the obj texcoords at the vertices, or if the
+        // mesh doesn't have texcoords by the default parameterization (0, 0), (1, 0), (1, 1)
+        let (ta, tb, tc) =
+            if self.texcoords.is_empty() {
+                (Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0), Point::new(1.0, 1.0, 0.0))
+            } else {
+                (self.texcoords[self.a], self.texcoords[self.b], self.texcoords[self.c])
+            };
+        let u = bary[0] * ta.x + bary[1] * tb.x + bary[2] * tc.x;
+        let v = bary[0] * ta.y + bary[1] * tb.y + bary[2] * tc.y;
         // Triangle points can be found by p_i = p_0 + u_i dp/du + v_i dp/dv
         // we use this property to find the derivatives dp/du and dp/dv
         let du = [ta.x - tc.x, tb.x - tc.x];
@@ -176,7 +320,61 @@ impl Geometry for Triangle {
                 let dp_dv = (-du[1] * dp[0] + du[0] * dp[1]) * det;
                 (dp_du, dp_dv)
             };
-        Some(DifferentialGeometry::with_normal(&p, &n, &dp_du, &dp_dv, self))
+        let mut dg = DifferentialGeometry::with_normal(&p, &n, u, v, &dp_du, &dp_dv, self);
+        dg.material_id = self.material_id;
+        Some(dg)
+    }
+}
+
+impl Sampleable for Triangle {
+    /// Uniformly sample a point on the triangle by warping the samples to
+    /// barycentric coordinates
+    fn sample_uniform(&self, samples: &(f32, f32)) -> (Point, Normal) {
+        let pa = &self.positions[self.a];
+        let pb = &self.positions[self.b];
+        let pc = &self.positions[self.c];
+        let su = f32::sqrt(samples.0);
+        let bary = [1.0 - su, samples.1 * su, (1.0 - samples.1) * su];
+        let p = *pa + (*pb - *pa) * bary[1] + (*pc - *pa) * bary[2];
+        // Return the geometric normal since the sampled density is per unit of the triangle's
+        // area, it's flipped to the side of the shading normal so it faces the same way as
+        // the normal intersecting the triangle at the point would give
+        let ng = linalg::cross(&(*pb - *pa), &(*pc - *pa)).normalized();
+        let ng = Normal::new(ng.x, ng.y, ng.z);
+        if self.normals.is_empty() {
+            (p, ng)
+        } else {
+            let na = &self.normals[self.a];
+            let nb = &self.normals[self.b];
+            let nc = &self.normals[self.c];
+            let ns = bary[0] * *na + bary[1] * *nb + bary[2] * *nc;
+            (p, ng.face_forward(&Vector::new(ns.x, ns.y, ns.z)))
+        }
+    }
+    fn sample(&self, _: &Point, samples: &(f32, f32)) -> (Point, Normal) {
+        self.sample_uniform(samples)
+    }
+    fn surface_area(&self) -> f32 {
+        let pa = &self.positions[self.a];
+        linalg::cross(&(self.positions[self.b] - *pa), &(self.positions[self.c] - *pa)).length() / 2.0
+    }
+    fn transformed_surface_area(&self, transform: &Transform) -> f32 {
+        let pa = &self.positions[self.a];
+        let e1 = *transform * (self.positions[self.b] - *pa);
+        let e2 = *transform * (self.positions[self.c] - *pa);
+        linalg::cross(&e1, &e2).length() / 2.0
+    }
+    fn pdf(&self, p: &Point, w_i: &Vector) -> f32 {
+        let mut ray = Ray::segment(p, w_i, 0.001, f32::INFINITY, 0.0);
+        match self.intersect(&mut ray) {
+            Some(d) => {
+                let ng = linalg::cross(&d.dp_du, &d.dp_dv).normalized();
+                let pdf = p.distance_sqr(&ray.at(ray.max_t))
+                    / (f32::abs(linalg::dot(&ng, w_i)) * self.surface_area());
+                if f32::is_finite(pdf) { pdf } else { 0.0 }
+            },
+            None => 0.0
+        }
     }
 }
 
@@ -188,3 +386,42 @@ impl Boundable for Triangle {
     }
 }
 
+
+#[test]
+fn test_sample_pdf() {
+    // A quad with shading normals tilted away from the geometric normal
+    let positions = Arc::new(vec![Point::new(0.0, 0.0, 0.0), Point::new(2.0, 0.0, 0.0),
+                                  Point::new(2.0, 1.0, 0.0), Point::new(0.0, 1.0, 0.0)]);
+    let normals = Arc::new(vec![Normal::new(0.5, 0.0, 1.0).normalized(), Normal::new(-0.5, 0.2, 1.0).normalized(),
+                                Normal::new(0.0, 0.5, 1.0).normalized(), Normal::new(0.3, -0.4, 1.0).normalized()]);
+    let mesh = Mesh::new(positions, normals, Arc::new(Vec::new()), vec![0, 1, 2, 0, 2, 3], vec![None; 2]);
+    let p = Point::new(0.5, 0.3, 1.5);
+    let n = 16;
+    for i in 0..n {
+        for j in 0..n {
+            let samples = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+            let (ps, ns) = mesh.sample(&p, &samples);
+            assert!(f32::abs(ns.z - 1.0) < 1e-5);
+            // Converting the area density to solid angle with the sampled normal should
+            // give the density computed by the mesh
+            let w_i = (ps - p).normalized();
+            let pdf = p.distance_sqr(&ps) / (f32::abs(linalg::dot(&ns, &w_i)) * mesh.surface_area());
+            assert!(f32::abs(pdf - mesh.pdf(&p, &w_i)) < 1e-3 * pdf);
+        }
+    }
+}
+
+#[test]
+fn test_transformed_surface_area() {
+    // A 2x1 quad in the xy plane and a 1x1 quad in the yz plane, scaling them non-uniformly
+    // scales the area of each by a different amount
+    let positions = Arc::new(vec![Point::new(0.0, 0.0, 0.0), Point::new(2.0, 0.0, 0.0),
+                                  Point::new(2.0, 1.0, 0.0), Point::new(0.0, 1.0, 0.0),
+                                  Point::new(0.0, 0.0, 1.0), Point::new(0.0, 1.0, 1.0)]);
+    let mesh = Mesh::new(positions, Arc::new(Vec::new()), Arc::new(Vec::new()),
+                         vec![0, 1, 2, 0, 2, 3, 0, 3, 5, 0, 5, 4], vec![None; 4]);
+    assert!(f32::abs(mesh.surface_area() - 3.0) < 1e-5);
+    let transform = Transform::rotate_z(60.0) * Transform::scale(&Vector::new(2.0, 3.0, 4.0));
+    let area = 2.0 * 6.0 + 12.0;
+    assert!(f32::abs(mesh.transformed_surface_area(&transform) - area) < 1e-4 * area);
+}
@@ -23,7 +23,7 @@
 //! ]
 //! ```
 
-use linalg::{Point, Vector, Ray, Normal};
+use linalg::{Point, Vector, Ray, Normal, Transform};
 
 pub use self::differential_geometry::DifferentialGeometry;
 pub use self::intersection::Intersection;
@@ -46,6 +46,7 @@ pub mod rectangle;
 pub mod bbox;
 pub mod bvh;
 pub mod mesh;
+pub mod ply;
 pub mod receiver;
 pub mod emitter;
 
@@ -78,9 +79,29 @@ pub trait Sampleable {
     fn sample(&self, p: &Point, samples: &(f32, f32)) -> (Point, Normal);
     /// Return the surface area of the shape
     fn surface_area(&self) -> f32;
+    /// Return the surface area of the shape after it's transformed by `transform`. The default
+    /// averages how much the transform scales the area around points sampled on the surface,
+    /// which is exact for flat shapes and shapes which are scaled uniformly
+    fn transformed_surface_area(&self, transform: &Transform) -> f32 {
+        let n = 8;
+        let mut scale = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let (_, normal) = self.sample_uniform(&((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32));
+                scale += transform.area_scale(&normal);
+            }
+        }
+        self.surface_area() * scale / (n * n) as f32
+    }
     /// Compute the PDF that the ray from `p` with direction `w_i` intersects
     /// the shape
     fn pdf(&self, p: &Point, w_i: &Vector) -> f32;
+    /// Find the point on the surface at the parameterization coordinates `uv`, used to sample
+    /// the surface based on a texture applied to it. Returns None if the geometry can't be
+    /// sampled through its parameterization
+    fn sample_parameterization(&self, _: &(f32, f32)) -> Option<DifferentialGeometry> {
+        None
+    }
 }
 
 pub trait BoundableGeom: Geometry + Boundable {}
@@ -35,9 +35,11 @@ impl Geometry for Plane {
         if p.x >= -1.0 && p.x <= 1.0 && p.y >= -1.0 && p.y <= 1.0 {
             ray.max_t = t;
             let n = Normal::new(0.0, 0.0, 1.0);
+            let u = (p.x + 1.0) / 2.0;
+            let v = (p.y + 1.0) / 2.0;
             let dp_du = Vector::new(2.0, 0.0, 0.0);
             let dp_dv = Vector::new(0.0, 2.0, 0.0);
-            Some(DifferentialGeometry::new(&p, &n, &dp_du, &dp_dv, self))
+            Some(DifferentialGeometry::new(&p, &n, u, v, &dp_du, &dp_dv, self))
         } else {
             None
         }
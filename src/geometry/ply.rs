@@ -0,0 +1,402 @@
+//! Provides a loader for triangle meshes stored in [PLY](http://paulbourke.net/dataformats/ply/)
+//! files. ASCII, binary little endian and binary big endian files are supported. The vertex
+//! positions are required while normals (nx, ny, nz) and texture coordinates (u, v or s, t)
+//! will be used if the file has them. Faces with more than three vertices are triangulated
+//! as a fan, any other elements in the file are skipped.
+//!
+//! # Scene Usage Example
+//! The PLY geometry is specified by the PLY file to load, if the same file is used by
+//! multiple objects it will only be loaded once.
+//!
+//! ```json
+//! "geometry": {
+//!     "type": "ply",
+//!     "file": "./dragon.ply"
+//! }
+//! ```
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use byteorder::{ByteOrder, LittleEndian, BigEndian, ReadBytesExt};
+
+use linalg::{Point, Normal};
+use geometry::Mesh;
+
+/// The format the body of the PLY file is stored in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// The scalar types that properties can have
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    /// Parse the scalar type from its name in the header
+    fn parse(name: &str) -> Result<Scalar, String> {
+        let ty = match name {
+            "char" | "int8" => Scalar::Int8,
+            "uchar" | "uint8" => Scalar::UInt8,
+            "short" | "int16" => Scalar::Int16,
+            "ushort" | "uint16" => Scalar::UInt16,
+            "int" | "int32" => Scalar::Int32,
+            "uint" | "uint32" => Scalar::UInt32,
+            "float" | "float32" => Scalar::Float32,
+            "double" | "float64" => Scalar::Float64,
+            _ => return Err(format!("unrecognized property type '{}'", name)),
+        };
+        Ok(ty)
+    }
+}
+
+/// A property of an element, either a single scalar or a list of scalars
+/// prefixed by the number of items in the list
+#[derive(Clone, Copy, Debug)]
+enum PropertyType {
+    Scalar(Scalar),
+    /// The type of the list count and the type of the items in the list
+    List(Scalar, Scalar),
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    ty: PropertyType,
+}
+
+/// An element described in the header, eg. the vertices or faces
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Find the index of the first property with one of the names passed
+    fn find_property(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| names.iter().any(|n| p.name == *n))
+    }
+}
+
+/// Reads the values of properties from the body of the file
+struct BodyReader<R: BufRead> {
+    reader: R,
+    format: Format,
+    /// Values parsed from the current line of an ASCII file
+    tokens: VecDeque<f64>,
+}
+
+impl<R: BufRead> BodyReader<R> {
+    /// Read the next value of type `ty` from the file
+    fn read(&mut self, ty: Scalar) -> Result<f64, String> {
+        match self.format {
+            Format::Ascii => {
+                while self.tokens.is_empty() {
+                    let mut line = String::new();
+                    match self.reader.read_line(&mut line) {
+                        Ok(0) => return Err("unexpected end of file".to_owned()),
+                        Ok(_) => {},
+                        Err(e) => return Err(format!("failed to read PLY data: {}", e)),
+                    }
+                    for t in line.split_whitespace() {
+                        match t.parse::<f64>() {
+                            Ok(x) => self.tokens.push_back(x),
+                            Err(e) => return Err(format!("invalid value '{}': {}", t, e)),
+                        }
+                    }
+                }
+                Ok(self.tokens.pop_front().unwrap())
+            },
+            Format::BinaryLittleEndian => read_binary::<LittleEndian, R>(&mut self.reader, ty),
+            Format::BinaryBigEndian => read_binary::<BigEndian, R>(&mut self.reader, ty),
+        }
+    }
+    /// Read an instance of the element, storing the values of its scalar properties in
+    /// `scalars` and the items of the list property `list_prop` in `list`. Other list
+    /// properties are skipped
+    fn read_element(&mut self, element: &Element, list_prop: Option<usize>, scalars: &mut [f64],
+                    list: &mut Vec<u32>) -> Result<(), String> {
+        for (i, p) in element.properties.iter().enumerate() {
+            match p.ty {
+                PropertyType::Scalar(ty) => scalars[i] = self.read(ty)?,
+                PropertyType::List(count_ty, item_ty) => {
+                    let count = self.read(count_ty)? as usize;
+                    if Some(i) == list_prop {
+                        list.clear();
+                        for _ in 0..count {
+                            list.push(self.read(item_ty)? as u32);
+                        }
+                    } else {
+                        for _ in 0..count {
+                            self.read(item_ty)?;
+                        }
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read a binary value of type `ty` stored with the byte order `B`
+fn read_binary<B: ByteOrder, R: Read>(reader: &mut R, ty: Scalar) -> Result<f64, String> {
+    let val = match ty {
+        Scalar::Int8 => reader.read_i8().map(|x| x as f64),
+        Scalar::UInt8 => reader.read_u8().map(|x| x as f64),
+        Scalar::Int16 => reader.read_i16::<B>().map(|x| x as f64),
+        Scalar::UInt16 => reader.read_u16::<B>().map(|x| x as f64),
+        Scalar::Int32 => reader.read_i32::<B>().map(|x| x as f64),
+        Scalar::UInt32 => reader.read_u32::<B>().map(|x| x as f64),
+        Scalar::Float32 => reader.read_f32::<B>().map(|x| x as f64),
+        Scalar::Float64 => reader.read_f64::<B>(),
+    };
+    val.map_err(|e| format!("failed to read PLY data: {}", e))
+}
+
+/// Parse the header of the PLY file, returns the format of the body and
+/// the elements stored in it
+fn read_header<R: BufRead>(reader: &mut R) -> Result<(Format, Vec<Element>), String> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut line = String::new();
+    let mut first = true;
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err("unexpected end of file in header".to_owned()),
+            Ok(_) => {},
+            Err(e) => return Err(format!("failed to read header: {}", e)),
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        if first {
+            if words != ["ply"] {
+                return Err("not a PLY file, missing magic number".to_owned());
+            }
+            first = false;
+            continue;
+        }
+        match words.first() {
+            Some(&"format") => {
+                format = match words.get(1) {
+                    Some(&"ascii") => Some(Format::Ascii),
+                    Some(&"binary_little_endian") => Some(Format::BinaryLittleEndian),
+                    Some(&"binary_big_endian") => Some(Format::BinaryBigEndian),
+                    _ => return Err(format!("unrecognized format '{}'", line.trim())),
+                };
+            },
+            Some(&"element") => {
+                if words.len() != 3 {
+                    return Err(format!("invalid element '{}'", line.trim()));
+                }
+                let count = match words[2].parse() {
+                    Ok(c) => c,
+                    Err(_) => return Err(format!("invalid element count '{}'", line.trim())),
+                };
+                elements.push(Element { name: words[1].to_owned(), count: count, properties: Vec::new() });
+            },
+            Some(&"property") => {
+                let property = match words.len() {
+                    3 => Property { name: words[2].to_owned(), ty: PropertyType::Scalar(Scalar::parse(words[1])?) },
+                    5 if words[1] == "list" => {
+                        Property { name: words[4].to_owned(),
+                                   ty: PropertyType::List(Scalar::parse(words[2])?, Scalar::parse(words[3])?) }
+                    },
+                    _ => return Err(format!("invalid property '{}'", line.trim())),
+                };
+                match elements.last_mut() {
+                    Some(e) => e.properties.push(property),
+                    None => return Err("property specified before any element".to_owned()),
+                }
+            },
+            Some(&"end_header") => break,
+            // Comments, obj_info and blank lines
+            _ => {},
+        }
+    }
+    match format {
+        Some(f) => Ok((f, elements)),
+        None => Err("no format specified in header".to_owned()),
+    }
+}
+
+/// The triangle mesh data read from a PLY file
+struct PlyMesh {
+    positions: Vec<Point>,
+    /// The vertex normals, empty if the file doesn't have normals
+    normals: Vec<Normal>,
+    /// The vertex texture coordinates, empty if the file doesn't have them
+    texcoords: Vec<Point>,
+    indices: Vec<u32>,
+}
+
+/// Read the triangle mesh from the PLY file being read by `reader`
+fn read_ply<R: BufRead>(mut reader: R) -> Result<PlyMesh, String> {
+    let (format, elements) = read_header(&mut reader)?;
+    let mut body = BodyReader { reader: reader, format: format, tokens: VecDeque::new() };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut indices = Vec::new();
+    let mut list = Vec::new();
+    for e in &elements {
+        let mut scalars = vec![0.0; e.properties.len()];
+        if e.name == "vertex" {
+            let pos = [e.find_property(&["x"]), e.find_property(&["y"]), e.find_property(&["z"])];
+            let norm = [e.find_property(&["nx"]), e.find_property(&["ny"]), e.find_property(&["nz"])];
+            let tex = [e.find_property(&["u", "s", "texture_u", "texture_s"]),
+                       e.find_property(&["v", "t", "texture_v", "texture_t"])];
+            if pos.iter().any(|p| p.is_none()) {
+                return Err("vertices are missing positions".to_owned());
+            }
+            let has_normals = norm.iter().all(|n| n.is_some());
+            let has_texcoords = tex.iter().all(|t| t.is_some());
+            positions.reserve(e.count);
+            for _ in 0..e.count {
+                body.read_element(e, None, &mut scalars[..], &mut list)?;
+                positions.push(Point::new(scalars[pos[0].unwrap()] as f32, scalars[pos[1].unwrap()] as f32,
+                                          scalars[pos[2].unwrap()] as f32));
+                if has_normals {
+                    normals.push(Normal::new(scalars[norm[0].unwrap()] as f32, scalars[norm[1].unwrap()] as f32,
+                                             scalars[norm[2].unwrap()] as f32));
+                }
+                if has_texcoords {
+                    texcoords.push(Point::new(scalars[tex[0].unwrap()] as f32, scalars[tex[1].unwrap()] as f32,
+                                              0.0));
+                }
+            }
+        } else if e.name == "face" {
+            let face = match e.find_property(&["vertex_indices", "vertex_index"]) {
+                Some(f) => f,
+                None => return Err("faces are missing vertex indices".to_owned()),
+            };
+            indices.reserve(3 * e.count);
+            for _ in 0..e.count {
+                body.read_element(e, Some(face), &mut scalars[..], &mut list)?;
+                // Triangulate the face as a fan around its first vertex
+                for i in 2..list.len() {
+                    indices.push(list[0]);
+                    indices.push(list[i - 1]);
+                    indices.push(list[i]);
+                }
+            }
+        } else {
+            for _ in 0..e.count {
+                body.read_element(e, None, &mut scalars[..], &mut list)?;
+            }
+        }
+    }
+    if indices.iter().any(|i| *i as usize >= positions.len()) {
+        return Err("faces reference vertices that don't exist".to_owned());
+    }
+    Ok(PlyMesh { positions: positions, normals: normals, texcoords: texcoords, indices: indices })
+}
+
+/// Load the triangle mesh stored in the PLY file
+pub fn load_file(path: &Path) -> Mesh {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => panic!("geometry::ply::load_file - failed to open {:?} due to {}", path, e),
+    };
+    let mut ply = match read_ply(BufReader::new(file)) {
+        Ok(p) => p,
+        Err(e) => panic!("geometry::ply::load_file - failed to load {:?}: {}", path, e),
+    };
+    println!("Loaded {:?} with {} triangles", path, ply.indices.len() / 3);
+    if ply.normals.is_empty() {
+        ply.normals = Mesh::compute_normals(&ply.positions[..], &ply.indices[..]);
+    }
+    let material_ids = vec![None; ply.indices.len() / 3];
+    Mesh::new(Arc::new(ply.positions), Arc::new(ply.normals), Arc::new(ply.texcoords), ply.indices,
+              material_ids)
+}
+
+#[test]
+fn test_ascii() {
+    use std::io::Cursor;
+    // A triangle and a quad with an extra vertex property and a list count type other than uchar
+    let data = "ply\nformat ascii 1.0\ncomment test\nelement vertex 4\nproperty float x\nproperty float y\n\
+                property float confidence\nproperty float z\nelement face 2\n\
+                property list ushort int vertex_indices\nend_header\n\
+                0 0 0.5 0\n1 0 0.5 0\n1 1 0.5 0\n0 1 0.5 0\n3 0 1 2\n4 0 1 2 3\n";
+    let ply = read_ply(Cursor::new(data.as_bytes())).unwrap();
+    assert_eq!(ply.positions, vec![Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0),
+                                   Point::new(1.0, 1.0, 0.0), Point::new(0.0, 1.0, 0.0)]);
+    assert!(ply.normals.is_empty() && ply.texcoords.is_empty());
+    assert_eq!(ply.indices, vec![0, 1, 2, 0, 1, 2, 0, 2, 3]);
+}
+
+#[test]
+fn test_binary_little_endian() {
+    use std::io::Cursor;
+    use byteorder::WriteBytesExt;
+    let header = "ply\nformat binary_little_endian 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+                  property float z\nproperty uchar red\nproperty float nx\nproperty float ny\nproperty float nz\n\
+                  property float u\nproperty float v\nelement face 2\nproperty list uint int vertex_indices\n\
+                  property list uchar float texcoord\nelement edge 1\nproperty int vertex1\n\
+                  property int vertex2\nend_header\n";
+    let mut data = header.as_bytes().to_vec();
+    let verts = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+    for v in &verts {
+        for x in &[v[0], v[1], 2.0] {
+            data.write_f32::<LittleEndian>(*x).unwrap();
+        }
+        data.write_u8(255).unwrap();
+        for x in &[0.0, 0.0, 1.0, v[0], v[1]] {
+            data.write_f32::<LittleEndian>(*x).unwrap();
+        }
+    }
+    for f in &[vec![0, 1, 2], vec![0, 1, 2, 3]] {
+        data.write_u32::<LittleEndian>(f.len() as u32).unwrap();
+        for i in f {
+            data.write_i32::<LittleEndian>(*i).unwrap();
+        }
+        // The texcoord list property isn't used and should be skipped
+        data.write_u8(2).unwrap();
+        data.write_f32::<LittleEndian>(0.5).unwrap();
+        data.write_f32::<LittleEndian>(0.5).unwrap();
+    }
+    data.write_i32::<LittleEndian>(0).unwrap();
+    data.write_i32::<LittleEndian>(1).unwrap();
+
+    let ply = read_ply(Cursor::new(&data[..])).unwrap();
+    assert_eq!(ply.positions.len(), 4);
+    for (p, v) in ply.positions.iter().zip(verts.iter()) {
+        assert_eq!(*p, Point::new(v[0], v[1], 2.0));
+    }
+    assert!(ply.normals.iter().all(|n| *n == Normal::new(0.0, 0.0, 1.0)));
+    assert_eq!(ply.texcoords[2], Point::new(1.0, 1.0, 0.0));
+    assert_eq!(ply.indices, vec![0, 1, 2, 0, 1, 2, 0, 2, 3]);
+}
+
+#[test]
+fn test_truncated() {
+    use std::io::Cursor;
+    let header = "ply\nformat binary_little_endian 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                  property float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+    let mut data = header.as_bytes().to_vec();
+    data.extend(&[0u8; 20]);
+    assert!(read_ply(Cursor::new(&data[..])).is_err());
+    // Missing the face in an ASCII file
+    let data = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1 0\n";
+    assert!(read_ply(Cursor::new(data.as_bytes())).is_err());
+    // Ending in the middle of the header
+    assert!(read_ply(Cursor::new("ply\nformat ascii 1.0\nelement vertex 3\n".as_bytes())).is_err());
+}
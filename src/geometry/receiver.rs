@@ -4,24 +4,41 @@ use std::sync::Arc;
 use geometry::{Boundable, BBox, BoundableGeom, DifferentialGeometry};
 use material::Material;
 use linalg::{Ray, AnimatedTransform};
+use media::MediumInterface;
 
 /// An instance of geometry in the scene that only receives light
 pub struct Receiver {
     /// The geometry that's being instanced.
     geom: Arc<BoundableGeom + Send + Sync>,
-    /// The material being used by this instance.
+    /// The material being used by this instance, used for any geometry that isn't
+    /// assigned a material from the material table
     pub material: Arc<Material + Send + Sync>,
+    /// Materials that pieces of the geometry can select by their material id,
+    /// eg. the materials of the triangles in a mesh
+    pub materials: Vec<Arc<Material + Send + Sync>>,
     /// The transform to world space
     transform: AnimatedTransform,
     /// Tag to identify the instance
     pub tag: String,
+    /// The media inside and outside of the geometry, if it's a boundary between media
+    medium_interface: Option<MediumInterface>,
 }
 
 impl Receiver {
     /// Create a new instance of some geometry in the scene
     pub fn new(geom: Arc<BoundableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
                transform: AnimatedTransform, tag: String) -> Receiver {
-        Receiver { geom: geom, material: material, transform: transform, tag: tag }
+        Receiver { geom: geom, material: material, materials: Vec::new(), transform: transform, tag: tag,
+                   medium_interface: None }
+    }
+    /// Create a new instance of some geometry in the scene where the geometry can select
+    /// which material to use from the table of `materials`. Geometry without a material id
+    /// will use `material`
+    pub fn with_materials(geom: Arc<BoundableGeom + Send + Sync>, material: Arc<Material + Send + Sync>,
+                          materials: Vec<Arc<Material + Send + Sync>>, transform: AnimatedTransform,
+                          tag: String) -> Receiver {
+        Receiver { geom: geom, material: material, materials: materials, transform: transform, tag: tag,
+                   medium_interface: None }
     }
     /// Test the ray for intersection against this insance of geometry.
     /// returns Some(Intersection) if an intersection was found and None if not.
@@ -39,7 +56,11 @@ impl Receiver {
         dg.ng = transform * dg.ng;
         dg.dp_du = transform * dg.dp_du;
         dg.dp_dv = transform * dg.dp_dv;
-        Some((dg, &*self.material))
+        let material = match dg.material_id.and_then(|i| self.materials.get(i)) {
+            Some(m) => &**m,
+            None => &*self.material,
+        };
+        Some((dg, material))
     }
     /// Get the transform to place the receiver into world space
     pub fn get_transform(&self) -> &AnimatedTransform {
@@ -49,6 +70,14 @@ impl Receiver {
     pub fn set_transform(&mut self, transform: AnimatedTransform) {
         self.transform = transform;
     }
+    /// Get the media on either side of the receiver's surface, if it's a boundary between media
+    pub fn medium_interface(&self) -> Option<&MediumInterface> {
+        self.medium_interface.as_ref()
+    }
+    /// Set the media on either side of the receiver's surface
+    pub fn set_medium_interface(&mut self, medium_interface: Option<MediumInterface>) {
+        self.medium_interface = medium_interface;
+    }
 }
 
 impl Boundable for Receiver {
@@ -52,9 +52,11 @@ impl Geometry for Rectangle {
         if p.x >= -half_width && p.x <= half_width && p.y >= -half_height && p.y <= half_height {
             ray.max_t = t;
             let n = Normal::new(0.0, 0.0, 1.0);
-            let dp_du = Vector::new(1.0, 0.0, 0.0);
-            let dp_dv = Vector::new(0.0, 1.0, 0.0);
-            Some(DifferentialGeometry::new(&p, &n, &dp_du, &dp_dv, self))
+            let u = (p.x + half_width) / self.width;
+            let v = (p.y + half_height) / self.height;
+            let dp_du = Vector::new(self.width, 0.0, 0.0);
+            let dp_dv = Vector::new(0.0, self.height, 0.0);
+            Some(DifferentialGeometry::new(&p, &n, u, v, &dp_du, &dp_dv, self))
         } else {
             None
         }
@@ -100,5 +102,11 @@ impl Sampleable for Rectangle {
             None => 0.0
         }
     }
+    fn sample_parameterization(&self, uv: &(f32, f32)) -> Option<DifferentialGeometry> {
+        let p = Point::new((uv.0 - 0.5) * self.width, (uv.1 - 0.5) * self.height, 0.0);
+        let dp_du = Vector::new(self.width, 0.0, 0.0);
+        let dp_dv = Vector::new(0.0, self.height, 0.0);
+        Some(DifferentialGeometry::new(&p, &Normal::new(0.0, 0.0, 1.0), uv.0, uv.1, &dp_du, &dp_dv, self))
+    }
 }
 
@@ -59,6 +59,12 @@ impl Geometry for Sphere {
         let p = ray.at(t_hit);
         let n = Normal::new(p.x, p.y, p.z);
         let theta = f32::acos(linalg::clamp(p.z / self.radius, -1.0, 1.0));
+        let mut phi = f32::atan2(p.y, p.x);
+        if phi < 0.0 {
+            phi += f32::consts::PI * 2.0;
+        }
+        let u = phi / (f32::consts::PI * 2.0);
+        let v = theta / f32::consts::PI;
 
         // Compute derivatives for point vs. parameterization
         let inv_z = 1.0 / f32::sqrt(p.x * p.x + p.y * p.y);
@@ -72,7 +78,7 @@ impl Geometry for Sphere {
         let dp_dv = Vector::new(p.z * cos_phi, p.z * sin_phi,
                                 -self.radius * f32::sin(theta)) * f32::consts::PI;
 
-        Some(DifferentialGeometry::with_normal(&p, &n, &dp_du, &dp_dv, self))
+        Some(DifferentialGeometry::with_normal(&p, &n, u, v, &dp_du, &dp_dv, self))
     }
 }
 
@@ -133,5 +139,18 @@ impl Sampleable for Sphere {
             mc::uniform_cone_pdf(cos_theta_max)
         }
     }
+    fn sample_parameterization(&self, uv: &(f32, f32)) -> Option<DifferentialGeometry> {
+        let phi = uv.0 * f32::consts::PI * 2.0;
+        let theta = uv.1 * f32::consts::PI;
+        let (sin_theta, cos_theta) = (f32::sin(theta), f32::cos(theta));
+        let (sin_phi, cos_phi) = (f32::sin(phi), f32::cos(phi));
+        let p = Point::new(self.radius * sin_theta * cos_phi, self.radius * sin_theta * sin_phi,
+                           self.radius * cos_theta);
+        // The derivatives match those computed when intersecting the sphere
+        let dp_du = Vector::new(-f32::consts::PI * 2.0 * p.y, f32::consts::PI * 2.0 * p.x, 0.0);
+        let dp_dv = Vector::new(p.z * cos_phi, p.z * sin_phi, -self.radius * sin_theta) * f32::consts::PI;
+        Some(DifferentialGeometry::with_normal(&p, &Normal::new(p.x, p.y, p.z), uv.0, uv.1,
+                                               &dp_du, &dp_dv, self))
+    }
 }
 
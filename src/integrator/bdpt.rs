@@ -0,0 +1,595 @@
+//! Defines the Bdpt integrator which implements bidirectional path tracing. For each
+//! camera sample a path is traced from the camera and another from a light in the scene,
+//! the vertices of the two paths are then connected in every possible way and the
+//! contributions of each strategy are combined with multiple importance sampling. This
+//! handles scenes lit indirectly, eg. through small openings, and caustics far better
+//! than the path tracer.
+//!
+//! Paths from the lights which are connected directly to the camera can contribute to any
+//! pixel in the image, these contributions are splatted to the image without filtering.
+//!
+//! See [Veach, Robust Monte Carlo Methods for Light Transport Simulation](http://graphics.stanford.edu/papers/veach_thesis/)
+//! Chapter 10 and [PBR](http://pbrt.org/) for details on the implementation.
+//!
+//! # Scene Usage Example
+//! The bidirectional path tracer needs a maximum depth for the paths it traces,
+//! Russian Roulette isn't used to terminate paths early.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "bdpt",
+//!     "max_depth": 8
+//! }
+//! ```
+
+use std::f32;
+use rand::StdRng;
+
+use scene::Scene;
+use linalg::{self, Ray, Point, Vector, Normal};
+use geometry::{Intersection, Instance, Emitter, DifferentialGeometry, Boundable};
+use film::{Camera, Colorf, ImageSample};
+use integrator::{self, Integrator};
+use bxdf::{BSDF, BxDFType};
+use light::{Light, LightList, OcclusionTester};
+use sampler::{Sampler, Sample};
+
+/// The Bdpt integrator implementing bidirectional path tracing
+#[derive(Clone, Copy, Debug)]
+pub struct Bdpt {
+    max_depth: usize,
+}
+
+/// The type of object a vertex on a path lies on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VertexType {
+    Camera,
+    Light,
+    Surface,
+}
+
+/// Information about the scene needed to trace and connect paths
+struct PathContext<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+    light_list: &'a LightList<'a>,
+    /// Center and radius of a sphere bounding the scene
+    scene_center: Point,
+    scene_radius: f32,
+    time: f32,
+}
+
+/// A vertex on a path traced from the camera or a light
+struct Vertex<'a> {
+    ty: VertexType,
+    p: Point,
+    /// Geometric normal at the vertex, zero if the vertex isn't on a surface
+    n: Normal,
+    /// Shading normal at the vertex
+    ns: Normal,
+    /// Direction the path arrived at the vertex from
+    w_o: Vector,
+    /// Throughput of the path up to this vertex
+    beta: Colorf,
+    /// Density of sampling this vertex w.r.t. area when tracing the path
+    pdf_fwd: f32,
+    /// Density of sampling this vertex w.r.t. area if the path was traced in the other direction
+    pdf_rev: f32,
+    /// Whether the path was continued from this vertex by sampling a specular BxDF
+    delta: bool,
+    /// Whether the vertex is on a light infinitely far away
+    infinite: bool,
+    bsdf: Option<BSDF<'a>>,
+    /// The light the vertex is on, if any
+    light: Option<&'a Emitter>,
+    dg: Option<DifferentialGeometry<'a>>,
+}
+
+impl<'a> Vertex<'a> {
+    /// Create a vertex on the camera at `p`
+    fn camera(p: &Point, beta: Colorf) -> Vertex<'a> {
+        Vertex { ty: VertexType::Camera, p: *p, n: Normal::broadcast(0.0), ns: Normal::broadcast(0.0),
+                 w_o: Vector::broadcast(0.0), beta: beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: false,
+                 infinite: false, bsdf: None, light: None, dg: None }
+    }
+    /// Create a vertex on the light at `p` with normal `n`
+    fn light(p: &Point, n: &Normal, light: &'a Emitter, beta: Colorf, pdf_fwd: f32) -> Vertex<'a> {
+        Vertex { ty: VertexType::Light, p: *p, n: *n, ns: *n, w_o: Vector::broadcast(0.0), beta: beta,
+                 pdf_fwd: pdf_fwd, pdf_rev: 0.0, delta: false, infinite: light.is_infinite(), bsdf: None,
+                 light: Some(light), dg: None }
+    }
+    /// Create a vertex on the lights infinitely far away for a ray which left the scene
+    fn escaped(ray: &Ray, beta: Colorf, pdf_fwd: f32) -> Vertex<'a> {
+        let n = Normal::new(-ray.d.x, -ray.d.y, -ray.d.z);
+        Vertex { ty: VertexType::Light, p: ray.o + ray.d, n: n, ns: n, w_o: -ray.d, beta: beta,
+                 pdf_fwd: pdf_fwd, pdf_rev: 0.0, delta: false, infinite: true, bsdf: None, light: None, dg: None }
+    }
+    /// Create a vertex at the intersection `hit` which the path arrived at from `w_o`
+    fn surface(hit: &Intersection<'a, 'a>, bsdf: BSDF<'a>, w_o: &Vector, beta: Colorf) -> Vertex<'a> {
+        let light = match *hit.instance {
+            Instance::Emitter(ref e) => Some(e),
+            _ => None,
+        };
+        Vertex { ty: VertexType::Surface, p: hit.dg.p, n: hit.dg.ng.normalized(), ns: bsdf.n, w_o: *w_o,
+                 beta: beta, pdf_fwd: 0.0, pdf_rev: 0.0, delta: false, infinite: false, bsdf: Some(bsdf),
+                 light: light, dg: Some(hit.dg) }
+    }
+    fn on_surface(&self) -> bool {
+        self.n.length_sqr() > 0.0
+    }
+    fn is_light(&self) -> bool {
+        self.ty == VertexType::Light || self.light.is_some()
+    }
+    fn is_delta_light(&self) -> bool {
+        self.ty == VertexType::Light && self.light.map_or(false, |l| l.delta_light())
+    }
+    /// Check if the vertex can be connected to another vertex, eg. it isn't on a
+    /// perfectly specular surface or a directional light
+    fn is_connectible(&self) -> bool {
+        match self.ty {
+            VertexType::Camera => true,
+            VertexType::Light => self.light.map_or(true, |l| !(l.delta_light() && l.is_infinite())),
+            VertexType::Surface => {
+                self.bsdf.as_ref().map_or(false, |b| b.num_matching(BxDFType::non_specular()) > 0)
+            },
+        }
+    }
+    /// Evaluate the BSDF at the vertex for light scattering towards `next`, `importance` is
+    /// set if importance is being transported, eg. on a path traced from a light
+    fn f(&self, next: &Vertex, importance: bool) -> Colorf {
+        match self.bsdf {
+            Some(ref bsdf) => {
+                let w_i = (next.p - self.p).normalized();
+                let f = bsdf.eval(&self.w_o, &w_i, BxDFType::all());
+                if importance { f * correct_shading_normal(&self.n, &self.ns, &self.w_o, &w_i) } else { f }
+            },
+            None => Colorf::black(),
+        }
+    }
+    /// Convert the density `pdf` w.r.t. solid angle of sampling the direction to `next` to
+    /// be w.r.t. area at `next`
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        if next.infinite {
+            return pdf;
+        }
+        let w = next.p - self.p;
+        let dist_sqr = w.length_sqr();
+        if dist_sqr == 0.0 {
+            return 0.0;
+        }
+        if next.on_surface() {
+            pdf * f32::abs(linalg::dot(&next.n, &w.normalized())) / dist_sqr
+        } else {
+            pdf / dist_sqr
+        }
+    }
+    /// Compute the density w.r.t. area of sampling `next` from this vertex, where the path
+    /// arrived at this vertex from `prev`
+    fn pdf(&self, ctx: &PathContext, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+        if self.ty == VertexType::Light {
+            return self.pdf_light(ctx, next);
+        }
+        let w_n = next.p - self.p;
+        if w_n.length_sqr() == 0.0 {
+            return 0.0;
+        }
+        let w_n = w_n.normalized();
+        let pdf = match (self.ty, &self.bsdf, prev) {
+            (VertexType::Camera, _, _) => ctx.camera.pdf_direction(&Ray::new(&self.p, &w_n, ctx.time)),
+            (_, &Some(ref bsdf), Some(prev)) => {
+                let w_p = (prev.p - self.p).normalized();
+                bsdf.pdf(&w_p, &w_n, BxDFType::all())
+            },
+            _ => 0.0,
+        };
+        self.convert_density(pdf, next)
+    }
+    /// Compute the density w.r.t. area of the light this vertex is on emitting light towards `v`
+    fn pdf_light(&self, ctx: &PathContext, v: &Vertex) -> f32 {
+        let w = v.p - self.p;
+        let dist_sqr = w.length_sqr();
+        if dist_sqr == 0.0 {
+            return 0.0;
+        }
+        let w = w.normalized();
+        let pdf = if self.infinite {
+            1.0 / (f32::consts::PI * ctx.scene_radius * ctx.scene_radius)
+        } else {
+            match self.light {
+                Some(l) => {
+                    let (_, pdf_dir) = l.pdf_emission(&Ray::new(&self.p, &w, ctx.time), &self.n, ctx.scene_radius);
+                    pdf_dir / dist_sqr
+                },
+                None => 0.0,
+            }
+        };
+        if v.on_surface() { pdf * f32::abs(linalg::dot(&v.n, &w)) } else { pdf }
+    }
+    /// Compute the density of choosing this point on the light this vertex is on
+    /// as the start of a path emitting light towards `v`
+    fn pdf_light_origin(&self, ctx: &PathContext, v: &Vertex) -> f32 {
+        let w = (v.p - self.p).normalized();
+        if self.infinite {
+            return infinite_light_density(ctx, &w);
+        }
+        match self.light {
+            Some(l) => {
+                let (pdf_pos, _) = l.pdf_emission(&Ray::new(&self.p, &w, ctx.time), &self.n, ctx.scene_radius);
+                pdf_pos * ctx.light_list.pmf_power(l)
+            },
+            None => 0.0,
+        }
+    }
+    /// Compute the radiance emitted from the light this vertex is on towards `v`
+    fn le(&self, ctx: &PathContext, v: &Vertex) -> Colorf {
+        if !self.is_light() {
+            return Colorf::black();
+        }
+        let w = (v.p - self.p).normalized();
+        if self.infinite {
+            integrator::escaped_radiance(ctx.light_list, &Ray::new(&v.p, &-w, ctx.time))
+        } else {
+            match (self.light, &self.dg) {
+                (Some(l), &Some(ref dg)) => l.radiance(&w, dg, ctx.time),
+                _ => Colorf::black(),
+            }
+        }
+    }
+}
+
+impl Bdpt {
+    /// Create a new bidirectional path tracer with the max length desired for paths
+    pub fn new(max_depth: u32) -> Bdpt {
+        Bdpt { max_depth: max_depth as usize }
+    }
+    /// Trace and connect the camera and light paths for the camera ray `ray` which first hits
+    /// `hit`, contributions to other pixels in the image from connecting light paths to the camera
+    /// are pushed to `splats`
+    fn trace<'a>(&self, scene: &'a Scene, light_list: &'a LightList<'a>, ray: &Ray, hit: Option<Intersection<'a, 'a>>,
+                 sampler: &mut Sampler, rng: &mut StdRng, splats: &mut Vec<ImageSample>) -> Colorf {
+        let (scene_center, scene_radius) = scene.bvh.bounds(0.0, 0.0).bounding_sphere();
+        let ctx = PathContext { scene: scene, camera: scene.active_camera(), light_list: light_list,
+                                scene_center: scene_center, scene_radius: scene_radius, time: ray.time };
+        // Trace the camera path
+        let mut camera_path = Vec::with_capacity(self.max_depth + 2);
+        camera_path.push(Vertex::camera(&ray.o, Colorf::broadcast(1.0)));
+        let pdf_dir = ctx.camera.pdf_direction(ray);
+        random_walk(&ctx, ray, hit, Colorf::broadcast(1.0), pdf_dir, self.max_depth + 1, false, sampler, rng,
+                    &mut camera_path);
+
+        // Trace the light path from a light chosen based on its power
+        let mut light_path = Vec::with_capacity(self.max_depth + 1);
+        let pos_sample = next_sample(sampler, rng);
+        let dir_sample = next_sample(sampler, rng);
+        let (light, light_pmf) = light_list.sample_power(pos_sample.one_d);
+        let (le, light_ray, n, pdf_pos, pdf_dir) = light.sample_emission(&scene_center, scene_radius,
+                                                                        &pos_sample.two_d, &dir_sample.two_d,
+                                                                        ray.time);
+        if light_pmf > 0.0 && pdf_pos > 0.0 && pdf_dir > 0.0 && !le.is_black() {
+            light_path.push(Vertex::light(&light_ray.o, &n, light, le, pdf_pos * light_pmf));
+            let beta = le * f32::abs(linalg::dot(&n, &light_ray.d)) / (light_pmf * pdf_pos * pdf_dir);
+            let mut light_ray = light_ray;
+            let light_hit = scene.intersect(&mut light_ray);
+            random_walk(&ctx, &light_ray, light_hit, beta, pdf_dir, self.max_depth, true, sampler, rng,
+                        &mut light_path);
+            // The origin of rays from lights infinitely far away is sampled on a disk covering the
+            // scene so the density of the first vertex hit is the density of sampling the disk
+            if light.is_infinite() {
+                if light_path.len() > 1 {
+                    let v = &mut light_path[1];
+                    v.pdf_fwd = pdf_pos;
+                    if v.on_surface() {
+                        v.pdf_fwd *= f32::abs(linalg::dot(&v.n, &light_ray.d));
+                    }
+                }
+                light_path[0].pdf_fwd = infinite_light_density(&ctx, &light_ray.d);
+            }
+        }
+
+        // Connect the paths with each strategy
+        let mut illum = Colorf::black();
+        for t in 1..camera_path.len() + 1 {
+            for s in 0..light_path.len() + 1 {
+                if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > self.max_depth {
+                    continue;
+                }
+                let sample = if s == 1 { next_sample(sampler, rng) } else { Sample::new(&(0.0, 0.0), 0.0) };
+                match connect(&ctx, &light_path[..], &camera_path[..], s, t, &sample) {
+                    (c, Some(raster)) => splats.push(ImageSample::new(raster.0, raster.1, c)),
+                    (c, None) => illum = illum + c,
+                }
+            }
+        }
+        illum
+    }
+}
+
+impl Integrator for Bdpt {
+    /// BDPT renders the image through `camera_illumination` so the contributions of light paths
+    /// connected directly to the camera can be splatted to the image, it can't compute the
+    /// illumination for just a hit point without dropping them.
+    fn illumination(&self, _: &Scene, _: &LightList, _: &Ray, _: &Intersection, _: &mut Sampler,
+                    _: &mut StdRng) -> Colorf {
+        unreachable!("BDPT illumination must be computed with camera_illumination")
+    }
+    fn camera_illumination<'a>(&self, scene: &'a Scene, light_list: &LightList, ray: &Ray,
+                               hit: Option<&Intersection<'a, 'a>>, sampler: &mut Sampler, rng: &mut StdRng,
+                               splats: &mut Vec<ImageSample>) -> Colorf {
+        self.trace(scene, light_list, ray, hit.cloned(), sampler, rng, splats)
+    }
+}
+
+/// Take a 2D and 1D sample from the sampler for a single decision along a path. Samples
+/// are taken separately for each decision since samples in the same pattern are stratified
+/// against each other, which would correlate the decisions made along the path
+fn next_sample(sampler: &mut Sampler, rng: &mut StdRng) -> Sample {
+    let mut two_d = [(0.0, 0.0)];
+    let mut one_d = [0.0];
+    sampler.get_samples_2d(&mut two_d[..], rng);
+    sampler.get_samples_1d(&mut one_d[..], rng);
+    Sample::new(&two_d[0], one_d[0])
+}
+
+/// Trace the path continuing along `ray` from the last vertex in `path`, pushing the
+/// vertices it hits on to `path`. `hit` is the first surface hit by `ray`, if any, `beta` is
+/// the throughput of the path so far and `pdf` the density w.r.t. solid angle of sampling the
+/// ray's direction. At most `max_depth` vertices are added, `importance` should be set for
+/// paths traced from the lights
+fn random_walk<'a>(ctx: &PathContext<'a>, ray: &Ray, hit: Option<Intersection<'a, 'a>>, beta: Colorf, pdf: f32,
+                   max_depth: usize, importance: bool, sampler: &mut Sampler, rng: &mut StdRng,
+                   path: &mut Vec<Vertex<'a>>) {
+    let mut ray = *ray;
+    let mut next_hit = hit;
+    let mut beta = beta;
+    let mut pdf_fwd = pdf;
+    let mut bounces = 0;
+    while !beta.is_black() {
+        let hit = match next_hit {
+            Some(h) => h,
+            None => {
+                // Camera paths which leave the scene can see the lights surrounding it
+                if !importance {
+                    let v = Vertex::escaped(&ray, beta, pdf_fwd);
+                    path.push(v);
+                }
+                break;
+            },
+        };
+        let w_o = -ray.d;
+        let mut vertex = Vertex::surface(&hit, hit.material.bsdf(&hit), &w_o, beta);
+        vertex.pdf_fwd = path.last().unwrap().convert_density(pdf_fwd, &vertex);
+        bounces += 1;
+        if bounces >= max_depth {
+            path.push(vertex);
+            break;
+        }
+        let (f, w_i, pdf, mut pdf_rev, sampled_type) = {
+            let bsdf = vertex.bsdf.as_ref().unwrap();
+            let sample = next_sample(sampler, rng);
+            let (f, w_i, pdf, sampled_type) = bsdf.sample(&w_o, BxDFType::all(), &sample);
+            (f, w_i, pdf, bsdf.pdf(&w_i, &w_o, BxDFType::all()), sampled_type)
+        };
+        if f.is_black() || pdf == 0.0 {
+            path.push(vertex);
+            break;
+        }
+        beta = beta * f * f32::abs(linalg::dot(&w_i, &vertex.ns)) / pdf;
+        if importance {
+            beta = beta * correct_shading_normal(&vertex.n, &vertex.ns, &w_o, &w_i);
+        }
+        pdf_fwd = pdf;
+        // Specular vertices can't be sampled by any other strategy so they're skipped when
+        // computing the MIS weights
+        if sampled_type.contains(&BxDFType::Specular) {
+            vertex.delta = true;
+            pdf_fwd = 0.0;
+            pdf_rev = 0.0;
+        }
+        let rev = vertex.convert_density(pdf_rev, path.last().unwrap());
+        path.last_mut().unwrap().pdf_rev = rev;
+        ray = ray.child(&vertex.p, &w_i.normalized());
+        ray.min_t = 0.001;
+        next_hit = ctx.scene.intersect(&mut ray);
+        path.push(vertex);
+    }
+}
+
+/// Compute the contribution of the path made by connecting the first `s` vertices of the light
+/// path to the first `t` vertices of the camera path, weighted by MIS. If the path is connected to
+/// the camera through a new camera vertex the raster position it's seen at is also returned.
+/// `sample` is used to sample a light if a new light vertex is needed.
+fn connect(ctx: &PathContext, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize,
+           sample: &Sample) -> (Colorf, Option<(f32, f32)>) {
+    // Paths which left the scene can't be connected to
+    if t > 1 && s != 0 && camera_path[t - 1].ty == VertexType::Light {
+        return (Colorf::black(), None);
+    }
+    let mut sampled = None;
+    let mut raster = None;
+    let mut l = Colorf::black();
+    if s == 0 {
+        // The camera path hit a light by itself
+        let pt = &camera_path[t - 1];
+        l = pt.beta * pt.le(ctx, &camera_path[t - 2]);
+    } else if t == 1 {
+        // Connect the light path to a new vertex on the camera
+        let qs = &light_path[s - 1];
+        if qs.is_connectible() {
+            if let Some((we, w_i, pdf, r, pos)) = ctx.camera.sample_incident(&qs.p, ctx.time) {
+                if pdf > 0.0 && we > 0.0 {
+                    let v = Vertex::camera(&pos, Colorf::broadcast(we / pdf));
+                    l = qs.beta * qs.f(&v, true) * v.beta;
+                    if qs.on_surface() {
+                        l = l * f32::abs(linalg::dot(&w_i, &qs.ns));
+                    }
+                    if !l.is_black() && OcclusionTester::test_points(&qs.p, &pos, ctx.time).occluded(ctx.scene) {
+                        l = Colorf::black();
+                    }
+                    raster = Some(r);
+                    sampled = Some(v);
+                }
+            }
+        }
+    } else if s == 1 {
+        // Connect the camera path to a new vertex on a light
+        let pt = &camera_path[t - 1];
+        if pt.is_connectible() {
+            let (light, pmf) = ctx.light_list.sample_power(sample.one_d);
+            let (li, w_i, pdf, occlusion) = light.sample_incident(&pt.p, &sample.two_d, ctx.time);
+            if pmf > 0.0 && pdf > 0.0 && !li.is_black() {
+                let (p, n) = sampled_light_point(ctx, light, &occlusion, &w_i);
+                let mut v = Vertex::light(&p, &n, light, li / (pdf * pmf), 0.0);
+                v.pdf_fwd = v.pdf_light_origin(ctx, pt);
+                l = pt.beta * pt.f(&v, false) * v.beta;
+                if pt.on_surface() {
+                    l = l * f32::abs(linalg::dot(&w_i, &pt.ns));
+                }
+                if !l.is_black() && occlusion.occluded(ctx.scene) {
+                    l = Colorf::black();
+                }
+                sampled = Some(v);
+            }
+        }
+    } else {
+        let qs = &light_path[s - 1];
+        let pt = &camera_path[t - 1];
+        if qs.is_connectible() && pt.is_connectible() {
+            l = qs.beta * qs.f(pt, true) * pt.f(qs, false) * pt.beta;
+            if !l.is_black() {
+                l = l * geometry_term(ctx, qs, pt);
+            }
+        }
+    }
+    if l.is_black() {
+        return (l, None);
+    }
+    let weight = if s + t == 2 {
+        1.0
+    } else {
+        mis_weight(ctx, light_path, camera_path, sampled.as_ref(), s, t)
+    };
+    (l * weight, raster)
+}
+
+/// Find the point and normal on the light sampled by `sample_incident` with the occlusion
+/// test `occlusion` and incident direction `w_i`. Points on lights which aren't on a surface
+/// are given a zero normal
+fn sampled_light_point(ctx: &PathContext, light: &Emitter, occlusion: &OcclusionTester, w_i: &Vector)
+                       -> (Point, Normal) {
+    if light.is_infinite() {
+        (occlusion.ray.o + *w_i * (2.0 * ctx.scene_radius), Normal::broadcast(0.0))
+    } else if light.delta_light() {
+        (occlusion.ray.at(1.0), Normal::broadcast(0.0))
+    } else {
+        let mut ray = occlusion.ray;
+        ray.max_t = f32::INFINITY;
+        match light.intersect(&mut ray) {
+            Some((dg, _)) => (occlusion.ray.at(1.0), dg.ng.normalized()),
+            None => (occlusion.ray.at(1.0), Normal::broadcast(0.0)),
+        }
+    }
+}
+
+/// Compute the geometry term between the vertices, including their visibility
+fn geometry_term(ctx: &PathContext, a: &Vertex, b: &Vertex) -> f32 {
+    let d = a.p - b.p;
+    let dist_sqr = d.length_sqr();
+    if dist_sqr == 0.0 {
+        return 0.0;
+    }
+    let d = d.normalized();
+    let mut g = 1.0 / dist_sqr;
+    if a.on_surface() {
+        g *= f32::abs(linalg::dot(&a.ns, &d));
+    }
+    if b.on_surface() {
+        g *= f32::abs(linalg::dot(&b.ns, &d));
+    }
+    if OcclusionTester::test_points(&a.p, &b.p, ctx.time).occluded(ctx.scene) {
+        0.0
+    } else {
+        g
+    }
+}
+
+/// Compute the MIS weight for the path connecting `s` light path vertices to `t` camera path
+/// vertices using the balance heuristic. `sampled` is the new vertex sampled on the light or camera
+/// to make the connection, if one was needed, which takes the place of the last vertex of that path
+fn mis_weight(ctx: &PathContext, light_path: &[Vertex], camera_path: &[Vertex], sampled: Option<&Vertex>,
+              s: usize, t: usize) -> f32 {
+    let remap0 = |f: f32| if f != 0.0 { f } else { 1.0 };
+    let qs = if s == 0 { None } else if s == 1 { sampled } else { Some(&light_path[s - 1]) };
+    let pt = if t == 1 { sampled.unwrap() } else { &camera_path[t - 1] };
+    let qs_minus = if s > 1 { Some(&light_path[s - 2]) } else { None };
+    let pt_minus = if t > 1 { Some(&camera_path[t - 2]) } else { None };
+
+    // Copy the densities of the vertices on the path so we can update them for this connection
+    let light_vertex = |i: usize| if i == s - 1 { qs.unwrap() } else { &light_path[i] };
+    let camera_vertex = |i: usize| if i == t - 1 { pt } else { &camera_path[i] };
+    let light_fwd: Vec<_> = (0..s).map(|i| light_vertex(i).pdf_fwd).collect();
+    let mut light_rev: Vec<_> = (0..s).map(|i| light_vertex(i).pdf_rev).collect();
+    let mut light_delta: Vec<_> = (0..s).map(|i| light_vertex(i).delta).collect();
+    let camera_fwd: Vec<_> = (0..t).map(|i| camera_vertex(i).pdf_fwd).collect();
+    let mut camera_rev: Vec<_> = (0..t).map(|i| camera_vertex(i).pdf_rev).collect();
+    let mut camera_delta: Vec<_> = (0..t).map(|i| camera_vertex(i).delta).collect();
+
+    // The vertices being connected can't be specular, and we update the reverse densities of
+    // the connected vertices and their predecessors to those of sampling them from the other path
+    camera_delta[t - 1] = false;
+    camera_rev[t - 1] = match qs {
+        Some(qs) => qs.pdf(ctx, qs_minus, pt),
+        None => pt.pdf_light_origin(ctx, pt_minus.unwrap()),
+    };
+    if let Some(ptm) = pt_minus {
+        camera_rev[t - 2] = match qs {
+            Some(qs) => pt.pdf(ctx, Some(qs), ptm),
+            None => pt.pdf_light(ctx, ptm),
+        };
+    }
+    if let Some(qs) = qs {
+        light_delta[s - 1] = false;
+        light_rev[s - 1] = pt.pdf(ctx, pt_minus, qs);
+        if let Some(qsm) = qs_minus {
+            light_rev[s - 2] = qs.pdf(ctx, Some(pt), qsm);
+        }
+    }
+
+    // Sum the ratios of the densities of the other strategies which could have sampled the path
+    // to this one's, strategies which would need to connect to a specular vertex are skipped
+    let mut sum_ri = 0.0;
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap0(camera_rev[i]) / remap0(camera_fwd[i]);
+        if !camera_delta[i] && !camera_delta[i - 1] {
+            sum_ri += ri;
+        }
+    }
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap0(light_rev[i]) / remap0(light_fwd[i]);
+        let delta_light = if i > 0 { light_delta[i - 1] } else { light_vertex(0).is_delta_light() };
+        if !light_delta[i] && !delta_light {
+            sum_ri += ri;
+        }
+    }
+    1.0 / (1.0 + sum_ri)
+}
+
+/// Compute the density of the lights infinitely far away emitting light in direction `w`,
+/// including the probability of choosing each light
+fn infinite_light_density(ctx: &PathContext, w: &Vector) -> f32 {
+    ctx.light_list.iter().filter(|l| l.is_infinite()).fold(0.0, |pdf, l| {
+        pdf + ctx.light_list.pmf_power(l) * l.pdf(&Point::broadcast(0.0), &-*w, ctx.time)
+    })
+}
+
+/// Compute the correction for the asymmetry of the BSDF caused by shading normals when
+/// transporting importance from the direction `w_o` to `w_i` at a vertex with geometric
+/// normal `n` and shading normal `ns`.
+/// See [Veach, Robust Monte Carlo Methods for Light Transport Simulation](http://graphics.stanford.edu/papers/veach_thesis/)
+/// Chapter 5.3
+fn correct_shading_normal(n: &Normal, ns: &Normal, w_o: &Vector, w_i: &Vector) -> f32 {
+    let num = f32::abs(linalg::dot(w_o, ns) * linalg::dot(w_i, n));
+    let denom = f32::abs(linalg::dot(w_o, n) * linalg::dot(w_i, ns));
+    if denom == 0.0 { 0.0 } else { num / denom }
+}
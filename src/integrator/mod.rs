@@ -14,37 +14,138 @@
 //! }
 //! ```
 
-use std::f32;
-use std::cmp;
+use std::{f32, iter};
 use enum_set::EnumSet;
 use rand::StdRng;
 
 use scene::Scene;
 use linalg::{self, Ray, Vector, Point};
-use geometry::{Intersection, Emitter, Instance};
-use film::Colorf;
+use geometry::{Intersection, Instance, Emitter};
+use film::{Colorf, ImageSample};
 use bxdf::{BSDF, BxDFType};
-use light::Light;
+use light::{Light, LightList};
 use sampler::{Sampler, Sample};
 use mc;
 
 pub use self::whitted::Whitted;
 pub use self::path::Path;
+pub use self::bdpt::Bdpt;
+pub use self::ppm::Ppm;
+pub use self::volpath::VolPath;
 pub use self::normals_debug::NormalsDebug;
+pub use self::photon_map::{Photon, PhotonMap};
 
 pub mod whitted;
 pub mod path;
+pub mod bdpt;
+pub mod ppm;
+pub mod volpath;
 pub mod normals_debug;
+pub mod photon_map;
+
+/// Compute the radiance arriving along a ray that left the scene without hitting
+/// anything from the lights in the scene which surround it, eg. environment lights
+pub fn escaped_radiance(light_list: &LightList, ray: &Ray) -> Colorf {
+    light_list.iter().fold(Colorf::black(), |c, l| c + l.escaped_radiance(ray))
+}
+
+/// Radiance arriving at the camera split into the direct light, which scattered at most once
+/// on its way from the lights, and the indirect light which scattered more than once. Light
+/// emitted by surfaces seen by the camera is direct. The radiance is also split by the light
+/// groups of the lights it came from.
+#[derive(Clone, Debug)]
+pub struct SplitRadiance {
+    pub direct: Colorf,
+    pub indirect: Colorf,
+    /// Radiance from the lights in each light group, indexed by the light group
+    pub light_groups: Vec<Colorf>,
+}
+
+impl SplitRadiance {
+    /// Create split radiance with no direct or indirect light for a scene with `num_light_groups`
+    /// light groups
+    pub fn new(num_light_groups: usize) -> SplitRadiance {
+        SplitRadiance { direct: Colorf::black(), indirect: Colorf::black(),
+                        light_groups: iter::repeat(Colorf::black()).take(num_light_groups).collect() }
+    }
+    /// Add light from `light` which scattered `scatterings` times on its way to the camera.
+    /// The light's group must be one of the light groups the radiance was created for
+    pub fn add(&mut self, c: &Colorf, scatterings: usize, light: &Emitter) {
+        if scatterings <= 1 {
+            self.direct = self.direct + *c;
+        } else {
+            self.indirect = self.indirect + *c;
+        }
+        if let Some(g) = light.light_group() {
+            self.light_groups[g] = self.light_groups[g] + *c;
+        }
+    }
+    /// Add the light from the lights surrounding the scene arriving along `ray`, which left the
+    /// scene without hitting anything, scaled by the path `throughput`. See `add`
+    pub fn add_escaped(&mut self, light_list: &LightList, ray: &Ray, throughput: &Colorf, scatterings: usize) {
+        for l in light_list.iter() {
+            let li = l.escaped_radiance(ray);
+            if !li.is_black() {
+                self.add(&(*throughput * li), scatterings, l);
+            }
+        }
+    }
+    /// Get the radiance from the lights in the light group `group`
+    pub fn light_group(&self, group: usize) -> Colorf {
+        self.light_groups.get(group).cloned().unwrap_or(Colorf::black())
+    }
+    /// Get the total radiance, the sum of the direct and indirect light
+    pub fn total(&self) -> Colorf {
+        self.direct + self.indirect
+    }
+}
 
 /// Trait implemented by the various integration methods that can be used to render
 /// the scene. For scene usage information see whitted and path to get information
 /// on how to specify them.
 pub trait Integrator {
+    /// Get the minimum number of passes over the image the integrator needs to render a frame,
+    /// eg. to average the estimates of photon maps traced with shrinking lookup radii. The
+    /// default is a single pass
+    fn passes(&self) -> usize { 1 }
+    /// Prepare to render pass `pass` of the frame with the lights in `light_list`, eg. by tracing
+    /// photons from the lights. This is called by the executor before rendering each pass on each
+    /// of its `num_threads` threads, with `thread` the index of the calling thread, so the work
+    /// can be split between them. The default implementation does nothing
+    fn preprocess(&self, _scene: &Scene, _light_list: &LightList, _pass: usize, _thread: usize,
+                  _num_threads: usize) {}
+    /// Finish preparing to render pass `pass` of the frame, called by the executor once all
+    /// its threads have returned from `preprocess`. The default implementation does nothing
+    fn finish_preprocess(&self, _pass: usize) {}
     /// Compute the illumination at the intersection in the scene
-    fn illumination(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
+    fn illumination(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
                     hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf;
+    /// Compute the radiance arriving at the camera along the camera ray `ray`, where `hit` is
+    /// the first surface hit by the ray, if any, and `ray` ends at it. The executor finds the hit
+    /// so it can also be used for the AOVs. Integrators which also trace paths from the lights
+    /// can contribute to other pixels in the image by pushing samples on to `splats`, these are
+    /// added to the image without filtering.
+    fn camera_illumination<'a>(&self, scene: &'a Scene, light_list: &LightList, ray: &Ray,
+                               hit: Option<&Intersection<'a, 'a>>, sampler: &mut Sampler, rng: &mut StdRng,
+                               _splats: &mut Vec<ImageSample>) -> Colorf {
+        match hit {
+            Some(h) => self.illumination(scene, light_list, ray, h, sampler, rng),
+            None => escaped_radiance(light_list, ray),
+        }
+    }
+    /// Check if the integrator supports splitting the radiance with `camera_illumination_split`,
+    /// the default is false
+    fn splits_radiance(&self) -> bool { false }
+    /// Compute the radiance arriving at the camera along the camera ray `ray` split into direct
+    /// and indirect light, the total is the radiance `camera_illumination` would compute. Returns
+    /// `None` if the integrator doesn't support splitting the radiance, which is the default.
+    fn camera_illumination_split<'a>(&self, _scene: &'a Scene, _light_list: &LightList, _ray: &Ray,
+                                     _hit: Option<&Intersection<'a, 'a>>, _sampler: &mut Sampler,
+                                     _rng: &mut StdRng) -> Option<SplitRadiance> {
+        None
+    }
     /// Compute the color of specularly reflecting light off the intersection
-    fn specular_reflection(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
+    fn specular_reflection(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
                            bsdf: &BSDF, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
         let w_o = -ray.d;
         let mut spec_refl = EnumSet::new();
@@ -60,15 +161,16 @@ pub trait Integrator {
         if pdf > 0.0 && !f.is_black() && f32::abs(linalg::dot(&w_i, &bsdf.n)) != 0.0 {
             let mut refl_ray = ray.child(&bsdf.p, &w_i);
             refl_ray.min_t = 0.001;
-            if let Some(hit) = scene.intersect(&mut refl_ray) {
-                let li = self.illumination(scene, light_list, &refl_ray, &hit, sampler, rng);
-                refl = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
-            }
+            let li = match scene.intersect(&mut refl_ray) {
+                Some(hit) => self.illumination(scene, light_list, &refl_ray, &hit, sampler, rng),
+                None => escaped_radiance(light_list, &refl_ray),
+            };
+            refl = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
         }
         refl
     }
     /// Compute the color of specularly transmitted light through the intersection
-    fn specular_transmission(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
+    fn specular_transmission(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
                              bsdf: &BSDF, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
         let w_o = -ray.d;
         let mut spec_trans = EnumSet::new();
@@ -84,26 +186,33 @@ pub trait Integrator {
         if pdf > 0.0 && !f.is_black() && f32::abs(linalg::dot(&w_i, &bsdf.n)) != 0.0 {
             let mut trans_ray = ray.child(&bsdf.p, &w_i);
             trans_ray.min_t = 0.001;
-            if let Some(hit) = scene.intersect(&mut trans_ray) {
-                let li = self.illumination(scene, light_list, &trans_ray, &hit, sampler, rng);
-                transmit = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
-            }
+            let li = match scene.intersect(&mut trans_ray) {
+                Some(hit) => self.illumination(scene, light_list, &trans_ray, &hit, sampler, rng),
+                None => escaped_radiance(light_list, &trans_ray),
+            };
+            transmit = f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
         }
         transmit
     }
-    /// Uniformly sample the contribution of a randomly chosen light in the scene
-    /// to the illumination of this BSDF at the point
+    /// Sample the contribution of a light in the scene to the illumination of this BSDF
+    /// at the point, the light is chosen randomly based on an estimate of its contribution.
+    /// Returns the contribution along with the light chosen
     ///
     /// - `w_o` outgoing direction of the light that is incident from the light being
     ///         sampled and reflecting off the surface
     /// - `bsdf` surface properties of the surface being illuminated
     /// - `light_sample` 3 random samples for the light
     /// - `bsdf_sample` 3 random samples for the bsdf
-    fn sample_one_light(&self, scene: &Scene, light_list: &[&Emitter], w_o: &Vector, p: &Point,
-                        bsdf: &BSDF, light_sample: &Sample, bsdf_sample: &Sample, time: f32) -> Colorf {
-        let l = cmp::min((light_sample.one_d * light_list.len() as f32) as usize, light_list.len() - 1);
-        self.estimate_direct(scene, w_o, p, bsdf, light_sample, bsdf_sample, light_list[l],
-                             BxDFType::non_specular(), time)
+    fn sample_one_light<'a>(&self, scene: &Scene, light_list: &LightList<'a>, w_o: &Vector, p: &Point,
+                            bsdf: &BSDF, light_sample: &Sample, bsdf_sample: &Sample, time: f32)
+                            -> (Colorf, &'a Emitter) {
+        let (light, pmf) = light_list.sample(p, light_sample.one_d);
+        if pmf == 0.0 {
+            return (Colorf::black(), light);
+        }
+        let li = self.estimate_direct(scene, w_o, p, bsdf, light_sample, bsdf_sample, light,
+                                      BxDFType::non_specular(), time) / pmf;
+        (li, light)
     }
     /// Estimate the direct light contribution to the surface being shaded by the light
     /// using multiple importance sampling
@@ -152,9 +261,11 @@ pub trait Integrator {
                 if let Some(h) = scene.intersect(&mut ray) {
                     if let Instance::Emitter(ref e) = *h.instance {
                         if e as *const Light == light as *const Light {
-                            li = e.radiance(&-w_i, &h.dg.p, &h.dg.ng, time)
+                            li = e.radiance(&-w_i, &h.dg, time)
                         }
                     }
+                } else {
+                    li = light.escaped_radiance(&ray);
                 }
                 if !li.is_black() {
                     direct_light = direct_light + f * li * f32::abs(linalg::dot(&w_i, &bsdf.n)) * w / pdf_bsdf;
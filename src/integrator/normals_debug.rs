@@ -14,9 +14,10 @@ use rand::StdRng;
 
 use scene::Scene;
 use linalg::Ray;
-use geometry::{Intersection, Emitter};
+use geometry::Intersection;
 use film::Colorf;
 use integrator::Integrator;
+use light::LightList;
 use sampler::Sampler;
 
 /// The `NormalsDebug` integrator implementing the `NormalsDebug` recursive ray tracing algorithm
@@ -24,7 +25,7 @@ use sampler::Sampler;
 pub struct NormalsDebug;
 
 impl Integrator for NormalsDebug {
-    fn illumination(&self, _: &Scene, _: &[&Emitter], _: &Ray,
+    fn illumination(&self, _: &Scene, _: &LightList, _: &Ray,
                     hit: &Intersection, _: &mut Sampler, _: &mut StdRng) -> Colorf {
         let bsdf = hit.material.bsdf(hit);
         (Colorf::new(bsdf.n.x, bsdf.n.y, bsdf.n.z) + Colorf::broadcast(1.0)) / 2.0
@@ -20,10 +20,11 @@ use rand::{StdRng, Rng};
 
 use scene::Scene;
 use linalg::{self, Ray};
-use geometry::{Intersection, Emitter, Instance};
+use geometry::{Intersection, Instance};
 use film::Colorf;
-use integrator::Integrator;
+use integrator::{Integrator, SplitRadiance};
 use bxdf::BxDFType;
+use light::LightList;
 use sampler::{Sampler, Sample};
 
 /// The path integrator implementing Path tracing with explicit light sampling
@@ -40,9 +41,11 @@ impl Path {
     }
 }
 
-impl Integrator for Path {
-    fn illumination(&self, scene: &Scene, light_list: &[&Emitter], r: &Ray,
-                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+impl Path {
+    /// Trace a path starting from the intersection `hit` found by the ray `r` and compute
+    /// the radiance arriving along the ray, split into direct and indirect light
+    fn trace(&self, scene: &Scene, light_list: &LightList, r: &Ray, hit: &Intersection,
+             sampler: &mut Sampler, rng: &mut StdRng) -> SplitRadiance {
         // TODO: We really need the memory pool now
         let num_samples = self.max_depth as usize + 1;
         let mut l_samples: Vec<_> = iter::repeat((0.0, 0.0)).take(num_samples).collect();
@@ -58,7 +61,7 @@ impl Integrator for Path {
         sampler.get_samples_1d(&mut bsdf_samples_comp[..], rng);
         sampler.get_samples_1d(&mut path_samples_comp[..], rng);
 
-        let mut illum = Colorf::black();
+        let mut illum = SplitRadiance::new(scene.num_light_groups());
         let mut path_throughput = Colorf::broadcast(1.0);
         // Track if the previous bounce was a specular one
         let mut specular_bounce = false;
@@ -69,16 +72,16 @@ impl Integrator for Path {
             if bounce == 0 || specular_bounce {
                 if let Instance::Emitter(ref e) = *current_hit.instance {
                     let w = -ray.d;
-                    illum = illum + path_throughput * e.radiance(&w, &hit.dg.p, &hit.dg.ng, ray.time);
+                    illum.add(&(path_throughput * e.radiance(&w, &current_hit.dg, ray.time)), bounce, e);
                 }
             }
             let bsdf = current_hit.material.bsdf(&current_hit);
             let w_o = -ray.d;
             let light_sample = Sample::new(&l_samples[bounce], l_samples_comp[bounce]);
             let bsdf_sample = Sample::new(&bsdf_samples[bounce], bsdf_samples_comp[bounce]);
-            let li = self.sample_one_light(scene, light_list, &w_o, &current_hit.dg.p, &bsdf,
-                                           &light_sample, &bsdf_sample, ray.time);
-            illum = illum + path_throughput * li;
+            let (li, light) = self.sample_one_light(scene, light_list, &w_o, &current_hit.dg.p, &bsdf,
+                                                    &light_sample, &bsdf_sample, ray.time);
+            illum.add(&(path_throughput * li), bounce + 1, light);
 
             // Determine the next direction to take the path by sampling the BSDF
             let path_sample = Sample::new(&path_samples[bounce], path_samples_comp[bounce]);
@@ -110,7 +113,14 @@ impl Integrator for Path {
             // Find the next vertex on the path
             match scene.intersect(&mut ray) {
                 Some(h) => current_hit = h,
-                None => break,
+                None => {
+                    // Light from lights surrounding the scene was already sampled by the direct
+                    // lighting, unless we took a specular bounce
+                    if specular_bounce {
+                        illum.add_escaped(light_list, &ray, &path_throughput, bounce + 1);
+                    }
+                    break;
+                },
             }
             bounce += 1;
         }
@@ -118,3 +128,23 @@ impl Integrator for Path {
     }
 }
 
+impl Integrator for Path {
+    fn illumination(&self, scene: &Scene, light_list: &LightList, r: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+        self.trace(scene, light_list, r, hit, sampler, rng).total()
+    }
+    fn splits_radiance(&self) -> bool { true }
+    fn camera_illumination_split<'a>(&self, scene: &'a Scene, light_list: &LightList, ray: &Ray,
+                                     hit: Option<&Intersection<'a, 'a>>, sampler: &mut Sampler,
+                                     rng: &mut StdRng) -> Option<SplitRadiance> {
+        match hit {
+            Some(h) => Some(self.trace(scene, light_list, ray, h, sampler, rng)),
+            None => {
+                let mut illum = SplitRadiance::new(scene.num_light_groups());
+                illum.add_escaped(light_list, ray, &Colorf::broadcast(1.0), 0);
+                Some(illum)
+            },
+        }
+    }
+}
+
@@ -0,0 +1,136 @@
+//! Provides the `PhotonMap` which stores the photons deposited on surfaces in the scene
+//! when tracing paths from the lights and finds the photons near a point to estimate
+//! the light arriving at it. The photons are stored in a hashed uniform grid with cells the
+//! size of the lookup radius so each lookup only needs to check the cells the lookup
+//! sphere overlaps.
+
+use std::f32;
+
+use linalg::{Point, Vector};
+use film::Colorf;
+
+/// A photon deposited on a surface in the scene
+#[derive(Clone, Copy, Debug)]
+pub struct Photon {
+    /// Position of the photon
+    pub p: Point,
+    /// Direction the photon arrived at the surface from
+    pub w_i: Vector,
+    /// Power carried by the photon
+    pub power: Colorf,
+}
+
+impl Photon {
+    /// Create a photon at `p` which arrived from `w_i` carrying `power`
+    pub fn new(p: &Point, w_i: &Vector, power: Colorf) -> Photon {
+        Photon { p: *p, w_i: *w_i, power: power }
+    }
+}
+
+/// A collection of photons which can be searched for the photons within the lookup radius of a point
+pub struct PhotonMap {
+    /// The photons sorted by the grid cell they hash to
+    photons: Vec<Photon>,
+    /// Index of the first photon of each hash bucket in `photons`, the photons of bucket `i`
+    /// are stored in `photons[cells[i]..cells[i + 1]]`
+    cells: Vec<usize>,
+    radius: f32,
+    /// Number of paths traced from the lights to find the photons
+    paths: usize,
+}
+
+impl PhotonMap {
+    /// Build the photon map for the photons found by tracing `paths` paths from the lights which will
+    /// be searched for photons within `radius` of the point looked up
+    pub fn new(photons: Vec<Photon>, radius: f32, paths: usize) -> PhotonMap {
+        let num_cells = photons.len() + 1;
+        let hashes: Vec<_> = photons.iter().map(|p| cell_hash(&grid_cell(&p.p, radius), num_cells)).collect();
+        // Sort the photons by the bucket they hash to
+        let mut cells = vec![0; num_cells + 1];
+        for h in &hashes {
+            cells[*h + 1] += 1;
+        }
+        for i in 1..cells.len() {
+            cells[i] += cells[i - 1];
+        }
+        let mut next = cells.clone();
+        let mut sorted = photons.clone();
+        for (p, h) in photons.iter().zip(hashes.iter()) {
+            sorted[next[*h]] = *p;
+            next[*h] += 1;
+        }
+        PhotonMap { photons: sorted, cells: cells, radius: radius, paths: paths }
+    }
+    /// Get the radius photons are looked up within
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+    /// Get the number of paths traced from the lights to find the photons
+    pub fn paths(&self) -> usize {
+        self.paths
+    }
+    /// Get the number of photons in the map
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+    /// Check if the map has no photons
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+    /// Call `f` for each photon within the lookup radius of `p`
+    pub fn lookup<F: FnMut(&Photon)>(&self, p: &Point, mut f: F) {
+        if self.photons.is_empty() {
+            return;
+        }
+        let r = Vector::broadcast(self.radius);
+        let lo = grid_cell(&(*p - r), self.radius);
+        let hi = grid_cell(&(*p + r), self.radius);
+        let radius_sqr = self.radius * self.radius;
+        let num_cells = self.cells.len() - 1;
+        for z in lo.2..hi.2 + 1 {
+            for y in lo.1..hi.1 + 1 {
+                for x in lo.0..hi.0 + 1 {
+                    let h = cell_hash(&(x, y, z), num_cells);
+                    for photon in &self.photons[self.cells[h]..self.cells[h + 1]] {
+                        // Other cells can hash to the same bucket, so make sure the photon is in this
+                        // cell to avoid visiting it again when checking the other cell
+                        if grid_cell(&photon.p, self.radius) == (x, y, z)
+                            && photon.p.distance_sqr(p) <= radius_sqr {
+                            f(photon);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Find the cell of the grid with cells `size` wide containing `p`
+fn grid_cell(p: &Point, size: f32) -> (i32, i32, i32) {
+    (f32::floor(p.x / size) as i32, f32::floor(p.y / size) as i32, f32::floor(p.z / size) as i32)
+}
+
+/// Hash the grid cell to one of `n` buckets
+fn cell_hash(c: &(i32, i32, i32), n: usize) -> usize {
+    let h = (c.0 as u32).wrapping_mul(73856093) ^ (c.1 as u32).wrapping_mul(19349663)
+        ^ (c.2 as u32).wrapping_mul(83492791);
+    h as usize % n
+}
+
+#[test]
+fn test_lookup() {
+    let photons: Vec<_> = (0..1000).map(|i| {
+        let f = i as f32;
+        let p = Point::new(f32::sin(f) * 4.0, f32::cos(f * 0.7) * 4.0, (f % 17.0) - 8.0);
+        Photon::new(&p, &Vector::new(0.0, 1.0, 0.0), Colorf::broadcast(f))
+    }).collect();
+    let map = PhotonMap::new(photons.clone(), 1.5, photons.len());
+    let q = Point::new(0.5, -1.0, 2.0);
+    let mut found = Vec::new();
+    map.lookup(&q, |p| found.push(p.power.r as usize));
+    found.sort();
+    let expected: Vec<_> = photons.iter().filter(|p| p.p.distance_sqr(&q) <= 1.5 * 1.5)
+        .map(|p| p.power.r as usize).collect();
+    assert!(!expected.is_empty());
+    assert_eq!(found, expected);
+}
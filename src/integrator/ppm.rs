@@ -0,0 +1,277 @@
+//! Defines the Ppm integrator which implements probabilistic progressive photon mapping.
+//! The frame is rendered in a number of passes over the image and before each pass photons are
+//! traced from the lights in parallel and stored in the pass's photon map. Camera samples follow
+//! specular reflection and transmission until they hit a non-specular surface, where direct
+//! lighting is computed by sampling the lights and indirect lighting is estimated from the
+//! density of the pass's photons around the point. The radius used to look up photons shrinks
+//! with each pass, so the bias of the image averaged over the passes is reduced as more are rendered.
+//! This handles caustics and specular-diffuse-specular paths, eg. a caustic seen through `Glass`,
+//! which the path tracer can't render.
+//!
+//! See [Knaus and Zwicker, Progressive Photon Mapping: A Probabilistic Approach](http://www.cs.jhu.edu/~misha/ReadingSeminar/Papers/Knaus11.pdf)
+//! and [Hachisuka et al., Progressive Photon Mapping](http://www.ci.i.u-tokyo.ac.jp/~hachisuka/ppm.pdf)
+//! for details on the method.
+//!
+//! # Scene Usage Example
+//! The photon mapper needs a maximum depth for the paths traced from the camera and lights,
+//! the number of photon paths to trace for each pass, the number of passes and the initial
+//! lookup radius for photons. The radius should be on the order of the distance between
+//! photons on surfaces in the scene. The `alpha` parameter controlling how quickly the radius
+//! is reduced is optional and defaults to 2/3. Each pass takes the film's samples per pixel, so
+//! the total samples per pixel are multiplied by the number of passes. When rendering with a
+//! time limit or noise threshold more passes are rendered until the render is stopped.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "ppm",
+//!     "max_depth": 8,
+//!     "photons": 200000,
+//!     "passes": 16,
+//!     "radius": 0.1,
+//!     "alpha": 0.66
+//! }
+//! ```
+
+use std::{f32, mem};
+use std::sync::{Mutex, RwLock};
+use rand::{Rng, StdRng};
+
+use scene::Scene;
+use linalg::{self, Point, Ray, Vector};
+use geometry::{Intersection, Instance, Emitter, Boundable};
+use film::Colorf;
+use integrator::{Integrator, Photon, PhotonMap};
+use bxdf::{BSDF, BxDFType};
+use light::{Light, LightList};
+use sampler::{Sampler, Sample};
+
+/// The Ppm integrator implementing probabilistic progressive photon mapping
+pub struct Ppm {
+    max_depth: u32,
+    /// Number of paths traced from the lights for each pass
+    photons: usize,
+    /// Minimum number of passes rendered for each frame
+    passes: usize,
+    /// Lookup radius used for the first pass
+    radius: f32,
+    /// Fraction of the photons found in a pass which are kept when reducing the radius for the next
+    alpha: f32,
+    /// Photons traced by the threads for the pass being prepared
+    pass_photons: Mutex<Vec<Photon>>,
+    /// The photon map for the pass being rendered
+    map: RwLock<Option<PhotonMap>>,
+}
+
+impl Ppm {
+    /// Create a new photon mapper with the max depth for paths, tracing `photons` paths from
+    /// the lights for each of at least `passes` passes. The first pass looks up photons within
+    /// `radius` and `alpha` controls how quickly the radius is reduced for later passes
+    pub fn new(max_depth: u32, photons: usize, passes: usize, radius: f32, alpha: f32) -> Ppm {
+        assert!(passes > 0, "The photon mapper must use at least one pass");
+        assert!(alpha > 0.0 && alpha < 1.0, "alpha must be in (0, 1)");
+        Ppm { max_depth: max_depth, photons: photons, passes: passes, radius: radius, alpha: alpha,
+              pass_photons: Mutex::new(Vec::new()), map: RwLock::new(None) }
+    }
+    /// Get the photon lookup radius for pass `pass`, the squared radius is scaled by
+    /// `(i + 1 + alpha) / (i + 2)` after each pass `i`
+    fn pass_radius(&self, pass: usize) -> f32 {
+        let mut radius_sqr = self.radius * self.radius;
+        for i in 0..pass {
+            radius_sqr *= (i as f32 + 1.0 + self.alpha) / (i as f32 + 2.0);
+        }
+        f32::sqrt(radius_sqr)
+    }
+    /// Trace `paths` paths from the lights and return the photons stored along them. Photons
+    /// are only stored after the first bounce as direct lighting is computed by sampling the lights
+    fn trace_photons(&self, scene: &Scene, light_list: &LightList, paths: usize, rng: &mut StdRng) -> Vec<Photon> {
+        let (scene_center, scene_radius) = scene.bvh.bounds(0.0, 0.0).bounding_sphere();
+        let shutter = scene.active_camera().shutter_time();
+        let mut photons = Vec::new();
+        for _ in 0..paths {
+            let time = shutter.0 + rng.next_f32() * (shutter.1 - shutter.0);
+            let (light, pmf) = light_list.sample_power(rng.next_f32());
+            let (mut ray, mut beta) = match emit_photon(light, pmf, &scene_center, scene_radius, time, rng) {
+                Some(p) => p,
+                None => continue,
+            };
+            for depth in 0..self.max_depth {
+                let hit = match scene.intersect(&mut ray) {
+                    Some(h) => h,
+                    None => break,
+                };
+                let bsdf = hit.material.bsdf(&hit);
+                let w_o = -ray.d;
+                if depth > 0 && bsdf.num_matching(BxDFType::non_specular()) > 0 {
+                    photons.push(Photon::new(&bsdf.p, &w_o, beta));
+                }
+                let sample = Sample::new(&(rng.next_f32(), rng.next_f32()), rng.next_f32());
+                let (f, w_i, pdf, _) = bsdf.sample(&w_o, BxDFType::all(), &sample);
+                if f.is_black() || pdf == 0.0 {
+                    break;
+                }
+                let next_beta = beta * f * f32::abs(linalg::dot(&w_i, &bsdf.n)) / pdf;
+                // Terminate photons with Russian roulette based on how much power they lost
+                // at the bounce so the photons stored have similar power
+                let cont_prob = f32::min(1.0, next_beta.luminance() / beta.luminance());
+                if !(rng.next_f32() < cont_prob) {
+                    break;
+                }
+                beta = next_beta / cont_prob;
+                ray = ray.child(&bsdf.p, &w_i.normalized());
+                ray.min_t = 0.001;
+            }
+        }
+        photons
+    }
+    /// Estimate the light reflected by the BSDF towards `w_o` from the density of the
+    /// photons in the map around the shading point
+    fn photon_estimate(&self, map: &PhotonMap, w_o: &Vector, bsdf: &BSDF) -> Colorf {
+        let mut sum = Colorf::black();
+        map.lookup(&bsdf.p, |photon| {
+            sum = sum + bsdf.eval(w_o, &photon.w_i, BxDFType::all()) * photon.power;
+        });
+        let radius = map.radius();
+        sum / (map.paths() as f32 * f32::consts::PI * radius * radius)
+    }
+}
+
+impl Integrator for Ppm {
+    fn passes(&self) -> usize {
+        self.passes
+    }
+    fn preprocess(&self, scene: &Scene, light_list: &LightList, _pass: usize, thread: usize,
+                  num_threads: usize) {
+        let mut rng = match StdRng::new() {
+            Ok(r) => r,
+            Err(e) => { println!("Failed to get StdRng, {}", e); return }
+        };
+        // Split the paths for the pass evenly between the threads
+        let paths = self.photons / num_threads + if thread < self.photons % num_threads { 1 } else { 0 };
+        let photons = self.trace_photons(scene, light_list, paths, &mut rng);
+        self.pass_photons.lock().unwrap().extend(photons);
+    }
+    fn finish_preprocess(&self, pass: usize) {
+        let photons = mem::replace(&mut *self.pass_photons.lock().unwrap(), Vec::new());
+        *self.map.write().unwrap() = Some(PhotonMap::new(photons, self.pass_radius(pass), self.photons));
+    }
+    fn illumination(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+        let bsdf = hit.material.bsdf(hit);
+        let w_o = -ray.d;
+        let mut illum = Colorf::black();
+        // Camera paths are only continued through specular bounces, so we need to include
+        // light emitted by any surface they hit
+        if let Instance::Emitter(ref e) = *hit.instance {
+            illum = illum + e.radiance(&w_o, &hit.dg, ray.time);
+        }
+        if bsdf.num_matching(BxDFType::non_specular()) > 0 {
+            let mut light_2d = [(0.0, 0.0)];
+            let mut light_1d = [0.0];
+            let mut bsdf_2d = [(0.0, 0.0)];
+            let mut bsdf_1d = [0.0];
+            sampler.get_samples_2d(&mut light_2d[..], rng);
+            sampler.get_samples_1d(&mut light_1d[..], rng);
+            sampler.get_samples_2d(&mut bsdf_2d[..], rng);
+            sampler.get_samples_1d(&mut bsdf_1d[..], rng);
+            let light_sample = Sample::new(&light_2d[0], light_1d[0]);
+            let bsdf_sample = Sample::new(&bsdf_2d[0], bsdf_1d[0]);
+            illum = illum + self.sample_one_light(scene, light_list, &w_o, &hit.dg.p, &bsdf,
+                                                  &light_sample, &bsdf_sample, ray.time).0;
+            if let Some(ref map) = *self.map.read().unwrap() {
+                illum = illum + self.photon_estimate(map, &w_o, &bsdf);
+            }
+        }
+        if ray.depth < self.max_depth {
+            illum = illum + self.specular_reflection(scene, light_list, ray, &bsdf, sampler, rng);
+            illum = illum + self.specular_transmission(scene, light_list, ray, &bsdf, sampler, rng);
+        }
+        illum
+    }
+}
+
+/// Sample the ray a photon leaves the light `light` along, which was chosen with probability
+/// `pmf`, and the photon's power. Returns None if no light is emitted along the ray sampled
+fn emit_photon(light: &Emitter, pmf: f32, scene_center: &Point, scene_radius: f32, time: f32, rng: &mut StdRng)
+               -> Option<(Ray, Colorf)> {
+    let pos_sample = (rng.next_f32(), rng.next_f32());
+    let dir_sample = (rng.next_f32(), rng.next_f32());
+    let (le, ray, n, pdf_pos, pdf_dir) = light.sample_emission(scene_center, scene_radius, &pos_sample,
+                                                               &dir_sample, time);
+    if pmf == 0.0 || pdf_pos == 0.0 || pdf_dir == 0.0 || le.is_black() {
+        None
+    } else {
+        Some((ray, le * f32::abs(linalg::dot(&n, &ray.d)) / (pmf * pdf_pos * pdf_dir)))
+    }
+}
+
+#[test]
+fn test_photon_estimate_convergence() {
+    use linalg::{Point, Normal};
+    use geometry::{DifferentialGeometry, Rectangle};
+    use bxdf::{BxDF, Lambertian};
+
+    // Photons on a white diffuse plane with irradiance 1 + 4x^2, the estimate at the origin
+    // averages it over the lookup disk so each pass is biased by the square of its radius.
+    // Averaging the passes should converge to the true radiance 1 / pi as the radius shrinks
+    let ppm = Ppm::new(1, 1, 1, 0.8, 2.0 / 3.0);
+    let geom = Rectangle::new(4.0, 4.0);
+    let dg = DifferentialGeometry::with_normal(&Point::broadcast(0.0), &Normal::new(0.0, 0.0, 1.0), 0.5, 0.5,
+                                               &Vector::new(1.0, 0.0, 0.0), &Vector::new(0.0, 1.0, 0.0), &geom);
+    let bxdfs = vec![Box::new(Lambertian::new(&Colorf::broadcast(1.0))) as Box<BxDF + Send + Sync>];
+    let bsdf = BSDF::new(&bxdfs[..], 1.0, &dg);
+    let w = Vector::new(0.0, 0.0, 1.0);
+    let spacing = 0.02;
+    let expected = f32::consts::FRAC_1_PI;
+    let mut sum = 0.0;
+    let mut errors = Vec::new();
+    for pass in 0..64 {
+        // Shift the grid of photons for each pass so the passes don't all see the same photons
+        let offset = ((pass as f32 * 0.618).fract() * spacing, (pass as f32 * 0.414).fract() * spacing);
+        let mut photons = Vec::new();
+        for i in 0..100 {
+            for j in 0..100 {
+                let x = -1.0 + offset.0 + i as f32 * spacing;
+                let y = -1.0 + offset.1 + j as f32 * spacing;
+                let power = Colorf::broadcast((1.0 + 4.0 * x * x) * spacing * spacing);
+                photons.push(Photon::new(&Point::new(x, y, 0.0), &w, power));
+            }
+        }
+        let map = PhotonMap::new(photons, ppm.pass_radius(pass), 1);
+        sum += ppm.photon_estimate(&map, &w, &bsdf).r;
+        if pass == 0 || pass == 3 || pass == 15 || pass == 63 {
+            errors.push(f32::abs(sum / (pass + 1) as f32 - expected));
+        }
+    }
+    for e in errors.windows(2) {
+        assert!(e[1] < e[0]);
+    }
+    assert!(errors[3] < 0.5 * errors[0]);
+}
+
+#[test]
+fn test_scaled_light_photon_power() {
+    use std::sync::Arc;
+    use linalg::{Transform, AnimatedTransform};
+    use geometry::Rectangle;
+    use material::Matte;
+    use film::{AnimatedColor, ColorKeyframe};
+    use texture;
+
+    // The photons from a diffuse area light carry its power, pi * area * radiance, which must
+    // be found from the area of the light after it's scaled
+    let white = Arc::new(texture::ConstantColor::new(&Colorf::broadcast(1.0)));
+    let mat = Arc::new(Matte::new(white, Arc::new(texture::ConstantScalar::new(0.0))));
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(2.0), 0.0)]);
+    let transform = Transform::rotate_y(45.0) * Transform::scale(&Vector::new(2.0, 3.0, 1.0));
+    let light = Emitter::area(Arc::new(Rectangle::new(2.0, 1.0)), mat, emission,
+                              AnimatedTransform::unanimated(&transform), "light".to_owned());
+    let expected = f32::consts::PI * 12.0 * 2.0;
+    let mut rng = StdRng::new().unwrap();
+    let n = 1000;
+    let mut flux = 0.0;
+    for _ in 0..n {
+        let (_, power) = emit_photon(&light, 1.0, &Point::broadcast(0.0), 10.0, 0.0, &mut rng).unwrap();
+        flux += power.r;
+    }
+    assert!(f32::abs(flux / n as f32 - expected) < 1e-3 * expected);
+}
@@ -0,0 +1,348 @@
+//! Defines the VolPath integrator which implements volumetric path tracing, extending
+//! path tracing to scenes with participating media such as fog, smoke or tinted liquids.
+//! At each step along the path a point where the ray is scattered by the medium it's
+//! travelling through is sampled, if the ray is scattered before reaching the next surface
+//! the path continues from the point in the medium in a direction sampled from the
+//! medium's phase function. Direct lighting is computed at both surfaces and points in media,
+//! with shadow rays attenuated by the media they pass through. Surfaces with the `interface`
+//! material only mark the boundary between media and are passed through by the paths.
+//!
+//! See [PBR](http://pbrt.org/) Chapter 15 for details on the implementation.
+//!
+//! # Scene Usage Example
+//! The volumetric path tracer takes the same parameters as the path tracer, a minimum
+//! depth after which paths are terminated with Russian roulette and a maximum path depth.
+//! Scattering events in media count towards the depth of the path.
+//!
+//! ```json
+//! "integrator": {
+//!     "type": "volpath",
+//!     "min_depth": 3,
+//!     "max_depth": 8
+//! }
+//! ```
+
+use std::f32;
+use enum_set::EnumSet;
+use rand::{Rng, StdRng};
+
+use scene::Scene;
+use linalg::{self, Ray, Vector, Point};
+use geometry::{Intersection, Instance, Emitter};
+use film::{Colorf, ImageSample};
+use integrator::{Integrator, SplitRadiance};
+use bxdf::{BSDF, BxDFType};
+use light::{Light, LightList};
+use media::{Medium, HenyeyGreenstein};
+use sampler::{Sampler, Sample};
+use mc;
+
+/// The volumetric path tracer
+#[derive(Clone, Copy, Debug)]
+pub struct VolPath {
+    min_depth: u32,
+    max_depth: u32,
+}
+
+/// The function describing how light is scattered at a vertex of the path, either
+/// the BSDF of a surface or the phase function of a medium
+enum Scattering<'a> {
+    Surface(&'a BSDF<'a>),
+    Medium(&'a HenyeyGreenstein),
+}
+
+impl<'a> Scattering<'a> {
+    /// Evaluate the light arriving from `w_i` scattered towards `w_o`, for surfaces
+    /// this includes the cosine term at the surface. Only the BxDFs matching `flags` are
+    /// evaluated for surfaces
+    fn f(&self, w_o: &Vector, w_i: &Vector, flags: EnumSet<BxDFType>) -> Colorf {
+        match *self {
+            Scattering::Surface(bsdf) => bsdf.eval(w_o, w_i, flags) * f32::abs(linalg::dot(w_i, &bsdf.n)),
+            Scattering::Medium(phase) => Colorf::broadcast(phase.eval(w_o, w_i)),
+        }
+    }
+    /// Compute the density of sampling `w_i` when scattering light towards `w_o`
+    fn pdf(&self, w_o: &Vector, w_i: &Vector, flags: EnumSet<BxDFType>) -> f32 {
+        match *self {
+            Scattering::Surface(bsdf) => bsdf.pdf(w_o, w_i, flags),
+            Scattering::Medium(phase) => phase.eval(w_o, w_i),
+        }
+    }
+    /// Sample a direction `w_i` that light scattered towards `w_o` arrives from. Returns the
+    /// scattered light as computed by `f`, the direction, its density and whether a specular
+    /// BxDF was sampled
+    fn sample(&self, w_o: &Vector, sample: &Sample, flags: EnumSet<BxDFType>) -> (Colorf, Vector, f32, bool) {
+        match *self {
+            Scattering::Surface(bsdf) => {
+                let (f, w_i, pdf, sampled_type) = bsdf.sample(w_o, flags, sample);
+                (f * f32::abs(linalg::dot(&w_i, &bsdf.n)), w_i, pdf, sampled_type.contains(&BxDFType::Specular))
+            },
+            Scattering::Medium(phase) => {
+                let (w_i, pdf) = phase.sample(w_o, &sample.two_d);
+                (Colorf::broadcast(pdf), w_i, pdf, false)
+            },
+        }
+    }
+}
+
+impl VolPath {
+    /// Create a new volumetric path tracer with the min and max length desired for paths
+    pub fn new(min_depth: u32, max_depth: u32) -> VolPath {
+        VolPath { min_depth: min_depth, max_depth: max_depth }
+    }
+    /// Compute the radiance arriving along `ray` travelling through `medium`, where `hit` is
+    /// the first surface hit by the ray, if any. The radiance is split into direct and indirect light
+    fn radiance<'a>(&self, scene: &'a Scene, light_list: &LightList, ray: &Ray, hit: Option<Intersection<'a, 'a>>,
+                    medium: Option<&'a Medium>, sampler: &mut Sampler, rng: &mut StdRng) -> SplitRadiance {
+        let mut ray = *ray;
+        let mut hit = hit;
+        let mut medium = medium;
+        let mut illum = SplitRadiance::new(scene.num_light_groups());
+        let mut path_throughput = Colorf::broadcast(1.0);
+        // Track if the previous bounce was a specular one
+        let mut specular_bounce = false;
+        let mut bounces = 0;
+        loop {
+            // Find if the ray is scattered by the medium it's travelling through before reaching the surface
+            let mut scatter_t = None;
+            if let Some(m) = medium {
+                let (weight, t) = m.sample(&ray, sampler, rng);
+                path_throughput = path_throughput * weight;
+                scatter_t = t;
+            }
+            if path_throughput.is_black() {
+                break;
+            }
+            let w_o = -ray.d.normalized();
+            if let Some(t) = scatter_t {
+                if bounces >= self.max_depth {
+                    break;
+                }
+                let p = ray.at(t);
+                let phase = medium.unwrap().phase();
+                let (li, light) = direct_lighting(scene, light_list, &Scattering::Medium(phase), &p, None, &w_o,
+                                                  medium, ray.time, sampler, rng);
+                illum.add(&(path_throughput * li), bounces as usize + 1, light);
+                // Sampling the phase function has a density equal to its value, so the throughput is unchanged
+                let mut samples = [(0.0, 0.0)];
+                sampler.get_samples_2d(&mut samples[..], rng);
+                let (w_i, _) = phase.sample(&w_o, &samples[0]);
+                ray = ray.child(&p, &w_i);
+                specular_bounce = false;
+            } else {
+                let current_hit = match hit {
+                    Some(h) => h,
+                    None => {
+                        // Light from lights surrounding the scene was already sampled by the direct
+                        // lighting, unless we took a specular bounce
+                        if bounces == 0 || specular_bounce {
+                            illum.add_escaped(light_list, &ray, &path_throughput, bounces as usize);
+                        }
+                        break;
+                    },
+                };
+                if bounces == 0 || specular_bounce {
+                    if let Instance::Emitter(ref e) = *current_hit.instance {
+                        illum.add(&(path_throughput * e.radiance(&w_o, &current_hit.dg, ray.time)), bounces as usize,
+                                  e);
+                    }
+                }
+                let bsdf = current_hit.material.bsdf(&current_hit);
+                // Surfaces which only mark the boundary between media are passed through
+                // without counting as a bounce
+                if bsdf.num_matching(BxDFType::all()) == 0 {
+                    medium = medium_after(&current_hit, &ray.d, medium);
+                    let d = ray.d;
+                    ray = ray.child(&current_hit.dg.p, &d);
+                    ray.min_t = 0.001;
+                    hit = scene.intersect(&mut ray);
+                    continue;
+                }
+                if bounces >= self.max_depth {
+                    break;
+                }
+                let scattering = Scattering::Surface(&bsdf);
+                let (li, light) = direct_lighting(scene, light_list, &scattering, &current_hit.dg.p,
+                                                  Some(&current_hit), &w_o, medium, ray.time, sampler, rng);
+                illum.add(&(path_throughput * li), bounces as usize + 1, light);
+                let sample = next_sample(sampler, rng);
+                let (f, w_i, pdf, specular) = scattering.sample(&w_o, &sample, BxDFType::all());
+                if f.is_black() || pdf == 0.0 {
+                    break;
+                }
+                path_throughput = path_throughput * f / pdf;
+                specular_bounce = specular;
+                medium = medium_after(&current_hit, &w_i, medium);
+                ray = ray.child(&bsdf.p, &w_i.normalized());
+                ray.min_t = 0.001;
+            }
+            bounces += 1;
+            // Check if we're beyond the min depth at which point we start trying to
+            // terminate rays using Russian Roulette
+            if bounces > self.min_depth {
+                let cont_prob = f32::min(1.0, path_throughput.luminance());
+                if rng.next_f32() > cont_prob {
+                    break;
+                }
+                path_throughput = path_throughput / cont_prob;
+            }
+            hit = scene.intersect(&mut ray);
+        }
+        illum
+    }
+}
+
+impl Integrator for VolPath {
+    /// Compute the illumination at the hit point seen by `ray`, the ray is assumed to
+    /// be travelling through the medium filling the scene
+    fn illumination(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
+                    hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+        let medium = scene.medium.as_ref().map(|m| &**m as &Medium);
+        self.radiance(scene, light_list, ray, Some(*hit), medium, sampler, rng).total()
+    }
+    fn camera_illumination<'a>(&self, scene: &'a Scene, light_list: &LightList, ray: &Ray,
+                               hit: Option<&Intersection<'a, 'a>>, sampler: &mut Sampler, rng: &mut StdRng,
+                               _: &mut Vec<ImageSample>) -> Colorf {
+        self.camera_illumination_split(scene, light_list, ray, hit, sampler, rng).unwrap().total()
+    }
+    fn splits_radiance(&self) -> bool { true }
+    fn camera_illumination_split<'a>(&self, scene: &'a Scene, light_list: &LightList, ray: &Ray,
+                                     hit: Option<&Intersection<'a, 'a>>, sampler: &mut Sampler,
+                                     rng: &mut StdRng) -> Option<SplitRadiance> {
+        let medium = scene.medium.as_ref().map(|m| &**m as &Medium);
+        Some(self.radiance(scene, light_list, ray, hit.cloned(), medium, sampler, rng))
+    }
+}
+
+/// Take a 2D and 1D sample from the sampler
+fn next_sample(sampler: &mut Sampler, rng: &mut StdRng) -> Sample {
+    let mut two_d = [(0.0, 0.0)];
+    let mut one_d = [0.0];
+    sampler.get_samples_2d(&mut two_d[..], rng);
+    sampler.get_samples_1d(&mut one_d[..], rng);
+    Sample::new(&two_d[0], one_d[0])
+}
+
+/// Check if the surface hit only marks the boundary between media
+fn is_interface(hit: &Intersection) -> bool {
+    hit.material.bsdf(hit).num_matching(BxDFType::all()) == 0
+}
+
+/// Find the medium a ray leaving the surface hit in direction `w` travels through, surfaces
+/// which aren't a boundary between media leave the ray in the `medium` it was already in
+fn medium_after<'a>(hit: &Intersection<'a, 'a>, w: &Vector, medium: Option<&'a Medium>) -> Option<&'a Medium> {
+    match hit.instance.medium_interface() {
+        Some(mi) => mi.crossing(w, &hit.dg.ng).map(|m| m as &Medium),
+        None => medium,
+    }
+}
+
+/// Compute the transmittance along the shadow ray starting in `medium`. The ray passes through
+/// surfaces which only mark the boundary between media but is blocked by any others
+fn transmittance<'a>(scene: &'a Scene, ray: &Ray, medium: Option<&'a Medium>, sampler: &mut Sampler,
+                     rng: &mut StdRng) -> Colorf {
+    let mut ray = *ray;
+    let mut medium = medium;
+    let mut tr = Colorf::broadcast(1.0);
+    loop {
+        let end = ray.max_t;
+        let hit = scene.intersect(&mut ray);
+        if let Some(m) = medium {
+            tr = tr * m.transmittance(&ray, sampler, rng);
+        }
+        match hit {
+            Some(h) => {
+                if !is_interface(&h) {
+                    return Colorf::black();
+                }
+                medium = medium_after(&h, &ray.d, medium);
+                let t = ray.max_t;
+                ray = Ray::segment(&ray.at(t), &ray.d, 0.001, end - t, ray.time);
+            },
+            None => return tr,
+        }
+    }
+}
+
+/// Find the radiance arriving at `p` from `light` along the direction `w_i`, attenuated by the
+/// media between them. The radiance is black if the ray hits something other than the light
+fn light_along<'a>(scene: &'a Scene, light: &Emitter, p: &Point, w_i: &Vector, medium: Option<&'a Medium>,
+                   time: f32, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
+    let mut ray = Ray::segment(p, w_i, 0.001, f32::INFINITY, time);
+    let mut medium = medium;
+    let mut tr = Colorf::broadcast(1.0);
+    loop {
+        let hit = scene.intersect(&mut ray);
+        if let Some(m) = medium {
+            tr = tr * m.transmittance(&ray, sampler, rng);
+        }
+        match hit {
+            Some(h) => {
+                if let Instance::Emitter(ref e) = *h.instance {
+                    if e as *const Emitter == light as *const Emitter {
+                        return tr * e.radiance(&-*w_i, &h.dg, time);
+                    }
+                }
+                if !is_interface(&h) {
+                    return Colorf::black();
+                }
+                medium = medium_after(&h, w_i, medium);
+                ray = Ray::segment(&h.dg.p, w_i, 0.001, f32::INFINITY, time);
+            },
+            None => return tr * light.escaped_radiance(&ray),
+        }
+    }
+}
+
+/// Estimate the direct lighting scattered towards `w_o` at `p` from a light chosen by the
+/// light list, using multiple importance sampling of the light and the scattering function.
+/// `surface` is the surface `p` is on, if it's not in a medium. The shadow rays start in `medium`,
+/// or the medium on the side of the surface they leave through, and are attenuated by the media
+/// they pass through. Returns the direct lighting along with the light chosen
+fn direct_lighting<'a, 'b>(scene: &'a Scene, light_list: &LightList<'b>, scattering: &Scattering, p: &Point,
+                           surface: Option<&Intersection<'a, 'a>>, w_o: &Vector, medium: Option<&'a Medium>,
+                           time: f32, sampler: &mut Sampler, rng: &mut StdRng) -> (Colorf, &'b Emitter) {
+    let medium_along = |w: &Vector| {
+        match surface {
+            Some(h) => medium_after(h, w, medium),
+            None => medium,
+        }
+    };
+    let light_sample = next_sample(sampler, rng);
+    let scattering_sample = next_sample(sampler, rng);
+    let (light, pmf) = light_list.sample(p, light_sample.one_d);
+    if pmf == 0.0 {
+        return (Colorf::black(), light);
+    }
+    let flags = BxDFType::non_specular();
+    let mut direct_light = Colorf::black();
+    // Sample the light first
+    let (li, w_i, pdf_light, occlusion) = light.sample_incident(p, &light_sample.two_d, time);
+    if pdf_light > 0.0 && !li.is_black() {
+        let f = scattering.f(w_o, &w_i, flags);
+        if !f.is_black() {
+            let tr = transmittance(scene, &occlusion.ray, medium_along(&w_i), sampler, rng);
+            if !tr.is_black() {
+                let w = if light.delta_light() {
+                    1.0
+                } else {
+                    mc::power_heuristic(1.0, pdf_light, 1.0, scattering.pdf(w_o, &w_i, flags))
+                };
+                direct_light = f * li * tr * w / pdf_light;
+            }
+        }
+    }
+    // Now sample the scattering function
+    if !light.delta_light() {
+        let (f, w_i, pdf, _) = scattering.sample(w_o, &scattering_sample, flags);
+        if pdf > 0.0 && !f.is_black() {
+            let pdf_light = light.pdf(p, &w_i, time);
+            if pdf_light > 0.0 {
+                let w = mc::power_heuristic(1.0, pdf, 1.0, pdf_light);
+                let li = light_along(scene, light, p, &w_i, medium_along(&w_i), time, sampler, rng);
+                direct_light = direct_light + f * li * w / pdf;
+            }
+        }
+    }
+    (direct_light / pmf, light)
+}
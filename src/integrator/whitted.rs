@@ -17,11 +17,11 @@ use rand::StdRng;
 
 use scene::Scene;
 use linalg::{self, Ray};
-use geometry::{Intersection, Emitter, Instance};
+use geometry::{Intersection, Instance};
 use film::Colorf;
 use integrator::Integrator;
 use bxdf::BxDFType;
-use light::Light;
+use light::{Light, LightList};
 use sampler::Sampler;
 
 /// The Whitted integrator implementing the Whitted recursive ray tracing algorithm
@@ -37,7 +37,7 @@ impl Whitted {
 }
 
 impl Integrator for Whitted {
-    fn illumination(&self, scene: &Scene, light_list: &[&Emitter], ray: &Ray,
+    fn illumination(&self, scene: &Scene, light_list: &LightList, ray: &Ray,
                     hit: &Intersection, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf {
         let bsdf = hit.material.bsdf(hit);
         let w_o = -ray.d;
@@ -47,11 +47,11 @@ impl Integrator for Whitted {
         if ray.depth == 0 {
             if let Instance::Emitter(ref e) = *hit.instance {
                 let w = -ray.d;
-                illum = illum + e.radiance(&w, &hit.dg.p, &hit.dg.ng, ray.time);
+                illum = illum + e.radiance(&w, &hit.dg, ray.time);
             }
         }
 
-        for light in light_list {
+        for light in light_list.iter() {
             let (li, w_i, pdf, occlusion) = light.sample_incident(&hit.dg.p, &sample_2d[0], ray.time);
             let f = bsdf.eval(&w_o, &w_i, BxDFType::all());
             if !li.is_black() && !f.is_black() && !occlusion.occluded(scene) {
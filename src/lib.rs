@@ -7,7 +7,7 @@
 //! tray\_rust is a toy physically based ray tracer built off of the techniques
 //! discussed in [Physically Based Rendering](http://pbrt.org/). It began life as a port of
 //! [tray](https://github.com/Twinklebear/tray) to [Rust](http://www.rust-lang.org) to check out the language.
-//! The renderer is currently capable of path tracing, supports triangle meshes (MTL support coming soon),
+//! The renderer is currently capable of path tracing, supports OBJ (with MTL materials) and PLY triangle meshes,
 //! and various physically based material models (including measured data from the
 //! [MERL BRDF Database](http://www.merl.com/brdf/)). tray\_rust also supports rigid body animation along
 //! B-spline paths and distributed rendering.
@@ -40,10 +40,6 @@
 //! ## TODO
 //!
 //! - More material models (eg. more microfacet models, rough glass, etc.)
-//! - Textures
-//! - Support for using an OBJ's associated MTL files
-//! - Bump mapping
-//! - [Subsurface scattering?](http://en.wikipedia.org/wiki/Subsurface_scattering)
 //! - [Vertex Connection and Merging?](http://iliyan.com/publications/VertexMerging)
 //! 
 //! ## Sample Renders
@@ -95,6 +91,8 @@ extern crate image;
 extern crate bincode;
 extern crate mio;
 extern crate la;
+extern crate tobj;
+extern crate flate2;
 
 pub mod linalg;
 pub mod film;
@@ -104,7 +102,9 @@ pub mod integrator;
 pub mod scene;
 pub mod bxdf;
 pub mod material;
+pub mod texture;
 pub mod light;
+pub mod media;
 pub mod mc;
 pub mod partition;
 pub mod exec;
@@ -0,0 +1,91 @@
+//! Provides the `EmissionMap` which varies the emission of an area light over its surface
+//! using a texture, eg. to make a TV screen or lightbox showing an image. If the light's geometry
+//! can be sampled through its parameterization the surface is importance sampled based on the
+//! power emitted by each part of it, so bright regions of the texture receive more samples.
+
+use std::sync::Arc;
+
+use geometry::{Sampleable, DifferentialGeometry, Rectangle};
+use linalg;
+use film::Colorf;
+use texture::Texture;
+use mc::Distribution2D;
+
+/// Resolution of the grid the texture is evaluated on over the surface parameterization
+/// to build the sampling distribution
+const MAP_RESOLUTION: usize = 256;
+
+/// A texture applied to the emission of an area light along with the distribution
+/// used to sample the light's surface based on it
+pub struct EmissionMap {
+    texture: Arc<Texture + Send + Sync>,
+    /// Distribution over the parameterization of the geometry, None if the
+    /// geometry can't be sampled through its parameterization
+    distribution: Option<Distribution2D>,
+    /// Average color of the texture over the surface
+    average: Colorf,
+}
+
+impl EmissionMap {
+    /// Create the emission map applying `texture` to the surface of `geom`
+    pub fn new<G: Sampleable + ?Sized>(texture: Arc<Texture + Send + Sync>, geom: &G) -> EmissionMap {
+        // Geometry we can't sample through its parameterization still has its texture averaged over
+        // the parameterization, using a unit square in place of its surface
+        let square = Rectangle::new(1.0, 1.0);
+        let mut parameterized = true;
+        let mut func = Vec::with_capacity(MAP_RESOLUTION * MAP_RESOLUTION);
+        let mut average = Colorf::black();
+        let mut total_area = 0.0;
+        for y in 0..MAP_RESOLUTION {
+            for x in 0..MAP_RESOLUTION {
+                let uv = ((x as f32 + 0.5) / MAP_RESOLUTION as f32, (y as f32 + 0.5) / MAP_RESOLUTION as f32);
+                let dg = match geom.sample_parameterization(&uv) {
+                    Some(dg) => dg,
+                    None => {
+                        parameterized = false;
+                        square.sample_parameterization(&uv).unwrap()
+                    },
+                };
+                // Weight the texture by the area of the surface this region of the parameterization covers
+                let area = linalg::cross(&dg.dp_du, &dg.dp_dv).length();
+                let c = texture.sample_color(&dg);
+                func.push(c.luminance() * area);
+                average = average + c * area;
+                total_area += area;
+            }
+        }
+        let distribution = if parameterized {
+            Some(Distribution2D::new(&func[..], MAP_RESOLUTION, MAP_RESOLUTION))
+        } else {
+            None
+        };
+        let average = if total_area > 0.0 { average / total_area } else { Colorf::black() };
+        EmissionMap { texture: texture, distribution: distribution, average: average }
+    }
+    /// Look up the color of the emission at the point on the surface
+    pub fn color(&self, dg: &DifferentialGeometry) -> Colorf {
+        self.texture.sample_color(dg)
+    }
+    /// Sample a point on the surface of `geom` based on the emission. Returns None if the
+    /// geometry can't be sampled through its parameterization, in which case the geometry's own
+    /// sampling should be used
+    pub fn sample<'a, G: Sampleable + ?Sized>(&self, geom: &'a G, samples: &(f32, f32))
+                                              -> Option<DifferentialGeometry<'a>> {
+        match self.distribution {
+            Some(ref d) => geom.sample_parameterization(&d.sample_continuous(samples).0),
+            None => None,
+        }
+    }
+    /// Compute the pdf with respect to surface area of sampling the point on the surface.
+    /// Returns None if the geometry can't be sampled through its parameterization
+    pub fn pdf(&self, dg: &DifferentialGeometry) -> Option<f32> {
+        self.distribution.as_ref().map(|d| {
+            let area = linalg::cross(&dg.dp_du, &dg.dp_dv).length();
+            if area > 0.0 { d.pdf(&(dg.u, dg.v)) / area } else { 0.0 }
+        })
+    }
+    /// Get the average color of the emission over the surface
+    pub fn average(&self) -> Colorf {
+        self.average
+    }
+}
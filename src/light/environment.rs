@@ -0,0 +1,109 @@
+//! Provides the environment map used by environment lights, which surround the scene
+//! with light from infinitely far away that's looked up from an equirectangular
+//! (latitude-longitude) image. The map is importance sampled based on the luminance
+//! of the image so bright regions like the sun are found quickly.
+//!
+//! The top of the image is mapped to +Y and the center of the image looks along +X,
+//! with +Z three quarters of the way across from the left side of the image.
+
+use std::f32;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use texture::Image;
+use mc::Distribution2D;
+
+/// An equirectangular environment map with a distribution for importance sampling
+/// directions based on the brightness of the image
+pub struct EnvironmentMap {
+    image: Image,
+    distribution: Distribution2D,
+    /// The average radiance arriving from all directions
+    average: Colorf,
+}
+
+impl EnvironmentMap {
+    /// Create an environment map from the image
+    pub fn new(image: Image) -> EnvironmentMap {
+        let (width, height) = image.dimensions();
+        let mut func = Vec::with_capacity(width * height);
+        let mut average = Colorf::black();
+        let mut total_weight = 0.0;
+        for y in 0..height {
+            // Rows near the poles cover less solid angle so weight them less
+            let sin_theta = f32::sin(f32::consts::PI * (y as f32 + 0.5) / height as f32);
+            for x in 0..width {
+                let c = image.pixel(x as i32, y as i32);
+                func.push(c.luminance() * sin_theta);
+                average = average + c * sin_theta;
+                total_weight += sin_theta;
+            }
+        }
+        let distribution = Distribution2D::new(&func[..], width, height);
+        EnvironmentMap { image: image, distribution: distribution, average: average / total_weight }
+    }
+    /// Get the image coordinates in [0, 1]^2 for the direction `w`, where (0, 0)
+    /// is the top left corner of the image
+    fn direction_to_uv(w: &Vector) -> (f32, f32) {
+        let theta = f32::acos(linalg::clamp(w.y, -1.0, 1.0));
+        let phi = match f32::atan2(w.z, w.x) {
+            x if x < 0.0 => x + f32::consts::PI * 2.0,
+            x => x,
+        };
+        // Put the center of the image along +X
+        let u = phi / (f32::consts::PI * 2.0) + 0.5;
+        (if u >= 1.0 { u - 1.0 } else { u }, theta / f32::consts::PI)
+    }
+    /// Get the direction for the image coordinates `uv` in [0, 1]^2, where (0, 0) is the top
+    /// left corner of the image. This is the inverse of the mapping used to look up directions
+    pub fn uv_to_direction(uv: &(f32, f32)) -> Vector {
+        let theta = uv.1 * f32::consts::PI;
+        let phi = (uv.0 - 0.5) * f32::consts::PI * 2.0;
+        let sin_theta = f32::sin(theta);
+        Vector::new(sin_theta * f32::cos(phi), f32::cos(theta), sin_theta * f32::sin(phi))
+    }
+    /// Get the average radiance arriving from all directions
+    pub fn average_radiance(&self) -> Colorf {
+        self.average
+    }
+    /// Look up the radiance arriving from the direction `w`, which should be normalized
+    pub fn radiance(&self, w: &Vector) -> Colorf {
+        let uv = EnvironmentMap::direction_to_uv(w);
+        // Image texture coordinates have v = 0 at the bottom of the image
+        self.image.bilinear(uv.0, 1.0 - uv.1)
+    }
+    /// Sample a direction from the environment map based on its brightness using the
+    /// random samples `samples`. Returns the direction and its pdf w.r.t. solid angle
+    pub fn sample(&self, samples: &(f32, f32)) -> (Vector, f32) {
+        let (uv, map_pdf) = self.distribution.sample_continuous(samples);
+        let w = EnvironmentMap::uv_to_direction(&uv);
+        let sin_theta = f32::sin(uv.1 * f32::consts::PI);
+        if map_pdf == 0.0 || sin_theta == 0.0 {
+            return (w, 0.0);
+        }
+        (w, map_pdf / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta))
+    }
+    /// Compute the pdf w.r.t. solid angle of sampling the direction `w`
+    pub fn pdf(&self, w: &Vector) -> f32 {
+        let uv = EnvironmentMap::direction_to_uv(w);
+        let sin_theta = f32::sin(uv.1 * f32::consts::PI);
+        if sin_theta == 0.0 {
+            0.0
+        } else {
+            self.distribution.pdf(&uv) / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta)
+        }
+    }
+}
+
+#[test]
+fn test_direction_uv_mapping() {
+    let dirs = [Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0),
+                Vector::new(0.0, 0.6, -0.8), Vector::new(-0.6, -0.8, 0.0)];
+    for w in &dirs {
+        let uv = EnvironmentMap::direction_to_uv(w);
+        let d = EnvironmentMap::uv_to_direction(&uv);
+        assert!((d - *w).length() < 1e-5, "{:?} mapped to {:?} and back to {:?}", w, uv, d);
+    }
+    let uv = EnvironmentMap::direction_to_uv(&Vector::new(1.0, 0.0, 0.0));
+    assert_eq!(uv, (0.5, 0.5));
+}
@@ -0,0 +1,124 @@
+//! Provides the `LightList` which holds the lights in the scene and selects which
+//! light to sample for direct lighting. Lights in the scene are placed in a `LightTree`
+//! so lights are chosen based on an estimate of their contribution to the point being
+//! shaded. Lights infinitely far away can't be placed in the tree, so they're chosen
+//! based on their power and the tree is sampled with probability proportional to the
+//! total power of the lights in it. Lights can also be chosen based only on their power,
+//! eg. to pick a light to start a path from when tracing paths from the lights.
+
+use std::ops::Deref;
+use std::collections::HashMap;
+
+use geometry::{Boundable, Emitter};
+use linalg::Point;
+use light::{Light, LightTree, LightInfo};
+use mc::Distribution1D;
+
+/// The lights in the scene along with the structures used to select them
+pub struct LightList<'a> {
+    lights: Vec<&'a Emitter>,
+    /// Indices of the lights infinitely far away from the scene
+    infinite: Vec<usize>,
+    /// Distribution for choosing between the lights infinitely far away based on their power
+    infinite_distribution: Option<Distribution1D>,
+    /// Tree over the rest of the lights in the scene
+    tree: Option<LightTree>,
+    /// Probability of choosing a light infinitely far away instead of one from the tree
+    infinite_prob: f32,
+    /// Distribution for choosing between all the lights based on their power
+    power_distribution: Distribution1D,
+    /// Map from the address of each light to its index in `lights`
+    light_indices: HashMap<usize, usize>,
+}
+
+impl<'a> LightList<'a> {
+    /// Create the light list for the lights passed over the shutter interval from `start` to
+    /// `end`. `scene_radius` is the radius of a sphere bounding the scene, used to find the
+    /// power of lights infinitely far away. The light's power is computed at `start`
+    pub fn new(lights: Vec<&'a Emitter>, scene_radius: f32, start: f32, end: f32) -> LightList<'a> {
+        assert!(!lights.is_empty(), "At least one light is required");
+        let mut infinite = Vec::new();
+        let mut infinite_power = Vec::new();
+        let mut tree_lights = Vec::new();
+        let mut light_power = Vec::with_capacity(lights.len());
+        for (i, l) in lights.iter().enumerate() {
+            let power = l.power(scene_radius, start).luminance();
+            light_power.push(power);
+            if l.is_infinite() {
+                infinite.push(i);
+                infinite_power.push(power);
+            } else {
+                tree_lights.push(LightInfo { light: i, bounds: l.bounds(start, end), power: power });
+            }
+        }
+        let total_infinite = infinite_power.iter().fold(0.0, |s, p| s + p);
+        let total_tree = tree_lights.iter().fold(0.0, |s, l| s + l.power);
+        let infinite_prob =
+            if tree_lights.is_empty() {
+                1.0
+            } else if infinite.is_empty() {
+                0.0
+            } else if total_infinite + total_tree > 0.0 {
+                total_infinite / (total_infinite + total_tree)
+            } else {
+                0.5
+            };
+        let infinite_distribution = if infinite.is_empty() {
+            None
+        } else {
+            Some(Distribution1D::new(&infinite_power[..]))
+        };
+        let tree = if tree_lights.is_empty() { None } else { Some(LightTree::new(tree_lights)) };
+        let light_indices = lights.iter().enumerate().map(|(i, l)| (light_address(l), i)).collect();
+        LightList { lights: lights, infinite: infinite, infinite_distribution: infinite_distribution,
+                    tree: tree, infinite_prob: infinite_prob,
+                    power_distribution: Distribution1D::new(&light_power[..]), light_indices: light_indices }
+    }
+    /// Select a light to sample for the point `p` using the random sample `u`.
+    /// Returns the light and the probability of selecting it
+    pub fn sample(&self, p: &Point, u: f32) -> (&'a Emitter, f32) {
+        if u < self.infinite_prob {
+            let d = self.infinite_distribution.as_ref().unwrap();
+            let (i, pmf) = d.sample_discrete(u / self.infinite_prob);
+            (self.lights[self.infinite[i]], pmf * self.infinite_prob)
+        } else {
+            let u = f32::min((u - self.infinite_prob) / (1.0 - self.infinite_prob), 0.99999994);
+            let (i, pmf) = self.tree.as_ref().unwrap().sample(p, u);
+            (self.lights[i], pmf * (1.0 - self.infinite_prob))
+        }
+    }
+    /// Compute the probability of selecting the light at index `i` with `sample` for the point `p`
+    pub fn pmf(&self, p: &Point, i: usize) -> f32 {
+        if self.lights[i].is_infinite() {
+            let j = self.infinite.iter().position(|l| *l == i).unwrap();
+            self.infinite_distribution.as_ref().unwrap().pmf(j) * self.infinite_prob
+        } else {
+            self.tree.as_ref().unwrap().pmf(p, i) * (1.0 - self.infinite_prob)
+        }
+    }
+    /// Select a light based on its power using the random sample `u`.
+    /// Returns the light and the probability of selecting it
+    pub fn sample_power(&self, u: f32) -> (&'a Emitter, f32) {
+        let (i, pmf) = self.power_distribution.sample_discrete(u);
+        (self.lights[i], pmf)
+    }
+    /// Get the probability of `sample_power` selecting the light passed
+    pub fn pmf_power(&self, light: &Emitter) -> f32 {
+        match self.light_indices.get(&light_address(light)) {
+            Some(i) => self.power_distribution.pmf(*i),
+            None => 0.0,
+        }
+    }
+}
+
+/// Get the address of the light, used to look up its index in the light list
+fn light_address(light: &Emitter) -> usize {
+    light as *const Emitter as usize
+}
+
+impl<'a> Deref for LightList<'a> {
+    type Target = [&'a Emitter];
+    fn deref(&self) -> &[&'a Emitter] {
+        &self.lights[..]
+    }
+}
@@ -0,0 +1,175 @@
+//! Provides the `LightTree`, a BVH built over the lights in the scene which is used
+//! to choose lights to sample based on an estimate of their contribution at the point
+//! being shaded. This makes scenes with many lights practical to render as lights far
+//! away from the point, or dim ones, are rarely chosen. The tree is built with the same SAH
+//! construction as the scene BVH, and each node stores the total power of the lights below it.
+//! See Conty Estevez and Kulla, Importance Sampling of Many Lights with Adaptive Tree Splitting,
+//! for a more sophisticated version of this approach.
+
+use std::cmp;
+
+use geometry::{BBox, Boundable, BVH};
+use linalg::Point;
+
+/// Information about a light stored in the tree
+#[derive(Clone, Copy, Debug)]
+pub struct LightInfo {
+    /// Index of the light in the light list
+    pub light: usize,
+    /// Bounds of the light
+    pub bounds: BBox,
+    /// Luminance of the light's power
+    pub power: f32,
+}
+
+impl Boundable for LightInfo {
+    fn bounds(&self, _: f32, _: f32) -> BBox {
+        self.bounds
+    }
+}
+
+/// A BVH over the lights in the scene with the total power of the lights in each node
+pub struct LightTree {
+    bvh: BVH<LightInfo>,
+    /// Total power of the lights in each node of the BVH
+    node_power: Vec<f32>,
+    /// Parent of each node in the BVH, the root is its own parent
+    parents: Vec<usize>,
+    /// Leaf node containing each light, indexed by the light's index in the light list
+    light_nodes: Vec<Option<usize>>,
+}
+
+impl LightTree {
+    /// Build the light tree over the lights passed
+    pub fn new(lights: Vec<LightInfo>) -> LightTree {
+        let num_lights = lights.iter().fold(0, |n, l| cmp::max(n, l.light + 1));
+        let bvh = BVH::unanimated(1, lights);
+        let mut tree = LightTree { node_power: vec![0.0; bvh.num_nodes()], parents: vec![0; bvh.num_nodes()],
+                                   light_nodes: vec![None; num_lights], bvh: bvh };
+        tree.build_node(0);
+        tree
+    }
+    /// Compute the power of the subtree rooted at `node` and record the parents of the
+    /// nodes and leaves of the lights in it
+    fn build_node(&mut self, node: usize) -> f32 {
+        let power = match self.bvh.node_children(node) {
+            Some((l, r)) => {
+                self.parents[l] = node;
+                self.parents[r] = node;
+                self.build_node(l) + self.build_node(r)
+            },
+            None => {
+                let mut power = 0.0;
+                for i in self.bvh.node_geometry(node) {
+                    let info = self.bvh.get(*i);
+                    self.light_nodes[info.light] = Some(node);
+                    power += info.power;
+                }
+                power
+            },
+        };
+        self.node_power[node] = power;
+        power
+    }
+    /// Estimate the importance of the node for lighting the point `p`, based on the power of
+    /// the lights in it and their distance to the point
+    fn importance(&self, node: usize, p: &Point) -> f32 {
+        let (center, radius) = self.bvh.node_bounds(node).bounding_sphere();
+        // Don't let the distance fall below the size of the node, otherwise points inside
+        // or very close to the node would make it arbitrarily important
+        let dist_sqr = f32::max(center.distance_sqr(p), radius * radius);
+        if dist_sqr > 0.0 {
+            self.node_power[node] / dist_sqr
+        } else {
+            self.node_power[node]
+        }
+    }
+    /// Get the probability of choosing the left child `l` over the right child `r` for the point `p`
+    fn left_prob(&self, l: usize, r: usize, p: &Point) -> f32 {
+        let imp_l = self.importance(l, p);
+        let imp_r = self.importance(r, p);
+        if imp_l + imp_r > 0.0 { imp_l / (imp_l + imp_r) } else { 0.5 }
+    }
+    /// Get the probability of choosing the light `info` out of the lights in the leaf `node`
+    fn leaf_prob(&self, node: usize, info: &LightInfo) -> f32 {
+        let total = self.node_power[node];
+        if total > 0.0 { info.power / total } else { 1.0 / self.bvh.node_geometry(node).len() as f32 }
+    }
+    /// Choose a light to sample for the point `p` using the random sample `u`.
+    /// Returns the index of the light in the light list and the probability of choosing it
+    pub fn sample(&self, p: &Point, u: f32) -> (usize, f32) {
+        let mut u = u;
+        let mut pmf = 1.0;
+        let mut node = 0;
+        // Walk down the tree picking children based on their importance
+        while let Some((l, r)) = self.bvh.node_children(node) {
+            let p_l = self.left_prob(l, r, p);
+            if u < p_l {
+                node = l;
+                u = u / p_l;
+                pmf *= p_l;
+            } else {
+                node = r;
+                u = f32::min((u - p_l) / (1.0 - p_l), 0.99999994);
+                pmf *= 1.0 - p_l;
+            }
+        }
+        // Pick a light in the leaf based on its power
+        let lights = self.bvh.node_geometry(node);
+        let mut sum = 0.0;
+        for (n, i) in lights.iter().enumerate() {
+            let info = self.bvh.get(*i);
+            let p_i = self.leaf_prob(node, info);
+            sum += p_i;
+            if u < sum || n == lights.len() - 1 {
+                return (info.light, pmf * p_i);
+            }
+        }
+        unreachable!()
+    }
+    /// Get the probability of `sample` choosing the light at index `light` in the light
+    /// list for the point `p`. Returns 0 if the light isn't in the tree
+    pub fn pmf(&self, p: &Point, light: usize) -> f32 {
+        let leaf = match self.light_nodes.get(light) {
+            Some(&Some(n)) => n,
+            _ => return 0.0,
+        };
+        let info = self.bvh.node_geometry(leaf).iter().map(|i| self.bvh.get(*i))
+            .find(|l| l.light == light).unwrap();
+        let mut pmf = self.leaf_prob(leaf, info);
+        // Walk up the tree to find the probability of reaching the leaf
+        let mut node = leaf;
+        while node != 0 {
+            let parent = self.parents[node];
+            let (l, r) = self.bvh.node_children(parent).unwrap();
+            let p_l = self.left_prob(l, r, p);
+            pmf *= if node == l { p_l } else { 1.0 - p_l };
+            node = parent;
+        }
+        pmf
+    }
+}
+
+#[test]
+fn test_pmf() {
+    let lights: Vec<_> = (0..7).map(|i| {
+        let p = Point::new(i as f32 * 2.0, (i % 3) as f32, 0.0);
+        LightInfo { light: i, bounds: BBox::span(p, p + ::linalg::Vector::broadcast(0.5)), power: 1.0 + i as f32 }
+    }).collect();
+    let tree = LightTree::new(lights);
+    let p = Point::new(3.0, 1.0, 1.0);
+    let total = (0..7).fold(0.0, |s, i| s + tree.pmf(&p, i));
+    assert!(f32::abs(total - 1.0) < 1e-5);
+    assert_eq!(tree.pmf(&p, 7), 0.0);
+    // The frequency each light is sampled with should match its probability
+    let n = 100000;
+    let mut counts = [0; 7];
+    for k in 0..n {
+        let (i, pmf) = tree.sample(&p, (k as f32 + 0.5) / n as f32);
+        assert!(f32::abs(pmf - tree.pmf(&p, i)) < 1e-5);
+        counts[i] += 1;
+    }
+    for (i, c) in counts.iter().enumerate() {
+        assert!(f32::abs(*c as f32 / n as f32 - tree.pmf(&p, i)) < 1e-3);
+    }
+}
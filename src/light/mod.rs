@@ -4,10 +4,22 @@
 
 use std::f32;
 
-use linalg::{Point, Vector, Ray};
+use linalg::{Point, Vector, Ray, Normal};
 use film::Colorf;
 use scene::Scene;
 
+pub use self::environment::EnvironmentMap;
+pub use self::emission_map::EmissionMap;
+pub use self::sky::Sky;
+pub use self::light_list::LightList;
+pub use self::light_tree::{LightTree, LightInfo};
+
+pub mod environment;
+pub mod emission_map;
+pub mod sky;
+pub mod light_list;
+pub mod light_tree;
+
 /// The `OcclusionTester` provides a simple interface for setting up and executing
 /// occlusion queries in the scene
 #[derive(Clone, Copy, Debug)]
@@ -38,8 +50,7 @@ impl OcclusionTester {
 }
 
 /// Trait implemented by all lights in `tray_rust`. Provides methods for sampling
-/// the light and in the future ones for checking if it's a delta light, computing
-/// its power and so on.
+/// the light, checking if it's a delta light, computing its power and so on.
 pub trait Light {
     /// Sample the illumination from the light arriving at the point `p`
     /// Returns the color, incident light direction, pdf and occlusion tester object
@@ -50,5 +61,26 @@ pub trait Light {
     fn delta_light(&self) -> bool;
     /// Compute the PDF for sampling the point with incident direction `w_i`
     fn pdf(&self, p: &Point, w_i: &Vector, time: f32) -> f32;
+    /// Compute the total power emitted by the light at `time`. `scene_radius` is the radius
+    /// of a sphere bounding the scene, used to find the power of lights infinitely far away
+    fn power(&self, scene_radius: f32, time: f32) -> Colorf;
+    /// Sample a ray of light leaving the light, used to trace paths starting at the lights.
+    /// `scene_center` and `scene_radius` describe a sphere bounding the scene, used to place
+    /// rays from lights infinitely far away. `pos_samples` and `dir_samples` are used to
+    /// sample the position and direction of the ray respectively.
+    /// Returns the radiance carried along the ray (or intensity for point lights), the ray,
+    /// the normal of the light's surface at the ray's origin and the pdfs of sampling the
+    /// ray's origin w.r.t. area and its direction w.r.t. solid angle
+    fn sample_emission(&self, scene_center: &Point, scene_radius: f32, pos_samples: &(f32, f32),
+                       dir_samples: &(f32, f32), time: f32) -> (Colorf, Ray, Normal, f32, f32);
+    /// Compute the pdfs of `sample_emission` sampling the ray `ray` leaving the light from a point
+    /// with surface normal `n`. Returns the pdf of the ray's origin w.r.t. area and of its
+    /// direction w.r.t. solid angle
+    fn pdf_emission(&self, ray: &Ray, n: &Normal, scene_radius: f32) -> (f32, f32);
+    /// Compute the radiance arriving along a ray which escaped the scene without hitting
+    /// anything. Only lights infinitely far away, eg. environment lights, contribute this
+    fn escaped_radiance(&self, _: &Ray) -> Colorf {
+        Colorf::black()
+    }
 }
 
@@ -0,0 +1,121 @@
+//! Provides the analytic daylight sky model described by
+//! [Preetham et al., A Practical Analytic Model for Daylight](http://dl.acm.org/citation.cfm?id=311545),
+//! which computes the radiance of the clear sky in some direction based on the
+//! position of the sun and the turbidity (haziness) of the atmosphere.
+//!
+//! The sky is baked into an equirectangular image so it can be importance sampled
+//! the same way as an environment map.
+
+use std::f32;
+
+use linalg::{self, Vector};
+use film::Colorf;
+use texture::Image;
+use light::EnvironmentMap;
+
+/// Coefficients of the Perez sky luminance distribution function for one channel
+#[derive(Clone, Copy, Debug)]
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    /// Evaluate the distribution for the sky at zenith angle `theta` with
+    /// angle `gamma` to the sun
+    fn eval(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let cos_gamma = f32::cos(gamma);
+        (1.0 + self.a * f32::exp(self.b / cos_theta))
+            * (1.0 + self.c * f32::exp(self.d * gamma) + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// The Preetham daylight model for a clear sky, with +Y as the zenith
+#[derive(Clone, Copy, Debug)]
+pub struct Sky {
+    sun_dir: Vector,
+    /// Perez distributions for the luminance Y and chromaticity x and y
+    perez: [Perez; 3],
+    /// The values of Y, x and y at the zenith divided by the value of their
+    /// distribution at the zenith
+    zenith: [f32; 3],
+}
+
+impl Sky {
+    /// Create the sky for the sun in direction `sun_dir` with the atmosphere's
+    /// `turbidity`, typically between 2 (very clear) and 10 (hazy)
+    pub fn new(sun_dir: &Vector, turbidity: f32) -> Sky {
+        let t = turbidity;
+        let sun_dir = sun_dir.normalized();
+        let theta_s = f32::acos(linalg::clamp(sun_dir.y, 0.0, 1.0));
+        let perez = [Perez { a: 0.1787 * t - 1.4630, b: -0.3554 * t + 0.4275, c: -0.0227 * t + 5.3251,
+                             d: 0.1206 * t - 2.5771, e: -0.0670 * t + 0.3703 },
+                     Perez { a: -0.0193 * t - 0.2592, b: -0.0665 * t + 0.0008, c: -0.0004 * t + 0.2125,
+                             d: -0.0641 * t - 0.8989, e: -0.0033 * t + 0.0452 },
+                     Perez { a: -0.0167 * t - 0.2608, b: -0.0950 * t + 0.0092, c: -0.0079 * t + 0.2102,
+                             d: -0.0441 * t - 1.6537, e: -0.0109 * t + 0.0529 }];
+        let chi = (4.0 / 9.0 - t / 120.0) * (f32::consts::PI - 2.0 * theta_s);
+        let zenith_lum = (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192;
+        let theta = [theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0];
+        let turb = [t * t, t, 1.0];
+        let zenith_x = chromaticity(&turb, &[[0.00166, -0.00375, 0.00209, 0.0],
+                                             [-0.02903, 0.06377, -0.03202, 0.00394],
+                                             [0.11693, -0.21196, 0.06052, 0.25886]], &theta);
+        let zenith_y = chromaticity(&turb, &[[0.00275, -0.00610, 0.00317, 0.0],
+                                             [-0.04214, 0.08970, -0.04153, 0.00516],
+                                             [0.15346, -0.26756, 0.06670, 0.26688]], &theta);
+        let zenith = [zenith_lum / perez[0].eval(1.0, theta_s),
+                      zenith_x / perez[1].eval(1.0, theta_s),
+                      zenith_y / perez[2].eval(1.0, theta_s)];
+        Sky { sun_dir: sun_dir, perez: perez, zenith: zenith }
+    }
+    /// Compute the radiance of the sky arriving from direction `w`, which should be normalized.
+    /// The radiance is in thousands of cd/m^2 and the sky below the horizon is black
+    pub fn radiance(&self, w: &Vector) -> Colorf {
+        if w.y <= 0.0 {
+            return Colorf::black();
+        }
+        // Keep the direction slightly above the horizon to avoid blowing up at the horizon
+        let cos_theta = f32::max(w.y, 0.001);
+        let gamma = f32::acos(linalg::clamp(linalg::dot(w, &self.sun_dir), -1.0, 1.0));
+        let lum = self.zenith[0] * self.perez[0].eval(cos_theta, gamma);
+        let x = self.zenith[1] * self.perez[1].eval(cos_theta, gamma);
+        let y = self.zenith[2] * self.perez[2].eval(cos_theta, gamma);
+        if y <= 0.0 || lum <= 0.0 {
+            return Colorf::black();
+        }
+        // Convert from xyY to XYZ and then to linear sRGB
+        let cx = x / y * lum;
+        let cz = (1.0 - x - y) / y * lum;
+        let c = Colorf::new(3.2404542 * cx - 1.5371385 * lum - 0.4985314 * cz,
+                            -0.9692660 * cx + 1.8760108 * lum + 0.0415560 * cz,
+                            0.0556434 * cx - 0.2040259 * lum + 1.0572252 * cz);
+        Colorf::new(f32::max(c.r, 0.0), f32::max(c.g, 0.0), f32::max(c.b, 0.0))
+    }
+    /// Bake the sky into an equirectangular environment map of `width` by `height` pixels
+    /// with its radiance scaled by `scale`
+    pub fn to_environment_map(&self, width: usize, height: usize, scale: f32) -> EnvironmentMap {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let uv = ((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32);
+                pixels.push(self.radiance(&EnvironmentMap::uv_to_direction(&uv)) * scale);
+            }
+        }
+        EnvironmentMap::new(Image::new(width, height, pixels))
+    }
+}
+
+/// Compute the zenith chromaticity, `turb * m * theta`
+fn chromaticity(turb: &[f32; 3], m: &[[f32; 4]; 3], theta: &[f32; 4]) -> f32 {
+    let mut c = 0.0;
+    for i in 0..3 {
+        for j in 0..4 {
+            c += turb[i] * m[i][j] * theta[j];
+        }
+    }
+    c
+}
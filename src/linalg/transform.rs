@@ -144,6 +144,12 @@ impl Transform {
         let c = (*self * Vector::new(0.0, 0.0, 1.0)).length_sqr();
         a < 0.999 || a > 1.001 || b < 0.999 || b > 1.001 || c < 0.999 || c > 1.001
     }
+    /// Get the factor the area of a small patch of surface with the normal `n` is scaled by
+    /// when it's transformed, eg. to convert densities over an object's surface to world space
+    pub fn area_scale(&self, n: &Normal) -> f32 {
+        let (t1, t2) = linalg::coordinate_system(&Vector::new(n.x, n.y, n.z).normalized());
+        linalg::cross(&(*self * t1), &(*self * t2)).length()
+    }
     /// Multiply the point by the inverse transformation
     /// TODO: These inverse mults are a bit hacky since Rust doesn't currently
     /// have function overloading, clean up when it's added
@@ -377,3 +383,12 @@ fn test_rotate() {
                 Transform::rotate_z(243.0));
 }
 
+#[test]
+fn test_area_scale() {
+    let t = Transform::rotate_y(30.0) * Transform::scale(&Vector::new(2.0, 3.0, 4.0));
+    // A patch in the xy plane is scaled by the x and y scales, rotating it doesn't change its area
+    assert!(f32::abs(t.area_scale(&Normal::new(0.0, 0.0, 1.0)) - 6.0) < 1e-4);
+    assert!(f32::abs(t.area_scale(&Normal::new(1.0, 0.0, 0.0)) - 12.0) < 1e-4);
+    assert!(f32::abs(Transform::translate(&Vector::new(1.0, 2.0, 3.0)).area_scale(&Normal::new(0.0, 1.0, 0.0))
+                     - 1.0) < 1e-5);
+}
@@ -1,7 +1,6 @@
 #![cfg_attr(feature = "unstable", feature(plugin))]
 #![cfg_attr(feature = "unstable", plugin(clippy))]
 
-extern crate image;
 extern crate rand;
 extern crate docopt;
 extern crate rustc_serialize;
@@ -17,24 +16,34 @@ use std::io::ErrorKind;
 use docopt::Docopt;
 
 use tray_rust::scene;
+use tray_rust::film::output;
 use tray_rust::exec::{self, Exec};
 use tray_rust::exec::distrib;
 
 static USAGE: &'static str = "
 Usage:
     tray_rust <scenefile> [-o <path>] [-n <number>] [--start-frame <number>] [--end-frame <number>]
+              [--time-limit <seconds>] [--noise-threshold <error>]
     tray_rust <scenefile> --master <workers>... [-o <path>] [--start-frame <number>] [--end-frame <number>]
+              [--time-limit <seconds>] [--noise-threshold <error>]
     tray_rust --worker [-n <number>]
     tray_rust (-h | --help)
 
 
 Options:
   -o <path>               Specify the output file or directory to save the image or frames. Supported formats are
-                          PNG, JPG and PPM. Default is 'frame<#>.png'.
+                          PNG, JPG, PPM, EXR and HDR. EXR and HDR images are saved as linear floating point,
+                          keeping values above 1. Default is 'frame<#>.png'.
   -n <number>             Specify the number of threads to use for rendering. Defaults to the number of cores
                           on the system.
   --start-frame <number>  Specify frame to start rendering at, specifies an inclusive range [start, end]
   --end-frame <number>    Specify frame to stop rendering at, specifies an inclusive range [start, end]
+  --time-limit <seconds>  Render each frame progressively, taking passes over the image until the time limit
+                          is reached. Each pass takes the samples per pixel set by the scene's sampler.
+  --noise-threshold <error>
+                          Render each frame progressively until the standard error of every pixel's luminance
+                          relative to its mean is below the threshold, eg. 0.01. Can be combined with
+                          --time-limit to stop at whichever is reached first.
   --master                Start a master process to manage the worker nodes in <workers>... for distributed
                           rendering. The master collects results from workers and saves the image(s).
   <workers>...            Specify the list of worker nodes the master will connect too.
@@ -51,6 +60,8 @@ struct Args {
     flag_n: Option<u32>,
     flag_start_frame: Option<usize>,
     flag_end_frame: Option<usize>,
+    flag_time_limit: Option<f64>,
+    flag_noise_threshold: Option<f32>,
     flag_master: Option<bool>,
     arg_workers: Vec<String>,
     flag_worker: Option<bool>,
@@ -77,7 +88,7 @@ fn single_node_render(args: Args) {
         None => PathBuf::from("./"),
     };
 
-    let (mut scene, mut rt, spp, mut frame_info) = scene::Scene::load_file(&args.arg_scenefile[..]);
+    let (mut scene, mut rt, sampler, mut frame_info) = scene::Scene::load_file(&args.arg_scenefile[..]);
     let dim = rt.dimensions();
 
     frame_info.start = match args.flag_start_frame {
@@ -89,18 +100,25 @@ fn single_node_render(args: Args) {
         _ => frame_info.end,
     };
     let scene_start = clock_ticks::precise_time_s();
-    let mut config = exec::Config::new(out_path, args.arg_scenefile, spp, num_threads, frame_info, (0, 0));
+    let mut config = exec::Config::new(out_path, args.arg_scenefile, sampler, num_threads, frame_info, (0, 0));
+    config.time_limit = args.flag_time_limit;
+    config.noise_threshold = args.flag_noise_threshold;
     let mut exec = exec::MultiThreaded::new(num_threads);
+    let mut denoise_pool = scoped_threadpool::Pool::new(num_threads);
     for i in frame_info.start..frame_info.end + 1 {
         config.current_frame = i;
         exec.render(&mut scene, &mut rt, &config);
 
-        let img = rt.get_render();
+        let mut aovs = rt.get_aovs_linear();
+        let img = match rt.denoiser() {
+            Some(d) => d.denoise(dim, rt.get_render_linear(), &mut aovs, &mut denoise_pool),
+            None => rt.get_render_linear(),
+        };
         let out_file = match config.out_path.extension() {
             Some(_) => config.out_path.clone(),
             None => config.out_path.join(PathBuf::from(format!("frame{:05}.png", i))),
         };
-        match image::save_buffer(&out_file.as_path(), &img[..], dim.0 as u32, dim.1 as u32, image::RGB(8)) {
+        match output::save_frame(&out_file.as_path(), dim, &img[..], &aovs, &rt.tone_map()) {
             Ok(_) => {},
             Err(e) => println!("Error saving image, {}", e),
         };
@@ -128,7 +146,7 @@ fn master_node(args: Args) {
         None => PathBuf::from("./"),
     };
 
-    let (_, rt, spp, mut frame_info) = scene::Scene::load_file(&args.arg_scenefile[..]);
+    let (_, rt, sampler, mut frame_info) = scene::Scene::load_file(&args.arg_scenefile[..]);
 
     frame_info.start = match args.flag_start_frame {
         Some(x) => x,
@@ -139,9 +157,15 @@ fn master_node(args: Args) {
         _ => frame_info.end,
     };
     let scene_start = clock_ticks::precise_time_s();
-    let config = exec::Config::new(out_path, args.arg_scenefile, spp, 0, frame_info, (0, 0));
+    // The master's threads are only used to denoise the frames
+    let mut config = exec::Config::new(out_path, args.arg_scenefile, sampler, num_cpus::get() as u32,
+                                       frame_info, (0, 0));
+    config.time_limit = args.flag_time_limit;
+    config.noise_threshold = args.flag_noise_threshold;
     // Connect to all the workers and prepare to send/receive data from/to them
-    let (mut master, mut event_loop) = distrib::Master::start_workers(args.arg_workers, config, rt.dimensions());
+    let (mut master, mut event_loop) = distrib::Master::start_workers(args.arg_workers, config, rt.dimensions(),
+                                                                      rt.tone_map(), rt.aovs().to_vec(),
+                                                                      rt.denoiser());
     // Start the event loop to wait for and read results from each worker. No
     event_loop.run(&mut master).unwrap();
     let time = clock_ticks::precise_time_s() - scene_start;
@@ -0,0 +1,159 @@
+//! Provides bump and normal mapping which perturb the shading normal of another material
+//! to add surface detail without modelling it as geometry. Bump mapping displaces the
+//! surface along its normal by a height texture, following the approach described in
+//! [PBRT](http://pbrt.org/) though the change in the normal across the surface is
+//! ignored. Normal mapping replaces the shading normal with one read from a tangent
+//! space normal map, where the red, green and blue channels map to the u and v
+//! directions and the surface normal respectively.
+//!
+//! # Scene Usage Example
+//! Any material can be given a bump map by setting `bump` to a scalar texture or
+//! a normal map by setting `normal_map` to a color texture. The heights in the bump
+//! map are scaled by `bump_scale` which defaults to 1. Normal maps should typically
+//! be loaded with `srgb` set to false, see the image texture.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "bumpy_plastic",
+//!         "type": "plastic",
+//!         "diffuse": [0.8, 0.1, 0.1],
+//!         "gloss": [0.8, 0.8, 0.8],
+//!         "roughness": 0.05,
+//!         "bump": "scratches",
+//!         "bump_scale": 0.01
+//!     },
+//!     {
+//!         "name": "brick",
+//!         "type": "matte",
+//!         "diffuse": "brick_color",
+//!         "roughness": 1.0,
+//!         "normal_map": "brick_normals"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::sync::Arc;
+
+use linalg::{self, Normal};
+use geometry::{Intersection, DifferentialGeometry};
+use bxdf::BSDF;
+use material::Material;
+use texture::Texture;
+use media::Medium;
+
+/// Offset in texture coordinates used to compute the derivatives of the bump
+/// map's height by finite differences
+const BUMP_DELTA: f32 = 0.0005;
+
+/// Set the shading normal of the differential geometry to `n`, flipped if needed to
+/// stay on the same side as the original shading normal, and update `dp_du` so it
+/// remains orthogonal to the new normal
+fn set_shading_normal(dg: &mut DifferentialGeometry, n: Normal) {
+    let n = if linalg::dot(&n, &dg.n) < 0.0 { -n.normalized() } else { n.normalized() };
+    let dp_du = dg.dp_du - linalg::dot(&dg.dp_du, &n) * linalg::Vector::new(n.x, n.y, n.z);
+    if dp_du.length_sqr() > 0.0 {
+        dg.dp_du = dp_du;
+    }
+    dg.n = n;
+}
+
+/// A material whose shading normal is perturbed by a bump map
+pub struct BumpMapped {
+    material: Arc<Material + Send + Sync>,
+    height: Arc<Texture + Send + Sync>,
+    scale: f32,
+}
+
+impl BumpMapped {
+    /// Apply the bump map `height` scaled by `scale` to `material`
+    pub fn new(material: Arc<Material + Send + Sync>, height: Arc<Texture + Send + Sync>, scale: f32)
+               -> BumpMapped {
+        BumpMapped { material: material, height: height, scale: scale }
+    }
+    /// Compute the bumped differential geometry for the hit
+    fn bump<'a>(&self, dg: &DifferentialGeometry<'a>) -> DifferentialGeometry<'a> {
+        let n = linalg::Vector::new(dg.n.x, dg.n.y, dg.n.z);
+        // Find the height of the surface at the hit point and shifted along u and v
+        let mut dg_shift = *dg;
+        dg_shift.p = dg.p + BUMP_DELTA * dg.dp_du;
+        dg_shift.p_obj = dg.p_obj + BUMP_DELTA * dg.dp_du_obj;
+        dg_shift.u = dg.u + BUMP_DELTA;
+        let u_displace = self.scale * self.height.sample_f32(&dg_shift);
+
+        dg_shift.p = dg.p + BUMP_DELTA * dg.dp_dv;
+        dg_shift.p_obj = dg.p_obj + BUMP_DELTA * dg.dp_dv_obj;
+        dg_shift.u = dg.u;
+        dg_shift.v = dg.v + BUMP_DELTA;
+        let v_displace = self.scale * self.height.sample_f32(&dg_shift);
+        let displace = self.scale * self.height.sample_f32(dg);
+
+        let dp_du = dg.dp_du + (u_displace - displace) / BUMP_DELTA * n;
+        let dp_dv = dg.dp_dv + (v_displace - displace) / BUMP_DELTA * n;
+        let bumped_n = linalg::cross(&dp_du, &dp_dv);
+        let mut bumped = *dg;
+        // Degenerate derivatives can't give us a new normal so leave the shading normal as is
+        if bumped_n.length_sqr() > 0.0 {
+            bumped.dp_du = dp_du;
+            bumped.dp_dv = dp_dv;
+            set_shading_normal(&mut bumped, Normal::new(bumped_n.x, bumped_n.y, bumped_n.z));
+        }
+        bumped
+    }
+}
+
+impl Material for BumpMapped {
+    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
+        let mut bumped = *hit;
+        bumped.dg = self.bump(&hit.dg);
+        self.material.bsdf(&bumped)
+    }
+    fn interior(&self) -> Option<Arc<Medium + Send + Sync>> {
+        self.material.interior()
+    }
+}
+
+/// A material whose shading normal is replaced by the normal read from a tangent space normal map
+pub struct NormalMapped {
+    material: Arc<Material + Send + Sync>,
+    normals: Arc<Texture + Send + Sync>,
+}
+
+impl NormalMapped {
+    /// Apply the tangent space normal map `normals` to `material`
+    pub fn new(material: Arc<Material + Send + Sync>, normals: Arc<Texture + Send + Sync>) -> NormalMapped {
+        NormalMapped { material: material, normals: normals }
+    }
+    /// Compute the differential geometry with the shading normal from the normal map for the hit
+    fn perturb<'a>(&self, dg: &DifferentialGeometry<'a>) -> DifferentialGeometry<'a> {
+        let c = self.normals.sample_color(dg);
+        let ts = linalg::Vector::new(2.0 * c.r - 1.0, 2.0 * c.g - 1.0, 2.0 * c.b - 1.0);
+        let n = linalg::Vector::new(dg.n.x, dg.n.y, dg.n.z).normalized();
+        let mut tan = dg.dp_du - linalg::dot(&dg.dp_du, &n) * n;
+        if tan.length_sqr() == 0.0 || ts.length_sqr() == 0.0 {
+            return *dg;
+        }
+        tan = tan.normalized();
+        let mut bitan = linalg::cross(&n, &tan);
+        // Keep the bitangent pointing along v so mirrored texture coordinates are handled
+        if linalg::dot(&bitan, &dg.dp_dv) < 0.0 {
+            bitan = -bitan;
+        }
+        let mapped = ts.x * tan + ts.y * bitan + ts.z * n;
+        let mut perturbed = *dg;
+        set_shading_normal(&mut perturbed, Normal::new(mapped.x, mapped.y, mapped.z));
+        perturbed
+    }
+}
+
+impl Material for NormalMapped {
+    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
+        let mut perturbed = *hit;
+        perturbed.dg = self.perturb(&hit.dg);
+        self.material.bsdf(&perturbed)
+    }
+    fn interior(&self) -> Option<Arc<Medium + Send + Sync>> {
+        self.material.interior()
+    }
+}
@@ -17,19 +17,27 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! The reflect and transmit colors can also be given the name of a texture to vary
+//! them over the surface, see the texture module.
 
 use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
 use bxdf::{BxDF, BSDF, SpecularReflection, SpecularTransmission};
 use bxdf::fresnel::{Dielectric, Fresnel};
 use material::Material;
+use texture::Texture;
 
 /// The Glass material describes specularly transmissive and reflective glass material
 pub struct Glass {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    reflect: Arc<Texture + Send + Sync>,
+    transmit: Arc<Texture + Send + Sync>,
     eta: f32,
+    /// The BxDFs are created once if the reflected and transmitted colors are constant
+    bxdfs: Option<Vec<Box<BxDF + Send + Sync>>>,
 }
 
 impl Glass {
@@ -37,7 +45,14 @@ impl Glass {
     /// `reflect`: color of reflected light
     /// `transmit`: color of transmitted light
     /// `eta`: refractive index of the material
-    pub fn new(reflect: &Colorf, transmit: &Colorf, eta: f32) -> Glass {
+    pub fn new(reflect: Arc<Texture + Send + Sync>, transmit: Arc<Texture + Send + Sync>, eta: f32) -> Glass {
+        let bxdfs = match (reflect.constant_color(), transmit.constant_color()) {
+            (Some(r), Some(t)) => Some(Glass::create_bxdfs(&r, &t, eta)),
+            _ => None,
+        };
+        Glass { reflect: reflect, transmit: transmit, eta: eta, bxdfs: bxdfs }
+    }
+    fn create_bxdfs(reflect: &Colorf, transmit: &Colorf, eta: f32) -> Vec<Box<BxDF + Send + Sync>> {
         let mut bxdfs = Vec::new();
         if !reflect.is_black() {
             bxdfs.push(Box::new(SpecularReflection::new(reflect,
@@ -48,14 +63,19 @@ impl Glass {
             bxdfs.push(Box::new(SpecularTransmission::new(transmit, Dielectric::new(1.0, eta)))
                       as Box<BxDF + Send + Sync>);
         }
-        Glass { bxdfs: bxdfs, eta: eta }
+        bxdfs
     }
 }
 
 impl Material for Glass {
     fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, self.eta, &hit.dg)
+        match self.bxdfs {
+            Some(ref bxdfs) => BSDF::new(bxdfs, self.eta, &hit.dg),
+            None => {
+                let reflect = self.reflect.sample_color(&hit.dg);
+                let transmit = self.transmit.sample_color(&hit.dg);
+                BSDF::owned(Glass::create_bxdfs(&reflect, &transmit, self.eta), self.eta, &hit.dg)
+            },
+        }
     }
 }
-
-
@@ -0,0 +1,31 @@
+//! Defines the interface material which is invisible, it's used for objects which only mark the
+//! boundary of a participating medium, eg. a box filled with smoke. Rays pass through surfaces
+//! with this material unchanged and only the volumetric path tracer supports it, other
+//! integrators treat these surfaces as black.
+//!
+//! # Scene Usage Example
+//! The interface material doesn't take any parameters.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "medium_boundary",
+//!         "type": "interface"
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use geometry::Intersection;
+use bxdf::BSDF;
+use material::Material;
+
+/// The interface material, which has no BxDFs
+#[derive(Clone, Copy, Debug)]
+pub struct Interface;
+
+impl Material for Interface {
+    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
+        BSDF::new(&[], 1.0, &hit.dg)
+    }
+}
@@ -18,37 +18,56 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! Both the diffuse color and roughness can also be given the name of a texture
+//! to vary them over the surface, see the texture module.
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
 use bxdf::{BxDF, BSDF, Lambertian, OrenNayar};
 use material::Material;
+use texture::Texture;
 
 /// The Matte material describes diffuse materials with either a Lambertian or
 /// Oren-Nayar BRDF. The Lambertian BRDF is used for materials with no roughness
 /// while Oren-Nayar is used for those with some roughness.
-/// TODO: Currently we create the BSDF when creating the material but later we'd
-/// like to change material properties over the surface and should use a memory pool
+/// TODO: Textured materials create their BxDFs for each hit point but should use a memory pool
 pub struct Matte {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    diffuse: Arc<Texture + Send + Sync>,
+    roughness: Arc<Texture + Send + Sync>,
+    /// The BxDFs are created once if the diffuse color and roughness are constant
+    bxdfs: Option<Vec<Box<BxDF + Send + Sync>>>,
 }
 
 impl Matte {
     /// Create a new Matte material with the desired diffuse color and roughness
-    pub fn new(diffuse: &Colorf, roughness: f32) -> Matte {
+    pub fn new(diffuse: Arc<Texture + Send + Sync>, roughness: Arc<Texture + Send + Sync>) -> Matte {
+        let bxdfs = match (diffuse.constant_color(), roughness.constant_f32()) {
+            (Some(d), Some(r)) => Some(Matte::create_bxdfs(&d, r)),
+            _ => None,
+        };
+        Matte { diffuse: diffuse, roughness: roughness, bxdfs: bxdfs }
+    }
+    fn create_bxdfs(diffuse: &Colorf, roughness: f32) -> Vec<Box<BxDF + Send + Sync>> {
         if roughness == 0.0 {
-            Matte { bxdfs: vec![Box::new(Lambertian::new(diffuse)) as Box<BxDF + Send + Sync>], }
+            vec![Box::new(Lambertian::new(diffuse)) as Box<BxDF + Send + Sync>]
         } else {
-            Matte { bxdfs: vec![Box::new(OrenNayar::new(diffuse, roughness)) as Box<BxDF + Send + Sync>], }
+            vec![Box::new(OrenNayar::new(diffuse, roughness)) as Box<BxDF + Send + Sync>]
         }
     }
 }
 
 impl Material for Matte {
     fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+        match self.bxdfs {
+            Some(ref bxdfs) => BSDF::new(bxdfs, 1.0, &hit.dg),
+            None => {
+                let diffuse = self.diffuse.sample_color(&hit.dg);
+                let roughness = self.roughness.sample_f32(&hit.dg);
+                BSDF::owned(Matte::create_bxdfs(&diffuse, roughness), 1.0, &hit.dg)
+            },
+        }
     }
 }
-
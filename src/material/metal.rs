@@ -19,8 +19,11 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! The roughness can also be given the name of a texture to vary it over the surface,
+//! see the texture module.
 
-use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
@@ -28,26 +31,39 @@ use bxdf::{BxDF, BSDF, TorranceSparrow};
 use bxdf::microfacet::{MicrofacetDistribution, Beckmann};
 use bxdf::fresnel::{Fresnel, Conductor};
 use material::Material;
+use texture::Texture;
 
 /// The Metal material describes metals of varying roughness
 pub struct Metal {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    eta: Colorf,
+    k: Colorf,
+    roughness: Arc<Texture + Send + Sync>,
+    /// The BxDFs are created once if the roughness is constant
+    bxdfs: Option<Vec<Box<BxDF + Send + Sync>>>,
 }
 
 impl Metal {
     /// Create a new metal material specifying the reflectance properties of the metal
-    pub fn new(eta: &Colorf, k: &Colorf, roughness: f32) -> Metal {
+    pub fn new(eta: &Colorf, k: &Colorf, roughness: Arc<Texture + Send + Sync>) -> Metal {
+        let bxdfs = roughness.constant_f32().map(|r| Metal::create_bxdfs(eta, k, r));
+        Metal { eta: *eta, k: *k, roughness: roughness, bxdfs: bxdfs }
+    }
+    fn create_bxdfs(eta: &Colorf, k: &Colorf, roughness: f32) -> Vec<Box<BxDF + Send + Sync>> {
         let fresnel = Box::new(Conductor::new(eta, k)) as Box<Fresnel + Send + Sync>;
         let microfacet = Box::new(Beckmann::new(roughness)) as Box<MicrofacetDistribution + Send + Sync>;
-        Metal { bxdfs: vec![Box::new(TorranceSparrow::new(&Colorf::broadcast(1.0), fresnel, microfacet))
-                            as Box<BxDF + Send + Sync>] }
+        vec![Box::new(TorranceSparrow::new(&Colorf::broadcast(1.0), fresnel, microfacet))
+             as Box<BxDF + Send + Sync>]
     }
 }
 
 impl Material for Metal {
     fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+        match self.bxdfs {
+            Some(ref bxdfs) => BSDF::new(bxdfs, 1.0, &hit.dg),
+            None => {
+                let roughness = self.roughness.sample_f32(&hit.dg);
+                BSDF::owned(Metal::create_bxdfs(&self.eta, &self.k, roughness), 1.0, &hit.dg)
+            },
+        }
     }
 }
-
-
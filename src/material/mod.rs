@@ -17,9 +17,15 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! Any material can also be given a bump map or normal map to perturb its shading
+//! normal, see the bump module.
+
+use std::sync::Arc;
 
 use geometry::Intersection;
 use bxdf::BSDF;
+use media::Medium;
 
 pub use self::matte::Matte;
 pub use self::specular_metal::SpecularMetal;
@@ -28,6 +34,9 @@ pub use self::merl::Merl;
 pub use self::plastic::Plastic;
 pub use self::metal::Metal;
 pub use self::rough_glass::RoughGlass;
+pub use self::bump::{BumpMapped, NormalMapped};
+pub use self::interface::Interface;
+pub use self::subsurface::Subsurface;
 
 pub mod matte;
 pub mod specular_metal;
@@ -36,6 +45,9 @@ pub mod merl;
 pub mod plastic;
 pub mod metal;
 pub mod rough_glass;
+pub mod bump;
+pub mod interface;
+pub mod subsurface;
 
 /// Trait implemented by materials. Provides method to get the BSDF describing
 /// the material properties at the intersection
@@ -45,5 +57,11 @@ pub trait Material {
     /// here, currently the BxDFs and BSDF are allocated once at surface
     /// creation instead of as needed based on material properties.
     fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a>;
+    /// Get the participating medium filling objects made of this material, used by
+    /// materials which scatter light beneath their surface. Most materials don't have
+    /// one so the default returns None
+    fn interior(&self) -> Option<Arc<Medium + Send + Sync>> {
+        None
+    }
 }
 
@@ -21,8 +21,12 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! The diffuse and gloss colors along with the roughness can also be given the name
+//! of a texture to vary them over the surface, see the texture module.
 
 use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
@@ -30,16 +34,29 @@ use bxdf::{BxDF, BSDF, TorranceSparrow, Lambertian};
 use bxdf::microfacet::{MicrofacetDistribution, Beckmann};
 use bxdf::fresnel::{Fresnel, Dielectric};
 use material::Material;
+use texture::Texture;
 
 /// The Plastic material describes plastic materials of varying roughness
 pub struct Plastic {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    diffuse: Arc<Texture + Send + Sync>,
+    gloss: Arc<Texture + Send + Sync>,
+    roughness: Arc<Texture + Send + Sync>,
+    /// The BxDFs are created once if the colors and roughness are constant
+    bxdfs: Option<Vec<Box<BxDF + Send + Sync>>>,
 }
 
 impl Plastic {
     /// Create a new plastic material specifying the diffuse and glossy colors
     /// along with the roughness of the surface
-    pub fn new(diffuse: &Colorf, gloss: &Colorf, roughness: f32) -> Plastic {
+    pub fn new(diffuse: Arc<Texture + Send + Sync>, gloss: Arc<Texture + Send + Sync>,
+               roughness: Arc<Texture + Send + Sync>) -> Plastic {
+        let bxdfs = match (diffuse.constant_color(), gloss.constant_color(), roughness.constant_f32()) {
+            (Some(d), Some(g), Some(r)) => Some(Plastic::create_bxdfs(&d, &g, r)),
+            _ => None,
+        };
+        Plastic { diffuse: diffuse, gloss: gloss, roughness: roughness, bxdfs: bxdfs }
+    }
+    fn create_bxdfs(diffuse: &Colorf, gloss: &Colorf, roughness: f32) -> Vec<Box<BxDF + Send + Sync>> {
         let mut bxdfs = Vec::new();
         if !diffuse.is_black() {
             bxdfs.push(Box::new(Lambertian::new(diffuse)) as Box<BxDF + Send + Sync>);
@@ -49,13 +66,20 @@ impl Plastic {
             let microfacet = Box::new(Beckmann::new(roughness)) as Box<MicrofacetDistribution + Send + Sync>;
             bxdfs.push(Box::new(TorranceSparrow::new(gloss, fresnel, microfacet)) as Box<BxDF + Send + Sync>);
         }
-        Plastic { bxdfs: bxdfs }
+        bxdfs
     }
 }
 
 impl Material for Plastic {
     fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, 1.0, &hit.dg)
+        match self.bxdfs {
+            Some(ref bxdfs) => BSDF::new(bxdfs, 1.0, &hit.dg),
+            None => {
+                let diffuse = self.diffuse.sample_color(&hit.dg);
+                let gloss = self.gloss.sample_color(&hit.dg);
+                let roughness = self.roughness.sample_f32(&hit.dg);
+                BSDF::owned(Plastic::create_bxdfs(&diffuse, &gloss, roughness), 1.0, &hit.dg)
+            },
+        }
     }
 }
-
@@ -18,8 +18,12 @@
 //!     ...
 //! ]
 //! ```
+//!
+//! The reflect and transmit colors along with the roughness can also be given the
+//! name of a texture to vary them over the surface, see the texture module.
 
 use std::vec::Vec;
+use std::sync::Arc;
 
 use film::Colorf;
 use geometry::Intersection;
@@ -27,11 +31,16 @@ use bxdf::{BxDF, BSDF, MicrofacetTransmission, TorranceSparrow};
 use bxdf::microfacet::{Beckmann, MicrofacetDistribution};
 use bxdf::fresnel::{Dielectric, Fresnel};
 use material::Material;
+use texture::Texture;
 
 /// The `RoughGlass` material describes specularly transmissive and reflective glass material
 pub struct RoughGlass {
-    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    reflect: Arc<Texture + Send + Sync>,
+    transmit: Arc<Texture + Send + Sync>,
     eta: f32,
+    roughness: Arc<Texture + Send + Sync>,
+    /// The BxDFs are created once if the colors and roughness are constant
+    bxdfs: Option<Vec<Box<BxDF + Send + Sync>>>,
 }
 
 impl RoughGlass {
@@ -40,7 +49,16 @@ impl RoughGlass {
     /// `transmit`: color of transmitted light
     /// `eta`: refractive index of the material
     /// `roughness`: roughness of the material
-    pub fn new(reflect: &Colorf, transmit: &Colorf, eta: f32, roughness: f32) -> RoughGlass {
+    pub fn new(reflect: Arc<Texture + Send + Sync>, transmit: Arc<Texture + Send + Sync>, eta: f32,
+               roughness: Arc<Texture + Send + Sync>) -> RoughGlass {
+        let bxdfs = match (reflect.constant_color(), transmit.constant_color(), roughness.constant_f32()) {
+            (Some(r), Some(t), Some(a)) => Some(RoughGlass::create_bxdfs(&r, &t, eta, a)),
+            _ => None,
+        };
+        RoughGlass { reflect: reflect, transmit: transmit, eta: eta, roughness: roughness, bxdfs: bxdfs }
+    }
+    fn create_bxdfs(reflect: &Colorf, transmit: &Colorf, eta: f32, roughness: f32)
+                    -> Vec<Box<BxDF + Send + Sync>> {
         let mut bxdfs = Vec::new();
         if !reflect.is_black() {
             let fresnel = Box::new(Dielectric::new(1.0, eta)) as Box<Fresnel + Send + Sync>;
@@ -53,15 +71,21 @@ impl RoughGlass {
             bxdfs.push(Box::new(MicrofacetTransmission::new(transmit, fresnel, microfacet))
                        as Box<BxDF + Send + Sync>);
         }
-        RoughGlass { bxdfs: bxdfs, eta: eta }
+        bxdfs
     }
 }
 
 impl Material for RoughGlass {
     fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
-        BSDF::new(&self.bxdfs, self.eta, &hit.dg)
+        match self.bxdfs {
+            Some(ref bxdfs) => BSDF::new(bxdfs, self.eta, &hit.dg),
+            None => {
+                let reflect = self.reflect.sample_color(&hit.dg);
+                let transmit = self.transmit.sample_color(&hit.dg);
+                let roughness = self.roughness.sample_f32(&hit.dg);
+                BSDF::owned(RoughGlass::create_bxdfs(&reflect, &transmit, self.eta, roughness), self.eta,
+                            &hit.dg)
+            },
+        }
     }
 }
-
-
-
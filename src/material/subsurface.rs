@@ -0,0 +1,75 @@
+//! Defines a translucent material which scatters light beneath its surface, eg. skin,
+//! wax, marble or milk. Light is transmitted diffusely through the surface into a medium
+//! filling the object, where it's followed on a random walk until it's absorbed or leaves
+//! the object again. The surface also reflects light arriving from outside specularly
+//! with the Fresnel reflectance of a dielectric, light reaching the surface from inside
+//! always leaves the object.
+//!
+//! Objects using the material are filled with its medium, so their geometry must be closed
+//! with their normals facing out. Only the volumetric path tracer follows light through the
+//! medium, other integrators render the material as a thin translucent surface. Light may
+//! scatter many times beneath the surface so the integrator's `max_depth` should be high,
+//! eg. 64 or more.
+//!
+//! # Scene Usage Example
+//! The subsurface material requires the refractive index of the surface, `eta`, and the
+//! absorption and scattering coefficients of the medium beneath it, see the homogeneous
+//! medium. The coefficients can optionally be scaled and the asymmetry `g` of the medium's
+//! phase function set, these default to 1 and 0 respectively.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "wax",
+//!         "type": "subsurface",
+//!         "eta": 1.4,
+//!         "sigma_a": [0.02, 0.05, 0.2],
+//!         "sigma_s": [8, 8, 8],
+//!         "scale": 1.0,
+//!         "g": 0.0
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::vec::Vec;
+use std::sync::Arc;
+
+use film::Colorf;
+use geometry::Intersection;
+use bxdf::{BxDF, BSDF, SpecularReflection, LambertianTransmission};
+use bxdf::fresnel::{ExteriorDielectric, Fresnel};
+use material::Material;
+use media::Medium;
+
+/// The Subsurface material describes translucent materials filled with a scattering medium
+pub struct Subsurface {
+    bxdfs: Vec<Box<BxDF + Send + Sync>>,
+    eta: f32,
+    medium: Arc<Medium + Send + Sync>,
+}
+
+impl Subsurface {
+    /// Create the subsurface scattering material with the desired surface and medium properties
+    /// `eta`: refractive index of the surface
+    /// `medium`: the medium scattering light beneath the surface
+    pub fn new(eta: f32, medium: Arc<Medium + Send + Sync>) -> Subsurface {
+        let white = Colorf::broadcast(1.0);
+        let bxdfs = vec![
+            Box::new(SpecularReflection::new(&white,
+                         Box::new(ExteriorDielectric::new(1.0, eta)) as Box<Fresnel + Send + Sync>))
+                as Box<BxDF + Send + Sync>,
+            Box::new(LambertianTransmission::new(&white, ExteriorDielectric::new(1.0, eta)))
+                as Box<BxDF + Send + Sync>];
+        Subsurface { bxdfs: bxdfs, eta: eta, medium: medium }
+    }
+}
+
+impl Material for Subsurface {
+    fn bsdf<'a, 'b>(&'a self, hit: &Intersection<'a, 'b>) -> BSDF<'a> {
+        BSDF::new(&self.bxdfs, self.eta, &hit.dg)
+    }
+    fn interior(&self) -> Option<Arc<Medium + Send + Sync>> {
+        Some(self.medium.clone())
+    }
+}
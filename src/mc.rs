@@ -87,4 +87,140 @@ pub fn uniform_sample_sphere(samples: &(f32, f32)) -> Vector {
     let phi = f32::consts::PI * 2.0 * samples.1;
     Vector::new(f32::cos(phi) * r, f32::sin(phi) * r, z)
 }
+/// Return the PDF for uniformly sampling a direction on the unit sphere
+pub fn uniform_sphere_pdf() -> f32 {
+    1.0 / (4.0 * f32::consts::PI)
+}
+
+
+/// A piecewise constant 1D distribution which can be sampled to pick values
+/// proportional to the function it was built from
+/// See: [PBR](http://pbrt.org/) Section 13.3.1
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    /// Integral of the function over [0, 1]
+    pub integral: f32,
+}
+
+impl Distribution1D {
+    /// Create a distribution from the values of the function `func` sampled at
+    /// evenly spaced intervals over [0, 1]
+    pub fn new(func: &[f32]) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for i in 0..n {
+            let c = cdf[i] + func[i] / n as f32;
+            cdf.push(c);
+        }
+        let integral = cdf[n];
+        // If the function is zero everywhere fall back to sampling it uniformly
+        if integral == 0.0 {
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f32 / n as f32;
+            }
+        } else {
+            for c in &mut cdf {
+                *c = *c / integral;
+            }
+        }
+        Distribution1D { func: func.to_vec(), cdf: cdf, integral: integral }
+    }
+    /// Get the number of pieces in the distribution
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+    /// Sample a continuous value in [0, 1) from the distribution using the random sample `u`.
+    /// Returns the value, its pdf and the index of the piece it was taken from
+    pub fn sample_continuous(&self, u: f32) -> (f32, f32, usize) {
+        // Find the last cdf entry that's <= u
+        let i = match self.cdf.binary_search_by(|c| c.partial_cmp(&u).unwrap()) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let i = if i >= self.count() { self.count() - 1 } else { i };
+        let mut du = u - self.cdf[i];
+        if self.cdf[i + 1] - self.cdf[i] > 0.0 {
+            du = du / (self.cdf[i + 1] - self.cdf[i]);
+        }
+        let pdf = if self.integral > 0.0 { self.func[i] / self.integral } else { 1.0 };
+        ((i as f32 + du) / self.count() as f32, pdf, i)
+    }
+    /// Sample one of the pieces of the distribution using the random sample `u`.
+    /// Returns the index of the piece and the probability of sampling it
+    pub fn sample_discrete(&self, u: f32) -> (usize, f32) {
+        let (_, _, i) = self.sample_continuous(u);
+        (i, self.pmf(i))
+    }
+    /// Compute the probability of sampling the piece `i` with `sample_discrete`
+    pub fn pmf(&self, i: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[i] / (self.integral * self.count() as f32)
+        } else {
+            1.0 / self.count() as f32
+        }
+    }
+    /// Compute the pdf of sampling the value `x` in [0, 1]
+    pub fn pdf(&self, x: f32) -> f32 {
+        if self.integral == 0.0 {
+            return 1.0;
+        }
+        let i = linalg::clamp((x * self.count() as f32) as usize, 0, self.count() - 1);
+        self.func[i] / self.integral
+    }
+}
+
+/// A piecewise constant 2D distribution over [0, 1]^2, sampled by first picking a
+/// row from the marginal distribution and then a column from that row's conditional distribution
+/// See: [PBR](http://pbrt.org/) Section 13.6.7
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
 
+impl Distribution2D {
+    /// Create a distribution from the function values stored in `func` as `height` rows
+    /// of `width` values each
+    pub fn new(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<_> = func.chunks(width).take(height).map(Distribution1D::new).collect();
+        let marginal_func: Vec<_> = conditional.iter().map(|c| c.integral).collect();
+        Distribution2D { conditional: conditional, marginal: Distribution1D::new(&marginal_func[..]) }
+    }
+    /// Sample a point in [0, 1]^2 from the distribution using the random samples `u`.
+    /// Returns the point, where `.0` is along the rows and `.1` picks the row, and its pdf
+    pub fn sample_continuous(&self, u: &(f32, f32)) -> ((f32, f32), f32) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u.1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+    /// Compute the pdf of sampling the point `p` in [0, 1]^2
+    pub fn pdf(&self, p: &(f32, f32)) -> f32 {
+        if self.marginal.integral == 0.0 {
+            return 1.0;
+        }
+        let row = linalg::clamp((p.1 * self.marginal.count() as f32) as usize, 0, self.marginal.count() - 1);
+        let cond = &self.conditional[row];
+        let col = linalg::clamp((p.0 * cond.count() as f32) as usize, 0, cond.count() - 1);
+        cond.func[col] / self.marginal.integral
+    }
+}
+
+#[test]
+fn test_distribution_1d() {
+    let d = Distribution1D::new(&[1.0, 3.0]);
+    assert_eq!(d.integral, 2.0);
+    let (x, pdf, i) = d.sample_continuous(0.125);
+    assert_eq!(i, 0);
+    assert_eq!(x, 0.25);
+    assert_eq!(pdf, 0.5);
+    let (x, pdf, i) = d.sample_continuous(0.625);
+    assert_eq!(i, 1);
+    assert_eq!(x, 0.75);
+    assert_eq!(pdf, 1.5);
+    assert_eq!(d.pdf(0.75), 1.5);
+    assert_eq!(d.sample_discrete(0.5), (1, 0.75));
+    assert_eq!(d.pmf(0), 0.25);
+}
@@ -0,0 +1,88 @@
+//! Defines a homogeneous medium which absorbs and scatters light uniformly throughout
+//! its volume, eg. fog or a tinted liquid.
+//!
+//! # Scene Usage Example
+//! The homogeneous medium is described by its absorption and scattering coefficients,
+//! which are the probability densities per unit distance of light being absorbed or scattered.
+//! An optional scale can be applied to both coefficients, eg. to change the units of distance
+//! they're specified in, and the asymmetry `g` of the Henyey-Greenstein phase function can
+//! optionally be set to make the medium scatter light forward (g > 0) or backward (g < 0).
+//! By default the scale is 1 and light is scattered uniformly in all directions.
+//!
+//! ```json
+//! "media": [
+//!     {
+//!         "name": "fog",
+//!         "type": "homogeneous",
+//!         "sigma_a": [0.01, 0.01, 0.01],
+//!         "sigma_s": [0.1, 0.1, 0.1],
+//!         "scale": 1.0,
+//!         "g": 0.3
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+
+use rand::StdRng;
+
+use linalg::Ray;
+use film::Colorf;
+use sampler::Sampler;
+use media::{Medium, HenyeyGreenstein};
+
+/// A medium with the same absorption and scattering everywhere in it
+pub struct Homogeneous {
+    sigma_s: Colorf,
+    /// Attenuation coefficient of the medium, the sum of its absorption and scattering coefficients
+    sigma_t: Colorf,
+    phase: HenyeyGreenstein,
+}
+
+impl Homogeneous {
+    /// Create a homogeneous medium with absorption and scattering coefficients `sigma_a` and
+    /// `sigma_s` which scatters light with the Henyey-Greenstein phase function with asymmetry `g`
+    pub fn new(sigma_a: &Colorf, sigma_s: &Colorf, g: f32) -> Homogeneous {
+        let mut sigma_t = *sigma_a + *sigma_s;
+        sigma_t.a = 1.0;
+        Homogeneous { sigma_s: *sigma_s, sigma_t: sigma_t, phase: HenyeyGreenstein::new(g) }
+    }
+    /// Compute the transmittance over the distance `dist`
+    fn transmittance_over(&self, dist: f32) -> Colorf {
+        let mut tr = (-self.sigma_t * f32::min(dist, f32::MAX)).exp();
+        tr.a = 1.0;
+        tr
+    }
+}
+
+impl Medium for Homogeneous {
+    fn transmittance(&self, ray: &Ray, _: &mut Sampler, _: &mut StdRng) -> Colorf {
+        self.transmittance_over((ray.max_t - ray.min_t) * ray.d.length())
+    }
+    fn sample(&self, ray: &Ray, sampler: &mut Sampler, rng: &mut StdRng) -> (Colorf, Option<f32>) {
+        let mut samples = [(0.0, 0.0)];
+        sampler.get_samples_2d(&mut samples[..], rng);
+        // Pick a color channel to sample the distance with, since the attenuation differs for each
+        let channel = f32::min(samples[0].0 * 3.0, 2.0) as usize;
+        let len = ray.d.length();
+        let t = ray.min_t - f32::ln(1.0 - samples[0].1) / (self.sigma_t[channel] * len);
+        let scattered = t < ray.max_t;
+        let t = f32::min(t, ray.max_t);
+        let tr = self.transmittance_over((t - ray.min_t) * len);
+        // The density of sampling the distance is the average of the densities of sampling it with each channel
+        let density = if scattered { self.sigma_t * tr } else { tr };
+        let pdf = (density.r + density.g + density.b) / 3.0;
+        if pdf == 0.0 {
+            return (Colorf::black(), None);
+        }
+        if scattered {
+            (tr * self.sigma_s / pdf, Some(t))
+        } else {
+            (tr / pdf, None)
+        }
+    }
+    fn phase(&self) -> &HenyeyGreenstein {
+        &self.phase
+    }
+}
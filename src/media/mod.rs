@@ -0,0 +1,102 @@
+//! The media module defines the Medium trait implemented by participating media, eg. fog,
+//! smoke or tinted liquids, which absorb and scatter light travelling through them.
+//! Media can fill the interior of objects in the scene and the scene itself, light
+//! scattered by them is computed by the volumetric path tracer.
+//!
+//! # Scene Usage Example
+//! The media are specified in a list in the root object of the scene, each with a name
+//! and type along with any additional parameters for the type of medium.
+//!
+//! ```json
+//! "media": [
+//!     {
+//!         "name": "my_medium",
+//!         "type": "The_Medium_Type",
+//!          ...
+//!     }
+//!     ...
+//! ]
+//! ```
+//!
+//! The scene can be filled with a medium by naming it in the root object of the scene, the
+//! camera and any objects which don't contain a different medium are placed in this medium.
+//!
+//! ```json
+//! "medium": "my_medium"
+//! ```
+//!
+//! Objects are filled with a medium by specifying the medium inside and outside of their
+//! surface. The exterior is optional and defaults to the medium filling the scene. The object's
+//! geometry must be closed with its normals facing out for the interior to be found correctly.
+//! Objects which only mark the boundary of a medium, eg. a box of smoke, can use a material
+//! of type `interface` which is invisible, here named `medium_boundary`.
+//!
+//! ```json
+//! "objects": [
+//!     {
+//!          "name": "smoke_box",
+//!          "type": "receiver",
+//!          "material": "medium_boundary",
+//!          "medium": {
+//!              "interior": "smoke",
+//!              "exterior": "my_medium"
+//!          },
+//!          ...
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::sync::Arc;
+
+use rand::StdRng;
+
+use linalg::{self, Ray, Vector, Normal};
+use film::Colorf;
+use sampler::Sampler;
+
+pub use self::phase::HenyeyGreenstein;
+pub use self::homogeneous::Homogeneous;
+
+pub mod phase;
+pub mod homogeneous;
+
+/// Trait implemented by participating media to compute the transmittance along rays
+/// through them and sample where light travelling through them is scattered
+pub trait Medium {
+    /// Compute the fraction of light transmitted along the ray from `ray.min_t` to `ray.max_t`
+    fn transmittance(&self, ray: &Ray, sampler: &mut Sampler, rng: &mut StdRng) -> Colorf;
+    /// Sample a point along the ray from `ray.min_t` to `ray.max_t` where the light travelling
+    /// along it is scattered. Returns the weight to apply to the throughput of the path and the
+    /// parametric distance along the ray of the point, or None if the ray passes through the
+    /// medium without being scattered
+    fn sample(&self, ray: &Ray, sampler: &mut Sampler, rng: &mut StdRng) -> (Colorf, Option<f32>);
+    /// Get the phase function describing the directions light is scattered in
+    fn phase(&self) -> &HenyeyGreenstein;
+}
+
+/// Describes the media on either side of an object's surface, None is used for vacuum
+#[derive(Clone)]
+pub struct MediumInterface {
+    /// The medium inside the object
+    pub interior: Option<Arc<Medium + Send + Sync>>,
+    /// The medium outside the object
+    pub exterior: Option<Arc<Medium + Send + Sync>>,
+}
+
+impl MediumInterface {
+    /// Create a medium interface between the `interior` and `exterior` media
+    pub fn new(interior: Option<Arc<Medium + Send + Sync>>, exterior: Option<Arc<Medium + Send + Sync>>)
+               -> MediumInterface {
+        MediumInterface { interior: interior, exterior: exterior }
+    }
+    /// Get the medium a ray travelling along `d` through the surface with outward facing
+    /// geometric normal `ng` passes into
+    pub fn crossing(&self, d: &Vector, ng: &Normal) -> Option<&(Medium + Send + Sync)> {
+        if linalg::dot(d, ng) < 0.0 {
+            self.interior.as_ref().map(|m| &**m)
+        } else {
+            self.exterior.as_ref().map(|m| &**m)
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Provides the Henyey-Greenstein phase function which describes the distribution of
+//! directions light is scattered in by particles in a participating medium.
+//! See [Henyey and Greenstein, Diffuse radiation in the galaxy](http://adsabs.harvard.edu/abs/1941ApJ....93...70H)
+
+use std::f32;
+
+use linalg::{self, Vector};
+
+/// The Henyey-Greenstein phase function, controlled by the asymmetry parameter `g` in (-1, 1).
+/// Positive values of `g` scatter light forward, negative values scatter it back towards where
+/// it came from and 0 scatters it uniformly in all directions
+#[derive(Clone, Copy, Debug)]
+pub struct HenyeyGreenstein {
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    /// Create the phase function with asymmetry parameter `g`
+    pub fn new(g: f32) -> HenyeyGreenstein {
+        assert!(g > -1.0 && g < 1.0, "Henyey-Greenstein g must be in (-1, 1)");
+        HenyeyGreenstein { g: g }
+    }
+    /// Evaluate the phase function for light arriving from `w_i` scattered towards `w_o`,
+    /// both directions point away from the scattering point
+    pub fn eval(&self, w_o: &Vector, w_i: &Vector) -> f32 {
+        // The angle between the direction light travels along and the scattered direction
+        // is the angle between -w_i and w_o
+        self.density(-linalg::dot(w_o, w_i))
+    }
+    /// Sample a direction `w_i` for light scattered towards `w_o` with the distribution of
+    /// the phase function. Returns the direction along with its density, which is the value
+    /// of the phase function
+    pub fn sample(&self, w_o: &Vector, samples: &(f32, f32)) -> (Vector, f32) {
+        let g = self.g;
+        // Sample the cosine of the angle between the direction light travels along and w_o
+        let cos_theta = if f32::abs(g) < 1e-3 {
+            1.0 - 2.0 * samples.0
+        } else {
+            let sqr_term = (1.0 - g * g) / (1.0 - g + 2.0 * g * samples.0);
+            (1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+        };
+        let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = 2.0 * f32::consts::PI * samples.1;
+        // Light arriving from w_i travels along -w_i
+        let (v1, v2) = linalg::coordinate_system(w_o);
+        let w_i = -(v1 * sin_theta * f32::cos(phi) + v2 * sin_theta * f32::sin(phi) + *w_o * cos_theta);
+        (w_i, self.density(cos_theta))
+    }
+    /// Compute the phase function for the cosine of the angle between the direction
+    /// light travels along and the direction it's scattered to
+    fn density(&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * f32::consts::PI * denom * f32::sqrt(denom))
+    }
+}
+
+#[test]
+fn test_henyey_greenstein() {
+    let w_o = Vector::new(0.0, 0.6, 0.8);
+    for g in &[-0.7, 0.0, 0.3, 0.9] {
+        let hg = HenyeyGreenstein::new(*g);
+        // The phase function should integrate to 1 over the sphere
+        let n = 400;
+        let mut sum = 0.0;
+        for i in 0..n {
+            let cos_theta = 1.0 - 2.0 * (i as f32 + 0.5) / n as f32;
+            let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+            for j in 0..n {
+                let phi = 2.0 * f32::consts::PI * (j as f32 + 0.5) / n as f32;
+                let w_i = Vector::new(sin_theta * f32::cos(phi), sin_theta * f32::sin(phi), cos_theta);
+                sum += hg.eval(&w_o, &w_i);
+            }
+        }
+        let integral = sum * 4.0 * f32::consts::PI / (n * n) as f32;
+        assert!(f32::abs(integral - 1.0) < 0.01);
+        // The density of sampled directions should match the phase function, and forward
+        // scattering should send light on along -w_i
+        let (w_i, pdf) = hg.sample(&w_o, &(0.3, 0.7));
+        assert!(f32::abs(w_i.length() - 1.0) < 1e-4);
+        assert!(f32::abs(pdf - hg.eval(&w_o, &w_i)) < 1e-4);
+        if *g > 0.5 {
+            let (w_i, _) = hg.sample(&w_o, &(0.99, 0.2));
+            assert!(linalg::dot(&w_i, &w_o) < -0.5);
+        }
+    }
+}
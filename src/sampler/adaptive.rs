@@ -2,7 +2,9 @@
 //! one rate and then take more samples if it determines more
 //! are necessary for the pixel. The samples generated are the
 //! same as those from the Low Discrepancy sampler but the
-//! number of samples taken per pixel will vary.
+//! number of samples taken per pixel will vary. More samples
+//! are taken while the standard error of the pixel's mean luminance
+//! is above the error threshold relative to the mean.
 
 use std::{u32, f32, iter};
 use rand::{Rng, StdRng};
@@ -23,58 +25,38 @@ pub struct Adaptive {
     step_size: usize,
     /// How many samples we've taken for this pixel so far
     samples_taken: usize,
-    /// The cumulative moving average of the luminance for the
-    /// number of samples taken so far
-    avg_luminance: f32,
+    /// Largest standard error of the pixel's mean luminance relative to the
+    /// mean that's accepted without taking more samples
+    error: f32,
     scramble_range: Range<u32>,
 }
 
 impl Adaptive {
-    /// Create a low discrepancy sampler to sample the image in `dim.0 * dim.1` sized blocks
-    pub fn new(dim: (u32, u32), mut min_spp: usize, mut max_spp: usize) -> Adaptive {
-        if !min_spp.is_power_of_two() {
-            min_spp = min_spp.next_power_of_two();
-            print!("Warning: Adaptive sampler requires power of two samples per pixel, ");
-            println!("rounding min_spp up to {}", min_spp);
-        }
-        if !max_spp.is_power_of_two() {
-            max_spp = max_spp.next_power_of_two();
-            print!("Warning: Adaptive sampler requires power of two samples per pixel, ");
-            println!("rounding max_spp up to {}", max_spp);
-        }
+    /// Create an adaptive sampler to sample the image in `dim.0 * dim.1` sized blocks, taking
+    /// `min_spp` to `max_spp` samples per pixel until the relative standard error of the
+    /// pixel is below `error`. The samples per pixel must be powers of two with `min_spp` no
+    /// greater than `max_spp`, these are rounded and checked when the scene is loaded
+    pub fn new(dim: (u32, u32), min_spp: usize, max_spp: usize, error: f32) -> Adaptive {
         let step_size = ((max_spp - min_spp) / 5).next_power_of_two();
         Adaptive { region: Region::new((0, 0), dim), min_spp: min_spp, max_spp: max_spp,
-                   step_size: step_size, samples_taken: 0, avg_luminance: 0.0,
+                   step_size: step_size, samples_taken: 0, error: error,
                    scramble_range: Range::new(0, u32::MAX) }
     }
     /// Determine if more samples need to be taken for the pixel currently sampled with the
-    /// set of samples passed. This is done by simply looking at the contrast difference
-    /// between the samples. TODO: What are some better strategies for estimating
-    /// if we need more samples?
-    fn needs_supersampling(&mut self, samples: &[ImageSample]) -> bool {
-        let max_contrast = 0.5;
-        // First sampling pass, compute the initial average luminance
-        if self.samples_taken == self.min_spp {
-            self.avg_luminance = samples.iter().fold(0.0, |ac, s| ac + s.color.luminance())
-                / samples.len() as f32;
-        } else {
-            // Otherwise update the average luminance to include these samples
-            let prev_samples = samples.len() - self.step_size;
-            self.avg_luminance = samples.iter().enumerate().skip(prev_samples)
-                .fold(self.avg_luminance, |ac, (i, s)| {
-                    (s.color.luminance() + (i - 1) as f32 * ac) / i as f32
-                });
-        }
-        // What if we kept and updated the average luminance? The result of this
-        // is that we re-inspect samples that we've seen before, eg after one step up of sampling
-        // we look at the first min_spp samples again, but we've already computed their average
-        // luminance! We should keep a moving average
-        for s in samples.iter() {
-            if f32::abs(s.color.luminance() - self.avg_luminance) / self.avg_luminance > max_contrast {
-                return true;
-            }
+    /// set of all samples taken for it so far. This is done by estimating the standard error
+    /// of the mean luminance of the samples and comparing it to the mean
+    fn needs_supersampling(&self, samples: &[ImageSample]) -> bool {
+        // We can't estimate the variance from a single sample
+        if samples.len() < 2 {
+            return true;
         }
-        false
+        let n = samples.len() as f32;
+        let mean = samples.iter().fold(0.0, |ac, s| ac + s.color.luminance()) / n;
+        let variance = samples.iter().fold(0.0, |ac, s| {
+            let d = s.color.luminance() - mean;
+            ac + d * d
+        }) / (n - 1.0);
+        f32::sqrt(variance / n) > self.error * mean
     }
 }
 
@@ -57,6 +57,19 @@ impl BlockQueue {
             Some(self.blocks[i])
         }
     }
+    /// Move back to the start of the queue so the blocks can be worked through again,
+    /// eg. for another pass over the image. Should only be called once all threads
+    /// have finished working through the queue
+    pub fn reset(&self) {
+        self.next.store(0, Ordering::Release);
+    }
+    /// Keep only the blocks for which `f` returns true and move back to the start of the queue,
+    /// eg. to only take another pass over the blocks which need more samples. Should only be
+    /// called once all threads have finished working through the queue
+    pub fn retain<F: FnMut(&(u32, u32)) -> bool>(&mut self, f: F) {
+        self.blocks.retain(f);
+        self.reset();
+    }
     /// Get the length of the queue
     pub fn len(&self) -> usize { self.blocks.len() }
     /// Check if the queue is empty
@@ -1,6 +1,40 @@
 //! Provides the Sampler trait which is implemented by the various samplers
 //! to provide stratified, low-discrepancy, adaptive sampling methods and so
 //! on through a simple trait interface
+//!
+//! # Scene Usage Example
+//! The sampler used to render the image is specified in the film object of the scene.
+//! If no sampler is specified the low discrepancy sampler is used, taking the number of
+//! samples per pixel set by the film's `samples`.
+//!
+//! ```json
+//! "film": {
+//!     ...
+//!     "sampler": {
+//!         "type": "low_discrepancy",
+//!         "spp": 64
+//!     }
+//! }
+//! ```
+//!
+//! The `uniform` sampler takes a single sample at the center of each pixel and doesn't
+//! take any parameters, while the `low_discrepancy` sampler takes `spp` samples per pixel.
+//! The `adaptive` sampler takes `min_spp` samples per pixel and then keeps taking more, up
+//! to `max_spp`, until the standard error of the mean luminance of the pixel's samples
+//! relative to the mean is below `error`. Flat regions of the image will then converge with
+//! few samples while noisy ones receive more.
+//!
+//! The samples per pixel of the `low_discrepancy` and `adaptive` samplers are rounded up to
+//! powers of two when the scene is loaded, and `min_spp` can't be greater than `max_spp`.
+//!
+//! ```json
+//! "sampler": {
+//!     "type": "adaptive",
+//!     "min_spp": 8,
+//!     "max_spp": 256,
+//!     "error": 0.02
+//! }
+//! ```
 
 use rand::StdRng;
 use film::ImageSample;
@@ -48,6 +82,33 @@ pub trait Sampler {
     fn report_results(&mut self, _samples: &[ImageSample]) -> bool { true }
 }
 
+/// Describes the sampler to use when rendering and its parameters, see the module
+/// documentation for how it's specified in the scene
+#[derive(Clone, Copy, Debug)]
+pub enum SamplerConfig {
+    /// The uniform sampler, taking a single sample per pixel
+    Uniform,
+    /// The low discrepancy sampler taking `spp` samples per pixel
+    LowDiscrepancy { spp: usize },
+    /// The adaptive sampler taking `min_spp` to `max_spp` samples per pixel, stopping
+    /// once the relative standard error of the pixel is below `error`. The samples per pixel
+    /// are powers of two with `min_spp <= max_spp` and `error` is positive
+    Adaptive { min_spp: usize, max_spp: usize, error: f32 },
+}
+
+impl SamplerConfig {
+    /// Create the sampler described to sample the image in `dim.0 * dim.1` sized blocks
+    pub fn create(&self, dim: (u32, u32)) -> Box<Sampler> {
+        match *self {
+            SamplerConfig::Uniform => Box::new(Uniform::new(dim)),
+            SamplerConfig::LowDiscrepancy { spp } => Box::new(LowDiscrepancy::new(dim, spp)),
+            SamplerConfig::Adaptive { min_spp, max_spp, error } => {
+                Box::new(Adaptive::new(dim, min_spp, max_spp, error))
+            },
+        }
+    }
+}
+
 /// Provides a simple way to pass around a 3 component sample consisting of one 2D and
 /// one 1D sample
 #[derive(Debug)]
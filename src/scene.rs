@@ -4,39 +4,82 @@
 //! # Scene JSON Files
 //! The scene file format has four required sections: a camera, an integrator,
 //! a list of materials and a list of objects and lights. The root object in the
-//! JSON file should contain one of each of these.
+//! JSON file should contain one of each of these. A list of textures used by
+//! the materials, a background, a list of participating media and the medium
+//! filling the scene can optionally be specified as well.
 //!
 //! ```json
 //! {
 //!     "camera": {...},
 //!     "integrator": {...},
+//!     "background": ...,
+//!     "textures": [...],
+//!     "media": [...],
+//!     "medium": ...,
 //!     "materials": [...],
 //!     "objects": [...]
 //! }
 //! ```
 //!
+//! ## Background
+//! The background is seen by rays which leave the scene without hitting anything and
+//! lights the scene from infinitely far away, it's black if not specified. It can be
+//! a constant color, which can be animated like the emission of lights.
+//!
+//! ```json
+//! "background": [0.1, 0.1, 0.15, 1]
+//! ```
+//!
+//! Or a clear daylight sky computed by the [Preetham sky model](light/sky/index.html),
+//! specified by the direction towards the sun and the turbidity of the atmosphere, which
+//! ranges from 2 for a very clear sky to 10 for a hazy one. The radiance of the sky is in
+//! thousands of cd/m^2 and can be adjusted with the optional scale, which defaults to 1.
+//! The sky below the horizon is black, the sun itself isn't included.
+//!
+//! ```json
+//! "background": {
+//!     "type": "sky",
+//!     "sun_direction": [0.5, 0.8, 0.3],
+//!     "turbidity": 3,
+//!     "scale": 0.1
+//! }
+//! ```
+//!
 //! For more information on each object see the corresponding modules:
 //!
 //! - Camera: See film/camera
 //! - Integrator: See integrator
+//! - Textures: See texture
 //! - Materials: See materials
+//! - Media: See media
 //! - Objects: See geometry
 //!
 
 use std::io::prelude::*;
 use std::fs::File;
 use std::sync::Arc;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 
 use serde_json::{self, Value};
+use tobj;
 
 use linalg::{Transform, Point, Vector, Ray, Keyframe, AnimatedTransform};
-use film::{filter, Camera, Colorf, RenderTarget, FrameInfo, AnimatedColor, ColorKeyframe};
-use geometry::{Sphere, Instance, Intersection, BVH, Mesh, Disk, Rectangle,
+use film::{filter, Aov, LightGroup, Denoiser, Camera, Colorf, RenderTarget, FrameInfo, AnimatedColor, ColorKeyframe, ToneMap,
+           ToneMapOperator};
+use geometry::{ply, Sphere, Instance, Intersection, BVH, Mesh, Disk, Rectangle,
                BoundableGeom, SampleableGeom};
-use material::{Material, Matte, Glass, Metal, Merl, Plastic, SpecularMetal, RoughGlass};
+use material::{Material, Matte, Glass, Metal, Merl, Plastic, SpecularMetal, RoughGlass, BumpMapped,
+               NormalMapped, Interface, Subsurface};
+use media::{Medium, MediumInterface, Homogeneous};
+use texture::{self, Texture};
 use integrator::{self, Integrator};
+use sampler::SamplerConfig;
+use light::{EnvironmentMap, Sky};
+
+/// Resolution of the environment map the sky background is baked into
+const SKY_MAP_WIDTH: usize = 512;
+const SKY_MAP_HEIGHT: usize = 256;
 
 /// The scene containing the objects and camera configuration we'd like to render,
 /// shared immutably among the ray tracing threads
@@ -45,10 +88,16 @@ pub struct Scene {
     active_camera: usize,
     pub bvh: BVH<Instance>,
     pub integrator: Box<Integrator + Send + Sync>,
+    /// The participating medium filling the scene which the camera is in, if any
+    pub medium: Option<Arc<Medium + Send + Sync>>,
+    /// IDs of the object tags in the scene, numbered from 1 in the order they're listed
+    tag_ids: HashMap<String, u32>,
+    /// Number of light groups the lights in the scene are split into
+    num_light_groups: usize,
 }
 
 impl Scene {
-    pub fn load_file(file: &str) -> (Scene, RenderTarget, usize, FrameInfo) {
+    pub fn load_file(file: &str) -> (Scene, RenderTarget, SamplerConfig, FrameInfo) {
         let mut f = match File::open(file) {
             Ok(f) => f,
             Err(e) => panic!("Failed to open scene file: {}", e),
@@ -68,26 +117,70 @@ impl Scene {
             None => Path::new(file),
         };
 
-        let (rt, spp, frame_info) = load_film(data.find("film").expect("The scene must specify a film to write to"));
+        let (rt, sampler, frame_info) = load_film(data.find("film").expect("The scene must specify a film to write to"));
         let cameras = load_cameras(&data, rt.dimensions());
         let integrator = load_integrator(data.find("integrator")
                                          .expect("The scene must specify the integrator to render with"));
-        let materials = load_materials(path, data.find("materials")
+        let textures = match data.find("textures") {
+            Some(t) => load_textures(path, t),
+            None => HashMap::new(),
+        };
+        let materials = load_materials(path, &textures, data.find("materials")
                                        .expect("The scene must specify an array of materials"));
-        // mesh cache is a map of file_name -> (map of mesh name -> mesh)
-        let mut mesh_cache = HashMap::new();
-        let instances = load_objects(path, &materials, &mut mesh_cache,
-                                     data.find("objects").expect("The scene must specify a list of objects"));
+        let media = match data.find("media") {
+            Some(m) => load_media(m),
+            None => HashMap::new(),
+        };
+        let medium = data.find("medium").map(|m| {
+            let name = m.as_str().expect("The scene medium must be the name of a medium");
+            media.get(name).expect("The scene medium was not found in the media list").clone()
+        });
+        let mut mesh_cache = MeshCache { obj: HashMap::new(), ply: HashMap::new() };
+        let mut instances = load_objects(path, &textures, &materials, &media, medium.as_ref(), &mut mesh_cache,
+                                         data.find("objects").expect("The scene must specify a list of objects"));
+        if let Some(b) = data.find("background") {
+            instances.push(load_background(b));
+        }
 
         assert!(!instances.is_empty(), "Aborting: the scene does not have any objects!");
+        let mut num_light_groups = 0;
+        for a in rt.aovs() {
+            if let Aov::LightGroup(ref g) = *a {
+                assign_light_group(g, &mut instances);
+                num_light_groups += 1;
+            }
+        }
+        let split_aovs: Vec<_> = rt.aovs().iter().filter(|a| a.is_split_radiance()).map(|a| a.name()).collect();
+        if !split_aovs.is_empty() && !integrator.splits_radiance() {
+            println!("Warning: the integrator doesn't split the light reaching the camera, these AOVs will be black: {}",
+                     split_aovs.join(", "));
+        }
+        let mut tag_ids = HashMap::new();
+        for i in &instances {
+            if !tag_ids.contains_key(i.tag()) {
+                let id = tag_ids.len() as u32 + 1;
+                tag_ids.insert(i.tag().to_owned(), id);
+            }
+        }
+        if rt.aovs().contains(&Aov::Id) {
+            let mut ids: Vec<_> = tag_ids.iter().collect();
+            ids.sort_by_key(|&(_, id)| *id);
+            println!("Object tag IDs for the id AOV:");
+            for (tag, id) in ids {
+                println!("    {}: {}", id, tag);
+            }
+        }
         let scene = Scene {
             cameras: cameras,
             active_camera: 0,
             // TODO: Read time parameters from the scene file, update BVH every few frames
             bvh: BVH::new(4, instances, 0.0, frame_info.time),
             integrator: integrator,
+            medium: medium,
+            tag_ids: tag_ids,
+            num_light_groups: num_light_groups,
         };
-        (scene, rt, spp, frame_info)
+        (scene, rt, sampler, frame_info)
     }
     /// Test the ray for intersections against the objects in the scene.
     /// Returns Some(Intersection) if an intersection was found and None if not.
@@ -106,6 +199,14 @@ impl Scene {
         println!("Frame {}: re-building bvh for {} to {}", frame, shutter_time.0, shutter_time.1);
         self.bvh.rebuild(shutter_time.0, shutter_time.1);
     }
+    /// Get the ID of the object tag for the id AOV, 0 if no object has the tag
+    pub fn tag_id(&self, tag: &str) -> u32 {
+        self.tag_ids.get(tag).cloned().unwrap_or(0)
+    }
+    /// Get the number of light groups the lights in the scene are split into
+    pub fn num_light_groups(&self) -> usize {
+        self.num_light_groups
+    }
     /// Get the active camera for the current frame
     pub fn active_camera(&self) -> &Camera {
         &self.cameras[self.active_camera]
@@ -113,14 +214,20 @@ impl Scene {
 }
 
 /// Load the film described by the JSON value passed. Returns the render target
-/// along with the image dimensions and samples per pixel
-fn load_film(elem: &Value) -> (RenderTarget, usize, FrameInfo) {
+/// along with the sampler to use and the frame timing information
+fn load_film(elem: &Value) -> (RenderTarget, SamplerConfig, FrameInfo) {
     let width = elem.find("width").expect("The film must specify the image width")
         .as_u64().expect("Image width must be a number") as usize;
     let height = elem.find("height").expect("The film must specify the image height")
         .as_u64().expect("Image height must be a number") as usize;
-    let spp = elem.find("samples").expect("The film must specify the number of samples per pixel")
-        .as_u64().expect("Samples per pixel must be a number") as usize;
+    let sampler = match elem.find("sampler") {
+        Some(s) => load_sampler(s),
+        None => {
+            let spp = elem.find("samples").expect("The film must specify the number of samples per pixel")
+                .as_u64().expect("Samples per pixel must be a number") as usize;
+            SamplerConfig::LowDiscrepancy { spp: power_of_two_spp(spp, "LowDiscrepancy", "spp") }
+        },
+    };
     let start_frame = elem.find("start_frame").expect("The film must specify the starting frame")
         .as_u64().expect("Start frame must be a number") as usize;
     let end_frame = elem.find("end_frame").expect("The film must specify the frame to end on")
@@ -134,7 +241,141 @@ fn load_film(elem: &Value) -> (RenderTarget, usize, FrameInfo) {
         .as_f64().expect("Scene time must be a number") as f32;
     let frame_info = FrameInfo::new(frames, scene_time, start_frame, end_frame);
     let filter = load_filter(elem.find("filter").expect("The film must specify a reconstruction filter"));
-    (RenderTarget::new((width, height), (2, 2), filter), spp, frame_info)
+    let tone_map = match elem.find("tone_map") {
+        Some(t) => load_tone_map(t),
+        None => ToneMap::new(ToneMapOperator::Exposure, 0.0, None),
+    };
+    let mut aovs = match elem.find("aovs") {
+        Some(a) => a.as_array().expect("The AOVs must be an array of AOV names").iter().map(|a| {
+            let name = a.as_str().expect("AOV names must be strings");
+            Aov::from_name(name).unwrap_or_else(|| panic!("Unrecognized AOV {}!", name))
+        }).collect(),
+        None => Vec::new(),
+    };
+    if let Some(g) = elem.find("light_groups") {
+        aovs.extend(load_light_groups(g).into_iter().map(Aov::LightGroup));
+    }
+    let denoiser = elem.find("denoise").map(load_denoiser);
+    // The denoiser is guided by the albedo and normals so these must be rendered
+    if denoiser.is_some() {
+        for a in &[Aov::Albedo, Aov::Normal] {
+            if !aovs.contains(a) {
+                aovs.push(a.clone());
+            }
+        }
+    }
+    (RenderTarget::new((width, height), (2, 2), filter, tone_map, aovs, denoiser), sampler, frame_info)
+}
+/// Load the sampler described by the JSON value passed
+fn load_sampler(elem: &Value) -> SamplerConfig {
+    let ty = elem.find("type").expect("A type is required for the sampler")
+        .as_str().expect("Sampler type must be a string");
+    if ty == "uniform" {
+        SamplerConfig::Uniform
+    } else if ty == "low_discrepancy" {
+        let spp = elem.find("spp").expect("The low discrepancy sampler must specify the samples per pixel")
+            .as_u64().expect("spp must be a number") as usize;
+        SamplerConfig::LowDiscrepancy { spp: power_of_two_spp(spp, "LowDiscrepancy", "spp") }
+    } else if ty == "adaptive" {
+        let min_spp = elem.find("min_spp").expect("The adaptive sampler must specify the min samples per pixel")
+            .as_u64().expect("min_spp must be a number") as usize;
+        let max_spp = elem.find("max_spp").expect("The adaptive sampler must specify the max samples per pixel")
+            .as_u64().expect("max_spp must be a number") as usize;
+        let error = elem.find("error").expect("The adaptive sampler must specify the error threshold")
+            .as_f64().expect("error must be a number") as f32;
+        let min_spp = power_of_two_spp(min_spp, "Adaptive", "min_spp");
+        let max_spp = power_of_two_spp(max_spp, "Adaptive", "max_spp");
+        if min_spp > max_spp {
+            panic!("Error loading adaptive sampler: min_spp ({}) must not be greater than max_spp ({})",
+                   min_spp, max_spp);
+        }
+        if error <= 0.0 {
+            panic!("Error loading adaptive sampler: error must be greater than 0, got {}", error);
+        }
+        SamplerConfig::Adaptive { min_spp: min_spp, max_spp: max_spp, error: error }
+    } else {
+        panic!("Unrecognized sampler type {}!", ty);
+    }
+}
+/// Round the samples per pixel `spp` set by the `param` of the sampler up to a power of two,
+/// which the low discrepancy and adaptive samplers require
+fn power_of_two_spp(spp: usize, sampler: &str, param: &str) -> usize {
+    if spp.is_power_of_two() {
+        spp
+    } else {
+        let rounded = spp.next_power_of_two();
+        print!("Warning: {} sampler requires power of two samples per pixel, ", sampler);
+        println!("rounding {} up to {}", param, rounded);
+        rounded
+    }
+}
+/// Load the tone mapping operator described by the JSON value passed
+fn load_tone_map(elem: &Value) -> ToneMap {
+    let ty = elem.find("type").expect("A type is required for the tone mapping operator")
+        .as_str().expect("Tone mapping operator type must be a string");
+    let exposure = match elem.find("exposure") {
+        Some(e) => e.as_f64().expect("exposure must be a number") as f32,
+        None => 0.0,
+    };
+    let gamma = elem.find("gamma").map(|g| g.as_f64().expect("gamma must be a number") as f32);
+    let operator = if ty == "exposure" {
+        ToneMapOperator::Exposure
+    } else if ty == "reinhard" {
+        let white = elem.find("white").map(|w| w.as_f64().expect("white must be a number") as f32);
+        ToneMapOperator::Reinhard { white: white }
+    } else if ty == "aces" {
+        ToneMapOperator::Aces
+    } else {
+        panic!("Unrecognized tone mapping operator type {}!", ty);
+    };
+    ToneMap::new(operator, exposure, gamma)
+}
+/// Load the light groups described by the JSON array passed, each light can only be in one group
+fn load_light_groups(elem: &Value) -> Vec<LightGroup> {
+    let groups = elem.as_array().expect("The light groups must be an array of light groups");
+    let mut light_groups: HashMap<String, String> = HashMap::new();
+    groups.iter().enumerate().map(|(i, g)| {
+        let name = g.find("name").expect(&format!("Error loading light group #{}: A name is required", i)[..])
+            .as_str().expect(&format!("Error loading light group #{}: name must be a string", i)[..]);
+        let lights = g.find("lights").expect(&format!("Error loading light group {}: lights are required", name)[..])
+            .as_array().expect(&format!("Error loading light group {}: lights must be an array", name)[..])
+            .iter().map(|l| {
+                l.as_str().expect(&format!("Error loading light group {}: light names must be strings", name)[..])
+                    .to_owned()
+            }).collect::<Vec<_>>();
+        for l in &lights {
+            if let Some(other) = light_groups.insert(l.clone(), name.to_owned()) {
+                panic!("Error loading light group {}: light {} is already in light group {}", name, l, other);
+            }
+        }
+        LightGroup::new(i, name.to_owned(), lights)
+    }).collect()
+}
+/// Load the denoiser described by the JSON value passed
+fn load_denoiser(elem: &Value) -> Denoiser {
+    let radius = match elem.find("radius") {
+        Some(r) => r.as_u64().expect("radius must be a number") as u32,
+        None => 7,
+    };
+    let color = match elem.find("color") {
+        Some(c) => c.as_f64().expect("color must be a number") as f32,
+        None => 0.5,
+    };
+    let albedo = match elem.find("albedo") {
+        Some(a) => a.as_f64().expect("albedo must be a number") as f32,
+        None => 0.1,
+    };
+    let normal = match elem.find("normal") {
+        Some(n) => n.as_f64().expect("normal must be a number") as f32,
+        None => 0.2,
+    };
+    // The scales divide the differences between pixels so they must be positive
+    for &(name, v) in &[("color", color), ("albedo", albedo), ("normal", normal)] {
+        if v <= 0.0 {
+            panic!("Error loading denoiser: {} must be greater than 0, got {}", name, v);
+        }
+    }
+    Denoiser::new(radius, color, albedo, normal)
 }
 /// Load the reconstruction filter described by the JSON value passed
 fn load_filter(elem: &Value) -> Box<filter::Filter + Send + Sync> {
@@ -239,6 +480,30 @@ fn load_integrator(elem: &Value) -> Box<Integrator + Send + Sync> {
         let min_depth = elem.find("min_depth").expect("The integrator must specify the minimum ray depth")
             .as_u64().expect("min_depth must be a number") as u32;
         Box::new(integrator::Whitted::new(min_depth))
+    } else if ty == "bdpt" {
+        let max_depth = elem.find("max_depth").expect("The integrator must specify the maximum ray depth")
+            .as_u64().expect("max_depth must be a number") as u32;
+        Box::new(integrator::Bdpt::new(max_depth))
+    } else if ty == "ppm" {
+        let max_depth = elem.find("max_depth").expect("The integrator must specify the maximum ray depth")
+            .as_u64().expect("max_depth must be a number") as u32;
+        let photons = elem.find("photons").expect("The photon mapper must specify the number of photons per pass")
+            .as_u64().expect("photons must be a number") as usize;
+        let passes = elem.find("passes").expect("The photon mapper must specify the number of passes")
+            .as_u64().expect("passes must be a number") as usize;
+        let radius = elem.find("radius").expect("The photon mapper must specify the initial photon lookup radius")
+            .as_f64().expect("radius must be a number") as f32;
+        let alpha = match elem.find("alpha") {
+            Some(a) => a.as_f64().expect("alpha must be a number") as f32,
+            None => 2.0 / 3.0,
+        };
+        Box::new(integrator::Ppm::new(max_depth, photons, passes, radius, alpha))
+    } else if ty == "volpath" {
+        let min_depth = elem.find("min_depth").expect("The integrator must specify the minimum ray depth")
+            .as_u64().expect("min_depth must be a number") as u32;
+        let max_depth = elem.find("max_depth").expect("The integrator must specify the maximum ray depth")
+            .as_u64().expect("max_depth must be a number") as u32;
+        Box::new(integrator::VolPath::new(min_depth, max_depth))
     } else if ty == "normals_debug" {
         Box::new(integrator::NormalsDebug)
     } else {
@@ -246,6 +511,148 @@ fn load_integrator(elem: &Value) -> Box<Integrator + Send + Sync> {
     }
 }
 
+/// Generate a texture loading error string
+fn tex_error(tex_name: &str, msg: &str) -> String {
+    format!("Error loading texture '{}': {}", tex_name, msg)
+}
+
+/// Load the array of textures used in the scene, panics if a texture is specified
+/// incorrectly. The path to the directory containing the scene file is required to find
+/// referenced texture data relative to the scene file.
+fn load_textures(path: &Path, elem: &Value) -> HashMap<String, Arc<Texture + Send + Sync>> {
+    let mut textures = HashMap::new();
+    let tex_vec = elem.as_array().expect("The textures must be an array of textures used");
+    for (i, t) in tex_vec.iter().enumerate() {
+        let name = t.find("name").expect(&format!("Error loading texture #{}: A name is required", i)[..])
+            .as_str().expect(&format!("Error loading texture #{}: name must be a string", i)[..])
+            .to_owned();
+        let ty = t.find("type").expect(&tex_error(&name, "a type is required")[..])
+            .as_str().expect(&tex_error(&name, "type must be a string")[..]);
+        // Make sure names are unique to avoid people accidently overwriting textures
+        if textures.contains_key(&name) {
+            panic!("Error loading texture '{}': name conflicts with an existing entry", name);
+        }
+        if ty == "image" {
+            let file_path = Path::new(t.find("file")
+                      .expect(&tex_error(&name, "A filename is required for an image texture")[..])
+                      .as_str().expect(&tex_error(&name, "The image file must be a string")[..]));
+            let srgb = match t.find("srgb") {
+                Some(s) => s.as_bool().expect(&tex_error(&name, "srgb must be a bool")[..]),
+                None => true,
+            };
+            if file_path.is_relative() {
+                textures.insert(name, Arc::new(texture::Image::load_file(path.join(file_path).as_path(), srgb))
+                                as Arc<Texture + Send + Sync>);
+            } else {
+                textures.insert(name, Arc::new(texture::Image::load_file(file_path, srgb))
+                                as Arc<Texture + Send + Sync>);
+            }
+        } else if ty == "checkerboard" {
+            let even = load_color_texture(t.find("even")
+                    .expect(&tex_error(&name, "An even color is required for checkerboard")[..]), &textures)
+                .expect(&tex_error(&name, "Invalid color or texture specified for even of checkerboard")[..]);
+            let odd = load_color_texture(t.find("odd")
+                    .expect(&tex_error(&name, "An odd color is required for checkerboard")[..]), &textures)
+                .expect(&tex_error(&name, "Invalid color or texture specified for odd of checkerboard")[..]);
+            let dimension = match t.find("dimension") {
+                Some(d) => d.as_u64().expect(&tex_error(&name, "checkerboard dimension must be 2 or 3")[..]),
+                None => 2,
+            };
+            let scale = t.find("scale").expect(&tex_error(&name, "A scale is required for checkerboard")[..]);
+            if dimension == 2 {
+                let scale = if scale.is_array() {
+                    let s = scale.as_array().unwrap();
+                    if s.len() != 2 {
+                        panic!("{}", tex_error(&name, "checkerboard scale must be a number or array of 2 numbers"));
+                    }
+                    (s[0].as_f64().expect(&tex_error(&name, "checkerboard scale must be a number")[..]) as f32,
+                     s[1].as_f64().expect(&tex_error(&name, "checkerboard scale must be a number")[..]) as f32)
+                } else {
+                    let s = scale.as_f64().expect(&tex_error(&name, "checkerboard scale must be a number")[..]);
+                    (s as f32, s as f32)
+                };
+                textures.insert(name, Arc::new(texture::Checkerboard2D::new(even, odd, scale))
+                                as Arc<Texture + Send + Sync>);
+            } else if dimension == 3 {
+                let scale = scale.as_f64().expect(&tex_error(&name, "3D checkerboard scale must be a number")[..]);
+                textures.insert(name, Arc::new(texture::Checkerboard3D::new(even, odd, scale as f32))
+                                as Arc<Texture + Send + Sync>);
+            } else {
+                panic!("{}", tex_error(&name, "checkerboard dimension must be 2 or 3"));
+            }
+        } else if ty == "fbm" || ty == "turbulence" || ty == "marble" || ty == "wood" {
+            let low = load_color_texture(t.find("low")
+                    .expect(&tex_error(&name, "A low color is required for noise textures")[..]), &textures)
+                .expect(&tex_error(&name, "Invalid color or texture specified for low")[..]);
+            let high = load_color_texture(t.find("high")
+                    .expect(&tex_error(&name, "A high color is required for noise textures")[..]), &textures)
+                .expect(&tex_error(&name, "Invalid color or texture specified for high")[..]);
+            let scale = match t.find("scale") {
+                Some(s) => s.as_f64().expect(&tex_error(&name, "scale must be a number")[..]) as f32,
+                None => 1.0,
+            };
+            let omega = match t.find("omega") {
+                Some(o) => o.as_f64().expect(&tex_error(&name, "omega must be a number")[..]) as f32,
+                None => 0.5,
+            };
+            let octaves = match t.find("octaves") {
+                Some(o) => o.as_u64().expect(&tex_error(&name, "octaves must be a positive integer")[..]) as usize,
+                None => 8,
+            };
+            let variation = match t.find("variation") {
+                Some(v) => v.as_f64().expect(&tex_error(&name, "variation must be a number")[..]) as f32,
+                None => if ty == "wood" { 0.1 } else { 1.0 },
+            };
+            let tex = if ty == "fbm" {
+                Arc::new(texture::FBm::new(low, high, scale, omega, octaves)) as Arc<Texture + Send + Sync>
+            } else if ty == "turbulence" {
+                Arc::new(texture::Turbulence::new(low, high, scale, omega, octaves)) as Arc<Texture + Send + Sync>
+            } else if ty == "marble" {
+                Arc::new(texture::Marble::new(low, high, scale, variation, omega, octaves))
+                    as Arc<Texture + Send + Sync>
+            } else {
+                Arc::new(texture::Wood::new(low, high, scale, variation, omega, octaves)) as Arc<Texture + Send + Sync>
+            };
+            textures.insert(name, tex);
+        } else {
+            panic!("Error parsing texture '{}': unrecognized type '{}'", name, ty);
+        }
+    }
+    textures
+}
+
+/// Load a color parameter from the JSON element passed. The element can either be a color
+/// or the name of a texture in `textures`. Returns None if the element was neither and
+/// panics if the texture name isn't in `textures`.
+fn load_color_texture(elem: &Value, textures: &HashMap<String, Arc<Texture + Send + Sync>>)
+                      -> Option<Arc<Texture + Send + Sync>> {
+    if let Some(name) = elem.as_str() {
+        match textures.get(name) {
+            Some(t) => Some(t.clone()),
+            None => panic!("Unknown texture '{}', textures must be listed in the scene's textures \
+                            before they're used", name),
+        }
+    } else {
+        load_color(elem).map(|c| Arc::new(texture::ConstantColor::new(&c)) as Arc<Texture + Send + Sync>)
+    }
+}
+
+/// Load a scalar parameter from the JSON element passed. The element can either be a number
+/// or the name of a texture in `textures`. Returns None if the element was neither and
+/// panics if the texture name isn't in `textures`.
+fn load_scalar_texture(elem: &Value, textures: &HashMap<String, Arc<Texture + Send + Sync>>)
+                       -> Option<Arc<Texture + Send + Sync>> {
+    if let Some(name) = elem.as_str() {
+        match textures.get(name) {
+            Some(t) => Some(t.clone()),
+            None => panic!("Unknown texture '{}', textures must be listed in the scene's textures \
+                            before they're used", name),
+        }
+    } else {
+        elem.as_f64().map(|x| Arc::new(texture::ConstantScalar::new(x as f32)) as Arc<Texture + Send + Sync>)
+    }
+}
+
 /// Generate a material loading error string
 fn mat_error(mat_name: &str, msg: &str) -> String {
     format!("Error loading material '{}': {}", mat_name, msg)
@@ -254,7 +661,8 @@ fn mat_error(mat_name: &str, msg: &str) -> String {
 /// Load the array of materials used in the scene, panics if a material is specified
 /// incorrectly. The path to the directory containing the scene file is required to find
 /// referenced material data relative to the scene file.
-fn load_materials(path: &Path, elem: &Value) -> HashMap<String, Arc<Material + Send + Sync>> {
+fn load_materials(path: &Path, textures: &HashMap<String, Arc<Texture + Send + Sync>>, elem: &Value)
+                  -> HashMap<String, Arc<Material + Send + Sync>> {
     let mut materials = HashMap::new();
     let mat_vec = elem.as_array().expect("The materials must be an array of materials used");
     for (i, m) in mat_vec.iter().enumerate() {
@@ -267,49 +675,57 @@ fn load_materials(path: &Path, elem: &Value) -> HashMap<String, Arc<Material + S
         if materials.contains_key(&name) {
             panic!("Error loading material '{}': name conflicts with an existing entry", name);
         }
-        if ty == "glass" {
-            let reflect = load_color(m.find("reflect")
-                                     .expect(&mat_error(&name, "A reflect color is required for glass")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for reflect of glass")[..]);
-            let transmit = load_color(m.find("transmit")
-                                      .expect(&mat_error(&name, "A transmit color is required for glass")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for transmit of glass")[..]);
+        let mat = if ty == "glass" {
+            let reflect = load_color_texture(m.find("reflect")
+                                     .expect(&mat_error(&name, "A reflect color is required for glass")[..]), textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for reflect of glass")[..]);
+            let transmit = load_color_texture(m.find("transmit")
+                                      .expect(&mat_error(&name, "A transmit color is required for glass")[..]), textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for transmit of glass")[..]);
             let eta = m.find("eta")
                 .expect(&mat_error(&name, "A refractive index 'eta' is required for glass")[..]).as_f64()
                 .expect(&mat_error(&name, "glass eta must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Glass::new(&reflect, &transmit, eta)) as Arc<Material + Send + Sync>);
+            Arc::new(Glass::new(reflect, transmit, eta)) as Arc<Material + Send + Sync>
+        } else if ty == "interface" {
+            Arc::new(Interface) as Arc<Material + Send + Sync>
+        } else if ty == "subsurface" {
+            let eta = m.find("eta")
+                .expect(&mat_error(&name, "A refractive index 'eta' is required for subsurface")[..]).as_f64()
+                .expect(&mat_error(&name, "subsurface eta must be a float")[..]) as f32;
+            let medium = Arc::new(load_homogeneous(m, |msg| mat_error(&name, msg)));
+            Arc::new(Subsurface::new(eta, medium)) as Arc<Material + Send + Sync>
         } else if ty == "rough_glass" {
-            let reflect = load_color(m.find("reflect")
-                                     .expect(&mat_error(&name, "A reflect color is required for roughglass")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for reflect of glass")[..]);
-            let transmit = load_color(m.find("transmit")
-                                      .expect(&mat_error(&name, "A transmit color is required for roughglass")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for transmit of roughglass")[..]);
+            let reflect = load_color_texture(m.find("reflect")
+                                     .expect(&mat_error(&name, "A reflect color is required for roughglass")[..]),
+                                     textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for reflect of glass")[..]);
+            let transmit = load_color_texture(m.find("transmit")
+                                      .expect(&mat_error(&name, "A transmit color is required for roughglass")[..]),
+                                      textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for transmit of roughglass")[..]);
             let eta = m.find("eta")
                 .expect(&mat_error(&name, "A refractive index 'eta' is required for roughglass")[..]).as_f64()
                 .expect(&mat_error(&name, "roughglass eta must be a float")[..]) as f32;
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for roughglass")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness of roughglass must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(RoughGlass::new(&reflect, &transmit, eta, roughness))
-                             as Arc<Material + Send + Sync>);
+            let roughness = load_scalar_texture(m.find("roughness")
+                .expect(&mat_error(&name, "A roughness is required for roughglass")[..]), textures)
+                .expect(&mat_error(&name, "roughness of roughglass must be a float or texture")[..]);
+            Arc::new(RoughGlass::new(reflect, transmit, eta, roughness)) as Arc<Material + Send + Sync>
         } else if ty == "matte" {
-            let diffuse = load_color(m.find("diffuse")
-                                     .expect(&mat_error(&name, "A diffuse color is required for matte")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for diffuse of matte")[..]);
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for matte")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Matte::new(&diffuse, roughness)) as Arc<Material + Send + Sync>);
+            let diffuse = load_color_texture(m.find("diffuse")
+                                     .expect(&mat_error(&name, "A diffuse color is required for matte")[..]), textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for diffuse of matte")[..]);
+            let roughness = load_scalar_texture(m.find("roughness")
+                .expect(&mat_error(&name, "A roughness is required for matte")[..]), textures)
+                .expect(&mat_error(&name, "roughness must be a float or texture")[..]);
+            Arc::new(Matte::new(diffuse, roughness)) as Arc<Material + Send + Sync>
         } else if ty == "merl" {
             let file_path = Path::new(m.find("file")
                       .expect(&mat_error(&name, "A filename containing the MERL material data is required")[..])
                       .as_str().expect(&mat_error(&name, "The MERL file must be a string")[..]));
             if file_path.is_relative() {
-                materials.insert(name, Arc::new(Merl::load_file(path.join(file_path).as_path()))
-                                 as Arc<Material + Send + Sync>);
+                Arc::new(Merl::load_file(path.join(file_path).as_path())) as Arc<Material + Send + Sync>
             } else {
-                materials.insert(name, Arc::new(Merl::load_file(file_path)) as Arc<Material + Send + Sync>);
+                Arc::new(Merl::load_file(file_path)) as Arc<Material + Send + Sync>
             }
         } else if ty == "metal" {
             let refr_index = load_color(m.find("refractive_index")
@@ -318,23 +734,21 @@ fn load_materials(path: &Path, elem: &Value) -> HashMap<String, Arc<Material + S
             let absorption_coef = load_color(m.find("absorption_coefficient")
                          .expect(&mat_error(&name, "An absorption_coefficient color is required for metal")[..]))
                 .expect(&mat_error(&name, "Invalid color specified for absorption_coefficient of metal")[..]);
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for metal")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Metal::new(&refr_index, &absorption_coef, roughness))
-                             as Arc<Material + Send + Sync>);
+            let roughness = load_scalar_texture(m.find("roughness")
+                .expect(&mat_error(&name, "A roughness is required for metal")[..]), textures)
+                .expect(&mat_error(&name, "roughness must be a float or texture")[..]);
+            Arc::new(Metal::new(&refr_index, &absorption_coef, roughness)) as Arc<Material + Send + Sync>
         } else if ty == "plastic" {
-            let diffuse = load_color(m.find("diffuse")
-                             .expect(&mat_error(&name, "A diffuse color is required for plastic")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for diffuse of plastic")[..]);
-            let gloss = load_color(m.find("gloss")
-                             .expect(&mat_error(&name, "A gloss color is required for plastic")[..]))
-                .expect(&mat_error(&name, "Invalid color specified for gloss of plastic")[..]);
-            let roughness = m.find("roughness")
-                .expect(&mat_error(&name, "A roughness is required for plastic")[..]).as_f64()
-                .expect(&mat_error(&name, "roughness must be a float")[..]) as f32;
-            materials.insert(name, Arc::new(Plastic::new(&diffuse, &gloss, roughness))
-                             as Arc<Material + Send + Sync>);
+            let diffuse = load_color_texture(m.find("diffuse")
+                             .expect(&mat_error(&name, "A diffuse color is required for plastic")[..]), textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for diffuse of plastic")[..]);
+            let gloss = load_color_texture(m.find("gloss")
+                             .expect(&mat_error(&name, "A gloss color is required for plastic")[..]), textures)
+                .expect(&mat_error(&name, "Invalid color or texture specified for gloss of plastic")[..]);
+            let roughness = load_scalar_texture(m.find("roughness")
+                .expect(&mat_error(&name, "A roughness is required for plastic")[..]), textures)
+                .expect(&mat_error(&name, "roughness must be a float or texture")[..]);
+            Arc::new(Plastic::new(diffuse, gloss, roughness)) as Arc<Material + Send + Sync>
         } else if ty == "specular_metal" {
             let refr_index = load_color(m.find("refractive_index")
                     .expect(&mat_error(&name, "A refractive_index color is required for specular metal")[..]))
@@ -344,20 +758,91 @@ fn load_materials(path: &Path, elem: &Value) -> HashMap<String, Arc<Material + S
                                         "An absorption_coefficient color is required for specular metal")[..]))
                 .expect(&mat_error(&name,
                                    "Invalid color specified for absorption_coefficient of specular metal")[..]);
-            materials.insert(name, Arc::new(SpecularMetal::new(&refr_index, &absorption_coef))
-                             as Arc<Material + Send + Sync>);
+            Arc::new(SpecularMetal::new(&refr_index, &absorption_coef)) as Arc<Material + Send + Sync>
         } else {
             panic!("Error parsing material '{}': unrecognized type '{}'", name, ty);
-        }
+        };
+        // Apply any bump or normal map to the material
+        let mat = match m.find("bump") {
+            Some(b) => {
+                let height = load_scalar_texture(b, textures)
+                    .expect(&mat_error(&name, "bump must be a float or texture")[..]);
+                let scale = match m.find("bump_scale") {
+                    Some(s) => s.as_f64().expect(&mat_error(&name, "bump_scale must be a float")[..]) as f32,
+                    None => 1.0,
+                };
+                Arc::new(BumpMapped::new(mat, height, scale)) as Arc<Material + Send + Sync>
+            },
+            None => mat,
+        };
+        let mat = match m.find("normal_map") {
+            Some(n) => {
+                let normals = load_color_texture(n, textures)
+                    .expect(&mat_error(&name, "Invalid color or texture specified for normal_map")[..]);
+                Arc::new(NormalMapped::new(mat, normals)) as Arc<Material + Send + Sync>
+            },
+            None => mat,
+        };
+        materials.insert(name, mat);
     }
     materials
 }
 
+/// Cache of the mesh files loaded by the scene so objects using the same file share the mesh
+struct MeshCache {
+    /// Map of OBJ file name -> loaded OBJ file
+    obj: HashMap<String, ObjFile>,
+    /// Map of PLY file name -> mesh
+    ply: HashMap<String, Arc<Mesh>>,
+}
+
+/// An OBJ file loaded for mesh geometry. The materials from the file's MTL files are
+/// only converted to our materials once an object requests to use them
+struct ObjFile {
+    /// Map of model name -> mesh
+    meshes: HashMap<String, Arc<Mesh>>,
+    /// All the models in the file combined into one mesh, created when first requested
+    merged: Option<Arc<Mesh>>,
+    mtl_materials: Vec<tobj::Material>,
+    materials: Option<Vec<Arc<Material + Send + Sync>>>,
+    /// Directory containing the OBJ file, texture paths in the MTL files are relative to it
+    dir: PathBuf,
+}
+
+/// Convert a material loaded from an MTL file to the closest matching material we support,
+/// see the geometry::mesh documentation for how the MTL parameters are mapped.
+fn convert_mtl_material(dir: &Path, mtl: &tobj::Material) -> Arc<Material + Send + Sync> {
+    let mtl_texture = |color: &[f32; 3], map: &str| -> Arc<Texture + Send + Sync> {
+        if map.is_empty() {
+            Arc::new(texture::ConstantColor::new(&Colorf::new(color[0], color[1], color[2])))
+        } else {
+            Arc::new(texture::Image::load_file(dir.join(map).as_path(), true))
+        }
+    };
+    // Approximate the roughness corresponding to the Phong specular exponent
+    let roughness = Arc::new(texture::ConstantScalar::new(f32::sqrt(2.0 / (mtl.shininess + 2.0))));
+    if mtl.dissolve < 1.0 {
+        let eta = match mtl.unknown_param.get("Ni").and_then(|n| n.parse::<f32>().ok()) {
+            Some(eta) if eta >= 1.0 => eta,
+            _ => 1.5,
+        };
+        let white = Arc::new(texture::ConstantColor::new(&Colorf::broadcast(1.0)));
+        Arc::new(Glass::new(white.clone(), white, eta))
+    } else if mtl.specular.iter().any(|s| *s > 0.0) || !mtl.specular_texture.is_empty() {
+        Arc::new(Plastic::new(mtl_texture(&mtl.diffuse, &mtl.diffuse_texture),
+                              mtl_texture(&mtl.specular, &mtl.specular_texture), roughness))
+    } else {
+        Arc::new(Matte::new(mtl_texture(&mtl.diffuse, &mtl.diffuse_texture),
+                            Arc::new(texture::ConstantScalar::new(0.0))))
+    }
+}
+
 /// Loads the array of objects in the scene, assigning them materials from the materials map. Will
 /// panic if an incorrectly specified object is found.
-fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + Sync>>,
-                mesh_cache: &mut HashMap<String, HashMap<String, Arc<Mesh>>>, elem: &Value)
-                -> Vec<Instance> {
+fn load_objects(path: &Path, textures: &HashMap<String, Arc<Texture + Send + Sync>>,
+                materials: &HashMap<String, Arc<Material + Send + Sync>>,
+                media: &HashMap<String, Arc<Medium + Send + Sync>>, scene_medium: Option<&Arc<Medium + Send + Sync>>,
+                mesh_cache: &mut MeshCache, elem: &Value) -> Vec<Instance> {
     let mut instances = Vec::new();
     let objects = elem.as_array().expect("The objects must be an array of objects used");
     for o in objects {
@@ -376,6 +861,7 @@ fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + S
                 AnimatedTransform::unanimated(&t)
             },
         };
+        let mut medium_interface = o.find("medium").map(|m| load_medium_interface(&name, m, media, scene_medium));
         if ty == "emitter" {
             let emit_ty = o.find("emitter").expect("An emitter type is required for emitters")
                 .as_str().expect("Emitter type must be a string");
@@ -384,30 +870,78 @@ fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + S
                     .expect("Emitter emission must be a color");
             if emit_ty == "point" {
                 instances.push(Instance::point_light(transform, emission, name));
+            } else if emit_ty == "spot" {
+                let cone_angle = o.find("cone_angle").expect("A cone_angle is required for spot lights")
+                    .as_f64().expect("Spot light cone_angle must be a number") as f32;
+                let falloff_angle = match o.find("falloff_angle") {
+                    Some(f) => f.as_f64().expect("Spot light falloff_angle must be a number") as f32,
+                    None => cone_angle,
+                };
+                instances.push(Instance::spot_light(transform, emission, cone_angle, falloff_angle, name));
+            } else if emit_ty == "directional" {
+                instances.push(Instance::directional_light(transform, emission, name));
             } else if emit_ty == "area" {
                 let mat_name = o.find("material").expect("A material is required for an object")
                     .as_str().expect("Object material name must be a string");
                 let mat = materials.get(mat_name)
                     .expect("Material was not found in the material list").clone();
-                let geom = load_sampleable_geometry(o.find("geometry")
+                medium_interface = fill_with_material_medium(medium_interface, &mat, scene_medium);
+                let geom = load_sampleable_geometry(path, mesh_cache, o.find("geometry")
                                                     .expect("Geometry is required for area lights"));
-
-                instances.push(Instance::area_light(geom, mat, emission, transform, name));
+                match o.find("emission_texture") {
+                    Some(t) => {
+                        let tex = load_color_texture(t, textures)
+                            .expect("emission_texture must be the name of a texture or a color");
+                        instances.push(Instance::textured_area_light(geom, mat, tex, emission, transform, name));
+                    },
+                    None => instances.push(Instance::area_light(geom, mat, emission, transform, name)),
+                }
+            } else if emit_ty == "environment" {
+                let file = Path::new(o.find("file").expect("A file is required for environment lights")
+                    .as_str().expect("Environment light file must be a string"));
+                let image = if file.is_relative() {
+                    texture::Image::load_file(path.join(file).as_path(), true)
+                } else {
+                    texture::Image::load_file(file, true)
+                };
+                let map = Arc::new(EnvironmentMap::new(image));
+                instances.push(Instance::environment_light(map, transform, emission, name));
             } else {
                 panic!("Invalid emitter type specified: {}", emit_ty);
             }
+            instances.last_mut().unwrap().set_medium_interface(medium_interface);
         } else if ty == "receiver" {
-            let mat_name = o.find("material").expect("A material is required for an object")
-                    .as_str().expect("Object material name must be a string");
-            let mat = materials.get(mat_name)
-                .expect("Material was not found in the material list").clone();
-            let geom = load_geometry(path, mesh_cache, o.find("geometry")
-                                     .expect("Geometry is required for receivers"));
+            let geom_elem = o.find("geometry").expect("Geometry is required for receivers");
+            let use_mtl = match o.find("use_mtl") {
+                Some(u) => u.as_bool().expect("use_mtl must be a bool"),
+                None => false,
+            };
+            let mat = o.find("material").map(|m| {
+                let mat_name = m.as_str().expect("Object material name must be a string");
+                materials.get(mat_name).expect("Material was not found in the material list").clone()
+            });
+            if let Some(ref m) = mat {
+                medium_interface = fill_with_material_medium(medium_interface, m, scene_medium);
+            }
+            let geom = load_geometry(path, mesh_cache, geom_elem);
 
-            instances.push(Instance::receiver(geom, mat, transform, name));
+            if use_mtl {
+                let mtl_materials = load_mtl_materials(path, mesh_cache, geom_elem);
+                // Triangles without an MTL material fall back to the object's material or white matte
+                let mat = mat.unwrap_or_else(|| {
+                    Arc::new(Matte::new(Arc::new(texture::ConstantColor::new(&Colorf::broadcast(0.8))),
+                                        Arc::new(texture::ConstantScalar::new(0.0))))
+                });
+                instances.push(Instance::receiver_with_materials(geom, mat, mtl_materials, transform, name));
+            } else {
+                let mat = mat.expect("A material is required for an object");
+                instances.push(Instance::receiver(geom, mat, transform, name));
+            }
+            instances.last_mut().unwrap().set_medium_interface(medium_interface);
         } else if ty == "group" {
             let group_objects = o.find("objects").expect("A group must specify an array of objects in the group");
-            let group_instances = load_objects(path, materials, mesh_cache, group_objects);
+            let group_instances = load_objects(path, textures, materials, media, scene_medium, mesh_cache,
+                                               group_objects);
             for mut gi in group_instances {
                 {
                     let t = gi.get_transform().clone();
@@ -422,9 +956,139 @@ fn load_objects(path: &Path, materials: &HashMap<String, Arc<Material + Send + S
     instances
 }
 
+/// Load the list of participating media used in the scene, panics if a medium is specified incorrectly
+fn load_media(elem: &Value) -> HashMap<String, Arc<Medium + Send + Sync>> {
+    let mut media = HashMap::new();
+    let media_vec = elem.as_array().expect("The media must be an array of media used");
+    for (i, m) in media_vec.iter().enumerate() {
+        let name = m.find("name").expect(&format!("Error loading medium #{}: A name is required", i)[..])
+            .as_str().expect(&format!("Error loading medium #{}: name must be a string", i)[..])
+            .to_owned();
+        let ty = m.find("type").expect(&medium_error(&name, "a type is required")[..])
+            .as_str().expect(&medium_error(&name, "type must be a string")[..]);
+        // Make sure names are unique to avoid people accidently overwriting media
+        if media.contains_key(&name) {
+            panic!("Error loading medium '{}': name conflicts with an existing entry", name);
+        }
+        if ty == "homogeneous" {
+            let medium = load_homogeneous(m, |msg| medium_error(&name, msg));
+            media.insert(name, Arc::new(medium) as Arc<Medium + Send + Sync>);
+        } else {
+            panic!("Error loading medium '{}': unrecognized type '{}'", name, ty);
+        }
+    }
+    media
+}
+
+/// Load a homogeneous medium from its absorption and scattering coefficients, which can optionally
+/// be scaled, and the asymmetry `g` of its phase function. This is used by homogeneous media and
+/// materials filled with a medium, eg. subsurface, `error` generates the error messages for the
+/// object being loaded. Panics if the medium is specified incorrectly
+fn load_homogeneous<F: Fn(&str) -> String>(elem: &Value, error: F) -> Homogeneous {
+    let sigma_a = load_color(elem.find("sigma_a").expect(&error("sigma_a is required")[..]))
+        .expect(&error("sigma_a must be a color")[..]);
+    let sigma_s = load_color(elem.find("sigma_s").expect(&error("sigma_s is required")[..]))
+        .expect(&error("sigma_s must be a color")[..]);
+    let scale = match elem.find("scale") {
+        Some(s) => s.as_f64().expect(&error("scale must be a number")[..]) as f32,
+        None => 1.0,
+    };
+    let g = match elem.find("g") {
+        Some(g) => g.as_f64().expect(&error("g must be a number")[..]) as f32,
+        None => 0.0,
+    };
+    Homogeneous::new(&(sigma_a * scale), &(sigma_s * scale), g)
+}
+
+/// Generate a medium loading error string
+fn medium_error(medium_name: &str, msg: &str) -> String {
+    format!("Error loading medium '{}': {}", medium_name, msg)
+}
+
+/// Load the media on either side of the object `name`'s surface described by the JSON value passed.
+/// The exterior defaults to the medium filling the scene if it's not specified
+fn load_medium_interface(name: &str, elem: &Value, media: &HashMap<String, Arc<Medium + Send + Sync>>,
+                         scene_medium: Option<&Arc<Medium + Send + Sync>>) -> MediumInterface {
+    let find_medium = |side: &str| {
+        elem.find(side).map(|m| {
+            let medium_name = m.as_str()
+                .expect(&format!("Error loading object '{}': the {} medium must be a string", name, side)[..]);
+            media.get(medium_name)
+                .expect(&format!("Error loading object '{}': medium '{}' was not found", name, medium_name)[..])
+                .clone()
+        })
+    };
+    let interior = find_medium("interior");
+    let exterior = find_medium("exterior").or_else(|| scene_medium.cloned());
+    MediumInterface::new(interior, exterior)
+}
+
+/// Fill the object with the medium of its material `mat`, if the material has one, unless
+/// the object's medium interface already specifies the medium inside it
+fn fill_with_material_medium(medium_interface: Option<MediumInterface>, mat: &Arc<Material + Send + Sync>,
+                             scene_medium: Option<&Arc<Medium + Send + Sync>>) -> Option<MediumInterface> {
+    match mat.interior() {
+        Some(interior) => {
+            let mut mi = medium_interface.unwrap_or_else(|| MediumInterface::new(None, scene_medium.cloned()));
+            if mi.interior.is_none() {
+                mi.interior = Some(interior);
+            }
+            Some(mi)
+        },
+        None => medium_interface,
+    }
+}
+
+/// Put the lights listed in the light group `group` in the group, all the lights listed must be
+/// lights in the scene
+fn assign_light_group(group: &LightGroup, instances: &mut [Instance]) {
+    for l in &group.lights {
+        let mut found = false;
+        for i in instances.iter_mut() {
+            if let Instance::Emitter(ref mut e) = *i {
+                if e.tag == *l {
+                    found = true;
+                    e.set_light_group(Some(group.index));
+                }
+            }
+        }
+        if !found {
+            panic!("Error loading light group {}: light {} is not a light in the scene", group.name, l);
+        }
+    }
+}
+
+/// Load the background specified by the JSON value, returns the light surrounding the scene
+fn load_background(elem: &Value) -> Instance {
+    let name = "background".to_owned();
+    if elem.is_array() {
+        let color = load_animated_color(elem).expect("The background color must be a valid color");
+        return Instance::constant_light(color, name);
+    }
+    let ty = elem.find("type").expect("A type is required for the background")
+        .as_str().expect("Background type must be a string");
+    if ty == "sky" {
+        let sun_dir = load_vector(elem.find("sun_direction").expect("A sun_direction is required for the sky"))
+            .expect("The sky's sun_direction must be a vector");
+        let turbidity = elem.find("turbidity").expect("A turbidity is required for the sky")
+            .as_f64().expect("The sky's turbidity must be a number") as f32;
+        let scale = match elem.find("scale") {
+            Some(s) => s.as_f64().expect("The sky's scale must be a number") as f32,
+            None => 1.0,
+        };
+        let map = Sky::new(&sun_dir, turbidity).to_environment_map(SKY_MAP_WIDTH, SKY_MAP_HEIGHT, scale);
+        let transform = AnimatedTransform::unanimated(&Transform::identity());
+        Instance::environment_light(Arc::new(map), transform,
+                                    AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(1.0), 0.0)]),
+                                    name)
+    } else {
+        panic!("Unrecognized background type '{}'", ty);
+    }
+}
+
 /// Load the geometry specified by the JSON value. Will re-use any already loaded meshes
 /// and will place newly loaded meshees in the mesh cache.
-fn load_geometry(path: &Path, meshes: &mut HashMap<String, HashMap<String, Arc<Mesh>>>, elem: &Value)
+fn load_geometry(path: &Path, meshes: &mut MeshCache, elem: &Value)
              -> Arc<BoundableGeom + Send + Sync> {
     let ty = elem.find("type").expect("A type is required for geometry")
         .as_str().expect("Geometry type must be a string");
@@ -448,31 +1112,93 @@ fn load_geometry(path: &Path, meshes: &mut HashMap<String, HashMap<String, Arc<M
             .expect("height must be a number") as f32;
         Arc::new(Rectangle::new(width, height))
     } else if ty == "mesh" {
-        let mut file = Path::new(elem.find("file").expect("An OBJ file is required for meshes")
-            .as_str().expect("OBJ filename must be a string")).to_path_buf();
-        let model = elem.find("model").expect("A model name is required for geometry")
-            .as_str().expect("Model name type must be a string");
-
-        if file.is_relative() {
-            file = path.join(file);
-        }
-        let file_string = file.to_str().expect("Invalid file name");
-        if meshes.get(file_string).is_none() {
-            meshes.insert(file_string.to_owned(), Mesh::load_obj(Path::new(&file)));
-        }
-        let file_meshes = &meshes[file_string];
-        match file_meshes.get(model) {
-            Some(m) => m.clone(),
-            None => panic!("Requested model '{}' was not found in '{:?}'", model, file),
-        }
+        load_mesh(path, meshes, elem)
+    } else if ty == "ply" {
+        load_ply(path, meshes, elem)
     } else {
         panic!("Unrecognized geometry type '{}'", ty);
     }
 }
 
+/// Load the PLY mesh geometry specified by the JSON value, re-using it if it's already
+/// in the mesh cache
+fn load_ply(path: &Path, meshes: &mut MeshCache, elem: &Value) -> Arc<Mesh> {
+    let mut file = Path::new(elem.find("file").expect("A PLY file is required for ply geometry")
+        .as_str().expect("PLY filename must be a string")).to_path_buf();
+    if file.is_relative() {
+        file = path.join(file);
+    }
+    let file_string = file.to_str().expect("Invalid file name").to_owned();
+    meshes.ply.entry(file_string).or_insert_with(|| Arc::new(ply::load_file(file.as_path()))).clone()
+}
+
+/// Load the OBJ file referenced by the mesh geometry specified by the JSON value into the
+/// mesh cache if it hasn't been loaded yet and return the loaded file
+fn load_obj_file<'a>(path: &Path, meshes: &'a mut MeshCache, elem: &Value) -> &'a mut ObjFile {
+    let mut file = Path::new(elem.find("file").expect("An OBJ file is required for meshes")
+        .as_str().expect("OBJ filename must be a string")).to_path_buf();
+    if file.is_relative() {
+        file = path.join(file);
+    }
+    let file_string = file.to_str().expect("Invalid file name").to_owned();
+    if meshes.obj.get(&file_string).is_none() {
+        let (file_meshes, mtl_materials) = Mesh::load_obj(Path::new(&file));
+        let dir = match file.parent() {
+            Some(p) => p.to_path_buf(),
+            None => PathBuf::new(),
+        };
+        meshes.obj.insert(file_string.clone(), ObjFile { meshes: file_meshes, merged: None,
+                                                     mtl_materials: mtl_materials, materials: None, dir: dir });
+    }
+    meshes.obj.get_mut(&file_string).unwrap()
+}
+
+/// Load the mesh geometry specified by the JSON value. If no model is specified all the
+/// models in the file are combined into a single mesh.
+fn load_mesh(path: &Path, meshes: &mut MeshCache, elem: &Value) -> Arc<Mesh> {
+    let obj = load_obj_file(path, meshes, elem);
+    if let Some(m) = elem.find("model") {
+        let model = m.as_str().expect("Model name type must be a string");
+        return match obj.meshes.get(model) {
+            Some(m) => m.clone(),
+            None => panic!("Requested model '{}' was not found in '{}'", model,
+                           elem.find("file").and_then(|f| f.as_str()).unwrap()),
+        };
+    }
+    if obj.merged.is_none() {
+        let merged = {
+            // Sort the models by name so the combined mesh is the same each time we load the scene
+            let mut names: Vec<_> = obj.meshes.keys().collect();
+            names.sort();
+            let models: Vec<_> = names.iter().map(|n| obj.meshes[*n].clone()).collect();
+            Arc::new(Mesh::merge(&models[..]))
+        };
+        obj.merged = Some(merged);
+    }
+    obj.merged.as_ref().unwrap().clone()
+}
+
+/// Load the table of materials from the MTL files of the OBJ used by the mesh geometry
+/// specified by the JSON value. The material ids of the mesh's triangles index into this
+/// table. Will panic if the geometry isn't a mesh.
+fn load_mtl_materials(path: &Path, meshes: &mut MeshCache, elem: &Value)
+                      -> Vec<Arc<Material + Send + Sync>> {
+    let ty = elem.find("type").expect("A type is required for geometry")
+        .as_str().expect("Geometry type must be a string");
+    if ty != "mesh" {
+        panic!("Only mesh geometry can use MTL materials, geometry type is '{}'", ty);
+    }
+    let obj = load_obj_file(path, meshes, elem);
+    if obj.materials.is_none() {
+        obj.materials = Some(obj.mtl_materials.iter().map(|m| convert_mtl_material(&obj.dir, m)).collect());
+    }
+    obj.materials.as_ref().unwrap().clone()
+}
+
 /// Load the sampleable geometry specified by the JSON value. Will panic if the geometry specified
-/// is not sampleable.
-fn load_sampleable_geometry(elem: &Value) -> Arc<SampleableGeom + Send + Sync> {
+/// is not sampleable. Meshes are shared through the mesh cache like in `load_geometry`.
+fn load_sampleable_geometry(path: &Path, meshes: &mut MeshCache, elem: &Value)
+                            -> Arc<SampleableGeom + Send + Sync> {
     let ty = elem.find("type").expect("A type is required for geometry")
         .as_str().expect("Geometry type must be a string");
     if ty == "sphere" {
@@ -491,6 +1217,10 @@ fn load_sampleable_geometry(elem: &Value) -> Arc<SampleableGeom + Send + Sync> {
         let height = elem.find("height").expect("A height is required for a rectangle").as_f64()
             .expect("height must be a number") as f32;
         Arc::new(Rectangle::new(width, height))
+    } else if ty == "mesh" {
+        load_mesh(path, meshes, elem)
+    } else if ty == "ply" {
+        load_ply(path, meshes, elem)
     } else {
         panic!("Geometry of type '{}' is not sampleable and can't be used for area light geometry", ty);
     }
@@ -692,3 +1422,58 @@ fn load_keyframes(elem: &Value) -> Option<AnimatedTransform> {
     Some(AnimatedTransform::with_keyframes(keyframes, knots, degree))
 }
 
+
+#[test]
+fn test_light_groups_sum_to_image() {
+    use integrator::SplitRadiance;
+    use light::LightList;
+
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(1.0), 0.0)]);
+    let at = |x: f32| AnimatedTransform::unanimated(&Transform::translate(&Vector::new(x, 2.0, 0.0)));
+    let mut instances = vec![Instance::point_light(at(-1.0), emission.clone(), "key".to_owned()),
+                             Instance::point_light(at(1.0), emission.clone(), "fill".to_owned()),
+                             Instance::constant_light(emission, "background".to_owned())];
+    let groups: Value = serde_json::from_str(r#"[{ "name": "front", "lights": ["key"] },
+                                                 { "name": "rest", "lights": ["fill", "background"] }]"#).unwrap();
+    let groups = load_light_groups(&groups);
+    for g in &groups {
+        assign_light_group(g, &mut instances);
+    }
+    let lights: Vec<_> = instances.iter().filter_map(|i| match *i {
+        Instance::Emitter(ref e) => Some(e),
+        _ => None,
+    }).collect();
+    assert_eq!(lights.iter().map(|l| l.light_group()).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(1)]);
+
+    // Every light is in a group so the groups should add up to the total light, no matter
+    // how many times the light scattered
+    let light_list = LightList::new(lights, 10.0, 0.0, 1.0);
+    let mut illum = SplitRadiance::new(groups.len());
+    for (i, l) in light_list.iter().enumerate() {
+        illum.add(&Colorf::new(0.5, i as f32, 2.0), i, l);
+    }
+    illum.add_escaped(&light_list, &Ray::new(&Point::broadcast(0.0), &Vector::new(0.0, 1.0, 0.0), 0.0),
+                      &Colorf::broadcast(0.25), 3);
+    let groups_sum = illum.light_group(0) + illum.light_group(1);
+    let total = illum.total();
+    assert!(f32::abs(groups_sum.r - total.r) < 1e-5 && f32::abs(groups_sum.g - total.g) < 1e-5
+            && f32::abs(groups_sum.b - total.b) < 1e-5);
+    assert!(!illum.light_group(0).is_black() && !illum.light_group(1).is_black());
+}
+
+#[test]
+#[should_panic(expected = "light key is already in light group front")]
+fn test_light_in_two_groups() {
+    let groups: Value = serde_json::from_str(r#"[{ "name": "front", "lights": ["key"] },
+                                                 { "name": "rest", "lights": ["fill", "key"] }]"#).unwrap();
+    load_light_groups(&groups);
+}
+
+#[test]
+#[should_panic(expected = "light lamp is not a light in the scene")]
+fn test_unknown_light_in_group() {
+    let emission = AnimatedColor::with_keyframes(vec![ColorKeyframe::new(&Colorf::broadcast(1.0), 0.0)]);
+    let mut instances = vec![Instance::point_light(AnimatedTransform::unanimated(&Transform::identity()),
+                                                   emission, "key".to_owned())];
+    assign_light_group(&LightGroup::new(0, "front".to_owned(), vec!["lamp".to_owned()]), &mut instances);
+}
@@ -0,0 +1,100 @@
+//! Defines 2D and 3D checkerboard textures which alternate between two textures.
+//! The 2D checkerboard is laid out using the surface's texture coordinates while the 3D
+//! checkerboard fills space and is evaluated at the hit point in the hit object's space,
+//! so it moves along with the object. The 2D checkerboard is useful for checking the
+//! parameterization of geometry.
+//!
+//! # Scene Usage Example
+//! The checkerboard requires the two colors or textures to alternate between and the
+//! number of checks to place along each texture coordinate (2D) or per unit length (3D).
+//! The dimension of the checkerboard defaults to 2 if not specified.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "uv_checks",
+//!         "type": "checkerboard",
+//!         "even": [1, 1, 1],
+//!         "odd": [0.1, 0.1, 0.1],
+//!         "scale": [16, 8]
+//!     },
+//!     {
+//!         "name": "solid_checks",
+//!         "type": "checkerboard",
+//!         "dimension": 3,
+//!         "even": [0.8, 0.1, 0.1],
+//!         "odd": "uv_checks",
+//!         "scale": 2
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::sync::Arc;
+
+use film::Colorf;
+use geometry::DifferentialGeometry;
+use texture::Texture;
+
+/// A checkerboard laid out over the surface's texture coordinates
+pub struct Checkerboard2D {
+    even: Arc<Texture + Send + Sync>,
+    odd: Arc<Texture + Send + Sync>,
+    /// Number of checks along the u and v texture coordinates
+    scale: (f32, f32),
+}
+
+impl Checkerboard2D {
+    /// Create a checkerboard alternating between `even` and `odd` with `scale`
+    /// checks along the u and v texture coordinates
+    pub fn new(even: Arc<Texture + Send + Sync>, odd: Arc<Texture + Send + Sync>, scale: (f32, f32))
+               -> Checkerboard2D {
+        Checkerboard2D { even: even, odd: odd, scale: scale }
+    }
+    /// Select the texture to use for the hit point
+    fn select(&self, dg: &DifferentialGeometry) -> &Arc<Texture + Send + Sync> {
+        let check = f32::floor(dg.u * self.scale.0) as i32 + f32::floor(dg.v * self.scale.1) as i32;
+        if check % 2 == 0 { &self.even } else { &self.odd }
+    }
+}
+
+impl Texture for Checkerboard2D {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        self.select(dg).sample_f32(dg)
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        self.select(dg).sample_color(dg)
+    }
+}
+
+/// A checkerboard filling space, evaluated at the hit point in the hit object's space
+pub struct Checkerboard3D {
+    even: Arc<Texture + Send + Sync>,
+    odd: Arc<Texture + Send + Sync>,
+    /// Number of checks per unit length
+    scale: f32,
+}
+
+impl Checkerboard3D {
+    /// Create a checkerboard alternating between `even` and `odd` with `scale`
+    /// checks per unit length
+    pub fn new(even: Arc<Texture + Send + Sync>, odd: Arc<Texture + Send + Sync>, scale: f32) -> Checkerboard3D {
+        Checkerboard3D { even: even, odd: odd, scale: scale }
+    }
+    /// Select the texture to use for the hit point
+    fn select(&self, dg: &DifferentialGeometry) -> &Arc<Texture + Send + Sync> {
+        let p = dg.p_obj * self.scale;
+        let check = f32::floor(p.x) as i32 + f32::floor(p.y) as i32 + f32::floor(p.z) as i32;
+        if check % 2 == 0 { &self.even } else { &self.odd }
+    }
+}
+
+impl Texture for Checkerboard3D {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        self.select(dg).sample_f32(dg)
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        self.select(dg).sample_color(dg)
+    }
+}
@@ -0,0 +1,289 @@
+//! Provides a loader for [OpenEXR](http://www.openexr.com/) images. Only single part
+//! scanline images are supported, with no compression or RLE, ZIPS or ZIP compression.
+//! The R, G and B channels are loaded from the image, or the Y channel if the image is
+//! grayscale. See the [file layout](http://www.openexr.com/openexrfilelayout.pdf) for
+//! details on the format.
+
+use std::f32;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use byteorder::{LittleEndian, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+
+use film::Colorf;
+
+const MAGIC: u32 = 20000630;
+/// Version flag marking the file as tiled
+const TILED_FLAG: u32 = 0x200;
+/// Version flags marking the file as containing deep data or multiple parts
+const MULTIPART_FLAGS: u32 = 0x800 | 0x1000;
+
+/// The compression methods we support
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None,
+    Rle,
+    Zips,
+    Zip,
+}
+
+impl Compression {
+    /// Number of scanlines stored in each chunk of the file
+    fn scanlines_per_chunk(&self) -> usize {
+        match *self {
+            Compression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+/// The types channels can store their values as
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PixelType {
+    UInt,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn size(&self) -> usize {
+        match *self {
+            PixelType::Half => 2,
+            _ => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+/// Convert the 16 bit half float to an f32
+pub fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    if exponent == 0 {
+        sign * f32::powi(2.0, -14) * mantissa / 1024.0
+    } else if exponent == 31 {
+        if mantissa == 0.0 { sign * f32::INFINITY } else { f32::NAN }
+    } else {
+        sign * f32::powi(2.0, exponent - 15) * (1.0 + mantissa / 1024.0)
+    }
+}
+
+/// Read a null terminated string from the file
+fn read_string<R: Read>(reader: &mut R) -> String {
+    let mut bytes = Vec::new();
+    loop {
+        let b = reader.read_u8().expect("texture::exr - unexpected end of file");
+        if b == 0 {
+            break;
+        }
+        bytes.push(b);
+    }
+    String::from_utf8(bytes).expect("texture::exr - invalid string in header")
+}
+
+/// Parse the channel list attribute
+fn parse_channels(mut data: &[u8]) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    loop {
+        let name = read_string(&mut data);
+        if name.is_empty() {
+            break;
+        }
+        let pixel_type = match data.read_i32::<LittleEndian>().expect("texture::exr - invalid channel list") {
+            0 => PixelType::UInt,
+            1 => PixelType::Half,
+            2 => PixelType::Float,
+            x => panic!("texture::exr - unrecognized pixel type {}", x),
+        };
+        // Skip pLinear and the reserved bytes
+        data = &data[4..];
+        let x_sampling = data.read_i32::<LittleEndian>().expect("texture::exr - invalid channel list");
+        let y_sampling = data.read_i32::<LittleEndian>().expect("texture::exr - invalid channel list");
+        if x_sampling != 1 || y_sampling != 1 {
+            panic!("texture::exr - subsampled channels are not supported");
+        }
+        channels.push(Channel { name: name, pixel_type: pixel_type });
+    }
+    channels
+}
+
+/// Undo the byte reordering and delta encoding applied to the data before
+/// RLE and ZIP compression
+fn reconstruct(data: &[u8]) -> Vec<u8> {
+    let mut t = data.to_vec();
+    for i in 1..t.len() {
+        t[i] = (t[i - 1] as i32 + t[i] as i32 - 128) as u8;
+    }
+    // The first half of the data holds the even bytes and the second half the odd bytes
+    let half = (t.len() + 1) / 2;
+    let mut out = Vec::with_capacity(t.len());
+    for i in 0..half {
+        out.push(t[i]);
+        if half + i < t.len() {
+            out.push(t[half + i]);
+        }
+    }
+    out
+}
+
+/// Decompress the run length encoded data
+fn decompress_rle(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let count = data[i] as i8;
+        i += 1;
+        if count < 0 {
+            let n = -(count as i32) as usize;
+            out.extend_from_slice(&data[i..i + n]);
+            i += n;
+        } else {
+            for _ in 0..count as usize + 1 {
+                out.push(data[i]);
+            }
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Load the OpenEXR image at `path`, returns the width and height of the image
+/// and its pixels in linear RGB, stored in rows from top to bottom
+pub fn load(path: &Path) -> (usize, usize, Vec<Colorf>) {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => panic!("texture::exr::load - failed to open {:?} due to {}", path, e),
+    };
+    let mut reader = BufReader::new(file);
+    let magic = reader.read_u32::<LittleEndian>().expect("texture::exr::load - failed to read header");
+    if magic != MAGIC {
+        panic!("texture::exr::load - {:?} is not an OpenEXR file", path);
+    }
+    let version = reader.read_u32::<LittleEndian>().expect("texture::exr::load - failed to read header");
+    if version & TILED_FLAG != 0 || version & MULTIPART_FLAGS != 0 {
+        panic!("texture::exr::load - {:?} is tiled, deep or multi-part which is not supported", path);
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = read_string(&mut reader);
+        if name.is_empty() {
+            break;
+        }
+        let _ = read_string(&mut reader);
+        let size = reader.read_i32::<LittleEndian>().expect("texture::exr::load - failed to read header") as usize;
+        let mut data = vec![0u8; size];
+        reader.read_exact(&mut data[..]).expect("texture::exr::load - failed to read header");
+        if name == "channels" {
+            channels = parse_channels(&data[..]);
+        } else if name == "compression" {
+            compression = Some(match data[0] {
+                0 => Compression::None,
+                1 => Compression::Rle,
+                2 => Compression::Zips,
+                3 => Compression::Zip,
+                x => panic!("texture::exr::load - unsupported compression type {} in {:?}", x, path),
+            });
+        } else if name == "dataWindow" {
+            let mut d = &data[..];
+            let mut b = [0; 4];
+            for x in &mut b {
+                *x = d.read_i32::<LittleEndian>().expect("texture::exr::load - invalid data window");
+            }
+            data_window = Some(b);
+        }
+    }
+    let compression = compression.expect("texture::exr::load - no compression specified");
+    let data_window = data_window.expect("texture::exr::load - no data window specified");
+    let width = (data_window[2] - data_window[0] + 1) as usize;
+    let height = (data_window[3] - data_window[1] + 1) as usize;
+
+    let find_channel = |name: &str| channels.iter().position(|c| c.name == name);
+    let rgb = match (find_channel("R"), find_channel("G"), find_channel("B")) {
+        (Some(r), Some(g), Some(b)) => [r, g, b],
+        _ => match find_channel("Y") {
+            Some(y) => [y, y, y],
+            None => panic!("texture::exr::load - {:?} doesn't have RGB or Y channels", path),
+        },
+    };
+    // Offset of each channel within a scanline, channels are stored one after another
+    let mut channel_offsets = Vec::with_capacity(channels.len());
+    let mut scanline_size = 0;
+    for c in &channels {
+        channel_offsets.push(scanline_size);
+        scanline_size += c.pixel_type.size() * width;
+    }
+
+    // The chunks are stored sequentially after the offset table so we can skip it
+    let lines_per_chunk = compression.scanlines_per_chunk();
+    let num_chunks = (height + lines_per_chunk - 1) / lines_per_chunk;
+    for _ in 0..num_chunks {
+        reader.read_u64::<LittleEndian>().expect("texture::exr::load - failed to read offset table");
+    }
+
+    let mut pixels = vec![Colorf::new(0.0, 0.0, 0.0); width * height];
+    for _ in 0..num_chunks {
+        let y = reader.read_i32::<LittleEndian>().expect("texture::exr::load - failed to read chunk");
+        let size = reader.read_i32::<LittleEndian>().expect("texture::exr::load - failed to read chunk") as usize;
+        let mut data = vec![0u8; size];
+        reader.read_exact(&mut data[..]).expect("texture::exr::load - failed to read chunk");
+        let y_start = (y - data_window[1]) as usize;
+        let lines = cmp_min(lines_per_chunk, height - y_start);
+        let expected_size = lines * scanline_size;
+        // If compression didn't make the chunk smaller it's stored uncompressed
+        let data =
+            if size == expected_size || compression == Compression::None {
+                data
+            } else if compression == Compression::Rle {
+                reconstruct(&decompress_rle(&data[..])[..])
+            } else {
+                let mut decompressed = Vec::with_capacity(expected_size);
+                if let Err(e) = ZlibDecoder::new(&data[..]).read_to_end(&mut decompressed) {
+                    panic!("texture::exr::load - failed to decompress chunk: {}", e);
+                }
+                reconstruct(&decompressed[..])
+            };
+        if data.len() != expected_size {
+            panic!("texture::exr::load - chunk at scanline {} has an invalid size", y);
+        }
+        for l in 0..lines {
+            let scanline = &data[l * scanline_size..(l + 1) * scanline_size];
+            let row = &mut pixels[(y_start + l) * width..(y_start + l + 1) * width];
+            for (i, c) in rgb.iter().enumerate() {
+                let ty = channels[*c].pixel_type;
+                let mut values = &scanline[channel_offsets[*c]..channel_offsets[*c] + ty.size() * width];
+                for p in row.iter_mut() {
+                    let v = match ty {
+                        PixelType::Half => half_to_f32(values.read_u16::<LittleEndian>().unwrap()),
+                        PixelType::Float => values.read_f32::<LittleEndian>().unwrap(),
+                        PixelType::UInt => values.read_u32::<LittleEndian>().unwrap() as f32,
+                    };
+                    p[i] = v;
+                }
+            }
+        }
+    }
+    (width, height, pixels)
+}
+
+fn cmp_min(a: usize, b: usize) -> usize {
+    if a < b { a } else { b }
+}
+
+#[test]
+fn test_half_to_f32() {
+    assert_eq!(half_to_f32(0x3c00), 1.0);
+    assert_eq!(half_to_f32(0xc000), -2.0);
+    assert_eq!(half_to_f32(0x3555), 0.333251953125);
+    assert_eq!(half_to_f32(0x7bff), 65504.0);
+    assert_eq!(half_to_f32(0x0001), f32::powi(2.0, -24));
+}
@@ -0,0 +1,112 @@
+//! Provides a loader for [Radiance HDR](http://www.graphics.cornell.edu/~bjw/rgbe.html)
+//! (.hdr, RGBE) images. Both flat and run length encoded scanlines are supported.
+
+use std::f32;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use film::Colorf;
+
+/// Convert the RGBE pixel to a linear RGB color
+fn rgbe_to_color(rgbe: &[u8]) -> Colorf {
+    if rgbe[3] == 0 {
+        Colorf::black()
+    } else {
+        let f = f32::powi(2.0, rgbe[3] as i32 - (128 + 8));
+        Colorf::new((rgbe[0] as f32 + 0.5) * f, (rgbe[1] as f32 + 0.5) * f, (rgbe[2] as f32 + 0.5) * f)
+    }
+}
+
+/// Read a run length encoded scanline of `width` pixels into `scanline`, the four
+/// byte marker at the start of the scanline has already been read
+fn read_rle_scanline<R: Read>(reader: &mut R, width: usize, scanline: &mut [u8]) {
+    let mut byte = [0u8; 1];
+    let mut run = [0u8; 128];
+    // Each channel of the scanline is stored separately
+    for c in 0..4 {
+        let mut x = 0;
+        while x < width {
+            reader.read_exact(&mut byte).expect("texture::hdr - unexpected end of file");
+            if byte[0] > 128 {
+                let count = byte[0] as usize - 128;
+                reader.read_exact(&mut byte).expect("texture::hdr - unexpected end of file");
+                if x + count > width {
+                    panic!("texture::hdr - invalid run length in scanline");
+                }
+                for i in x..x + count {
+                    scanline[4 * i + c] = byte[0];
+                }
+                x += count;
+            } else {
+                let count = byte[0] as usize;
+                if count == 0 || x + count > width {
+                    panic!("texture::hdr - invalid run length in scanline");
+                }
+                reader.read_exact(&mut run[..count]).expect("texture::hdr - unexpected end of file");
+                for (i, v) in run[..count].iter().enumerate() {
+                    scanline[4 * (x + i) + c] = *v;
+                }
+                x += count;
+            }
+        }
+    }
+}
+
+/// Load the Radiance HDR image at `path`, returns the width and height of the image
+/// and its pixels in linear RGB, stored in rows from top to bottom
+pub fn load(path: &Path) -> (usize, usize, Vec<Colorf>) {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => panic!("texture::hdr::load - failed to open {:?} due to {}", path, e),
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    reader.read_line(&mut line).expect("texture::hdr::load - failed to read header");
+    if !line.starts_with("#?") {
+        panic!("texture::hdr::load - {:?} is not a Radiance HDR file", path);
+    }
+    // Read the rest of the header until the blank line separating it from the resolution
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => panic!("texture::hdr::load - unexpected end of header in {:?}", path),
+            Ok(_) => {},
+        }
+        let l = line.trim();
+        if l.is_empty() {
+            break;
+        }
+        if l.starts_with("FORMAT=") && l != "FORMAT=32-bit_rle_rgbe" {
+            panic!("texture::hdr::load - unsupported format '{}' in {:?}", l, path);
+        }
+    }
+    line.clear();
+    reader.read_line(&mut line).expect("texture::hdr::load - failed to read resolution");
+    let res: Vec<_> = line.split_whitespace().collect();
+    if res.len() != 4 || (res[0] != "-Y" && res[0] != "+Y") || res[2] != "+X" {
+        panic!("texture::hdr::load - unsupported resolution '{}' in {:?}", line.trim(), path);
+    }
+    let height: usize = res[1].parse().expect("texture::hdr::load - invalid image height");
+    let width: usize = res[3].parse().expect("texture::hdr::load - invalid image width");
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![0u8; 4 * width];
+    for _ in 0..height {
+        reader.read_exact(&mut scanline[..4]).expect("texture::hdr::load - unexpected end of file");
+        let rle = width >= 8 && width < 32768 && scanline[0] == 2 && scanline[1] == 2
+            && ((scanline[2] as usize) << 8 | scanline[3] as usize) == width;
+        if rle {
+            read_rle_scanline(&mut reader, width, &mut scanline[..]);
+        } else {
+            reader.read_exact(&mut scanline[4..]).expect("texture::hdr::load - unexpected end of file");
+        }
+        pixels.extend(scanline.chunks(4).map(rgbe_to_color));
+    }
+    // Images stored from the bottom row up need to be flipped
+    if res[0] == "+Y" {
+        let rows: Vec<_> = pixels.chunks(width).rev().flat_map(|r| r.iter().cloned()).collect();
+        pixels = rows;
+    }
+    (width, height, pixels)
+}
@@ -0,0 +1,141 @@
+//! Defines a texture that looks up its values from an image loaded from disk.
+//! The image is repeated outside the [0, 1] texture coordinate range and
+//! lookups are bilinearly filtered.
+//!
+//! # Scene Usage Example
+//! The image texture requires the path to the image file to load, relative paths
+//! are resolved relative to the scene file. Any format the image crate can load
+//! is supported (eg. PNG, JPG, PPM) along with Radiance HDR (.hdr) and OpenEXR (.exr)
+//! images. Colors in the image are assumed to be in sRGB and are converted to linear RGB
+//! when loaded. Images storing data instead of colors, eg. normal maps, should set `srgb`
+//! to false to use the stored values directly. HDR and EXR images are always treated as
+//! linear and `srgb` is ignored for them.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "wood_planks",
+//!         "type": "image",
+//!         "file": "./planks.png"
+//!     },
+//!     {
+//!         "name": "planks_normals",
+//!         "type": "image",
+//!         "file": "./planks_normals.png",
+//!         "srgb": false
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::path::Path;
+
+use image::{self, GenericImage};
+
+use linalg;
+use film::Colorf;
+use geometry::DifferentialGeometry;
+use texture::{Texture, hdr, exr};
+
+/// A texture backed by an image, stored as linear RGB floats
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colorf>,
+}
+
+impl Image {
+    /// Create an image texture from `width` by `height` linear RGB pixels, stored in
+    /// rows from top to bottom
+    pub fn new(width: usize, height: usize, pixels: Vec<Colorf>) -> Image {
+        assert_eq!(width * height, pixels.len());
+        Image { width: width, height: height, pixels: pixels }
+    }
+    /// Load the image at `path` to create the texture. If `srgb` is true the image colors
+    /// are converted from sRGB to linear RGB, HDR and EXR images are always loaded as linear
+    pub fn load_file(path: &Path, srgb: bool) -> Image {
+        let hdr_data = match path.extension().and_then(|e| e.to_str()) {
+            Some("hdr") => Some(hdr::load(path)),
+            Some("exr") => Some(exr::load(path)),
+            _ => None,
+        };
+        if let Some((width, height, pixels)) = hdr_data {
+            return Image { width: width, height: height, pixels: pixels };
+        }
+        let img = match image::open(path) {
+            Ok(img) => img,
+            Err(e) => panic!("texture::Image::load_file - failed to load {:?} due to {}", path, e),
+        };
+        let (width, height) = img.dimensions();
+        let pixels = img.to_rgb().pixels().map(|p| {
+            let c = Colorf::new(p.data[0] as f32 / 255.0, p.data[1] as f32 / 255.0, p.data[2] as f32 / 255.0);
+            if srgb { c.to_linear() } else { c }
+        }).collect();
+        Image { width: width as usize, height: height as usize, pixels: pixels }
+    }
+    /// Get the width and height of the image
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+    /// Get the pixel at `x`, `y`, wrapping the coordinates to repeat the image
+    pub fn pixel(&self, x: i32, y: i32) -> Colorf {
+        let x = x.wrapping_rem(self.width as i32);
+        let y = y.wrapping_rem(self.height as i32);
+        let x = if x < 0 { x + self.width as i32 } else { x } as usize;
+        let y = if y < 0 { y + self.height as i32 } else { y } as usize;
+        self.pixels[y * self.width + x]
+    }
+    /// Bilinearly filter the image at the texture coordinates `u`, `v`. Texture coordinates
+    /// follow the OBJ convention where `v = 0` is the bottom of the image.
+    pub fn bilinear(&self, u: f32, v: f32) -> Colorf {
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        let x0 = f32::floor(x);
+        let y0 = f32::floor(y);
+        let tx = x - x0;
+        let ty = y - y0;
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let top = linalg::lerp(tx, &self.pixel(x0, y0), &self.pixel(x0 + 1, y0));
+        let bottom = linalg::lerp(tx, &self.pixel(x0, y0 + 1), &self.pixel(x0 + 1, y0 + 1));
+        linalg::lerp(ty, &top, &bottom)
+    }
+}
+
+impl Texture for Image {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        self.bilinear(dg.u, dg.v).luminance()
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        self.bilinear(dg.u, dg.v)
+    }
+}
+
+#[test]
+fn test_bilinear() {
+    let img = Image::new(2, 2, vec![Colorf::broadcast(0.0), Colorf::broadcast(1.0),
+                                    Colorf::broadcast(2.0), Colorf::broadcast(3.0)]);
+    // Pixel centers return the pixel values, v = 0 is the bottom row of the image
+    assert_eq!(img.bilinear(0.25, 0.75), Colorf::broadcast(0.0));
+    assert_eq!(img.bilinear(0.75, 0.75), Colorf::broadcast(1.0));
+    assert_eq!(img.bilinear(0.25, 0.25), Colorf::broadcast(2.0));
+    assert_eq!(img.bilinear(0.75, 0.25), Colorf::broadcast(3.0));
+    // Between the pixel centers the values are interpolated
+    assert_eq!(img.bilinear(0.5, 0.75), Colorf::broadcast(0.5));
+    assert_eq!(img.bilinear(0.25, 0.5), Colorf::broadcast(1.0));
+    assert_eq!(img.bilinear(0.5, 0.5), Colorf::broadcast(1.5));
+}
+
+#[test]
+fn test_wrap() {
+    let img = Image::new(3, 2, (0..6).map(|i| Colorf::broadcast(i as f32)).collect());
+    assert_eq!(img.pixel(-1, 0), img.pixel(2, 0));
+    assert_eq!(img.pixel(3, 2), img.pixel(0, 0));
+    assert_eq!(img.pixel(-4, -3), img.pixel(2, 1));
+    // The image repeats outside [0, 1] and filtering across the edge blends the opposite sides
+    let close = |a: Colorf, b: Colorf| f32::abs(a.r - b.r) < 1e-4;
+    let (u, v) = (0.3, 0.6);
+    assert!(close(img.bilinear(u + 1.0, v), img.bilinear(u, v)));
+    assert!(close(img.bilinear(u, v - 2.0), img.bilinear(u, v)));
+    assert_eq!(img.bilinear(0.0, 0.75), Colorf::broadcast(1.0));
+}
@@ -0,0 +1,62 @@
+//! Defines a procedural marble texture which perturbs bands along the y axis
+//! with fBm noise to produce veins of color, similar to the marble texture in PBRT.
+//!
+//! # Scene Usage Example
+//! The marble texture requires the two colors or textures to blend between for the
+//! veins. The scale of the noise (default 1), the strength of the noise's perturbation
+//! of the bands (variation, default 1) and the octaves and omega of the fBm noise
+//! (default 8 and 0.5) can optionally be specified.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "white_marble",
+//!         "type": "marble",
+//!         "low": [0.9, 0.9, 0.85],
+//!         "high": [0.3, 0.3, 0.35],
+//!         "scale": 4.0,
+//!         "variation": 2.0
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::sync::Arc;
+
+use linalg;
+use film::Colorf;
+use geometry::DifferentialGeometry;
+use texture::{self, Texture};
+
+/// A marble texture blending between two textures
+pub struct Marble {
+    low: Arc<Texture + Send + Sync>,
+    high: Arc<Texture + Send + Sync>,
+    scale: f32,
+    variation: f32,
+    omega: f32,
+    octaves: usize,
+}
+
+impl Marble {
+    /// Create a marble texture blending between `low` and `high`
+    pub fn new(low: Arc<Texture + Send + Sync>, high: Arc<Texture + Send + Sync>, scale: f32,
+               variation: f32, omega: f32, octaves: usize) -> Marble {
+        Marble { low: low, high: high, scale: scale, variation: variation, omega: omega, octaves: octaves }
+    }
+    fn weight(&self, dg: &DifferentialGeometry) -> f32 {
+        let p = dg.p_obj * self.scale;
+        let marble = p.y + self.variation * texture::noise::fbm(&p, self.omega, self.octaves);
+        0.5 + 0.5 * f32::sin(marble)
+    }
+}
+
+impl Texture for Marble {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        linalg::lerp(self.weight(dg), &self.low.sample_f32(dg), &self.high.sample_f32(dg))
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        linalg::lerp(self.weight(dg), &self.low.sample_color(dg), &self.high.sample_color(dg))
+    }
+}
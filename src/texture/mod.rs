@@ -0,0 +1,128 @@
+//! Defines the Texture trait implemented by textures that can be used to vary
+//! material parameters over a surface and provides some standard textures.
+//!
+//! # Scene Usage Example
+//! Textures are listed in an optional textures array in the scene and are given a
+//! name so material parameters can reference them. A type and name for the texture
+//! along with any additional parameters is required to specify one.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "my_texture",
+//!         "type": "The_Texture_Type",
+//!          ...
+//!     }
+//!     ...
+//! ]
+//! ```
+//!
+//! Textures which are composed of other textures, eg. the two colors of a checkerboard,
+//! can reference textures listed before them by name or be given a constant value.
+//!
+//! Material parameters that accept textures can then be given the name of the
+//! texture instead of a constant value. Parameters can still be specified with a
+//! constant value as before, eg. an RGB triple for colors or a number for scalars.
+//!
+//! ```json
+//! "materials": [
+//!     {
+//!         "name": "textured_matte",
+//!         "type": "matte",
+//!         "diffuse": "my_texture",
+//!         "roughness": 0.5
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use film::Colorf;
+use geometry::DifferentialGeometry;
+
+pub use self::image::Image;
+pub use self::checkerboard::{Checkerboard2D, Checkerboard3D};
+pub use self::noise::{FBm, Turbulence};
+pub use self::marble::Marble;
+pub use self::wood::Wood;
+
+pub mod image;
+pub mod checkerboard;
+pub mod noise;
+pub mod marble;
+pub mod wood;
+pub mod hdr;
+pub mod exr;
+
+/// Trait implemented by textures. Provides methods to look up the texture's
+/// value at the hit point on a surface
+pub trait Texture {
+    /// Sample the texture as a scalar value at the hit point
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32;
+    /// Sample the texture as a color at the hit point
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf;
+    /// Get the scalar value of the texture if it's the same everywhere on the surface,
+    /// materials use this to create their BxDFs once instead of at each hit
+    fn constant_f32(&self) -> Option<f32> {
+        None
+    }
+    /// Get the color of the texture if it's the same everywhere on the surface
+    fn constant_color(&self) -> Option<Colorf> {
+        None
+    }
+}
+
+/// A texture that returns the same scalar value everywhere on the surface
+#[derive(Clone, Copy, Debug)]
+pub struct ConstantScalar {
+    val: f32,
+}
+
+impl ConstantScalar {
+    /// Create a constant texture returning `val`
+    pub fn new(val: f32) -> ConstantScalar {
+        ConstantScalar { val: val }
+    }
+}
+
+impl Texture for ConstantScalar {
+    fn sample_f32(&self, _: &DifferentialGeometry) -> f32 {
+        self.val
+    }
+    fn sample_color(&self, _: &DifferentialGeometry) -> Colorf {
+        Colorf::broadcast(self.val)
+    }
+    fn constant_f32(&self) -> Option<f32> {
+        Some(self.val)
+    }
+    fn constant_color(&self) -> Option<Colorf> {
+        Some(Colorf::broadcast(self.val))
+    }
+}
+
+/// A texture that returns the same color everywhere on the surface
+#[derive(Clone, Copy, Debug)]
+pub struct ConstantColor {
+    val: Colorf,
+}
+
+impl ConstantColor {
+    /// Create a constant texture returning the color `val`
+    pub fn new(val: &Colorf) -> ConstantColor {
+        ConstantColor { val: *val }
+    }
+}
+
+impl Texture for ConstantColor {
+    fn sample_f32(&self, _: &DifferentialGeometry) -> f32 {
+        self.val.luminance()
+    }
+    fn sample_color(&self, _: &DifferentialGeometry) -> Colorf {
+        self.val
+    }
+    fn constant_f32(&self) -> Option<f32> {
+        Some(self.val.luminance())
+    }
+    fn constant_color(&self) -> Option<Colorf> {
+        Some(self.val)
+    }
+}
@@ -0,0 +1,225 @@
+//! Provides an implementation of Ken Perlin's improved noise function along with
+//! fractional Brownian motion (fBm) and turbulence functions built on top of it.
+//! The fBm and turbulence textures blend between two textures based on the noise value
+//! at the hit point. See [Perlin, Improving Noise](http://mrl.nyu.edu/~perlin/paper445.pdf).
+//!
+//! # Scene Usage Example
+//! The fBm and turbulence textures require the two colors or textures to blend between
+//! and can optionally specify a scaling factor for the noise frequency (default 1),
+//! the number of octaves of noise to sum (default 8) and omega, the falloff in
+//! amplitude of each octave (default 0.5). The noise is evaluated at the hit point in
+//! the hit object's space, so it moves along with the object.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "clouds",
+//!         "type": "fbm",
+//!         "low": [0.2, 0.3, 0.8],
+//!         "high": [1, 1, 1],
+//!         "scale": 2.0,
+//!         "octaves": 6,
+//!         "omega": 0.5
+//!     },
+//!     {
+//!         "name": "smudges",
+//!         "type": "turbulence",
+//!         "low": [0.8, 0.8, 0.8],
+//!         "high": [0.1, 0.1, 0.1],
+//!         "scale": 4.0
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::sync::Arc;
+
+use linalg::{self, Point};
+use film::Colorf;
+use geometry::DifferentialGeometry;
+use texture::Texture;
+
+/// Ken Perlin's permutation table for the improved noise function
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180];
+
+/// Look up the permutation table, wrapping `i` into the table's range
+fn perm(i: i32) -> i32 {
+    PERMUTATION[(i & 255) as usize] as i32
+}
+/// Smoothly fade `t` with the curve 6t^5 - 15t^4 + 10t^3
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+/// Compute the dot product of the offset with the gradient selected by the hash `h`
+fn grad(h: i32, x: f32, y: f32, z: f32) -> f32 {
+    let h = h & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Evaluate the Perlin noise function at the point, returns a value in [-1, 1]
+pub fn noise(p: &Point) -> f32 {
+    let (fx, fy, fz) = (f32::floor(p.x), f32::floor(p.y), f32::floor(p.z));
+    let (ix, iy, iz) = (fx as i32, fy as i32, fz as i32);
+    // Offset of the point within its lattice cell
+    let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = perm(ix) + iy;
+    let aa = perm(a) + iz;
+    let ab = perm(a + 1) + iz;
+    let b = perm(ix + 1) + iy;
+    let ba = perm(b) + iz;
+    let bb = perm(b + 1) + iz;
+
+    let x0 = linalg::lerp(u, &grad(perm(aa), x, y, z), &grad(perm(ba), x - 1.0, y, z));
+    let x1 = linalg::lerp(u, &grad(perm(ab), x, y - 1.0, z), &grad(perm(bb), x - 1.0, y - 1.0, z));
+    let x2 = linalg::lerp(u, &grad(perm(aa + 1), x, y, z - 1.0), &grad(perm(ba + 1), x - 1.0, y, z - 1.0));
+    let x3 = linalg::lerp(u, &grad(perm(ab + 1), x, y - 1.0, z - 1.0),
+                          &grad(perm(bb + 1), x - 1.0, y - 1.0, z - 1.0));
+    linalg::lerp(w, &linalg::lerp(v, &x0, &x1), &linalg::lerp(v, &x2, &x3))
+}
+/// Compute fractional Brownian motion at the point by summing `octaves` octaves of noise
+/// with each successive octave doubling in frequency and scaling in amplitude by `omega`
+pub fn fbm(p: &Point, omega: f32, octaves: usize) -> f32 {
+    let mut sum = 0.0;
+    let mut lambda = 1.0;
+    let mut o = 1.0;
+    for _ in 0..octaves {
+        sum += o * noise(&(*p * lambda));
+        lambda *= 2.0;
+        o *= omega;
+    }
+    sum
+}
+/// Compute turbulence at the point, this is the same as `fbm` except the absolute value
+/// of each octave of noise is summed
+pub fn turbulence(p: &Point, omega: f32, octaves: usize) -> f32 {
+    let mut sum = 0.0;
+    let mut lambda = 1.0;
+    let mut o = 1.0;
+    for _ in 0..octaves {
+        sum += o * f32::abs(noise(&(*p * lambda)));
+        lambda *= 2.0;
+        o *= omega;
+    }
+    sum
+}
+
+/// A texture blending between two textures based on fBm noise
+pub struct FBm {
+    low: Arc<Texture + Send + Sync>,
+    high: Arc<Texture + Send + Sync>,
+    scale: f32,
+    omega: f32,
+    octaves: usize,
+}
+
+impl FBm {
+    /// Create an fBm texture blending between `low` and `high`
+    pub fn new(low: Arc<Texture + Send + Sync>, high: Arc<Texture + Send + Sync>, scale: f32,
+               omega: f32, octaves: usize) -> FBm {
+        FBm { low: low, high: high, scale: scale, omega: omega, octaves: octaves }
+    }
+    fn weight(&self, dg: &DifferentialGeometry) -> f32 {
+        linalg::clamp(0.5 + 0.5 * fbm(&(dg.p_obj * self.scale), self.omega, self.octaves), 0.0, 1.0)
+    }
+}
+
+impl Texture for FBm {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        linalg::lerp(self.weight(dg), &self.low.sample_f32(dg), &self.high.sample_f32(dg))
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        linalg::lerp(self.weight(dg), &self.low.sample_color(dg), &self.high.sample_color(dg))
+    }
+}
+
+/// A texture blending between two textures based on turbulence
+pub struct Turbulence {
+    low: Arc<Texture + Send + Sync>,
+    high: Arc<Texture + Send + Sync>,
+    scale: f32,
+    omega: f32,
+    octaves: usize,
+}
+
+impl Turbulence {
+    /// Create a turbulence texture blending between `low` and `high`
+    pub fn new(low: Arc<Texture + Send + Sync>, high: Arc<Texture + Send + Sync>, scale: f32,
+               omega: f32, octaves: usize) -> Turbulence {
+        Turbulence { low: low, high: high, scale: scale, omega: omega, octaves: octaves }
+    }
+    fn weight(&self, dg: &DifferentialGeometry) -> f32 {
+        linalg::clamp(turbulence(&(dg.p_obj * self.scale), self.omega, self.octaves), 0.0, 1.0)
+    }
+}
+
+impl Texture for Turbulence {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        linalg::lerp(self.weight(dg), &self.low.sample_f32(dg), &self.high.sample_f32(dg))
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        linalg::lerp(self.weight(dg), &self.low.sample_color(dg), &self.high.sample_color(dg))
+    }
+}
+
+#[test]
+fn test_noise_lattice() {
+    // The noise function should be zero at all integer lattice points
+    for i in -4..5 {
+        let p = Point::new(i as f32, 2.0 * i as f32, -3.0 * i as f32);
+        assert_eq!(noise(&p), 0.0);
+    }
+}
+
+#[test]
+fn test_noise_range() {
+    for i in 0..1000 {
+        let t = i as f32 * 0.137;
+        let n = noise(&Point::new(t, -0.71 * t + 0.3, 1.37 * t - 5.2));
+        assert!(n >= -1.0 && n <= 1.0);
+    }
+}
+
+#[test]
+fn test_fbm_moves_with_object() {
+    use linalg::{Transform, AnimatedTransform, Ray, Vector};
+    use geometry::{Receiver, Sphere};
+    use material::Matte;
+    use texture::{ConstantColor, ConstantScalar};
+
+    let black = Arc::new(ConstantColor::new(&Colorf::black()));
+    let white = Arc::new(ConstantColor::new(&Colorf::broadcast(1.0)));
+    let tex = FBm::new(black, white.clone(), 4.0, 0.5, 8);
+    let mat = Arc::new(Matte::new(white, Arc::new(ConstantScalar::new(0.0))));
+    let sphere = Arc::new(Sphere::new(1.0));
+    let moved = Transform::translate(&Vector::new(3.0, -2.0, 5.0)) * Transform::rotate_y(40.0);
+    let a = Receiver::new(sphere.clone(), mat.clone(), AnimatedTransform::unanimated(&Transform::identity()),
+                          "a".to_owned());
+    let b = Receiver::new(sphere, mat, AnimatedTransform::unanimated(&moved), "b".to_owned());
+    // Hit the same point on the surface of each sphere, the texture should move with the sphere
+    for i in 0..8 {
+        let d = Vector::new(0.04 * i as f32 - 0.14, 0.05, -1.0).normalized();
+        let o = Point::new(0.0, 0.0, 4.0);
+        let (dg_a, _) = a.intersect(&mut Ray::new(&o, &d, 0.0)).unwrap();
+        let (dg_b, _) = b.intersect(&mut Ray::new(&(moved * o), &(moved * d), 0.0)).unwrap();
+        let (ca, cb) = (tex.sample_color(&dg_a), tex.sample_color(&dg_b));
+        assert!(f32::abs(ca.r - cb.r) < 1e-4);
+    }
+}
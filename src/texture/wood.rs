@@ -0,0 +1,62 @@
+//! Defines a procedural wood texture made of concentric rings around the y axis
+//! which are perturbed by fBm noise to give the rings an irregular grain.
+//!
+//! # Scene Usage Example
+//! The wood texture requires the two colors or textures to blend between across each
+//! ring. The scale of the rings (default 1), the strength of the noise's perturbation
+//! of the rings (variation, default 0.1) and the octaves and omega of the fBm noise
+//! (default 8 and 0.5) can optionally be specified.
+//!
+//! ```json
+//! "textures": [
+//!     {
+//!         "name": "oak",
+//!         "type": "wood",
+//!         "low": [0.6, 0.4, 0.2],
+//!         "high": [0.35, 0.2, 0.08],
+//!         "scale": 8.0,
+//!         "variation": 0.2
+//!     },
+//!     ...
+//! ]
+//! ```
+
+use std::f32;
+use std::sync::Arc;
+
+use linalg;
+use film::Colorf;
+use geometry::DifferentialGeometry;
+use texture::{self, Texture};
+
+/// A wood texture blending between two textures
+pub struct Wood {
+    low: Arc<Texture + Send + Sync>,
+    high: Arc<Texture + Send + Sync>,
+    scale: f32,
+    variation: f32,
+    omega: f32,
+    octaves: usize,
+}
+
+impl Wood {
+    /// Create a wood texture blending between `low` and `high`
+    pub fn new(low: Arc<Texture + Send + Sync>, high: Arc<Texture + Send + Sync>, scale: f32,
+               variation: f32, omega: f32, octaves: usize) -> Wood {
+        Wood { low: low, high: high, scale: scale, variation: variation, omega: omega, octaves: octaves }
+    }
+    fn weight(&self, dg: &DifferentialGeometry) -> f32 {
+        let p = dg.p_obj * self.scale;
+        let r = f32::sqrt(p.x * p.x + p.z * p.z) + self.variation * texture::noise::fbm(&p, self.omega, self.octaves);
+        r - f32::floor(r)
+    }
+}
+
+impl Texture for Wood {
+    fn sample_f32(&self, dg: &DifferentialGeometry) -> f32 {
+        linalg::lerp(self.weight(dg), &self.low.sample_f32(dg), &self.high.sample_f32(dg))
+    }
+    fn sample_color(&self, dg: &DifferentialGeometry) -> Colorf {
+        linalg::lerp(self.weight(dg), &self.low.sample_color(dg), &self.high.sample_color(dg))
+    }
+}